@@ -20,6 +20,7 @@ use log::*;
 use nymsphinx::acknowledgements::identifier::AckAes128Key;
 use nymsphinx::addressing::clients::Recipient;
 use nymsphinx::cover::generate_loop_cover_packet;
+use nymsphinx::params::delays::DelayParameters;
 use nymsphinx::utils::sample_poisson_duration;
 use rand::{rngs::OsRng, CryptoRng, Rng};
 use std::pin::Pin;
@@ -37,11 +38,16 @@ where
     /// Key used to encrypt and decrypt content of an ACK packet.
     ack_key: Arc<AckAes128Key>,
 
-    /// Average delay an acknowledgement packet is going to get delay at a single mixnode.
-    average_ack_delay: time::Duration,
+    /// Number of mixnodes the packets are going to go through. If not set, it's determined
+    /// by the number of layers in the topology.
+    route_length: Option<usize>,
 
-    /// Average delay a data packet is going to get delay at a single mixnode.
-    average_packet_delay: time::Duration,
+    /// Parameters determining average delays an acknowledgement packet is going to get
+    /// at each mixnode.
+    ack_delay_parameters: DelayParameters,
+
+    /// Parameters determining average delays a data packet is going to get at each mixnode.
+    packet_delay_parameters: DelayParameters,
 
     /// Average delay between sending subsequent cover packets.
     average_cover_message_sending_delay: time::Duration,
@@ -101,8 +107,9 @@ where
 impl<T: 'static + NymTopology> LoopCoverTrafficStream<OsRng, T> {
     pub(crate) fn new(
         ack_key: Arc<AckAes128Key>,
-        average_ack_delay: time::Duration,
-        average_packet_delay: time::Duration,
+        route_length: Option<usize>,
+        ack_delay_parameters: DelayParameters,
+        packet_delay_parameters: DelayParameters,
         average_cover_message_sending_delay: time::Duration,
         mix_tx: MixMessageSender,
         our_full_destination: Recipient,
@@ -112,8 +119,9 @@ impl<T: 'static + NymTopology> LoopCoverTrafficStream<OsRng, T> {
 
         LoopCoverTrafficStream {
             ack_key,
-            average_ack_delay,
-            average_packet_delay,
+            route_length,
+            ack_delay_parameters,
+            packet_delay_parameters,
             average_cover_message_sending_delay,
             next_delay: time::delay_for(Default::default()),
             mix_tx,
@@ -139,15 +147,24 @@ impl<T: 'static + NymTopology> LoopCoverTrafficStream<OsRng, T> {
         }
         let topology_ref = topology_ref_option.unwrap();
 
-        let cover_message = generate_loop_cover_packet(
+        let cover_message = match generate_loop_cover_packet(
             &mut self.rng,
             topology_ref,
             &*self.ack_key,
             &self.our_full_destination,
-            self.average_ack_delay,
-            self.average_packet_delay,
-        )
-        .expect("Somehow failed to generate a loop cover message with a valid topology");
+            self.route_length,
+            &self.ack_delay_parameters,
+            &self.packet_delay_parameters,
+        ) {
+            Ok(cover_message) => cover_message,
+            Err(err) => {
+                warn!(
+                    "Failed to generate a loop cover message - {:?}. Won't send any this time",
+                    err
+                );
+                return;
+            }
+        };
 
         // if this one fails, there's no retrying because it means that either:
         // - we run out of memory
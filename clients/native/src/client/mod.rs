@@ -94,8 +94,9 @@ impl NymClient {
             .enter(|| {
                 LoopCoverTrafficStream::new(
                     ack_key,
-                    self.config.get_average_ack_delay(),
-                    self.config.get_average_packet_delay(),
+                    self.config.get_route_length(),
+                    self.config.get_ack_delay_parameters(),
+                    self.config.get_packet_delay_parameters(),
                     self.config.get_loop_cover_traffic_average_delay(),
                     mix_tx,
                     self.as_mix_recipient(),
@@ -118,9 +119,10 @@ impl NymClient {
         let controller_config = real_messages_control::Config::new(
             self.config.get_ack_wait_multiplier(),
             self.config.get_ack_wait_addition(),
-            self.config.get_average_ack_delay(),
+            self.config.get_route_length(),
+            self.config.get_ack_delay_parameters(),
             self.config.get_message_sending_average_delay(),
-            self.config.get_average_packet_delay(),
+            self.config.get_packet_delay_parameters(),
             self.as_mix_recipient(),
         );
 
@@ -205,6 +207,25 @@ impl NymClient {
             shared_key.to_base58_string()
         );
 
+        // the gateway does not know about our preference unless we tell it (again, in case it
+        // changed since the last run)
+        let publish_address = self.config.get_publish_address();
+        if let Err(err) = self.runtime.block_on(async {
+            gateway_client
+                .set_address_publication(publish_address)
+                .await
+        }) {
+            warn!("Failed to update publication of our address - {}", err);
+        }
+
+        let delivery_policy = self.config.get_delivery_policy();
+        if let Err(err) = self
+            .runtime
+            .block_on(async { gateway_client.set_delivery_policy(delivery_policy).await })
+        {
+            warn!("Failed to update our delivery policy - {}", err);
+        }
+
         gateway_client
     }
 
@@ -239,6 +260,23 @@ impl NymClient {
             );
         }
 
+        // routes shorter than the network never reach its final layer, which is the only one
+        // allowed to forward traffic to gateways by nodes with layer filtering enabled
+        if let Some(route_length) = self.config.get_route_length() {
+            if let Some(num_layers) = self
+                .runtime
+                .block_on(topology_refresher.number_of_topology_layers())
+            {
+                if route_length < num_layers {
+                    warn!(
+                        "The configured route length ({}) is shorter than the number of network layers ({}) \
+                        - packets might get dropped by nodes filtering traffic by their layer",
+                        route_length, num_layers
+                    );
+                }
+            }
+        }
+
         info!("Starting topology refresher...");
         topology_refresher.start(self.runtime.handle());
     }
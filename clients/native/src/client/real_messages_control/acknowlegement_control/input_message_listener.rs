@@ -69,7 +69,6 @@ where
 
     async fn on_input_message(&mut self, msg: InputMessage) {
         let (recipient, content) = msg.destruct();
-        let split_message = self.message_chunker.split_message(&content);
         let topology_permit = self.topology_access.get_read_permit().await;
 
         let topology_ref_option =
@@ -80,18 +79,44 @@ where
         }
         let topology_ref = topology_ref_option.unwrap();
 
+        // routes for the acks are generated before splitting the message so that we would know
+        // how much space is actually available in each fragment
+        // the topology might still not allow constructing them, for example if it changed
+        // in the meantime, in which case the message is dropped
+        let split_message = match self
+            .message_chunker
+            .split_message_with_ack_routes(&content, topology_ref)
+        {
+            Ok(split_message) => split_message,
+            Err(err) => {
+                warn!("Could not split the message into fragments - {:?}", err);
+                return;
+            }
+        };
+
         let mut pending_acks = Vec::with_capacity(split_message.len());
         let mut real_messages = Vec::with_capacity(split_message.len());
-        for message_chunk in split_message {
-            // since the paths can be constructed, this CAN'T fail, if it does, there's a bug somewhere
+        for (message_chunk, ack_route) in split_message {
             let frag_id = message_chunk.fragment_identifier();
             // we need to clone it because we need to keep it in memory in case we had to retransmit
             // it. And then we'd need to recreate entire ACK again.
             let chunk_clone = message_chunk.clone();
-            let (total_delay, (first_hop, packet)) = self
+            // nothing was sent or awaits an ack yet, so the whole message can be safely dropped
+            let (total_delay, (first_hop, packet)) = match self
                 .message_chunker
-                .prepare_chunk_for_sending(chunk_clone, topology_ref, &self.ack_key, &recipient)
-                .unwrap();
+                .prepare_chunk_for_sending_with_ack_route(
+                    chunk_clone,
+                    &ack_route,
+                    topology_ref,
+                    &self.ack_key,
+                    &recipient,
+                ) {
+                Ok(prepared_chunk) => prepared_chunk,
+                Err(err) => {
+                    warn!("Could not prepare the message for sending - {:?}", err);
+                    return;
+                }
+            };
 
             real_messages.push(RealMessage::new(first_hop, packet, frag_id));
 
@@ -30,6 +30,7 @@ use nymsphinx::{
         fragment::{Fragment, FragmentIdentifier},
         MessageChunker,
     },
+    params::delays::DelayParameters,
     Delay,
 };
 use rand::{CryptoRng, Rng};
@@ -119,8 +120,9 @@ where
         mut rng: R,
         topology_access: TopologyAccessor<T>,
         ack_recipient: Recipient,
-        average_packet_delay_duration: Duration,
-        average_ack_delay_duration: Duration,
+        route_length: Option<usize>,
+        packet_delay_parameters: DelayParameters,
+        ack_delay_parameters: DelayParameters,
         ack_wait_multiplier: f64,
         ack_wait_addition: Duration,
         connectors: AcknowledgementControllerConnectors,
@@ -133,9 +135,10 @@ where
             rng,
             ack_recipient.clone(),
             true,
-            average_packet_delay_duration,
-            average_ack_delay_duration,
-        );
+            packet_delay_parameters,
+            ack_delay_parameters,
+        )
+        .with_route_length(route_length);
 
         let acknowledgement_listener = AcknowledgementListener::new(
             Arc::clone(&ack_key),
@@ -95,10 +95,20 @@ where
         }
         let topology_ref = topology_ref_option.unwrap();
 
-        let (total_delay, (first_hop, packet)) = self
+        // the topology might not allow constructing the routes anymore, in which case
+        // the fragment is given up on as it could not be retransmitted anyway
+        let (total_delay, (first_hop, packet)) = match self
             .message_chunker
             .prepare_chunk_for_sending(chunk_clone, topology_ref, &self.ack_key, &packet_recipient)
-            .unwrap();
+        {
+            Ok(prepared_chunk) => prepared_chunk,
+            Err(err) => {
+                warn!("Could not retransmit the packet - {:?}", err);
+                drop(topology_permit);
+                self.pending_acks.write().await.remove(&frag_id);
+                return;
+            }
+        };
 
         // minor optimization to not hold the permit while we no longer need it and might have to block
         // waiting for the write lock on `pending_acks`
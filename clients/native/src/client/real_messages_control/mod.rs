@@ -29,6 +29,7 @@ use gateway_client::AcknowledgementReceiver;
 use log::*;
 use nymsphinx::acknowledgements::identifier::AckAes128Key;
 use nymsphinx::addressing::clients::Recipient;
+use nymsphinx::params::delays::DelayParameters;
 use rand::{rngs::OsRng, CryptoRng, Rng};
 use std::sync::Arc;
 use std::time::Duration;
@@ -43,8 +44,9 @@ pub(crate) struct Config {
     ack_wait_multiplier: f64,
     ack_wait_addition: Duration,
     self_recipient: Recipient,
-    average_packet_delay_duration: Duration,
-    average_ack_delay_duration: Duration,
+    route_length: Option<usize>,
+    packet_delay_parameters: DelayParameters,
+    ack_delay_parameters: DelayParameters,
     average_message_sending_delay: Duration,
 }
 
@@ -52,15 +54,17 @@ impl Config {
     pub(crate) fn new(
         ack_wait_multiplier: f64,
         ack_wait_addition: Duration,
-        average_ack_delay_duration: Duration,
+        route_length: Option<usize>,
+        ack_delay_parameters: DelayParameters,
         average_message_sending_delay: Duration,
-        average_packet_delay_duration: Duration,
+        packet_delay_parameters: DelayParameters,
         self_recipient: Recipient,
     ) -> Self {
         Config {
             self_recipient,
-            average_packet_delay_duration,
-            average_ack_delay_duration,
+            route_length,
+            packet_delay_parameters,
+            ack_delay_parameters,
             average_message_sending_delay,
             ack_wait_multiplier,
             ack_wait_addition,
@@ -103,8 +107,9 @@ impl<T: 'static + NymTopology> RealMessagesController<OsRng, T> {
             rng,
             topology_access.clone(),
             config.self_recipient.clone(),
-            config.average_packet_delay_duration,
-            config.average_ack_delay_duration,
+            config.route_length,
+            config.packet_delay_parameters.clone(),
+            config.ack_delay_parameters.clone(),
             config.ack_wait_multiplier,
             config.ack_wait_addition,
             ack_controller_connectors,
@@ -112,8 +117,9 @@ impl<T: 'static + NymTopology> RealMessagesController<OsRng, T> {
 
         let out_queue_control = OutQueueControl::new(
             ack_control.ack_key(),
-            config.average_ack_delay_duration,
-            config.average_packet_delay_duration,
+            config.route_length,
+            config.ack_delay_parameters,
+            config.packet_delay_parameters,
             config.average_message_sending_delay,
             sent_notifier_tx,
             mix_sender,
@@ -23,6 +23,7 @@ use nymsphinx::acknowledgements::identifier::AckAes128Key;
 use nymsphinx::addressing::clients::Recipient;
 use nymsphinx::chunking::fragment::FragmentIdentifier;
 use nymsphinx::cover::generate_loop_cover_packet;
+use nymsphinx::params::delays::DelayParameters;
 use nymsphinx::utils::sample_poisson_duration;
 use nymsphinx::SphinxPacket;
 use rand::{CryptoRng, Rng};
@@ -41,11 +42,16 @@ where
     /// Key used to encrypt and decrypt content of an ACK packet.
     ack_key: Arc<AckAes128Key>,
 
-    /// Average delay an acknowledgement packet is going to get delay at a single mixnode.
-    average_ack_delay: Duration,
+    /// Number of mixnodes the packets are going to go through. If not set, it's determined
+    /// by the number of layers in the topology.
+    route_length: Option<usize>,
 
-    /// Average delay a data packet is going to get delay at a single mixnode.
-    average_packet_delay: Duration,
+    /// Parameters determining average delays an acknowledgement packet is going to get
+    /// at each mixnode.
+    ack_delay_parameters: DelayParameters,
+
+    /// Parameters determining average delays a data packet is going to get at each mixnode.
+    packet_delay_parameters: DelayParameters,
 
     /// Average delay between sending subsequent packets.
     average_message_sending_delay: Duration,
@@ -151,8 +157,9 @@ where
 {
     pub(crate) fn new(
         ack_key: Arc<AckAes128Key>,
-        average_ack_delay: Duration,
-        average_packet_delay: Duration,
+        route_length: Option<usize>,
+        ack_delay_parameters: DelayParameters,
+        packet_delay_parameters: DelayParameters,
         average_message_sending_delay: Duration,
         sent_notifier: SentPacketNotificationSender,
         mix_tx: MixMessageSender,
@@ -163,8 +170,9 @@ where
     ) -> Self {
         OutQueueControl {
             ack_key,
-            average_ack_delay,
-            average_packet_delay,
+            route_length,
+            ack_delay_parameters,
+            packet_delay_parameters,
             average_message_sending_delay,
             sent_notifier,
             next_delay: time::delay_for(Default::default()),
@@ -198,15 +206,24 @@ where
                 }
                 let topology_ref = topology_ref_option.unwrap();
 
-                let cover_message = generate_loop_cover_packet(
+                let cover_message = match generate_loop_cover_packet(
                     &mut self.rng,
                     topology_ref,
                     &*self.ack_key,
                     &self.our_full_destination,
-                    self.average_ack_delay,
-                    self.average_packet_delay,
-                )
-                .expect("Somehow failed to generate a loop cover message with a valid topology");
+                    self.route_length,
+                    &self.ack_delay_parameters,
+                    &self.packet_delay_parameters,
+                ) {
+                    Ok(cover_message) => cover_message,
+                    Err(err) => {
+                        warn!(
+                            "Failed to generate a loop cover message - {:?}. Won't send any this time",
+                            err
+                        );
+                        return;
+                    }
+                };
 
                 MixMessage::new(cover_message.0, cover_message.1)
             }
@@ -137,6 +137,17 @@ impl<T: NymTopology> TopologyAccessor<T> {
         }
     }
 
+    /// Number of mix layers of the current topology, if it is available and valid.
+    pub(crate) async fn number_of_layers(&self) -> Option<usize> {
+        match &self.inner.read().await.0 {
+            None => None,
+            Some(ref topology) => topology
+                .make_layered_topology()
+                .ok()
+                .map(|layered_topology| layered_topology.len()),
+        }
+    }
+
     pub(crate) async fn get_all_clients(&self) -> Option<Vec<gateway::Client>> {
         // TODO: this will need to be modified to instead return pairs (provider, client)
         match &self.inner.read().await.0 {
@@ -212,6 +223,10 @@ impl TopologyRefresher<directory_client::Topology> {
         self.topology_accessor.is_routable().await
     }
 
+    pub(crate) async fn number_of_topology_layers(&self) -> Option<usize> {
+        self.topology_accessor.number_of_layers().await
+    }
+
     pub(crate) fn start(mut self, handle: &Handle) -> JoinHandle<()> {
         handle.spawn(async move {
             loop {
@@ -0,0 +1,62 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::commands::{authenticate_with_gateway, load_identity_keys};
+use crate::config::Config;
+use clap::{App, Arg, ArgMatches};
+use config::NymConfig;
+use std::sync::Arc;
+
+pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
+    App::new("deregister")
+        .about("Makes the gateway forget the client, i.e. its shared key and all of its stored messages")
+        .arg(Arg::with_name("id")
+            .long("id")
+            .help("Id of the nym-mixnet-client we want to deregister.")
+            .takes_value(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("config")
+            .long("config")
+            .help("Custom path to the nym-mixnet-client configuration file")
+            .takes_value(true)
+        )
+}
+
+pub fn execute(matches: &ArgMatches) {
+    let id = matches.value_of("id").unwrap();
+
+    let config =
+        Config::load_from_file(matches.value_of("config").map(|path| path.into()), Some(id))
+            .expect("Failed to load config file");
+    let identity_keypair = Arc::new(load_identity_keys(&config));
+
+    println!("Deregistering from gateway {}...", config.get_gateway_id());
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let deregistration_res = rt.block_on(async {
+        let mut gateway_client = authenticate_with_gateway(&config, identity_keypair).await?;
+        gateway_client.deregister().await
+    });
+    if let Err(err) = deregistration_res {
+        println!("Failed to deregister from the gateway - {}", err);
+        return;
+    }
+
+    // the key is of no use anymore
+    let config = config.without_gateway_shared_key();
+    config
+        .save_to_file(None)
+        .expect("Failed to save the config file");
+    println!("The client got deregistered. Run `init` again in order to use a gateway");
+}
@@ -46,6 +46,10 @@ pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
             .help("Address of the directory server the client is getting topology from")
             .takes_value(true),
         )
+        .arg(Arg::with_name("publish-address")
+            .long("publish-address")
+            .help("Whether the gateway should make the address of this client publicly discoverable")
+        )
         .arg(Arg::with_name("disable-socket")
             .long("disable-socket")
             .help("Whether to not start the websocket")
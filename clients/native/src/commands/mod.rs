@@ -12,12 +12,61 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::{Config, SocketType};
+use crate::config::{persistence::pathfinder::ClientPathfinder, Config, SocketType};
 use clap::ArgMatches;
+use crypto::asymmetric::identity;
+use gateway_client::error::GatewayClientError;
+use gateway_client::GatewayClient;
+use gateway_requests::registration::handshake::SharedKey;
+use pemstore::pemstore::PemStore;
+use std::sync::Arc;
 
+pub mod deregister;
 pub mod init;
+pub mod rotate_key;
 pub mod run;
 
+pub(crate) fn load_identity_keys(config_file: &Config) -> identity::KeyPair {
+    let identity_keypair = PemStore::new(ClientPathfinder::new_from_config(&config_file))
+        .read_identity_keypair()
+        .expect("Failed to read stored identity key files");
+    println!(
+        "Public identity key: {}\n",
+        identity_keypair.public_key().to_base58_string()
+    );
+    identity_keypair
+}
+
+/// Connects to the gateway of the client and authenticates using the stored shared key,
+/// without starting to listen for any mixnet messages.
+pub(crate) async fn authenticate_with_gateway(
+    config: &Config,
+    identity_keypair: Arc<identity::KeyPair>,
+) -> Result<GatewayClient<'static, url::Url>, GatewayClientError> {
+    let gateway_identity = identity::PublicKey::from_base58_string(config.get_gateway_id())
+        .expect("provided gateway id is invalid!");
+    let gateway_address = url::Url::parse(&config.get_gateway_listener())
+        .expect("provided gateway address is invalid!");
+    let shared_key = match config.get_gateway_shared_key() {
+        Some(shared_key) => SharedKey::try_from_base58_string(shared_key)
+            .expect("The stored shared key is invalid!"),
+        None => return Err(GatewayClientError::NoSharedKeyAvailable),
+    };
+
+    let mut gateway_client = GatewayClient::new_init(
+        gateway_address,
+        gateway_identity,
+        identity_keypair,
+        config.get_gateway_response_timeout(),
+    );
+    gateway_client.establish_connection().await?;
+    if gateway_client.authenticate(Some(shared_key)).await? {
+        Ok(gateway_client)
+    } else {
+        Err(GatewayClientError::AuthenticationFailure)
+    }
+}
+
 pub(crate) fn override_config(mut config: Config, matches: &ArgMatches) -> Config {
     if let Some(directory) = matches.value_of("directory") {
         config = config.with_custom_directory(directory);
@@ -27,6 +76,10 @@ pub(crate) fn override_config(mut config: Config, matches: &ArgMatches) -> Confi
         config = config.with_gateway_id(gateway_id);
     }
 
+    if matches.is_present("publish-address") {
+        config = config.with_address_publication(true);
+    }
+
     if matches.is_present("disable-socket") {
         config = config.with_socket(SocketType::None);
     }
@@ -0,0 +1,75 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::commands::{authenticate_with_gateway, load_identity_keys};
+use crate::config::Config;
+use clap::{App, Arg, ArgMatches};
+use config::NymConfig;
+use std::sync::Arc;
+
+pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
+    App::new("rotate-key")
+        .about("Replaces the key shared with the gateway with a freshly derived one")
+        .arg(
+            Arg::with_name("id")
+                .long("id")
+                .help("Id of the nym-mixnet-client we want to rotate the key of.")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("Custom path to the nym-mixnet-client configuration file")
+                .takes_value(true),
+        )
+}
+
+pub fn execute(matches: &ArgMatches) {
+    let id = matches.value_of("id").unwrap();
+
+    let config =
+        Config::load_from_file(matches.value_of("config").map(|path| path.into()), Some(id))
+            .expect("Failed to load config file");
+    let identity_keypair = Arc::new(load_identity_keys(&config));
+
+    println!(
+        "Rotating the key shared with gateway {}...",
+        config.get_gateway_id()
+    );
+    let mut rt = tokio::runtime::Runtime::new().unwrap();
+    let rotation_res = rt.block_on(async {
+        let mut gateway_client = authenticate_with_gateway(&config, identity_keypair).await?;
+        let new_shared_key = gateway_client.rotate_key().await?;
+        // the new key is already in use, so there's no harm if the closing fails
+        if let Err(err) = gateway_client.close_connection().await {
+            println!("Failed to cleanly close the connection - {}", err);
+        }
+        Ok::<_, gateway_client::error::GatewayClientError>(new_shared_key)
+    });
+    let new_shared_key = match rotation_res {
+        Ok(new_shared_key) => new_shared_key,
+        Err(err) => {
+            println!("Failed to rotate the shared key - {}", err);
+            return;
+        }
+    };
+
+    // the previous key is no longer accepted by the gateway
+    let config = config.with_gateway_shared_key(new_shared_key.to_base58_string());
+    config
+        .save_to_file(None)
+        .expect("Failed to save the new shared key to the config file");
+    println!("The shared key got rotated");
+}
@@ -13,12 +13,10 @@
 // limitations under the License.
 
 use crate::client::NymClient;
-use crate::commands::override_config;
-use crate::config::{persistence::pathfinder::ClientPathfinder, Config};
+use crate::commands::{load_identity_keys, override_config};
+use crate::config::Config;
 use clap::{App, Arg, ArgMatches};
 use config::NymConfig;
-use crypto::asymmetric::identity;
-use pemstore::pemstore::PemStore;
 
 pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
     App::new("run")
@@ -45,6 +43,10 @@ pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
             .help("Id of the gateway we want to connect to. If overridden, it is user's responsibility to ensure prior registration happened")
             .takes_value(true)
         )
+        .arg(Arg::with_name("publish-address")
+            .long("publish-address")
+            .help("Whether the gateway should make the address of this client publicly discoverable")
+        )
         .arg(Arg::with_name("disable-socket")
             .long("disable-socket")
             .help("Whether to not start the websocket")
@@ -57,17 +59,6 @@ pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
         )
 }
 
-fn load_identity_keys(config_file: &Config) -> identity::KeyPair {
-    let identity_keypair = PemStore::new(ClientPathfinder::new_from_config(&config_file))
-        .read_identity_keypair()
-        .expect("Failed to read stored identity key files");
-    println!(
-        "Public identity key: {}\n",
-        identity_keypair.public_key().to_base58_string()
-    );
-    identity_keypair
-}
-
 pub fn execute(matches: &ArgMatches) {
     let id = matches.value_of("id").unwrap();
 
@@ -76,6 +67,12 @@ pub fn execute(matches: &ArgMatches) {
             .expect("Failed to load config file");
 
     config = override_config(config, matches);
+
+    if let Err(err) = config.validate() {
+        println!("Invalid configuration - {}", err);
+        return;
+    }
+
     let identity_keypair = load_identity_keys(&config);
     NymClient::new(config, identity_keypair).run_forever();
 }
@@ -14,7 +14,11 @@
 
 use crate::config::template::config_template;
 use config::NymConfig;
+use gateway_requests::DeliveryPolicy;
+use nymsphinx::params::delays::DelayParameters;
+use nymsphinx::MAX_PATH_LENGTH;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 use std::time;
 
@@ -54,6 +58,23 @@ impl SocketType {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ConfigValidationError {
+    InvalidRouteLength(usize),
+}
+
+impl Display for ConfigValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValidationError::InvalidRouteLength(route_length) => write!(
+                f,
+                "route length has to be in range [1, {}), got {}",
+                MAX_PATH_LENGTH, route_length
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
@@ -136,11 +157,21 @@ impl Config {
         self
     }
 
+    pub fn without_gateway_shared_key(mut self) -> Self {
+        self.client.gateway_shared_key = None;
+        self
+    }
+
     pub fn with_custom_directory<S: Into<String>>(mut self, directory_server: S) -> Self {
         self.client.directory_server = directory_server.into();
         self
     }
 
+    pub fn with_address_publication(mut self, publish_address: bool) -> Self {
+        self.client.publish_address = publish_address;
+        self
+    }
+
     pub fn with_socket(mut self, socket_type: SocketType) -> Self {
         self.socket.socket_type = socket_type;
         self
@@ -158,6 +189,21 @@ impl Config {
         self
     }
 
+    /// Checks values that can't be represented by their types alone, so that invalid
+    /// configuration is rejected at startup rather than misbehaving at runtime.
+    pub fn validate(&self) -> Result<(), ConfigValidationError> {
+        // whether the route fits into the network can only be determined once the topology
+        // is known, but it can never go beyond the sphinx limit, which has to leave space for
+        // the gateway
+        if let Some(route_length) = self.debug.route_length {
+            if route_length == 0 || route_length >= MAX_PATH_LENGTH {
+                return Err(ConfigValidationError::InvalidRouteLength(route_length));
+            }
+        }
+
+        Ok(())
+    }
+
     // getters
     pub fn get_config_file_save_location(&self) -> PathBuf {
         self.config_directory().join(Self::config_file_name())
@@ -187,6 +233,14 @@ impl Config {
         self.client.gateway_shared_key.clone()
     }
 
+    pub fn get_publish_address(&self) -> bool {
+        self.client.publish_address
+    }
+
+    pub fn get_delivery_policy(&self) -> DeliveryPolicy {
+        self.client.delivery_policy
+    }
+
     pub fn get_socket_type(&self) -> SocketType {
         self.socket.socket_type
     }
@@ -204,6 +258,37 @@ impl Config {
         time::Duration::from_millis(self.debug.average_ack_delay)
     }
 
+    pub fn get_route_length(&self) -> Option<usize> {
+        self.debug.route_length
+    }
+
+    pub fn get_packet_delay_parameters(&self) -> DelayParameters {
+        let per_hop_average_delays = self
+            .debug
+            .average_packet_delays_per_hop
+            .iter()
+            .map(|&delay| time::Duration::from_millis(delay))
+            .collect();
+        let delay_parameters = DelayParameters::new(self.get_average_packet_delay())
+            .with_per_hop_average_delays(per_hop_average_delays);
+
+        match self.debug.max_total_expected_delay {
+            Some(max_delay) => delay_parameters
+                .with_max_total_expected_delay(time::Duration::from_millis(max_delay)),
+            None => delay_parameters,
+        }
+    }
+
+    pub fn get_ack_delay_parameters(&self) -> DelayParameters {
+        let delay_parameters = DelayParameters::new(self.get_average_ack_delay());
+
+        match self.debug.max_total_expected_delay {
+            Some(max_delay) => delay_parameters
+                .with_max_total_expected_delay(time::Duration::from_millis(max_delay)),
+            None => delay_parameters,
+        }
+    }
+
     pub fn get_ack_wait_multiplier(&self) -> f64 {
         self.debug.ack_wait_multiplier
     }
@@ -272,6 +357,16 @@ pub struct Client {
     #[serde(deserialize_with = "de_option_string")]
     gateway_shared_key: Option<String>,
 
+    /// Whether the gateway should list the address of this client in its presence,
+    /// making it discoverable by other clients.
+    #[serde(default)]
+    publish_address: bool,
+
+    /// How the gateway should hand messages over to this client if the same identity
+    /// is used by several clients connected to it at once.
+    #[serde(default)]
+    delivery_policy: DeliveryPolicy,
+
     /// nym_home_directory specifies absolute path to the home nym Clients directory.
     /// It is expected to use default value and hence .toml file should not redefine this field.
     nym_root_directory: PathBuf,
@@ -288,6 +383,8 @@ impl Default for Client {
             gateway_id: "".to_string(),
             gateway_listener: "".to_string(),
             gateway_shared_key: None,
+            publish_address: false,
+            delivery_policy: DeliveryPolicy::default(),
             nym_root_directory: Config::default_root_directory(),
         }
     }
@@ -346,6 +443,23 @@ pub struct Debug {
     /// The provided value is interpreted as milliseconds.
     average_ack_delay: u64,
 
+    /// Number of mix nodes every packet, and its acknowledgement, is going to go through.
+    /// If not set, a single node from every layer of the network topology is used.
+    /// Otherwise that many first layers are traversed in order. It must not exceed the number
+    /// of layers of the network. Note that packets sent through shorter routes are dropped by
+    /// the nodes with layer filtering enabled, as such routes never reach the final layer.
+    route_length: Option<usize>,
+
+    /// Average delays for the consecutive hops of the route of a data packet. They take precedence
+    /// over `average_packet_delay`, which is used for any hop without its value specified here.
+    /// The provided values are interpreted as milliseconds.
+    average_packet_delays_per_hop: Vec<u64>,
+
+    /// If set, the average delays of all hops are proportionally scaled down, so that
+    /// the total expected delay of a data packet, or of an acknowledgement, would not exceed it.
+    /// The provided value is interpreted as milliseconds.
+    max_total_expected_delay: Option<u64>,
+
     /// Value multiplied with the expected round trip time of an acknowledgement packet before
     /// it is assumed it was lost and retransmission of the data packet happens.
     /// In an ideal network with 0 latency, this value would have been 1.
@@ -391,6 +505,9 @@ impl Default for Debug {
         Debug {
             average_packet_delay: DEFAULT_AVERAGE_PACKET_DELAY,
             average_ack_delay: DEFAULT_AVERAGE_PACKET_DELAY,
+            route_length: None,
+            average_packet_delays_per_hop: Vec::new(),
+            max_total_expected_delay: None,
             ack_wait_multiplier: DEFAULT_ACK_WAIT_MULTIPLIER,
             ack_wait_addition: DEFAULT_ACK_WAIT_ADDITION,
             loop_cover_traffic_average_delay: DEFAULT_LOOP_COVER_STREAM_AVERAGE_DELAY,
@@ -420,4 +537,24 @@ mod client_config {
 
         assert_eq!(default_config, loaded_config);
     }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn route_length_outside_of_sphinx_limits_is_rejected() {
+        let mut config = Config::default();
+        for &route_length in &[0, MAX_PATH_LENGTH, MAX_PATH_LENGTH + 1] {
+            config.debug.route_length = Some(route_length);
+            assert_eq!(
+                Err(ConfigValidationError::InvalidRouteLength(route_length)),
+                config.validate()
+            );
+        }
+
+        config.debug.route_length = Some(MAX_PATH_LENGTH - 1);
+        assert!(config.validate().is_ok());
+    }
 }
@@ -47,6 +47,15 @@ gateway_listener = '{{ client.gateway_listener }}'
 # A gateway specific, optional, base58 stringified shared key used for
 # communication with particular gateway.
 gateway_shared_key = '{{ client.gateway_shared_key }}'
+
+# Whether the gateway should list the address of this client in its presence,
+# making it discoverable by other clients.
+publish_address = {{ client.publish_address }}
+
+# How the gateway should hand messages over to this client if the same identity is used
+# by several clients connected to it at once. Either 'fanOut', where every client receives
+# every message, or 'firstAcknowledged', where every message is received by only one of them.
+delivery_policy = '{{ client.delivery_policy }}'
     
 ##### advanced configuration options #####
 
@@ -29,7 +29,9 @@ fn main() {
         .version(built_info::PKG_VERSION)
         .author("Nymtech")
         .about("Implementation of the Nym Client")
+        .subcommand(commands::deregister::command_args())
         .subcommand(commands::init::command_args())
+        .subcommand(commands::rotate_key::command_args())
         .subcommand(commands::run::command_args())
         .get_matches();
 
@@ -38,7 +40,9 @@ fn main() {
 
 fn execute(matches: ArgMatches) {
     match matches.subcommand() {
+        ("deregister", Some(m)) => commands::deregister::execute(m),
         ("init", Some(m)) => commands::init::execute(m),
+        ("rotate-key", Some(m)) => commands::rotate_key::execute(m),
         ("run", Some(m)) => commands::run::execute(m),
         _ => println!("{}", usage()),
     }
@@ -20,7 +20,11 @@ use tokio_tungstenite::tungstenite::protocol::Message;
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum ClientRequest {
-    Send { message: String, recipient: String },
+    Send {
+        message: String,
+        recipient: String,
+    },
+    /// Lists the clients that chose to publish their addresses.
     GetClients,
     SelfAddress,
 }
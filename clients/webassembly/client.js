@@ -188,11 +188,18 @@ export class Client {
                 case "authenticate": return this.onAuthenticateResponse(event);
                 case "error": return this.onErrorResponse(event);
                 case "register": return this.onRegisterResponse(event);
+                case "storedMessages": return this.handleStoredMessages(receivedData.messages);
                 default: return this.onUnknownResponse(event);
             }
         }
     }
 
+    // the gateway only removes stored messages (and pushes more of them) once we acknowledge them
+    handleStoredMessages(messages) {
+        this.onStoredMessages(messages);
+        this.gateway.conn.send(buildAckMessagesRequest(messages.map(message => message.id)));
+    }
+
     // all the callbacks that can be overwritten
 
     /**
@@ -254,6 +261,15 @@ export class Client {
         console.error("Received unknown response", event);
     }
 
+    // Messages that the gateway stored while we were offline. Each one carries its data
+    // alongside the time (milliseconds since the epoch) at which it reached the gateway.
+    onStoredMessages(messages) {
+        for (const message of messages) {
+            console.log("Default: received message stored at", new Date(message.arrivalTimestamp));
+            this.onBlobResponse({ data: new Blob([new Uint8Array(message.data)]) });
+        }
+    }
+
     // Gateway returns any received data from the mix network as a Blob,
     // So most likely this is your best bet to override
     onBlobResponse(event) {
@@ -300,6 +316,15 @@ function buildAuthenticateRequest(address, token) {
     return JSON.stringify({ "type": "authenticate", "address": address, "token": token });
 }
 
+/**
+ * Build a JSON acknowledgement of received stored messages.
+ * 
+ * @param {number[]} ids 
+ */
+function buildAckMessagesRequest(ids) {
+    return JSON.stringify({ "type": "ackMessages", "ids": ids });
+}
+
 /**
  * Make an HTTP request.
  * @param {string} method 
@@ -0,0 +1,45 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+const SIGNATURE_PURPOSE: &[u8] = b"nym-layer-assignment";
+
+/// Request for the layer the mixnode with the given public key should be part of.
+/// It is signed with the identity key of the mixnode.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerAssignmentRequest {
+    pub pub_key: String,
+    pub identity_key: String,
+    /// Time at which the request was made, as milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl LayerAssignmentRequest {
+    /// Message that has to be signed with the identity key of the mixnode for the request
+    /// for the given public key made at the given time to be valid.
+    pub fn signed_message(pub_key: &str, timestamp: u64) -> Vec<u8> {
+        super::signed_message(SIGNATURE_PURPOSE, pub_key, timestamp)
+    }
+}
+
+/// Layer assigned to the mixnode with the given public key, based on current sizes of the layers.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LayerAssignment {
+    pub pub_key: String,
+    pub layer: u64,
+}
@@ -29,6 +29,28 @@ pub struct MixNodePresence {
     pub version: String,
 }
 
+const DEREGISTRATION_SIGNATURE_PURPOSE: &[u8] = b"nym-mixnode-deregistration";
+
+/// Announcement of the mixnode with the given public key leaving the network.
+/// It is signed with the identity key of the mixnode.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MixNodeDeregistration {
+    pub pub_key: String,
+    pub identity_key: String,
+    /// Time at which the mixnode deregistered, as milliseconds since the unix epoch.
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl MixNodeDeregistration {
+    /// Message that has to be signed with the identity key of the mixnode for the deregistration
+    /// of the given public key made at the given time to be valid.
+    pub fn signed_message(pub_key: &str, timestamp: u64) -> Vec<u8> {
+        super::signed_message(DEREGISTRATION_SIGNATURE_PURPOSE, pub_key, timestamp)
+    }
+}
+
 impl TryInto<topology::mix::Node> for MixNodePresence {
     type Error = io::Error;
 
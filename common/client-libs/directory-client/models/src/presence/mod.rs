@@ -14,8 +14,20 @@
 
 pub mod coconodes;
 pub mod gateways;
+pub mod layers;
 pub mod mixnodes;
 pub mod providers;
 pub mod topology;
 
 pub use self::topology::Topology;
+
+// both the purpose and the timestamp are signed, so that the signed request could not be reused
+// for anything else, nor long after it was made
+fn signed_message(purpose: &[u8], pub_key: &str, timestamp: u64) -> Vec<u8> {
+    purpose
+        .iter()
+        .chain(pub_key.as_bytes())
+        .chain(&timestamp.to_be_bytes())
+        .copied()
+        .collect()
+}
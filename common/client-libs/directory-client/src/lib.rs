@@ -13,8 +13,10 @@
 // limitations under the License.
 
 use crate::requests::health_check_get::Request as HealthCheckRequest;
+use crate::requests::layer_assignment_post::Request as LayerAssignmentPost;
 use crate::requests::metrics_mixes_get::Request as MetricsMixRequest;
 use crate::requests::metrics_mixes_post::Request as MetricsMixPost;
+use crate::requests::mixnode_deregistration_post::Request as MixNodeDeregistrationPost;
 use crate::requests::presence_coconodes_post::Request as PresenceCocoNodesPost;
 use crate::requests::presence_gateways_post::Request as PresenceGatewayPost;
 use crate::requests::presence_mixnodes_post::Request as PresenceMixNodesPost;
@@ -22,7 +24,10 @@ use crate::requests::presence_providers_post::Request as PresenceProvidersPost;
 use crate::requests::presence_topology_get::Request as PresenceTopologyRequest;
 use directory_client_models::metrics::{MixMetric, PersistedMixMetric};
 use directory_client_models::presence::{
-    coconodes::CocoPresence, gateways::GatewayPresence, mixnodes::MixNodePresence,
+    coconodes::CocoPresence,
+    gateways::GatewayPresence,
+    layers::{LayerAssignment, LayerAssignmentRequest},
+    mixnodes::{MixNodeDeregistration, MixNodePresence},
     providers::MixProviderPresence,
 };
 use requests::{health_check_get::HealthCheckResponse, DirectoryGetRequest, DirectoryPostRequest};
@@ -123,6 +128,25 @@ impl Client {
         self.post(req).await
     }
 
+    /// Informs the validator that the mixnode is leaving the network.
+    pub async fn post_mixnode_deregistration(
+        &self,
+        deregistration: MixNodeDeregistration,
+    ) -> reqwest::Result<()> {
+        let req = MixNodeDeregistrationPost::new(&self.base_url, deregistration);
+        self.post(req).await?.error_for_status()?;
+        Ok(())
+    }
+
+    /// Asks the validator for the layer the mixnode with the given public key should be part of.
+    pub async fn post_layer_assignment_request(
+        &self,
+        request: LayerAssignmentRequest,
+    ) -> reqwest::Result<LayerAssignment> {
+        let req = LayerAssignmentPost::new(&self.base_url, request);
+        self.post(req).await?.error_for_status()?.json().await
+    }
+
     // this should be soft-deprecated as the whole concept of provider will
     // be removed in the next topology rework
     pub async fn post_provider_presence(
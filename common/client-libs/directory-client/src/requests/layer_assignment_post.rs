@@ -0,0 +1,105 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{DirectoryPostRequest, DirectoryRequest};
+use crate::presence::layers::LayerAssignmentRequest;
+
+// served by the validator rather than the directory server
+const PATH: &str = "/presence/mixnodes/layer";
+
+pub struct Request {
+    base_url: String,
+    path: String,
+    payload: LayerAssignmentRequest,
+}
+
+impl DirectoryRequest for Request {
+    fn url(&self) -> String {
+        format!("{}{}", self.base_url, self.path)
+    }
+}
+
+impl DirectoryPostRequest for Request {
+    type Payload = LayerAssignmentRequest;
+    fn json_payload(&self) -> &LayerAssignmentRequest {
+        &self.payload
+    }
+
+    fn new(base_url: &str, payload: Self::Payload) -> Self {
+        Request {
+            base_url: base_url.to_string(),
+            path: PATH.to_string(),
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod layer_assignment_post_request {
+    use super::*;
+    use crate::client_test_fixture;
+    use crate::presence::layers::LayerAssignment;
+    use mockito::mock;
+
+    fn request_fixture() -> LayerAssignmentRequest {
+        LayerAssignmentRequest {
+            pub_key: "abc".to_string(),
+            identity_key: "def".to_string(),
+            timestamp: 1234,
+            signature: "ghi".to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    mod on_a_400_status {
+        use super::*;
+
+        #[tokio::test]
+        async fn it_returns_an_error() {
+            let _m = mock("POST", PATH).with_status(400).create();
+            let client = client_test_fixture(&mockito::server_url());
+            let result = client
+                .post_layer_assignment_request(request_fixture())
+                .await;
+            assert!(result.is_err());
+            _m.assert();
+        }
+    }
+
+    #[cfg(test)]
+    mod on_a_200 {
+        use super::*;
+
+        #[tokio::test]
+        async fn it_returns_the_assigned_layer() {
+            let json = r#"{
+                          "pubKey": "abc",
+                          "layer": 2
+                      }"#;
+            let _m = mock("POST", PATH).with_status(200).with_body(json).create();
+            let client = client_test_fixture(&mockito::server_url());
+            let result = client
+                .post_layer_assignment_request(request_fixture())
+                .await;
+            assert_eq!(
+                LayerAssignment {
+                    pub_key: "abc".to_string(),
+                    layer: 2
+                },
+                result.unwrap()
+            );
+            _m.assert();
+        }
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{DirectoryPostRequest, DirectoryRequest};
+use crate::presence::mixnodes::MixNodeDeregistration;
+
+// served by the validator rather than the directory server
+const PATH: &str = "/presence/mixnodes/deregister";
+
+pub struct Request {
+    base_url: String,
+    path: String,
+    payload: MixNodeDeregistration,
+}
+
+impl DirectoryRequest for Request {
+    fn url(&self) -> String {
+        format!("{}{}", self.base_url, self.path)
+    }
+}
+
+impl DirectoryPostRequest for Request {
+    type Payload = MixNodeDeregistration;
+    fn json_payload(&self) -> &MixNodeDeregistration {
+        &self.payload
+    }
+
+    fn new(base_url: &str, payload: Self::Payload) -> Self {
+        Request {
+            base_url: base_url.to_string(),
+            path: PATH.to_string(),
+            payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod mixnode_deregistration_post_request {
+    use super::*;
+    use crate::client_test_fixture;
+    use mockito::mock;
+
+    fn deregistration_fixture() -> MixNodeDeregistration {
+        MixNodeDeregistration {
+            pub_key: "abc".to_string(),
+            identity_key: "def".to_string(),
+            timestamp: 1234,
+            signature: "ghi".to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    mod on_a_403_status {
+        use super::*;
+
+        #[tokio::test]
+        async fn it_returns_an_error() {
+            let _m = mock("POST", PATH).with_status(403).create();
+            let client = client_test_fixture(&mockito::server_url());
+            let result = client
+                .post_mixnode_deregistration(deregistration_fixture())
+                .await;
+            assert!(result.is_err());
+            _m.assert();
+        }
+    }
+
+    #[cfg(test)]
+    mod on_a_200 {
+        use super::*;
+
+        #[tokio::test]
+        async fn it_returns_ok() {
+            let _m = mock("POST", PATH).with_status(200).create();
+            let client = client_test_fixture(&mockito::server_url());
+            let result = client
+                .post_mixnode_deregistration(deregistration_fixture())
+                .await;
+            assert!(result.is_ok());
+            _m.assert();
+        }
+    }
+}
@@ -13,8 +13,10 @@
 // limitations under the License.
 
 pub mod health_check_get;
+pub mod layer_assignment_post;
 pub mod metrics_mixes_get;
 pub mod metrics_mixes_post;
+pub mod mixnode_deregistration_post;
 pub mod presence_coconodes_post;
 pub mod presence_gateways_post;
 pub mod presence_mixnodes_post;
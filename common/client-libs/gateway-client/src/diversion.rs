@@ -0,0 +1,79 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::task::{Context, Poll};
+use futures::{Sink, Stream};
+use std::pin::Pin;
+use tokio_tungstenite::tungstenite::{protocol::Message, Error as WsError};
+
+/// Wraps the connection while the key rotation handshake is performed. The gateway might have sent
+/// some data through the channel before it received our request. Such data would have been dropped
+/// by the handshake, so instead it is put aside to be handled with the previous key once
+/// the handshake is over.
+pub(crate) struct ChannelDataDiverter<'a, S> {
+    conn: &'a mut S,
+    diverted: Vec<Vec<u8>>,
+}
+
+impl<'a, S> ChannelDataDiverter<'a, S> {
+    pub(crate) fn new(conn: &'a mut S) -> Self {
+        ChannelDataDiverter {
+            conn,
+            diverted: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_diverted(self) -> Vec<Vec<u8>> {
+        self.diverted
+    }
+}
+
+impl<'a, S> Stream for ChannelDataDiverter<'a, S>
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut *self.conn).poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Binary(data)))) => self.diverted.push(data),
+                other => return other,
+            }
+        }
+    }
+}
+
+impl<'a, S> Sink<Message> for ChannelDataDiverter<'a, S>
+where
+    S: Sink<Message, Error = WsError> + Unpin,
+{
+    type Error = WsError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.conn).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut *self.conn).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.conn).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.conn).poll_close(cx)
+    }
+}
@@ -22,6 +22,7 @@ pub enum GatewayClientError {
     GatewayError(String),
     NetworkError(WsError),
     NoSharedKeyAvailable,
+    NoSessionTokenAvailable,
     ConnectionAbruptlyClosed,
     MalformedResponse,
     NotAuthenticated,
@@ -48,6 +49,9 @@ impl fmt::Display for GatewayClientError {
             GatewayClientError::NoSharedKeyAvailable => {
                 write!(f, "no shared key was provided or obtained")
             }
+            GatewayClientError::NoSessionTokenAvailable => {
+                write!(f, "no session token was obtained")
+            }
             GatewayClientError::NotAuthenticated => write!(f, "client is not authenticated"),
             GatewayClientError::NetworkError(err) => {
                 write!(f, "there was a network error - {}", err)
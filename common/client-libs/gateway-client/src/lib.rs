@@ -12,24 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::diversion::ChannelDataDiverter;
 use crate::error::GatewayClientError;
 use crate::packet_router::PacketRouter;
 pub use crate::packet_router::{
     AcknowledgementReceiver, AcknowledgementSender, MixnetMessageReceiver, MixnetMessageSender,
 };
 use crypto::asymmetric::identity;
+use futures::lock::Mutex;
 use futures::stream::{SplitSink, SplitStream};
 use futures::{future::BoxFuture, FutureExt, SinkExt, Stream, StreamExt};
-use gateway_requests::authentication::encrypted_address::EncryptedAddressBytes;
-use gateway_requests::authentication::iv::AuthenticationIV;
-use gateway_requests::registration::handshake::{client_handshake, SharedKey, DEFAULT_RNG};
-use gateway_requests::{BinaryRequest, ClientControlRequest, ServerResponse};
+use gateway_requests::authentication::challenge::{
+    AuthenticationChallenge, ChallengePurpose, ChallengeResponse, SessionToken,
+};
+use gateway_requests::channel::{
+    derive_channel, ChannelDecryptor, ChannelEncryptor, ChannelSide, SessionNonce,
+};
+use gateway_requests::publication::{AddressPublication, PublicationAction};
+use gateway_requests::registration::handshake::{client_handshake, SharedKey};
+use gateway_requests::{BinaryRequest, ClientControlRequest, ServerData, ServerResponse};
+pub use gateway_requests::{DeliveryPolicy, RetrievalMode};
 use log::*;
 use nymsphinx::SphinxPacket;
 use std::convert::TryFrom;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::net::TcpStream;
 use tokio::sync::Notify;
 use tokio_tungstenite::{
@@ -38,6 +46,7 @@ use tokio_tungstenite::{
     WebSocketStream,
 };
 
+mod diversion;
 pub mod error;
 pub mod packet_router;
 
@@ -55,6 +64,30 @@ where
     }
 }
 
+/// Handles data the gateway sent through the channel, i.e. either a mix message or a push
+/// of stored messages. Returns ids of the pushed messages that should be acknowledged, if any.
+fn handle_channel_data(
+    data: &[u8],
+    decryptor: &mut ChannelDecryptor,
+    packet_router: &PacketRouter,
+) -> Option<Vec<u64>> {
+    match ServerData::try_from_encrypted_bytes(data, decryptor) {
+        // TODO: some batching mechanism to allow reading and sending more than
+        // one packet at the time, because the receiver can easily handle it
+        Ok(ServerData::MixMessage(message)) => {
+            packet_router.route_received(vec![message]);
+        }
+        Ok(ServerData::Push(push)) => {
+            let ids = packet_router.route_pushed(push);
+            if !ids.is_empty() {
+                return Some(ids);
+            }
+        }
+        Err(err) => warn!("received invalid data from the gateway - {}", err),
+    }
+    None
+}
+
 // TODO: some batching mechanism to allow reading and sending more than a single packet through
 
 // type alias for not having to type the whole thing every single time
@@ -62,10 +95,12 @@ type WsConn = WebSocketStream<TcpStream>;
 
 // We have ownership over sink half of the connection, but the stream is owned
 // by some other task, however, we can notify it to get the stream back.
+// The task also holds onto the sink to acknowledge pushed stored messages and onto
+// the decrypting half of the channel, which is given back alongside the stream.
 struct PartiallyDelegated<'a> {
-    sink_half: SplitSink<WsConn, Message>,
+    sink_half: Arc<Mutex<SplitSink<WsConn, Message>>>,
     delegated_stream: (
-        BoxFuture<'a, Result<SplitStream<WsConn>, GatewayClientError>>,
+        BoxFuture<'a, Result<(SplitStream<WsConn>, ChannelDecryptor), GatewayClientError>>,
         Arc<Notify>,
     ),
 }
@@ -76,6 +111,7 @@ impl<'a> PartiallyDelegated<'a> {
     // runtime handle to the constructor and using that instead?
     fn split_and_listen_for_mixnet_messages(
         conn: WsConn,
+        mut decryptor: ChannelDecryptor,
         packet_router: PacketRouter,
     ) -> Result<Self, GatewayClientError> {
         // when called for, it NEEDS TO yield back the stream so that we could merge it and
@@ -84,6 +120,8 @@ impl<'a> PartiallyDelegated<'a> {
         let notify_clone = Arc::clone(&notify);
 
         let (sink, mut stream) = conn.split();
+        let sink = Arc::new(Mutex::new(sink));
+        let ack_sink = Arc::clone(&sink);
 
         let mixnet_receiver_future = async move {
             let mut should_return = false;
@@ -95,10 +133,11 @@ impl<'a> PartiallyDelegated<'a> {
                     msg = read_ws_stream_message(&mut stream) => {
                         match msg? {
                             Message::Binary(bin_msg) => {
-                                // TODO: some batching mechanism to allow reading and sending more than
-                                // one packet at the time, because the receiver can easily handle it
-                                packet_router.route_received(vec![bin_msg])
-                            },
+                                if let Some(ids) = handle_channel_data(&bin_msg, &mut decryptor, &packet_router) {
+                                    let ack: Message = ClientControlRequest::new_ack_messages(ids).into();
+                                    ack_sink.lock().await.send(ack).await.map_err(GatewayClientError::from)?;
+                                }
+                            }
                             // I think that in the future we should perhaps have some sequence number system, i.e.
                             // so each request/response pair can be easily identified, so that if messages are
                             // not ordered (for some peculiar reason) we wouldn't lose anything.
@@ -109,7 +148,7 @@ impl<'a> PartiallyDelegated<'a> {
                     }
                 };
             }
-            Ok(stream)
+            Ok((stream, decryptor))
         };
 
         let spawned_boxed_task = tokio::spawn(mixnet_receiver_future)
@@ -127,16 +166,21 @@ impl<'a> PartiallyDelegated<'a> {
     // if we want to send a message and don't care about response, we can don't need to reunite the split,
     // the sink itself is enough
     async fn send_without_response(&mut self, msg: Message) -> Result<(), GatewayClientError> {
-        Ok(self.sink_half.send(msg).await?)
+        Ok(self.sink_half.lock().await.send(msg).await?)
     }
 
-    async fn merge(self) -> Result<WsConn, GatewayClientError> {
+    async fn merge(self) -> Result<(WsConn, ChannelDecryptor), GatewayClientError> {
         let (stream_fut, notify) = self.delegated_stream;
         notify.notify();
-        let stream = stream_fut.await?;
+        let (stream, decryptor) = stream_fut.await?;
+        // the task holding the other reference to the sink has finished by now
+        let sink_half = match Arc::try_unwrap(self.sink_half) {
+            Ok(sink_half) => sink_half.into_inner(),
+            Err(_) => unreachable!("the sink is still shared after the listener has finished!"),
+        };
         // the error is thrown when trying to reunite sink and stream that did not originate
         // from the same split which is impossible to happen here
-        Ok(self.sink_half.reunite(stream).unwrap())
+        Ok((sink_half.reunite(stream).unwrap(), decryptor))
     }
 }
 
@@ -180,6 +224,16 @@ pub struct GatewayClient<'a, R> {
     gateway_identity: identity::PublicKey,
     local_identity: Arc<identity::KeyPair>,
     shared_key: Option<SharedKey>,
+    // all binary data exchanged with the gateway goes through the channel derived from
+    // the shared key for the current session
+    session_nonce: Option<SessionNonce>,
+    channel_encryptor: Option<ChannelEncryptor>,
+    // while listening for mixnet messages, the decryptor is owned by the listening task
+    channel_decryptor: Option<ChannelDecryptor>,
+    // lets us skip requesting an authentication challenge once we reconnect
+    session_token: Option<SessionToken>,
+    // whether stored messages are pushed to us or we have to fetch them ourselves
+    retrieval_mode: RetrievalMode,
     connection: SocketState<'a>,
     packet_router: PacketRouter,
     response_timeout_duration: Duration,
@@ -202,6 +256,11 @@ impl<'a, R> GatewayClient<'static, R> {
             gateway_identity,
             local_identity,
             shared_key,
+            session_nonce: None,
+            channel_encryptor: None,
+            channel_decryptor: None,
+            session_token: None,
+            retrieval_mode: RetrievalMode::Push,
             connection: SocketState::NotConnected,
             packet_router: PacketRouter::new(ack_sender, mixnet_message_sender),
             response_timeout_duration,
@@ -228,12 +287,25 @@ impl<'a, R> GatewayClient<'static, R> {
             gateway_identity,
             local_identity,
             shared_key: None,
+            session_nonce: None,
+            channel_encryptor: None,
+            channel_decryptor: None,
+            session_token: None,
+            retrieval_mode: RetrievalMode::Push,
             connection: SocketState::NotConnected,
             packet_router,
             response_timeout_duration,
         }
     }
 
+    /// Makes the gateway hand stored messages over only once they are fetched with
+    /// `fetch_messages`, rather than pushing them as soon as we authenticate. It takes effect
+    /// upon the next authentication.
+    pub fn with_retrieval_mode(mut self, retrieval_mode: RetrievalMode) -> Self {
+        self.retrieval_mode = retrieval_mode;
+        self
+    }
+
     pub async fn close_connection(&mut self) -> Result<(), GatewayClientError> {
         if self.connection.is_partially_delegated() {
             self.recover_socket_connection().await?;
@@ -262,8 +334,8 @@ impl<'a, R> GatewayClient<'static, R> {
     }
 
     async fn read_control_response(&mut self) -> Result<ServerResponse, GatewayClientError> {
-        // we use the fact that all request responses are Message::Text and only pushed
-        // sphinx packets are Message::Binary
+        // we use the fact that all request responses are Message::Text and only data
+        // sent through the channel (mix messages and pushes) is Message::Binary
 
         let conn = match self.connection {
             SocketState::Available(ref mut conn) => conn,
@@ -274,6 +346,7 @@ impl<'a, R> GatewayClient<'static, R> {
 
         let mut res = None;
         while res.is_none() {
+            let mut to_acknowledge = None;
             tokio::select! {
                 _ = &mut timeout => {
                     res = Some(Err(GatewayClientError::Timeout))
@@ -286,9 +359,11 @@ impl<'a, R> GatewayClient<'static, R> {
                         break;
                     }
                     match msg.unwrap() {
-                        Message::Binary(bin_msg) => {
-                            self.packet_router.route_received(vec![bin_msg]);
-                        }
+                        // pushes can arrive at any time, including before the response
+                        Message::Binary(bin_msg) => match self.channel_decryptor.as_mut() {
+                            Some(decryptor) => to_acknowledge = handle_channel_data(&bin_msg, decryptor, &self.packet_router),
+                            None => warn!("received binary data from the gateway before establishing the channel"),
+                        },
                         Message::Text(txt_msg) => {
                             res = Some(ServerResponse::try_from(txt_msg).map_err(|_| GatewayClientError::MalformedResponse));
                         }
@@ -296,6 +371,13 @@ impl<'a, R> GatewayClient<'static, R> {
                     }
                 }
             }
+
+            if let Some(ids) = to_acknowledge {
+                let ack: Message = ClientControlRequest::new_ack_messages(ids).into();
+                if let Err(err) = conn.send(ack).await {
+                    res = Some(Err(err.into()));
+                }
+            }
         }
 
         res.expect("response value should have been written in one of the branches!. If you see this error, please report a bug!")
@@ -348,19 +430,101 @@ impl<'a, R> GatewayClient<'static, R> {
 
         debug_assert!(self.connection.is_available());
 
-        match &mut self.connection {
+        let shared_key = match &mut self.connection {
             SocketState::Available(ws_stream) => client_handshake(
-                &mut DEFAULT_RNG,
                 ws_stream,
                 self.local_identity.as_ref(),
                 self.gateway_identity.clone(),
             )
             .await
-            .map_err(|handshake_err| GatewayClientError::RegistrationFailure(handshake_err)),
+            .map_err(|handshake_err| GatewayClientError::RegistrationFailure(handshake_err))?,
             _ => unreachable!(),
+        };
+
+        let response = self.read_control_response().await?;
+        match response {
+            ServerResponse::Register { status, .. } => {
+                if status {
+                    let session_nonce = response
+                        .session_nonce()
+                        .ok_or(GatewayClientError::MalformedResponse)?;
+                    self.start_session(shared_key.clone(), session_nonce);
+                    self.session_token = response.session_token();
+                }
+                self.authenticated = status;
+                Ok(shared_key)
+            }
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    fn start_session(&mut self, shared_key: SharedKey, session_nonce: SessionNonce) {
+        self.session_nonce = Some(session_nonce);
+        self.update_shared_key(shared_key);
+    }
+
+    // the channel has to be derived anew whenever the key changes
+    fn update_shared_key(&mut self, shared_key: SharedKey) {
+        let session_nonce = self
+            .session_nonce
+            .as_ref()
+            .expect("attempted to update shared key outside of a session!");
+        let (encryptor, decryptor) =
+            derive_channel(&shared_key, session_nonce, ChannelSide::Client);
+        self.channel_encryptor = Some(encryptor);
+        self.channel_decryptor = Some(decryptor);
+        self.shared_key = Some(shared_key);
+    }
+
+    fn end_session(&mut self) {
+        self.session_token = None;
+        self.shared_key = None;
+        self.session_nonce = None;
+        self.channel_encryptor = None;
+        self.channel_decryptor = None;
+    }
+
+    async fn request_challenge(&mut self) -> Result<AuthenticationChallenge, GatewayClientError> {
+        match self
+            .send_websocket_message(ClientControlRequest::RequestChallenge.into())
+            .await?
+        {
+            ServerResponse::Challenge { challenge } => {
+                AuthenticationChallenge::try_from_base58_string(challenge)
+                    .map_err(|_| GatewayClientError::MalformedResponse)
+            }
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    // both the authentication and the session resumption result in the same response
+    async fn send_authentication_request(
+        &mut self,
+        msg: Message,
+        shared_key: SharedKey,
+    ) -> Result<bool, GatewayClientError> {
+        let response = self.send_websocket_message(msg).await?;
+        match response {
+            ServerResponse::Authenticate { status, .. } => {
+                if status {
+                    let session_nonce = response
+                        .session_nonce()
+                        .ok_or(GatewayClientError::MalformedResponse)?;
+                    self.start_session(shared_key, session_nonce);
+                }
+                // the previous token is no longer valid regardless of the result
+                self.session_token = response.session_token();
+                self.authenticated = status;
+                Ok(status)
+            }
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
         }
     }
 
+    /// Proves to the gateway we are the owner of our address by signing the challenge it sent us.
     pub async fn authenticate(
         &mut self,
         shared_key: Option<SharedKey>,
@@ -372,32 +536,63 @@ impl<'a, R> GatewayClient<'static, R> {
             return Err(GatewayClientError::ConnectionNotEstablished);
         }
         // because of the previous check one of the unwraps MUST succeed
-        let shared_key = shared_key
-            .as_ref()
-            .unwrap_or_else(|| self.shared_key.as_ref().unwrap());
-        let iv = AuthenticationIV::new_random(&mut DEFAULT_RNG);
-        let self_address = self.local_identity.as_ref().public_key().derive_address();
-        let encrypted_address = EncryptedAddressBytes::new(&self_address, shared_key, &iv);
-
+        let shared_key = shared_key.unwrap_or_else(|| self.shared_key.clone().unwrap());
+
+        let challenge = self.request_challenge().await?;
+        let challenge_response = ChallengeResponse::new_signed(
+            self.local_identity.as_ref(),
+            &self.gateway_identity,
+            &challenge,
+            ChallengePurpose::Authentication,
+        );
         let msg =
-            ClientControlRequest::new_authenticate(self_address, encrypted_address, iv).into();
+            ClientControlRequest::new_authenticate(challenge_response, self.retrieval_mode).into();
 
-        let authenticated = match self.send_websocket_message(msg).await? {
-            ServerResponse::Authenticate { status } => {
-                self.authenticated = status;
-                Ok(status)
-            }
-            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
-            _ => unreachable!(),
-        }?;
-        Ok(authenticated)
+        self.send_authentication_request(msg, shared_key).await
+    }
+
+    /// Resumes our session using the token obtained during the previous one, which saves us
+    /// from requesting a fresh authentication challenge. Each token can only be used once.
+    pub async fn resume_session(&mut self) -> Result<bool, GatewayClientError> {
+        let shared_key = match self.shared_key.clone() {
+            Some(shared_key) => shared_key,
+            None => return Err(GatewayClientError::NoSharedKeyAvailable),
+        };
+        if !self.connection.is_established() {
+            return Err(GatewayClientError::ConnectionNotEstablished);
+        }
+        let session_token = match self.session_token.take() {
+            Some(session_token) => session_token,
+            None => return Err(GatewayClientError::NoSessionTokenAvailable),
+        };
+
+        let challenge_response = ChallengeResponse::new_signed(
+            self.local_identity.as_ref(),
+            &self.gateway_identity,
+            &session_token,
+            ChallengePurpose::SessionResumption,
+        );
+        let msg = ClientControlRequest::new_resume_session(
+            session_token,
+            challenge_response,
+            self.retrieval_mode,
+        )
+        .into();
+
+        self.send_authentication_request(msg, shared_key).await
     }
 
     /// Helper method to either call register or authenticate based on self.shared_key value
     pub async fn perform_initial_authentication(
         &mut self,
     ) -> Result<SharedKey, GatewayClientError> {
-        if self.shared_key.is_some() {
+        if self.session_token.is_some() {
+            // the token might have expired in the meantime, so we can still fall back
+            // to the regular authentication
+            if !self.resume_session().await? {
+                self.authenticate(None).await?;
+            }
+        } else if self.shared_key.is_some() {
             self.authenticate(None).await?;
         } else {
             self.register().await?;
@@ -410,6 +605,205 @@ impl<'a, R> GatewayClient<'static, R> {
         }
     }
 
+    /// Tells the gateway whether the address of this client should be listed in its presence,
+    /// i.e. whether it should be discoverable by other clients. It is not listed by default.
+    pub async fn set_address_publication(
+        &mut self,
+        publish: bool,
+    ) -> Result<(), GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+        let action = if publish {
+            PublicationAction::Publish
+        } else {
+            PublicationAction::Revoke
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is before the unix epoch")
+            .as_millis() as u64;
+        let publication = AddressPublication::new_signed(
+            self.local_identity.as_ref(),
+            &self.gateway_identity,
+            action,
+            timestamp,
+        );
+
+        let msg = ClientControlRequest::new_address_publication(publication).into();
+        match self.send_websocket_message(msg).await? {
+            ServerResponse::AddressPublication { status: true } => Ok(()),
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    /// Chooses how the gateway hands messages over to the sessions of this client if it has
+    /// several of them open at once, for example when the same identity is used on multiple devices.
+    pub async fn set_delivery_policy(
+        &mut self,
+        policy: DeliveryPolicy,
+    ) -> Result<(), GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+
+        let msg = ClientControlRequest::SetDeliveryPolicy { policy }.into();
+        match self.send_websocket_message(msg).await? {
+            ServerResponse::SetDeliveryPolicy { status: true } => Ok(()),
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    /// Fetches at most `max_count` of the oldest messages the gateway stored for us, of at most
+    /// `max_bytes` in total, unless the oldest one alone is larger than that. The messages are
+    /// routed like any other received ones and acknowledged, so that the gateway could remove
+    /// them. Returns how many messages got fetched. It requires the pull retrieval mode.
+    pub async fn fetch_messages(
+        &mut self,
+        max_count: u32,
+        max_bytes: u64,
+    ) -> Result<usize, GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+
+        // the messages themselves arrive through the channel before the response
+        let msg = ClientControlRequest::FetchMessages {
+            max_count,
+            max_bytes,
+        }
+        .into();
+        match self.send_websocket_message(msg).await? {
+            ServerResponse::FetchMessages { count } => Ok(count),
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    /// Checks how many messages, and of how many bytes in total, the gateway currently
+    /// stores for us.
+    pub async fn inbox_status(&mut self) -> Result<(usize, usize), GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+
+        match self
+            .send_websocket_message(ClientControlRequest::InboxStatus.into())
+            .await?
+        {
+            ServerResponse::InboxStatus { count, bytes } => Ok((count, bytes)),
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    /// Tells the gateway to forget this client, i.e. its shared key and all of its stored messages.
+    /// The connection is closed by the gateway afterwards.
+    pub async fn deregister(&mut self) -> Result<(), GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+        // we must not resume listening for mixnet messages on a connection that is about to be closed
+        if self.connection.is_partially_delegated() {
+            self.recover_socket_connection().await?;
+        }
+
+        match self
+            .send_websocket_message(ClientControlRequest::Deregister.into())
+            .await?
+        {
+            ServerResponse::Deregister { status: true } => {
+                self.authenticated = false;
+                self.end_session();
+                // the gateway closes the connection on its side
+                self.connection = SocketState::NotConnected;
+                Ok(())
+            }
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    async fn perform_key_rotation(&mut self) -> Result<SharedKey, GatewayClientError> {
+        let conn = match self.connection {
+            SocketState::Available(ref mut conn) => conn,
+            SocketState::NotConnected => return Err(GatewayClientError::ConnectionNotEstablished),
+            _ => return Err(GatewayClientError::ConnectionInInvalidState),
+        };
+        conn.send(ClientControlRequest::RotateKey.into()).await?;
+
+        // the gateway does not respond to the request itself, but starts the handshake instead
+        let mut diverter = ChannelDataDiverter::new(conn);
+        let handshake = client_handshake(
+            &mut diverter,
+            self.local_identity.as_ref(),
+            self.gateway_identity.clone(),
+        );
+        let handshake_res = tokio::time::timeout(self.response_timeout_duration, handshake).await;
+
+        // whatever the gateway sent before it got our request still uses the previous key
+        let diverted = diverter.into_diverted();
+        if let Some(decryptor) = self.channel_decryptor.as_mut() {
+            for data in diverted {
+                if let Some(ids) = handle_channel_data(&data, decryptor, &self.packet_router) {
+                    conn.send(ClientControlRequest::new_ack_messages(ids).into())
+                        .await?;
+                }
+            }
+        }
+
+        let new_shared_key = match handshake_res {
+            Ok(handshake_res) => handshake_res.map_err(GatewayClientError::RegistrationFailure)?,
+            Err(_) => return Err(GatewayClientError::Timeout),
+        };
+
+        match self.read_control_response().await? {
+            ServerResponse::RotateKey { status: true } => (),
+            ServerResponse::Error { message } => {
+                return Err(GatewayClientError::GatewayError(message))
+            }
+            _ => return Err(GatewayClientError::MalformedResponse),
+        }
+
+        // the gateway keeps the previous key until we confirm we have got the new one as well
+        match self
+            .send_websocket_message(ClientControlRequest::ConfirmKeyRotation.into())
+            .await?
+        {
+            ServerResponse::ConfirmKeyRotation { status: true } => Ok(new_shared_key),
+            ServerResponse::Error { message } => Err(GatewayClientError::GatewayError(message)),
+            _ => Err(GatewayClientError::MalformedResponse),
+        }
+    }
+
+    /// Performs the registration handshake again in order to replace the current shared key.
+    /// The gateway keeps using the previous key until it receives our confirmation of the new one,
+    /// so that a rotation interrupted at any earlier point leaves the previous key valid.
+    /// It is up to the caller to persist the returned key, as the previous one is no longer valid.
+    pub async fn rotate_key(&mut self) -> Result<SharedKey, GatewayClientError> {
+        if !self.authenticated {
+            return Err(GatewayClientError::NotAuthenticated);
+        }
+
+        let mut should_restart_mixnet_listener = false;
+        if self.connection.is_partially_delegated() {
+            self.recover_socket_connection().await?;
+            should_restart_mixnet_listener = true;
+        }
+
+        let rotation_res = self.perform_key_rotation().await;
+        if let Ok(new_shared_key) = &rotation_res {
+            self.update_shared_key(new_shared_key.clone());
+        }
+
+        if should_restart_mixnet_listener {
+            self.start_listening_for_mixnet_messages()?;
+        }
+        rotation_res
+    }
+
     // TODO: possibly make responses optional
     pub async fn send_sphinx_packet(
         &mut self,
@@ -423,9 +817,9 @@ impl<'a, R> GatewayClient<'static, R> {
             return Err(GatewayClientError::ConnectionNotEstablished);
         }
         let msg = BinaryRequest::new_forward_request(address, packet).into_ws_message(
-            self.shared_key
-                .as_ref()
-                .expect("no shared key present even though we're authenticated!"),
+            self.channel_encryptor
+                .as_mut()
+                .expect("no channel present even though we're authenticated!"),
         );
         self.send_websocket_message_without_response(msg).await
     }
@@ -438,12 +832,14 @@ impl<'a, R> GatewayClient<'static, R> {
             return Err(GatewayClientError::ConnectionInInvalidState);
         }
 
-        let conn = match std::mem::replace(&mut self.connection, SocketState::Invalid) {
+        let (conn, decryptor) = match std::mem::replace(&mut self.connection, SocketState::Invalid)
+        {
             SocketState::PartiallyDelegated(delegated_conn) => delegated_conn.merge().await?,
             _ => unreachable!(),
         };
 
         self.connection = SocketState::Available(conn);
+        self.channel_decryptor = Some(decryptor);
         Ok(())
     }
 
@@ -464,6 +860,9 @@ impl<'a, R> GatewayClient<'static, R> {
                 SocketState::Available(conn) => {
                     PartiallyDelegated::split_and_listen_for_mixnet_messages(
                         conn,
+                        self.channel_decryptor
+                            .take()
+                            .expect("no channel present even though we're authenticated!"),
                         self.packet_router.clone(),
                     )?
                 }
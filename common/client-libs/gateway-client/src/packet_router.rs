@@ -16,9 +16,11 @@
 // I will gladly take any suggestions on how to rename this.
 
 use futures::channel::mpsc;
+use gateway_requests::ServerPush;
 use log::*;
-use nymsphinx::addressing::nodes::MAX_NODE_ADDRESS_UNPADDED_LEN;
+use nymsphinx::addressing::nodes::{MAX_NODE_ADDRESS_UNPADDED_LEN, MIN_NODE_ADDRESS_UNPADDED_LEN};
 use nymsphinx::params::packet_sizes::PacketSize;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type MixnetMessageSender = mpsc::UnboundedSender<Vec<Vec<u8>>>;
 pub type MixnetMessageReceiver = mpsc::UnboundedReceiver<Vec<Vec<u8>>>;
@@ -43,14 +45,22 @@ impl PacketRouter {
         }
     }
 
-    pub(super) fn route_received(&self, unwrapped_packets: Vec<Vec<u8>>) {
+    /// Returns whether the packets got routed. It is not the case if the receiving side is gone,
+    /// such as for clients created with `new_init` that are not meant to receive anything.
+    pub(super) fn route_received(&self, unwrapped_packets: Vec<Vec<u8>>) -> bool {
         let mut received_messages = Vec::new();
         let mut received_acks = Vec::new();
 
         // remember: gateway removes final layer of sphinx encryption and from the unwrapped
         // data he takes the SURB-ACK and first hop address.
         // currently SURB-ACKs are attached in EVERY packet, even cover, so this is always true
-        let ack_overhead = PacketSize::ACKPacket.size() + MAX_NODE_ADDRESS_UNPADDED_LEN;
+        // however, the length of the first hop address depends on whether it was ipv4 or ipv6
+        let max_ack_overhead = PacketSize::ACKPacket.size() + MAX_NODE_ADDRESS_UNPADDED_LEN;
+        let min_ack_overhead = PacketSize::ACKPacket.size() + MIN_NODE_ADDRESS_UNPADDED_LEN;
+        let is_of_packet_size = |len: usize, packet_size: PacketSize| {
+            len == packet_size.plaintext_size() - max_ack_overhead
+                || len == packet_size.plaintext_size() - min_ack_overhead
+        };
 
         for received_packet in unwrapped_packets {
             // NOTE TO FUTURE-SELF:
@@ -62,13 +72,9 @@ impl PacketRouter {
 
             if received_packet.len() == PacketSize::ACKPacket.plaintext_size() {
                 received_acks.push(received_packet);
-            } else if received_packet.len()
-                == PacketSize::RegularPacket.plaintext_size() - ack_overhead
-            {
+            } else if is_of_packet_size(received_packet.len(), PacketSize::RegularPacket) {
                 received_messages.push(received_packet);
-            } else if received_packet.len()
-                == PacketSize::ExtendedPacket.plaintext_size() - ack_overhead
-            {
+            } else if is_of_packet_size(received_packet.len(), PacketSize::ExtendedPacket) {
                 warn!("received extended packet? Did not expect this...");
                 received_messages.push(received_packet);
             } else {
@@ -78,19 +84,51 @@ impl PacketRouter {
             }
         }
 
-        // due to how we are currently using it, those unwraps can't fail, but if we ever
-        // wanted to make `gateway-client` into some more generic library, we would probably need
-        // to catch that error or something.
+        let mut routed = true;
         if !received_messages.is_empty() {
             trace!("routing 'real'");
-            self.mixnet_message_sender
+            if self
+                .mixnet_message_sender
                 .unbounded_send(received_messages)
-                .unwrap();
+                .is_err()
+            {
+                warn!("there is no receiver of mixnet messages - they are getting dropped");
+                routed = false;
+            }
         }
 
         if !received_acks.is_empty() {
             trace!("routing acks");
-            self.ack_sender.unbounded_send(received_acks).unwrap();
+            if self.ack_sender.unbounded_send(received_acks).is_err() {
+                warn!("there is no receiver of acknowledgements - they are getting dropped");
+                routed = false;
+            }
+        }
+        routed
+    }
+
+    /// Routes the pushed messages, returning ids of the ones that should be acknowledged.
+    /// Messages that could not be routed are not acknowledged, so that the gateway would keep them.
+    pub(super) fn route_pushed(&self, push: ServerPush) -> Vec<u64> {
+        match push {
+            ServerPush::StoredMessages { messages } => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|since_epoch| since_epoch.as_millis() as u64)
+                    .unwrap_or_default();
+                for message in &messages {
+                    debug!(
+                        "received message that was stored by the gateway for {}ms",
+                        now.saturating_sub(message.arrival_timestamp)
+                    );
+                }
+                let ids = messages.iter().map(|message| message.id).collect();
+                if self.route_received(messages.into_iter().map(|message| message.data).collect()) {
+                    ids
+                } else {
+                    Vec::new()
+                }
+            }
         }
     }
 }
@@ -14,15 +14,19 @@
 
 use crate::connection_manager::reconnector::ConnectionReconnector;
 use crate::connection_manager::writer::ConnectionWriter;
+use crate::stats::PeerConnectionStats;
 use futures::channel::{mpsc, oneshot};
 use futures::future::{abortable, AbortHandle};
 use futures::task::Poll;
 use futures::{SinkExt, StreamExt};
+use link_encryption::InitiatorConfig;
 use log::*;
+use nymsphinx::framing::FrameVersion;
 use nymsphinx::SphinxPacket;
 use std::io;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
 
 mod reconnector;
@@ -49,6 +53,69 @@ pub(crate) struct ConnectionManager<'a> {
 
     state: ConnectionState<'a>,
     pending_messages_buffer: Vec<SphinxPacket>,
+
+    link_encryption: Option<InitiatorConfig>,
+    // set after a failed handshake if the plaintext fallback is allowed
+    plaintext_until: Option<Instant>,
+    frame_version: FrameVersion,
+
+    stats: Arc<PeerConnectionStats>,
+}
+
+// Performs the link handshake if encryption is enabled and the static key of the remote is known.
+// If the handshake fails, but we're allowed to fall back to plaintext, the connection attempts
+// made within the fallback duration skip the handshake and an error is returned so that
+// the connection gets re-established. Once the duration passes, encryption is attempted again.
+async fn establish_link(
+    address: SocketAddr,
+    mut stream: tokio::net::TcpStream,
+    link_encryption: Option<&InitiatorConfig>,
+    plaintext_until: &mut Option<Instant>,
+    frame_version: FrameVersion,
+) -> io::Result<ConnectionWriter> {
+    let (config, remote_key) = match link_encryption
+        .and_then(|config| config.remote_key(&address).map(|key| (config, key)))
+    {
+        Some(config_and_key) => config_and_key,
+        None => return Ok(ConnectionWriter::new(stream, None, frame_version)),
+    };
+
+    if let Some(deadline) = *plaintext_until {
+        if Instant::now() < deadline {
+            debug!(
+                "establishing plaintext link to {} after failed handshake",
+                address
+            );
+            return Ok(ConnectionWriter::new(stream, None, frame_version));
+        }
+        *plaintext_until = None;
+    }
+
+    match link_encryption::initiate(&mut stream, config, &remote_key).await {
+        Ok(transport) => {
+            debug!("established encrypted link to {}", address);
+            Ok(ConnectionWriter::new(
+                stream,
+                Some(transport),
+                frame_version,
+            ))
+        }
+        Err(err) if config.allows_plaintext_fallback() => {
+            warn!(
+                "failed to establish encrypted link to {} ({}). Falling back to plaintext for {:?}",
+                address,
+                err,
+                config.plaintext_fallback_duration()
+            );
+            *plaintext_until = Some(Instant::now() + config.plaintext_fallback_duration());
+            // the remote has already received our handshake data, so the stream can't be reused
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "link handshake failed - the connection is going to be re-established in plaintext",
+            ))
+        }
+        Err(err) => Err(err.into()),
+    }
 }
 
 impl<'a> Drop for ConnectionManager<'a> {
@@ -63,24 +130,42 @@ impl<'a> ConnectionManager<'static> {
         reconnection_backoff: Duration,
         maximum_reconnection_backoff: Duration,
         connection_timeout: Duration,
+        link_encryption: Option<InitiatorConfig>,
+        frame_version: FrameVersion,
+        stats: Arc<PeerConnectionStats>,
     ) -> ConnectionManager<'a> {
         let (conn_tx, conn_rx) = mpsc::unbounded();
+        let mut plaintext_until = None;
 
+        stats.increment_connection_attempts();
         // the blocking call here is fine as initially we want to wait the timeout interval (at most) anyway:
         let tcp_stream_res = std::net::TcpStream::connect_timeout(&address, connection_timeout);
 
-        let initial_state = match tcp_stream_res {
+        let initial_writer = match tcp_stream_res {
             Ok(stream) => {
                 let tokio_stream = tokio::net::TcpStream::from_std(stream).unwrap();
                 debug!("managed to establish initial connection to {}", address);
-                ConnectionState::Writing(ConnectionWriter::new(tokio_stream))
+                establish_link(
+                    address,
+                    tokio_stream,
+                    link_encryption.as_ref(),
+                    &mut plaintext_until,
+                    frame_version,
+                )
+                .await
             }
+            Err(e) => Err(e),
+        };
+
+        let initial_state = match initial_writer {
+            Ok(writer) => ConnectionState::Writing(writer),
             Err(e) => {
                 warn!("failed to establish initial connection to {} within {:?} ({}). Going into reconnection mode", address, connection_timeout, e);
                 ConnectionState::Reconnecting(ConnectionReconnector::new(
                     address,
                     reconnection_backoff,
                     maximum_reconnection_backoff,
+                    Arc::clone(&stats),
                 ))
             }
         };
@@ -93,13 +178,19 @@ impl<'a> ConnectionManager<'static> {
             reconnection_backoff,
             state: initial_state,
             pending_messages_buffer: Vec::new(),
+            link_encryption,
+            plaintext_until,
+            frame_version,
+            stats,
         }
     }
 
     async fn run(mut self) {
         while let Some(msg) = self.conn_rx.next().await {
             let (msg_content, res_ch) = msg;
+            self.stats.decrement_queued();
             let res = self.handle_new_packet(msg_content).await;
+            self.stats.set_buffered(self.pending_messages_buffer.len());
             if let Some(res_ch) = res_ch {
                 if let Err(e) = res_ch.send(res) {
                     error!(
@@ -140,8 +231,30 @@ impl<'a> ConnectionManager<'static> {
                 Poll::Ready(conn) => conn,
             };
 
-            debug!("Managed to reconnect to {}!", self.address);
-            self.state = ConnectionState::Writing(ConnectionWriter::new(new_connection));
+            match establish_link(
+                self.address,
+                new_connection,
+                self.link_encryption.as_ref(),
+                &mut self.plaintext_until,
+                self.frame_version,
+            )
+            .await
+            {
+                Ok(conn_writer) => {
+                    debug!("Managed to reconnect to {}!", self.address);
+                    self.state = ConnectionState::Writing(conn_writer);
+                }
+                Err(e) => {
+                    self.pending_messages_buffer.push(packet);
+                    self.state = ConnectionState::Reconnecting(ConnectionReconnector::new(
+                        self.address,
+                        self.reconnection_backoff,
+                        self.maximum_reconnection_backoff,
+                        Arc::clone(&self.stats),
+                    ));
+                    return Err(e);
+                }
+            }
         }
 
         // we must be in writing state if we are here, either by being here from beginning or just
@@ -177,6 +290,7 @@ impl<'a> ConnectionManager<'static> {
                             self.address,
                             self.reconnection_backoff,
                             self.maximum_reconnection_backoff,
+                            Arc::clone(&self.stats),
                         ));
                         Err(e.into())
                     }
@@ -191,7 +305,9 @@ impl<'a> ConnectionManager<'static> {
                         self.address,
                         self.reconnection_backoff,
                         self.maximum_reconnection_backoff,
+                        Arc::clone(&self.stats),
                     ));
+                    return Err(e.into());
                 }
                 Ok(())
             };
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::stats::PeerConnectionStats;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use log::*;
@@ -19,6 +20,7 @@ use std::future::Future;
 use std::io;
 use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -32,6 +34,8 @@ pub(crate) struct ConnectionReconnector<'a> {
     maximum_reconnection_backoff: Duration,
 
     initial_reconnection_backoff: Duration,
+
+    stats: Arc<PeerConnectionStats>,
 }
 
 impl<'a> ConnectionReconnector<'a> {
@@ -39,7 +43,9 @@ impl<'a> ConnectionReconnector<'a> {
         address: SocketAddr,
         initial_reconnection_backoff: Duration,
         maximum_reconnection_backoff: Duration,
+        stats: Arc<PeerConnectionStats>,
     ) -> ConnectionReconnector<'a> {
+        stats.increment_connection_attempts();
         ConnectionReconnector {
             address,
             connection: tokio::net::TcpStream::connect(address).boxed(),
@@ -47,6 +53,7 @@ impl<'a> ConnectionReconnector<'a> {
             current_retry_attempt: 0,
             maximum_reconnection_backoff,
             initial_reconnection_backoff,
+            stats,
         }
     }
 }
@@ -95,6 +102,7 @@ impl<'a> Future for ConnectionReconnector<'a> {
 
                 self.connection = tokio::net::TcpStream::connect(self.address).boxed();
                 self.current_retry_attempt += 1;
+                self.stats.increment_connection_attempts();
 
                 Poll::Pending
             }
@@ -14,19 +14,28 @@
 
 use futures::task::{Context, Poll};
 use futures::Sink;
-use nymsphinx::framing::{SphinxCodec, SphinxCodecError};
+use link_encryption::{LinkCodec, TransportState};
+use nymsphinx::framing::{FrameVersion, SphinxCodec, SphinxCodecError};
 use nymsphinx::SphinxPacket;
 use std::pin::Pin;
 use tokio_util::codec::Framed;
 
 pub(crate) struct ConnectionWriter {
-    framed_connection: Framed<tokio::net::TcpStream, SphinxCodec>,
+    framed_connection: Framed<tokio::net::TcpStream, LinkCodec<SphinxCodec>>,
 }
 
 impl ConnectionWriter {
-    pub(crate) fn new(connection: tokio::net::TcpStream) -> Self {
+    /// If `transport` is provided, all packets are going to be encrypted with it.
+    pub(crate) fn new(
+        connection: tokio::net::TcpStream,
+        transport: Option<TransportState>,
+        frame_version: FrameVersion,
+    ) -> Self {
         ConnectionWriter {
-            framed_connection: Framed::new(connection, SphinxCodec),
+            framed_connection: Framed::new(
+                connection,
+                LinkCodec::new(SphinxCodec::new(frame_version), transport),
+            ),
         }
     }
 }
@@ -13,22 +13,31 @@
 // limitations under the License.
 
 use crate::connection_manager::{ConnectionManager, ConnectionManagerSender};
+use crate::stats::PeerConnectionStats;
 use futures::channel::oneshot;
 use futures::future::AbortHandle;
+use link_encryption::InitiatorConfig;
 use log::*;
+use nymsphinx::framing::FrameVersion;
 use nymsphinx::SphinxPacket;
 use std::collections::HashMap;
 use std::io;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Handle;
 
 mod connection_manager;
+mod stats;
+
+pub use stats::{ConnectionStats, PeerStats};
 
 pub struct Config {
     initial_reconnection_backoff: Duration,
     maximum_reconnection_backoff: Duration,
     initial_connection_timeout: Duration,
+    link_encryption: Option<InitiatorConfig>,
+    frame_version: FrameVersion,
 }
 
 impl Config {
@@ -41,16 +50,41 @@ impl Config {
             initial_reconnection_backoff,
             maximum_reconnection_backoff,
             initial_connection_timeout,
+            link_encryption: None,
+            frame_version: FrameVersion::Legacy,
         }
     }
+
+    /// Attempts to encrypt and authenticate connections to all nodes with known static keys.
+    pub fn with_link_encryption(mut self, link_encryption: InitiatorConfig) -> Self {
+        self.link_encryption = Some(link_encryption);
+        self
+    }
+
+    /// Frames all sent packets with the specified version of the header. The receiving nodes
+    /// must be able to decode it.
+    pub fn with_frame_version(mut self, frame_version: FrameVersion) -> Self {
+        self.frame_version = frame_version;
+        self
+    }
 }
 
 pub struct Client {
     runtime_handle: Handle,
-    connections_managers: HashMap<SocketAddr, (ConnectionManagerSender, AbortHandle)>,
+    connections_managers: HashMap<
+        SocketAddr,
+        (
+            ConnectionManagerSender,
+            AbortHandle,
+            Arc<PeerConnectionStats>,
+        ),
+    >,
+    connection_stats: ConnectionStats,
     maximum_reconnection_backoff: Duration,
     initial_reconnection_backoff: Duration,
     initial_connection_timeout: Duration,
+    link_encryption: Option<InitiatorConfig>,
+    frame_version: FrameVersion,
 }
 
 impl Client {
@@ -61,21 +95,33 @@ impl Client {
             runtime_handle: Handle::try_current()
                 .expect("The client MUST BE used within tokio runtime context"),
             connections_managers: HashMap::new(),
+            connection_stats: Default::default(),
             initial_reconnection_backoff: config.initial_reconnection_backoff,
             maximum_reconnection_backoff: config.maximum_reconnection_backoff,
             initial_connection_timeout: config.initial_connection_timeout,
+            link_encryption: config.link_encryption,
+            frame_version: config.frame_version,
         }
     }
 
+    /// Returns handle to the statistics of all connections established by this client.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.connection_stats.clone()
+    }
+
     async fn start_new_connection_manager(
         &mut self,
         address: SocketAddr,
+        peer_stats: Arc<PeerConnectionStats>,
     ) -> (ConnectionManagerSender, AbortHandle) {
         let (sender, abort_handle) = ConnectionManager::new(
             address,
             self.initial_reconnection_backoff,
             self.maximum_reconnection_backoff,
             self.initial_connection_timeout,
+            self.link_encryption.clone(),
+            self.frame_version,
+            peer_stats,
         )
         .await
         .start_abortable(&self.runtime_handle);
@@ -99,13 +145,16 @@ impl Client {
                 address
             );
 
-            let (new_manager_sender, abort_handle) =
-                self.start_new_connection_manager(address).await;
+            let peer_stats = self.connection_stats.peer(address);
+            let (new_manager_sender, abort_handle) = self
+                .start_new_connection_manager(address, Arc::clone(&peer_stats))
+                .await;
             self.connections_managers
-                .insert(address, (new_manager_sender, abort_handle));
+                .insert(address, (new_manager_sender, abort_handle, peer_stats));
         }
 
         let manager = self.connections_managers.get_mut(&address).unwrap();
+        manager.2.increment_queued();
 
         if wait_for_response {
             let (res_tx, res_rx) = oneshot::channel();
@@ -120,7 +169,7 @@ impl Client {
 
 impl Drop for Client {
     fn drop(&mut self) {
-        for (_, abort_handle) in self.connections_managers.values() {
+        for (_, abort_handle, _) in self.connections_managers.values() {
             abort_handle.abort()
         }
     }
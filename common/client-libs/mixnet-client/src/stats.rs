@@ -0,0 +1,85 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub(crate) struct PeerConnectionStats {
+    queued_packets: AtomicUsize,
+    buffered_packets: AtomicUsize,
+    connection_attempts: AtomicU64,
+}
+
+impl PeerConnectionStats {
+    pub(crate) fn increment_queued(&self) {
+        self.queued_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn decrement_queued(&self) {
+        self.queued_packets.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_buffered(&self, buffered: usize) {
+        self.buffered_packets.store(buffered, Ordering::Relaxed);
+    }
+
+    pub(crate) fn increment_connection_attempts(&self) {
+        self.connection_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> PeerStats {
+        PeerStats {
+            queued_packets: self.queued_packets.load(Ordering::Relaxed),
+            buffered_packets: self.buffered_packets.load(Ordering::Relaxed),
+            connection_attempts: self.connection_attempts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Current state of the connection to particular peer.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerStats {
+    /// Number of packets waiting to be handled by the connection manager.
+    pub queued_packets: usize,
+    /// Number of packets buffered while the connection is being re-established.
+    pub buffered_packets: usize,
+    /// Total number of attempts to (re)establish the connection made in the background.
+    pub connection_attempts: u64,
+}
+
+/// Shared handle to the statistics of all connections established by the `Client`.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    peers: Arc<Mutex<HashMap<SocketAddr, Arc<PeerConnectionStats>>>>,
+}
+
+impl ConnectionStats {
+    pub(crate) fn peer(&self, address: SocketAddr) -> Arc<PeerConnectionStats> {
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        let mut peers = self.peers.lock().unwrap();
+        Arc::clone(peers.entry(address).or_insert_with(Default::default))
+    }
+
+    pub fn snapshot(&self) -> Vec<(SocketAddr, PeerStats)> {
+        let peers = self.peers.lock().unwrap();
+        peers
+            .iter()
+            .map(|(address, stats)| (*address, stats.snapshot()))
+            .collect()
+    }
+}
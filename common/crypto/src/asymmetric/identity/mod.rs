@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::asymmetric::encryption;
 use crate::{PemStorableKey, PemStorableKeyPair};
 use bs58;
+use curve25519_dalek::edwards::CompressedEdwardsY;
 use ed25519_dalek::SignatureError;
 pub use ed25519_dalek::{PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
 use nymsphinx_types::{DestinationAddressBytes, DESTINATION_ADDRESS_LENGTH};
 use rand::{rngs::OsRng, CryptoRng, RngCore};
+use sha2::{Digest, Sha512};
 
 /// Keypair for usage in ed25519 EdDSA.
 pub struct KeyPair {
@@ -112,6 +115,16 @@ impl PublicKey {
     pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), SignatureError> {
         self.0.verify(message, &signature.0)
     }
+
+    /// Converts this key into the equivalent x25519 key (i.e. its Montgomery form), so that
+    /// it could be used for Diffie-Hellman exchanges with the owner of the private key.
+    pub fn to_x25519(&self) -> encryption::PublicKey {
+        // the point was already decompressed (and thus validated) when the key was created
+        let edwards_point = CompressedEdwardsY(self.to_bytes())
+            .decompress()
+            .expect("ed25519 public key is not a valid curve point");
+        encryption::PublicKey::from_bytes(edwards_point.to_montgomery().as_bytes()).unwrap()
+    }
 }
 
 impl PemStorableKey for PublicKey {
@@ -154,6 +167,14 @@ impl PrivateKey {
         Self::from_bytes(&bytes)
     }
 
+    /// Converts this key into the equivalent x25519 key, whose public counterpart is the one
+    /// obtained from `PublicKey::to_x25519`.
+    pub fn to_x25519(&self) -> encryption::PrivateKey {
+        // it's the very same scalar that ed25519 uses for signing, x25519 takes care of clamping
+        let hashed_secret = Sha512::digest(self.0.as_bytes());
+        encryption::PrivateKey::from_bytes(&hashed_secret[..encryption::PRIVATE_KEY_SIZE]).unwrap()
+    }
+
     pub fn sign(&self, message: &[u8]) -> Signature {
         let expanded_secret_key = ed25519_dalek::ExpandedSecretKey::from(&self.0);
         let public_key: PublicKey = self.into();
@@ -184,3 +205,38 @@ impl Signature {
         Ok(Signature(ed25519_dalek::Signature::from_bytes(bytes)?))
     }
 }
+
+#[cfg(test)]
+mod x25519_conversion {
+    use super::*;
+
+    #[test]
+    fn converted_keys_form_a_keypair() {
+        for _ in 0..100 {
+            let keys = KeyPair::new();
+            let x25519_private = keys.private_key().to_x25519();
+            let x25519_public = keys.public_key().to_x25519();
+
+            assert_eq!(
+                encryption::PublicKey::from(&x25519_private).to_bytes(),
+                x25519_public.to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn converted_keys_agree_on_shared_secret() {
+        let alice = KeyPair::new();
+        let bob = KeyPair::new();
+
+        assert_eq!(
+            alice
+                .private_key()
+                .to_x25519()
+                .diffie_hellman(&bob.public_key().to_x25519()),
+            bob.private_key()
+                .to_x25519()
+                .diffie_hellman(&alice.public_key().to_x25519())
+        );
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::symmetric::aes_ctr::Aes128Key;
+use aes_gcm::aead::{
+    generic_array::{typenum::U12, GenericArray},
+    Aead, NewAead,
+};
+use aes_gcm::Aes128Gcm;
+
+pub use aes_gcm::aead::Error as DecryptionError;
+
+// AES-128-GCM shares the key type with AES-128-CTR, so the same keys can be used with either
+pub type Aes128GcmNonceSize = U12;
+pub type Aes128GcmNonce = GenericArray<u8, Aes128GcmNonceSize>;
+
+/// Number of bytes the authentication tag adds to each ciphertext.
+pub const TAG_SIZE: usize = 16;
+
+pub fn nonce_from_slice(b: &[u8]) -> &Aes128GcmNonce {
+    GenericArray::from_slice(b)
+}
+
+/// Encrypts the data, appending the authentication tag to the ciphertext.
+/// Note that the same nonce must NEVER be used twice with the same key.
+pub fn encrypt(key: &Aes128Key, nonce: &Aes128GcmNonce, data: &[u8]) -> Vec<u8> {
+    // encryption can only fail if the data is larger than what GCM is capable of handling,
+    // i.e. ~64GB, which is impossible in our case
+    Aes128Gcm::new(key)
+        .encrypt(nonce, data)
+        .expect("data is too long to be encrypted")
+}
+
+/// Decrypts the data if the authentication tag is valid for it.
+pub fn decrypt(
+    key: &Aes128Key,
+    nonce: &Aes128GcmNonce,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, DecryptionError> {
+    Aes128Gcm::new(key).decrypt(nonce, ciphertext)
+}
+
+#[cfg(test)]
+mod authenticated_encryption {
+    use super::*;
+    use crate::symmetric::aes_ctr::generate_key;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn decryption_is_reciprocal_to_encryption() {
+        let key = generate_key(&mut OsRng);
+        let nonce = nonce_from_slice(&[1; 12]);
+        let data = vec![42; 200];
+
+        let ciphertext = encrypt(&key, nonce, &data);
+        assert_eq!(data.len() + TAG_SIZE, ciphertext.len());
+        assert_eq!(data, decrypt(&key, nonce, &ciphertext).unwrap());
+    }
+
+    #[test]
+    fn modified_ciphertext_is_rejected() {
+        let key = generate_key(&mut OsRng);
+        let nonce = nonce_from_slice(&[1; 12]);
+
+        let mut ciphertext = encrypt(&key, nonce, &[42; 200]);
+        ciphertext[10] ^= 1;
+        assert!(decrypt(&key, nonce, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn ciphertext_is_bound_to_the_nonce() {
+        let key = generate_key(&mut OsRng);
+
+        let ciphertext = encrypt(&key, nonce_from_slice(&[1; 12]), &[42; 200]);
+        assert!(decrypt(&key, nonce_from_slice(&[2; 12]), &ciphertext).is_err());
+    }
+}
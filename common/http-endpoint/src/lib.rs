@@ -0,0 +1,70 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use log::*;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+pub mod token;
+
+pub fn response(status: StatusCode, body: &'static str) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// Serves HTTP requests received on the given address with the provided handler.
+/// The `name` of the endpoint is only used for logging.
+pub fn start<F, R>(
+    handle: &Handle,
+    address: SocketAddr,
+    name: &'static str,
+    handler: F,
+) -> JoinHandle<()>
+where
+    F: Fn(Request<Body>) -> R + Send + Sync + 'static,
+    R: Future<Output = Response<Body>> + Send + 'static,
+{
+    let handler = Arc::new(handler);
+    handle.spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let handler = Arc::clone(&handler);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = handler(request);
+                    async move { Ok::<_, Infallible>(response.await) }
+                }))
+            }
+        });
+
+        let server = match Server::try_bind(&address) {
+            Ok(builder) => builder.serve(make_service),
+            Err(err) => {
+                error!("Failed to bind the {} to {} - {:?}", name, address, err);
+                return;
+            }
+        };
+
+        info!("Exposing {} on {}", name, address);
+        if let Err(err) = server.await {
+            error!("The {} has failed - {:?}", name, err);
+        }
+    })
+}
@@ -0,0 +1,130 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Client, Method, Request};
+use rand::{rngs::OsRng, RngCore};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Reads the token required for accessing an endpoint.
+pub fn load_token(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+/// Reads the token required for accessing an endpoint or, if there's none yet,
+/// generates a new one that is readable only by the owner of the file.
+pub fn load_or_create_token(path: &Path) -> io::Result<String> {
+    if path.exists() {
+        return load_token(path);
+    }
+
+    let mut token_bytes = [0u8; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut token_bytes);
+    let token = bs58::encode(token_bytes).into_string();
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+// compares the tokens in time that does not depend on where they differ
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Checks whether the request carries the token as `Authorization: Bearer <token>`.
+pub fn is_authorized(request: &Request<Body>, token: &str) -> bool {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|provided| tokens_match(token, provided))
+        .unwrap_or(false)
+}
+
+/// Sends an empty request carrying the token and returns the status and body of the response.
+pub async fn send_authorized_request(
+    method: Method,
+    uri: String,
+    token: String,
+) -> Result<String, hyper::Error> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())
+        .expect("Failed to create the request");
+    let response = Client::new().request(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok(format!(
+        "{} - {}",
+        status,
+        String::from_utf8_lossy(&body).trim()
+    ))
+}
+
+#[cfg(test)]
+mod endpoint_token {
+    use super::*;
+
+    #[test]
+    fn is_generated_once_and_then_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("admin_token");
+
+        let token = load_or_create_token(&path).unwrap();
+        assert!(!token.is_empty());
+        assert_eq!(token, load_or_create_token(&path).unwrap());
+        assert_eq!(token, load_token(&path).unwrap());
+    }
+
+    #[test]
+    fn only_exactly_matching_tokens_match() {
+        assert!(tokens_match("foomp", "foomp"));
+        assert!(!tokens_match("foomp", "foomq"));
+        assert!(!tokens_match("foomp", "foom"));
+        assert!(!tokens_match("foomp", ""));
+    }
+
+    #[test]
+    fn requests_are_authorized_only_with_bearer_token() {
+        let request = |header: &str| {
+            Request::builder()
+                .header(AUTHORIZATION, header)
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert!(is_authorized(&request("Bearer foomp"), "foomp"));
+        assert!(!is_authorized(&request("foomp"), "foomp"));
+        assert!(!is_authorized(&request("Bearer foomq"), "foomp"));
+        assert!(!is_authorized(&Request::new(Body::empty()), "foomp"));
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::NodeRole;
+use crypto::asymmetric::encryption::PUBLIC_KEY_SIZE;
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+/// Node present in the topology, identified by its address and, if it could be parsed,
+/// the static key it uses for authenticating its links.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Peer {
+    pub(crate) ip: IpAddr,
+    pub(crate) static_key: Option<[u8; PUBLIC_KEY_SIZE]>,
+}
+
+#[derive(Debug, Default)]
+struct PeerSet {
+    ips: HashSet<IpAddr>,
+    static_keys: HashSet<[u8; PUBLIC_KEY_SIZE]>,
+}
+
+impl PeerSet {
+    fn new<'a>(peers: impl Iterator<Item = &'a Peer>) -> Self {
+        let mut set = PeerSet::default();
+        for peer in peers {
+            set.ips.insert(peer.ip);
+            if let Some(static_key) = peer.static_key {
+                set.static_keys.insert(static_key);
+            }
+        }
+        set
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AllowedPeers {
+    ingress: PeerSet,
+    // `None` means forwarding is not restricted
+    egress: Option<HashSet<IpAddr>>,
+}
+
+impl AllowedPeers {
+    /// Derives the allowed peers from mixnodes (alongside their layers) and gateways
+    /// present in the topology.
+    pub(crate) fn new(role: NodeRole, mixes: &[(u64, Peer)], gateways: &[Peer]) -> Self {
+        let final_layer = mixes
+            .iter()
+            .map(|(layer, _)| *layer)
+            .max()
+            .unwrap_or_default();
+        let mixes_at = |wanted_layer: u64| {
+            mixes
+                .iter()
+                .filter(move |(layer, _)| *layer == wanted_layer)
+                .map(|(_, peer)| peer)
+        };
+
+        match role {
+            NodeRole::Mix { layer } => {
+                // packets enter the mix network only through the first layer
+                let ingress = if layer <= 1 {
+                    PeerSet::new(gateways.iter())
+                } else {
+                    PeerSet::new(mixes_at(layer - 1))
+                };
+                // if we're not (yet) in the topology with a layer beyond the final one,
+                // we're treating ourselves as part of the final layer
+                let egress = if layer >= final_layer {
+                    gateways.iter().map(|peer| peer.ip).collect()
+                } else {
+                    mixes_at(layer + 1).map(|peer| peer.ip).collect()
+                };
+                AllowedPeers {
+                    ingress,
+                    egress: Some(egress),
+                }
+            }
+            NodeRole::Gateway => AllowedPeers {
+                ingress: PeerSet::new(mixes_at(final_layer)),
+                egress: None,
+            },
+        }
+    }
+
+    /// If the link with the peer is authenticated, its static key has to belong to an allowed
+    /// node, regardless of the address the connection came from. Otherwise only the address
+    /// can be checked.
+    pub(crate) fn allows_ingress(&self, peer: &IpAddr, static_key: Option<&[u8]>) -> bool {
+        match static_key {
+            Some(static_key) => self
+                .ingress
+                .static_keys
+                .iter()
+                .any(|allowed_key| allowed_key[..] == *static_key),
+            None => self.ingress.ips.contains(peer),
+        }
+    }
+
+    pub(crate) fn allows_egress(&self, next_hop: &IpAddr) -> bool {
+        match &self.egress {
+            Some(egress) => egress.contains(next_hop),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod deriving_allowed_peers {
+    use super::*;
+
+    fn ip(last_octet: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last_octet])
+    }
+
+    fn key(last_octet: u8) -> [u8; PUBLIC_KEY_SIZE] {
+        [last_octet; PUBLIC_KEY_SIZE]
+    }
+
+    fn peer(last_octet: u8) -> Peer {
+        Peer {
+            ip: ip(last_octet),
+            static_key: Some(key(last_octet)),
+        }
+    }
+
+    // layer 1: .1, .2; layer 2: .3; layer 3: .4; gateways: .10, .11
+    fn topology() -> (Vec<(u64, Peer)>, Vec<Peer>) {
+        (
+            vec![(1, peer(1)), (1, peer(2)), (2, peer(3)), (3, peer(4))],
+            vec![peer(10), peer(11)],
+        )
+    }
+
+    #[test]
+    fn first_layer_only_talks_to_gateways_and_second_layer() {
+        let (mixes, gateways) = topology();
+        let allowed = AllowedPeers::new(NodeRole::Mix { layer: 1 }, &mixes, &gateways);
+
+        assert!(allowed.allows_ingress(&ip(10), None));
+        assert!(allowed.allows_ingress(&ip(11), None));
+        assert!(!allowed.allows_ingress(&ip(2), None));
+        assert!(!allowed.allows_ingress(&ip(3), None));
+
+        assert!(allowed.allows_egress(&ip(3)));
+        assert!(!allowed.allows_egress(&ip(1)));
+        assert!(!allowed.allows_egress(&ip(4)));
+        assert!(!allowed.allows_egress(&ip(10)));
+    }
+
+    #[test]
+    fn middle_layer_only_talks_to_adjacent_layers() {
+        let (mixes, gateways) = topology();
+        let allowed = AllowedPeers::new(NodeRole::Mix { layer: 2 }, &mixes, &gateways);
+
+        assert!(allowed.allows_ingress(&ip(1), None));
+        assert!(allowed.allows_ingress(&ip(2), None));
+        assert!(!allowed.allows_ingress(&ip(10), None));
+        assert!(!allowed.allows_ingress(&ip(4), None));
+
+        assert!(allowed.allows_egress(&ip(4)));
+        assert!(!allowed.allows_egress(&ip(1)));
+        assert!(!allowed.allows_egress(&ip(10)));
+    }
+
+    #[test]
+    fn final_layer_forwards_to_gateways() {
+        let (mixes, gateways) = topology();
+        let allowed = AllowedPeers::new(NodeRole::Mix { layer: 3 }, &mixes, &gateways);
+
+        assert!(allowed.allows_ingress(&ip(3), None));
+        assert!(!allowed.allows_ingress(&ip(1), None));
+        assert!(!allowed.allows_ingress(&ip(10), None));
+
+        assert!(allowed.allows_egress(&ip(10)));
+        assert!(allowed.allows_egress(&ip(11)));
+        assert!(!allowed.allows_egress(&ip(1)));
+    }
+
+    #[test]
+    fn gateway_only_accepts_final_layer() {
+        let (mixes, gateways) = topology();
+        let allowed = AllowedPeers::new(NodeRole::Gateway, &mixes, &gateways);
+
+        assert!(allowed.allows_ingress(&ip(4), None));
+        assert!(!allowed.allows_ingress(&ip(1), None));
+        assert!(!allowed.allows_ingress(&ip(3), None));
+        assert!(!allowed.allows_ingress(&ip(10), None));
+
+        assert!(allowed.allows_egress(&ip(1)));
+    }
+
+    #[test]
+    fn authenticated_peers_are_matched_by_their_key() {
+        let (mixes, gateways) = topology();
+        let allowed = AllowedPeers::new(NodeRole::Mix { layer: 2 }, &mixes, &gateways);
+
+        // allowed key connecting from an unexpected address
+        assert!(allowed.allows_ingress(&ip(42), Some(&key(1))));
+        // allowed address presenting a key of a node from a different layer
+        assert!(!allowed.allows_ingress(&ip(1), Some(&key(4))));
+        assert!(!allowed.allows_ingress(&ip(1), Some(&key(42))));
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::allowed_peers::{AllowedPeers, Peer};
+use log::*;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+mod allowed_peers;
+mod refresher;
+
+pub use refresher::LayerFilterRefresher;
+
+/// Position of the node in the network, which determines whom it exchanges packets with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeRole {
+    /// Mixnode at the given layer. It only accepts packets from the previous layer, or from
+    /// gateways if it's the first one, and only forwards them to the next layer, or to gateways
+    /// if it's the final one.
+    Mix { layer: u64 },
+
+    /// Gateway only accepts packets from the mixnodes at the final layer.
+    Gateway,
+}
+
+/// Shared view of which peers are allowed to send packets to this node and which ones
+/// it is allowed to forward packets to, according to the layering of the network topology.
+/// Until the topology is obtained for the first time, all peers are allowed.
+///
+/// Peers sending packets over authenticated links are identified by their static keys.
+/// Otherwise, and for the next hops, peers are identified by their IP addresses only,
+/// as the outbound connections of other nodes originate from ephemeral ports rather than
+/// from their announced listening ports.
+#[derive(Clone)]
+pub struct LayerFilter {
+    state: Arc<RwLock<FilterState>>,
+    rejected_ingress: Arc<AtomicU64>,
+    rejected_egress: Arc<AtomicU64>,
+}
+
+struct FilterState {
+    role: NodeRole,
+    // mixnodes (alongside their layers) and gateways as of the last refresh,
+    // kept so that the allowed peers could be recomputed if our role changes
+    mixes: Vec<(u64, Peer)>,
+    gateways: Vec<Peer>,
+    allowed_peers: Option<AllowedPeers>,
+}
+
+impl LayerFilter {
+    pub fn new(role: NodeRole) -> Self {
+        LayerFilter {
+            state: Arc::new(RwLock::new(FilterState {
+                role,
+                mixes: Vec::new(),
+                gateways: Vec::new(),
+                allowed_peers: None,
+            })),
+            rejected_ingress: Arc::new(AtomicU64::new(0)),
+            rejected_egress: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn role(&self) -> NodeRole {
+        self.state.read().unwrap().role
+    }
+
+    /// Changes position of the node in the network, for example after the mixnode got assigned
+    /// to a different layer. If the topology was already obtained, the allowed peers are
+    /// recomputed immediately.
+    pub fn set_role(&self, role: NodeRole) {
+        let mut state = self.state.write().unwrap();
+        state.role = role;
+        if state.allowed_peers.is_some() {
+            state.allowed_peers = Some(AllowedPeers::new(role, &state.mixes, &state.gateways));
+        }
+    }
+
+    fn update(&self, mixes: Vec<(u64, Peer)>, gateways: Vec<Peer>) {
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        let mut state = self.state.write().unwrap();
+        let allowed_peers = AllowedPeers::new(state.role, &mixes, &gateways);
+        trace!("Updated layer filter - {:?}", allowed_peers);
+        state.allowed_peers = Some(allowed_peers);
+        state.mixes = mixes;
+        state.gateways = gateways;
+    }
+
+    /// Checks whether a packet received from the given peer should be processed.
+    /// If the link with the peer is authenticated, its remote static key has to be provided.
+    /// Any violation is counted.
+    pub fn allows_ingress(&self, peer: IpAddr, static_key: Option<&[u8]>) -> bool {
+        let allowed = match self.state.read().unwrap().allowed_peers.as_ref() {
+            Some(allowed_peers) => allowed_peers.allows_ingress(&peer, static_key),
+            None => true,
+        };
+        if !allowed {
+            self.rejected_ingress.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    /// Checks whether a packet should be forwarded to the given next hop.
+    /// Any violation is counted.
+    pub fn allows_egress(&self, next_hop: IpAddr) -> bool {
+        let allowed = match self.state.read().unwrap().allowed_peers.as_ref() {
+            Some(allowed_peers) => allowed_peers.allows_egress(&next_hop),
+            None => true,
+        };
+        if !allowed {
+            self.rejected_egress.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    /// Total number of packets rejected due to being received from a disallowed peer.
+    pub fn rejected_ingress(&self) -> u64 {
+        self.rejected_ingress.load(Ordering::Relaxed)
+    }
+
+    /// Total number of packets rejected due to being addressed to a disallowed next hop.
+    pub fn rejected_egress(&self) -> u64 {
+        self.rejected_egress.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod layer_filter {
+    use super::*;
+
+    fn ip(last_octet: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last_octet])
+    }
+
+    fn peer(last_octet: u8) -> Peer {
+        Peer {
+            ip: ip(last_octet),
+            static_key: None,
+        }
+    }
+
+    #[test]
+    fn allows_everything_before_first_update() {
+        let filter = LayerFilter::new(NodeRole::Mix { layer: 1 });
+        assert!(filter.allows_ingress(ip(1), None));
+        assert!(filter.allows_egress(ip(1)));
+        assert_eq!(0, filter.rejected_ingress());
+    }
+
+    #[test]
+    fn role_change_is_applied_to_known_topology() {
+        let filter = LayerFilter::new(NodeRole::Mix { layer: 1 });
+        filter.update(
+            vec![(1, peer(1)), (2, peer(2)), (3, peer(3))],
+            vec![peer(10)],
+        );
+        assert!(filter.allows_egress(ip(2)));
+        assert!(!filter.allows_egress(ip(3)));
+
+        filter.set_role(NodeRole::Mix { layer: 2 });
+        assert!(filter.allows_egress(ip(3)));
+        assert!(filter.allows_ingress(ip(1), None));
+        assert!(!filter.allows_ingress(ip(2), None));
+        assert_eq!(1, filter.rejected_egress());
+        assert_eq!(1, filter.rejected_ingress());
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::allowed_peers::Peer;
+use crate::LayerFilter;
+use crypto::asymmetric::encryption;
+use directory_client::DirectoryClient;
+use log::*;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+use topology::NymTopology;
+
+// both mixnodes and gateways use their sphinx keys as the static keys of their links
+fn parse_static_key(key: &str) -> Option<[u8; encryption::PUBLIC_KEY_SIZE]> {
+    encryption::PublicKey::from_base58_string(key)
+        .ok()
+        .map(|key| key.to_bytes())
+}
+
+/// Periodically updates the `LayerFilter` with the current topology of the network,
+/// as seen by the directory server.
+pub struct LayerFilterRefresher {
+    directory_client: directory_client::Client,
+    filter: LayerFilter,
+    refresh_rate: Duration,
+}
+
+impl LayerFilterRefresher {
+    pub fn new(directory_server: String, filter: LayerFilter, refresh_rate: Duration) -> Self {
+        LayerFilterRefresher {
+            directory_client: directory_client::Client::new(directory_client::Config::new(
+                directory_server,
+            )),
+            filter,
+            refresh_rate,
+        }
+    }
+
+    async fn refresh(&self) {
+        let topology = match self.directory_client.get_topology().await {
+            Ok(topology) => topology,
+            Err(err) => {
+                // keep using the view we already have
+                warn!(
+                    "Failed to refresh the topology for layer filtering - {}",
+                    err
+                );
+                return;
+            }
+        };
+
+        let mixes = topology
+            .mix_nodes()
+            .into_iter()
+            .map(|node| {
+                let peer = Peer {
+                    ip: node.host.ip(),
+                    static_key: parse_static_key(&node.pub_key),
+                };
+                (node.layer, peer)
+            })
+            .collect();
+        let gateways = topology
+            .gateways()
+            .into_iter()
+            .map(|node| Peer {
+                ip: node.mixnet_listener.ip(),
+                static_key: parse_static_key(&node.sphinx_key),
+            })
+            .collect();
+
+        self.filter.update(mixes, gateways);
+    }
+
+    pub fn start(self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                self.refresh().await;
+                tokio::time::delay_for(self.refresh_rate).await;
+            }
+        })
+    }
+}
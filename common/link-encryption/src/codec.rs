@@ -0,0 +1,239 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{MAX_NOISE_MESSAGE_LEN, NOISE_TAG_LEN};
+use bytes::{Buf, BufMut, BytesMut};
+use snow::TransportState;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Length of the prefix put in front of each encrypted message.
+const LENGTH_PREFIX_LEN: usize = 2;
+
+/// Maximum number of plaintext bytes that can be put into a single noise message.
+const MAX_PLAINTEXT_CHUNK_LEN: usize = MAX_NOISE_MESSAGE_LEN - NOISE_TAG_LEN;
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("link encryption failure - {}", err),
+    )
+}
+
+/// Codec wrapping another one. If the link is encrypted, everything produced by the inner codec
+/// is split into length-prefixed noise messages, and everything received is decrypted before
+/// being passed to the inner codec. Otherwise it's fully transparent.
+pub struct LinkCodec<C> {
+    inner: C,
+    transport: Option<Box<TransportState>>,
+    // decrypted bytes not yet consumed by the inner codec
+    plaintext: BytesMut,
+    // reusable buffer for the noise operations
+    scratch: Vec<u8>,
+}
+
+impl<C> LinkCodec<C> {
+    pub fn new(inner: C, transport: Option<TransportState>) -> Self {
+        LinkCodec {
+            inner,
+            transport: transport.map(Box::new),
+            plaintext: BytesMut::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn plain(inner: C) -> Self {
+        Self::new(inner, None)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// Static public key of the remote, as proven during the handshake, if the link is encrypted.
+    pub fn remote_static_key(&self) -> Option<&[u8]> {
+        self.transport
+            .as_ref()
+            .and_then(|transport| transport.get_remote_static())
+    }
+}
+
+impl<I, C: Encoder<I>> Encoder<I> for LinkCodec<C> {
+    type Error = C::Error;
+
+    fn encode(&mut self, item: I, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let transport = match self.transport.as_mut() {
+            Some(transport) => transport,
+            None => return self.inner.encode(item, dst),
+        };
+
+        let mut plaintext = BytesMut::new();
+        self.inner.encode(item, &mut plaintext)?;
+
+        for chunk in plaintext.chunks(MAX_PLAINTEXT_CHUNK_LEN) {
+            self.scratch.resize(chunk.len() + NOISE_TAG_LEN, 0);
+            let len = transport
+                .write_message(chunk, &mut self.scratch)
+                .map_err(noise_error)?;
+
+            dst.reserve(LENGTH_PREFIX_LEN + len);
+            // this can't overflow as the message is never longer than `MAX_NOISE_MESSAGE_LEN`
+            dst.put_u16(len as u16);
+            dst.put_slice(&self.scratch[..len]);
+        }
+        Ok(())
+    }
+}
+
+impl<C: Decoder> Decoder for LinkCodec<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let transport = match self.transport.as_mut() {
+            Some(transport) => transport,
+            None => return self.inner.decode(src),
+        };
+
+        loop {
+            if let Some(item) = self.inner.decode(&mut self.plaintext)? {
+                return Ok(Some(item));
+            }
+
+            if src.len() < LENGTH_PREFIX_LEN {
+                return Ok(None);
+            }
+            let len = u16::from_be_bytes([src[0], src[1]]) as usize;
+            if src.len() < LENGTH_PREFIX_LEN + len {
+                src.reserve(LENGTH_PREFIX_LEN + len - src.len());
+                return Ok(None);
+            }
+
+            src.advance(LENGTH_PREFIX_LEN);
+            let message = src.split_to(len);
+            self.scratch.resize(len, 0);
+            let plaintext_len = transport
+                .read_message(&message, &mut self.scratch)
+                .map_err(noise_error)?;
+            self.plaintext
+                .extend_from_slice(&self.scratch[..plaintext_len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod link_codec {
+    use super::*;
+    use crate::NOISE_PARAMS;
+    use tokio_util::codec::BytesCodec;
+
+    // runs the entire handshake in memory to get a pair of matching transport states
+    fn transport_pair() -> (TransportState, TransportState) {
+        let builder = snow::Builder::new(NOISE_PARAMS.parse().unwrap());
+        let initiator_keys = builder.generate_keypair().unwrap();
+        let responder_keys = builder.generate_keypair().unwrap();
+
+        let mut initiator = snow::Builder::new(NOISE_PARAMS.parse().unwrap())
+            .local_private_key(&initiator_keys.private)
+            .remote_public_key(&responder_keys.public)
+            .build_initiator()
+            .unwrap();
+        let mut responder = snow::Builder::new(NOISE_PARAMS.parse().unwrap())
+            .local_private_key(&responder_keys.private)
+            .build_responder()
+            .unwrap();
+
+        let mut message = [0u8; MAX_NOISE_MESSAGE_LEN];
+        let mut payload = [0u8; MAX_NOISE_MESSAGE_LEN];
+        let len = initiator.write_message(&[], &mut message).unwrap();
+        responder
+            .read_message(&message[..len], &mut payload)
+            .unwrap();
+        let len = responder.write_message(&[], &mut message).unwrap();
+        initiator
+            .read_message(&message[..len], &mut payload)
+            .unwrap();
+
+        (
+            initiator.into_transport_mode().unwrap(),
+            responder.into_transport_mode().unwrap(),
+        )
+    }
+
+    #[test]
+    fn plain_codec_is_transparent() {
+        let mut codec = LinkCodec::plain(BytesCodec::new());
+        let mut buf = BytesMut::new();
+        codec
+            .encode(bytes::Bytes::from_static(b"foomp"), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], b"foomp");
+    }
+
+    #[test]
+    fn encrypted_data_is_recovered_by_the_remote() {
+        let (initiator, responder) = transport_pair();
+        let mut sender = LinkCodec::new(BytesCodec::new(), Some(initiator));
+        let mut receiver = LinkCodec::new(BytesCodec::new(), Some(responder));
+
+        // make sure the data spans multiple noise messages
+        let data: Vec<u8> = (0..3 * MAX_NOISE_MESSAGE_LEN).map(|i| i as u8).collect();
+        let mut buf = BytesMut::new();
+        sender
+            .encode(bytes::Bytes::from(data.clone()), &mut buf)
+            .unwrap();
+        assert!(!buf.windows(64).any(|window| window == &data[..64]));
+
+        let mut received = Vec::new();
+        while let Some(chunk) = receiver.decode(&mut buf).unwrap() {
+            received.extend_from_slice(&chunk);
+        }
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn partial_messages_are_not_decoded() {
+        let (initiator, responder) = transport_pair();
+        let mut sender = LinkCodec::new(BytesCodec::new(), Some(initiator));
+        let mut receiver = LinkCodec::new(BytesCodec::new(), Some(responder));
+
+        let mut buf = BytesMut::new();
+        sender
+            .encode(bytes::Bytes::from_static(b"foomp"), &mut buf)
+            .unwrap();
+        let mut partial = buf.split_to(buf.len() - 1);
+        assert!(receiver.decode(&mut partial).unwrap().is_none());
+
+        partial.unsplit(buf);
+        assert_eq!(
+            &receiver.decode(&mut partial).unwrap().unwrap()[..],
+            b"foomp"
+        );
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let (initiator, responder) = transport_pair();
+        let mut sender = LinkCodec::new(BytesCodec::new(), Some(initiator));
+        let mut receiver = LinkCodec::new(BytesCodec::new(), Some(responder));
+
+        let mut buf = BytesMut::new();
+        sender
+            .encode(bytes::Bytes::from_static(b"foomp"), &mut buf)
+            .unwrap();
+        let last = buf.len() - 1;
+        buf[last] ^= 1;
+        assert!(receiver.decode(&mut buf).is_err());
+    }
+}
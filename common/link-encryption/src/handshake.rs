@@ -0,0 +1,289 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{InitiatorConfig, LinkError, ResponderConfig, MAX_NOISE_MESSAGE_LEN, NOISE_PARAMS};
+use snow::TransportState;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Bytes sent by the initiator before the handshake itself. Neither legacy nor versioned sphinx
+/// frames can start with them, which lets the responder tell encrypted links apart from
+/// plaintext ones arriving on the same listener. The last byte is the version of the protocol.
+pub const LINK_PREAMBLE: [u8; 8] = *b"NYMLINK\x01";
+
+// how long to wait before peeking again if only part of the preamble has arrived
+const PREAMBLE_POLL_DELAY: Duration = Duration::from_millis(5);
+
+async fn write_handshake_message<S>(stream: &mut S, message: &[u8]) -> Result<(), LinkError>
+where
+    S: AsyncWrite + Unpin,
+{
+    // noise messages are never longer than `MAX_NOISE_MESSAGE_LEN`, so they always fit in u16
+    stream
+        .write_all(&(message.len() as u16).to_be_bytes())
+        .await?;
+    stream.write_all(message).await?;
+    Ok(())
+}
+
+async fn read_handshake_message<S>(stream: &mut S) -> Result<Vec<u8>, LinkError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len_bytes = [0u8; 2];
+    stream.read_exact(&mut len_bytes).await?;
+    let mut message = vec![0u8; u16::from_be_bytes(len_bytes) as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// Performs the initiator side of the handshake with the node owning `remote_public_key`.
+/// Fails if the remote does not complete the handshake within the configured timeout,
+/// which is also what happens if it does not support encrypted links at all.
+pub async fn initiate<S>(
+    stream: &mut S,
+    config: &InitiatorConfig,
+    remote_public_key: &[u8],
+) -> Result<TransportState, LinkError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = async {
+        let mut handshake_state = snow::Builder::new(NOISE_PARAMS.parse()?)
+            .prologue(&LINK_PREAMBLE)
+            .local_private_key(&config.local_private_key)
+            .remote_public_key(remote_public_key)
+            .build_initiator()?;
+
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LEN];
+        let len = handshake_state.write_message(&[], &mut buf)?;
+        stream.write_all(&LINK_PREAMBLE).await?;
+        write_handshake_message(stream, &buf[..len]).await?;
+
+        let response = read_handshake_message(stream).await?;
+        handshake_state.read_message(&response, &mut buf)?;
+        Ok(handshake_state.into_transport_mode()?)
+    };
+
+    tokio::time::timeout(config.handshake_timeout, handshake)
+        .await
+        .map_err(|_| LinkError::HandshakeTimeout)?
+}
+
+// checks whether the connection starts with the preamble without consuming any data
+async fn has_link_preamble(stream: &mut TcpStream) -> Result<bool, LinkError> {
+    let mut buf = [0u8; LINK_PREAMBLE.len()];
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == 0 || buf[..n] != LINK_PREAMBLE[..n] {
+            return Ok(false);
+        }
+        if n == LINK_PREAMBLE.len() {
+            return Ok(true);
+        }
+        // only part of the preamble has arrived so far
+        tokio::time::delay_for(PREAMBLE_POLL_DELAY).await;
+    }
+}
+
+/// Performs the responder side of the handshake if the remote has initiated one. If it didn't,
+/// or if we don't have encrypted links enabled, `None` is returned and the connection
+/// should be treated as a plaintext one. Initiators with unknown static keys are rejected.
+pub async fn accept(
+    stream: &mut TcpStream,
+    config: Option<&ResponderConfig>,
+) -> Result<Option<TransportState>, LinkError> {
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+
+    let handshake = async {
+        if !has_link_preamble(stream).await? {
+            return Ok(None);
+        }
+
+        let mut preamble = [0u8; LINK_PREAMBLE.len()];
+        stream.read_exact(&mut preamble).await?;
+
+        let mut handshake_state = snow::Builder::new(NOISE_PARAMS.parse()?)
+            .prologue(&LINK_PREAMBLE)
+            .local_private_key(&config.local_private_key)
+            .build_responder()?;
+
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LEN];
+        let request = read_handshake_message(stream).await?;
+        handshake_state.read_message(&request, &mut buf)?;
+
+        // with the `IK` pattern the static key of the initiator is known after its first message,
+        // so anyone outside of the network is rejected before we even respond
+        match handshake_state.get_remote_static() {
+            Some(remote_key) if config.peer_keys.contains_key(remote_key) => (),
+            _ => return Err(LinkError::UnknownRemoteKey),
+        }
+
+        let len = handshake_state.write_message(&[], &mut buf)?;
+        write_handshake_message(stream, &buf[..len]).await?;
+        Ok(Some(handshake_state.into_transport_mode()?))
+    };
+
+    tokio::time::timeout(config.handshake_timeout, handshake)
+        .await
+        .map_err(|_| LinkError::HandshakeTimeout)?
+}
+
+#[cfg(test)]
+mod link_handshake {
+    use super::*;
+    use crate::{LinkCodec, PeerKeys};
+    use futures::{SinkExt, StreamExt};
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio_util::codec::{Framed, LinesCodec};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn keypair() -> snow::Keypair {
+        snow::Builder::new(NOISE_PARAMS.parse().unwrap())
+            .generate_keypair()
+            .unwrap()
+    }
+
+    async fn listener() -> (TcpListener, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        (listener, address)
+    }
+
+    fn initiator_config(local_private_key: &[u8]) -> InitiatorConfig {
+        InitiatorConfig::new(local_private_key, PeerKeys::default(), TIMEOUT)
+    }
+
+    fn responder_config(local_private_key: &[u8], known_key: &[u8]) -> ResponderConfig {
+        let peer_keys = PeerKeys::default();
+        let mut keys = std::collections::HashMap::new();
+        keys.insert(
+            "127.0.0.1:1789".parse().unwrap(),
+            crate::copy_key(known_key),
+        );
+        peer_keys.update(keys);
+        ResponderConfig::new(local_private_key, peer_keys, TIMEOUT)
+    }
+
+    #[tokio::test]
+    async fn established_link_authenticates_both_sides() {
+        let initiator_keys = keypair();
+        let responder_keys = keypair();
+        let (mut listener, address) = listener().await;
+
+        let responder_config = responder_config(&responder_keys.private, &initiator_keys.public);
+        let responder = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let transport = accept(&mut socket, Some(&responder_config)).await.unwrap();
+            let mut framed = Framed::new(socket, LinkCodec::new(LinesCodec::new(), transport));
+            let line = framed.next().await.unwrap().unwrap();
+            (
+                line,
+                framed.codec().remote_static_key().map(|key| key.to_vec()),
+            )
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let transport = initiate(
+            &mut stream,
+            &initiator_config(&initiator_keys.private),
+            &responder_keys.public,
+        )
+        .await
+        .unwrap();
+        let mut framed = Framed::new(stream, LinkCodec::new(LinesCodec::new(), Some(transport)));
+        framed.send("foomp").await.unwrap();
+
+        let (line, remote_key) = responder.await.unwrap();
+        assert_eq!(line, "foomp");
+        assert_eq!(remote_key, Some(initiator_keys.public));
+    }
+
+    #[tokio::test]
+    async fn plaintext_connections_are_still_accepted() {
+        let responder_keys = keypair();
+        let (mut listener, address) = listener().await;
+
+        let responder_config = responder_config(&responder_keys.private, &keypair().public);
+        let responder = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let transport = accept(&mut socket, Some(&responder_config)).await.unwrap();
+            assert!(transport.is_none());
+            let mut framed = Framed::new(socket, LinkCodec::plain(LinesCodec::new()));
+            framed.next().await.unwrap().unwrap()
+        });
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let mut framed = Framed::new(stream, LinesCodec::new());
+        framed.send("foomp").await.unwrap();
+
+        assert_eq!(responder.await.unwrap(), "foomp");
+    }
+
+    #[tokio::test]
+    async fn handshake_with_wrong_remote_key_fails() {
+        let initiator_keys = keypair();
+        let responder_keys = keypair();
+        let unrelated_keys = keypair();
+        let (mut listener, address) = listener().await;
+
+        let responder_config = responder_config(&responder_keys.private, &initiator_keys.public);
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            assert!(accept(&mut socket, Some(&responder_config)).await.is_err());
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let res = initiate(
+            &mut stream,
+            &initiator_config(&initiator_keys.private),
+            &unrelated_keys.public,
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn initiator_with_unknown_key_is_rejected() {
+        let initiator_keys = keypair();
+        let responder_keys = keypair();
+        let (mut listener, address) = listener().await;
+
+        let responder_config = responder_config(&responder_keys.private, &keypair().public);
+        let responder = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            accept(&mut socket, Some(&responder_config)).await
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let res = initiate(
+            &mut stream,
+            &initiator_config(&initiator_keys.private),
+            &responder_keys.public,
+        )
+        .await;
+        assert!(res.is_err());
+
+        match responder.await.unwrap() {
+            Err(LinkError::UnknownRemoteKey) => (),
+            _ => panic!("the initiator should have been rejected"),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+mod codec;
+mod handshake;
+
+pub use codec::LinkCodec;
+pub use handshake::{accept, initiate, LINK_PREAMBLE};
+pub use snow::TransportState;
+
+/// Noise protocol used for the node links. With the `IK` pattern the initiator already knows
+/// the static key of the responder (from the network topology) and both sides get authenticated
+/// within a single round trip.
+pub(crate) const NOISE_PARAMS: &str = "Noise_IK_25519_ChaChaPoly_BLAKE2s";
+
+/// Maximum length of a single noise message, as defined by the specification.
+pub(crate) const MAX_NOISE_MESSAGE_LEN: usize = 65535;
+
+/// Length of the authentication tag appended to each encrypted noise message.
+pub(crate) const NOISE_TAG_LEN: usize = 16;
+
+pub const KEY_LEN: usize = 32;
+
+/// Default duration for which links to a node that failed to complete the handshake
+/// are re-established in plaintext, if that's allowed at all.
+pub const DEFAULT_PLAINTEXT_FALLBACK_DURATION: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+pub enum LinkError {
+    IoError(io::Error),
+    NoiseError(snow::Error),
+    HandshakeTimeout,
+    UnknownRemoteKey,
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LinkError::IoError(err) => write!(f, "io error during link handshake - {}", err),
+            LinkError::NoiseError(err) => write!(f, "noise handshake failure - {}", err),
+            LinkError::HandshakeTimeout => write!(f, "link handshake has timed out"),
+            LinkError::UnknownRemoteKey => write!(
+                f,
+                "the static key of the remote does not belong to any node in the network"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+impl From<io::Error> for LinkError {
+    fn from(err: io::Error) -> Self {
+        LinkError::IoError(err)
+    }
+}
+
+impl From<snow::Error> for LinkError {
+    fn from(err: snow::Error) -> Self {
+        LinkError::NoiseError(err)
+    }
+}
+
+impl From<LinkError> for io::Error {
+    fn from(err: LinkError) -> Self {
+        match err {
+            LinkError::IoError(err) => err,
+            LinkError::NoiseError(_) => io::Error::new(io::ErrorKind::InvalidData, err),
+            LinkError::HandshakeTimeout => io::Error::new(io::ErrorKind::TimedOut, err),
+            LinkError::UnknownRemoteKey => io::Error::new(io::ErrorKind::PermissionDenied, err),
+        }
+    }
+}
+
+/// Shared mapping between addresses of nodes and their static public keys,
+/// used by both sides of the handshake to authenticate the remotes.
+#[derive(Clone, Default)]
+pub struct PeerKeys {
+    keys: Arc<RwLock<HashMap<SocketAddr, [u8; KEY_LEN]>>>,
+}
+
+impl PeerKeys {
+    /// Replaces all currently known keys with the new set.
+    pub fn update(&self, keys: HashMap<SocketAddr, [u8; KEY_LEN]>) {
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        *self.keys.write().unwrap() = keys;
+    }
+
+    pub fn get(&self, address: &SocketAddr) -> Option<[u8; KEY_LEN]> {
+        self.keys.read().unwrap().get(address).copied()
+    }
+
+    /// Checks whether the key belongs to any known node.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.keys
+            .read()
+            .unwrap()
+            .values()
+            .any(|known_key| known_key[..] == *key)
+    }
+}
+
+fn copy_key(key: &[u8]) -> [u8; KEY_LEN] {
+    let mut bytes = [0u8; KEY_LEN];
+    bytes.copy_from_slice(key);
+    bytes
+}
+
+#[derive(Clone)]
+pub struct InitiatorConfig {
+    local_private_key: [u8; KEY_LEN],
+    peer_keys: PeerKeys,
+    handshake_timeout: Duration,
+    allow_plaintext_fallback: bool,
+    plaintext_fallback_duration: Duration,
+}
+
+impl InitiatorConfig {
+    /// Panics if `local_private_key` is not exactly `KEY_LEN` bytes long.
+    pub fn new(local_private_key: &[u8], peer_keys: PeerKeys, handshake_timeout: Duration) -> Self {
+        InitiatorConfig {
+            local_private_key: copy_key(local_private_key),
+            peer_keys,
+            handshake_timeout,
+            allow_plaintext_fallback: false,
+            plaintext_fallback_duration: DEFAULT_PLAINTEXT_FALLBACK_DURATION,
+        }
+    }
+
+    /// Whether the link should be re-established in plaintext if the remote failed to complete
+    /// the handshake, for example because it's running an older version of the software.
+    /// Note that this lets anyone able to interfere with the handshake downgrade the link.
+    pub fn with_plaintext_fallback(mut self, allow_plaintext_fallback: bool) -> Self {
+        self.allow_plaintext_fallback = allow_plaintext_fallback;
+        self
+    }
+
+    /// For how long after a failed handshake the links are re-established in plaintext,
+    /// before encryption is attempted again.
+    pub fn with_plaintext_fallback_duration(mut self, duration: Duration) -> Self {
+        self.plaintext_fallback_duration = duration;
+        self
+    }
+
+    pub fn allows_plaintext_fallback(&self) -> bool {
+        self.allow_plaintext_fallback
+    }
+
+    pub fn plaintext_fallback_duration(&self) -> Duration {
+        self.plaintext_fallback_duration
+    }
+
+    /// Static public key of the node at the given address, if it's known.
+    pub fn remote_key(&self, address: &SocketAddr) -> Option<[u8; KEY_LEN]> {
+        self.peer_keys.get(address)
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponderConfig {
+    local_private_key: [u8; KEY_LEN],
+    peer_keys: PeerKeys,
+    handshake_timeout: Duration,
+}
+
+impl ResponderConfig {
+    /// Only initiators whose static keys are present in `peer_keys` are going to be accepted.
+    /// Panics if `local_private_key` is not exactly `KEY_LEN` bytes long.
+    pub fn new(local_private_key: &[u8], peer_keys: PeerKeys, handshake_timeout: Duration) -> Self {
+        ResponderConfig {
+            local_private_key: copy_key(local_private_key),
+            peer_keys,
+            handshake_timeout,
+        }
+    }
+}
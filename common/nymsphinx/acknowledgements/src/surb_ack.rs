@@ -15,15 +15,12 @@
 use crate::identifier::{prepare_identifier, AckAes128Key};
 use nymsphinx_addressing::clients::Recipient;
 use nymsphinx_addressing::nodes::{NymNodeRoutingAddress, MAX_NODE_ADDRESS_UNPADDED_LEN};
+use nymsphinx_params::delays::DelayParameters;
 use nymsphinx_params::packet_sizes::PacketSize;
 use nymsphinx_types::builder::SphinxPacketBuilder;
-use nymsphinx_types::{
-    delays::{self, Delay},
-    Destination, SphinxPacket,
-};
+use nymsphinx_types::{delays::Delay, Destination, Node as SphinxNode, SphinxPacket};
 use rand::{CryptoRng, RngCore};
 use std::convert::TryFrom;
-use std::time;
 use topology::{NymTopology, NymTopologyError};
 
 #[allow(non_snake_case)]
@@ -46,15 +43,41 @@ impl SURBAck {
         recipient: &Recipient,
         ack_key: &AckAes128Key,
         marshaled_fragment_id: [u8; 5],
-        average_delay: time::Duration,
+        route_length: Option<usize>,
+        delay_parameters: &DelayParameters,
         topology: &T,
     ) -> Result<Self, NymTopologyError>
     where
         R: RngCore + CryptoRng,
         T: NymTopology,
     {
-        let route = topology.random_route_to_gateway(&recipient.gateway())?;
-        let delays = delays::generate_from_average_duration(route.len(), average_delay);
+        let route =
+            topology.random_route_to_gateway_with_length(&recipient.gateway(), route_length)?;
+        Ok(Self::construct_with_route(
+            rng,
+            recipient,
+            ack_key,
+            marshaled_fragment_id,
+            delay_parameters,
+            &route,
+        ))
+    }
+
+    /// Constructs the SURB-ACK using an already generated route to the gateway of the `recipient`.
+    /// This allows the caller to know the length of the SURB-ACK before the fragment
+    /// it's going to be attached to even exists.
+    pub fn construct_with_route<R>(
+        rng: &mut R,
+        recipient: &Recipient,
+        ack_key: &AckAes128Key,
+        marshaled_fragment_id: [u8; 5],
+        delay_parameters: &DelayParameters,
+        route: &[SphinxNode],
+    ) -> Self
+    where
+        R: RngCore + CryptoRng,
+    {
+        let delays = delay_parameters.generate_delays(route.len());
         let destination = Destination::new(recipient.destination(), Default::default());
 
         let surb_ack_payload = prepare_identifier(rng, ack_key, marshaled_fragment_id);
@@ -62,58 +85,177 @@ impl SURBAck {
         // once merged, that's an easy rng injection point for sphinx packets : )
         let surb_ack_packet = SphinxPacketBuilder::new()
             .with_payload_size(PacketSize::ACKPacket.payload_size())
-            .build_packet(surb_ack_payload, &route, &destination, &delays)
+            .build_packet(surb_ack_payload, route, &destination, &delays)
             .unwrap();
 
         let expected_total_delay = delays.iter().sum();
         let first_hop_address =
             NymNodeRoutingAddress::try_from(route.first().unwrap().address.clone()).unwrap();
 
-        Ok(SURBAck {
+        SURBAck {
             surb_ack_packet,
             first_hop_address,
             expected_total_delay,
-        })
+        }
+    }
+
+    /// Length of the SURB-ACK, alongside its first hop address, if the first hop was
+    /// the provided node.
+    pub fn len_with_first_hop(first_hop_address: &NymNodeRoutingAddress) -> usize {
+        PacketSize::ACKPacket.size() + first_hop_address.bytes_min_len()
     }
 
-    pub fn len() -> usize {
-        // TODO: this will be variable once/if we decide to introduce optimization described
-        // in common/nymsphinx/chunking/src/lib.rs:available_plaintext_size()
+    /// Maximum length the SURB-ACK, alongside its first hop address, could possibly have.
+    pub fn max_len() -> usize {
         PacketSize::ACKPacket.size() + MAX_NODE_ADDRESS_UNPADDED_LEN
     }
 
     pub fn prepare_for_sending(self) -> (Delay, Vec<u8>) {
         // SURB_FIRST_HOP || SURB_ACK
+        // note that the first hop address is not padded - its length is implied by its version prefix
         let surb_bytes: Vec<_> = self
             .first_hop_address
-            .as_zero_padded_bytes(MAX_NODE_ADDRESS_UNPADDED_LEN)
+            .as_bytes()
             .into_iter()
             .chain(self.surb_ack_packet.to_bytes().into_iter())
             .collect();
         (self.expected_total_delay, surb_bytes)
     }
 
+    /// Number of bytes taken by the already parsed first hop address located at the beginning
+    /// of the provided bytes.
+    // Older clients always zero-padded the address to `MAX_NODE_ADDRESS_UNPADDED_LEN` bytes.
+    // Such padding can be told apart from the compact layout, as otherwise the address would
+    // have been immediately followed by the sphinx header, which starts with a curve point
+    // rather than with a run of zeroes.
+    fn encoded_first_hop_len(b: &[u8], first_hop_address: &NymNodeRoutingAddress) -> usize {
+        let unpadded_len = first_hop_address.bytes_min_len();
+        match b.get(unpadded_len..MAX_NODE_ADDRESS_UNPADDED_LEN) {
+            Some(padding) if !padding.is_empty() && padding.iter().all(|&byte| byte == 0) => {
+                MAX_NODE_ADDRESS_UNPADDED_LEN
+            }
+            _ => unpadded_len,
+        }
+    }
+
+    /// Determines length of the SURB-ACK, alongside its first hop address, that is located
+    /// at the beginning of the provided bytes.
+    pub fn try_get_len(b: &[u8]) -> Result<usize, SURBAckRecoveryError> {
+        match NymNodeRoutingAddress::try_from_bytes(b) {
+            Ok(address) => {
+                Ok(PacketSize::ACKPacket.size() + Self::encoded_first_hop_len(b, &address))
+            }
+            Err(_) => Err(SURBAckRecoveryError::InvalidAddress),
+        }
+    }
+
     // partial reciprocal of `prepare_for_sending` performed by the gateway
     pub fn try_recover_first_hop_packet(
         b: &[u8],
     ) -> Result<(NymNodeRoutingAddress, SphinxPacket), SURBAckRecoveryError> {
-        if b.len() != Self::len() {
-            Err(SURBAckRecoveryError::InvalidPacketSize)
-        } else {
-            let address = match NymNodeRoutingAddress::try_from_bytes(&b) {
-                Ok(address) => address,
-                Err(_) => return Err(SURBAckRecoveryError::InvalidAddress),
-            };
-
-            // TODO: this will be variable once/if we decide to introduce optimization described
-            // in common/nymsphinx/chunking/src/lib.rs:available_plaintext_size()
-            let address_offset = MAX_NODE_ADDRESS_UNPADDED_LEN;
-            let packet = match SphinxPacket::from_bytes(&b[address_offset..]) {
-                Ok(packet) => packet,
-                Err(_) => return Err(SURBAckRecoveryError::InvalidSphinxPacket),
-            };
-
-            Ok((address, packet))
+        let address = match NymNodeRoutingAddress::try_from_bytes(&b) {
+            Ok(address) => address,
+            Err(_) => return Err(SURBAckRecoveryError::InvalidAddress),
+        };
+
+        let address_offset = Self::encoded_first_hop_len(b, &address);
+        if b.len() != PacketSize::ACKPacket.size() + address_offset {
+            return Err(SURBAckRecoveryError::InvalidPacketSize);
         }
+
+        let packet = match SphinxPacket::from_bytes(&b[address_offset..]) {
+            Ok(packet) => packet,
+            Err(_) => return Err(SURBAckRecoveryError::InvalidSphinxPacket),
+        };
+
+        Ok((address, packet))
+    }
+}
+
+#[cfg(test)]
+mod surb_ack_encoding {
+    use super::*;
+    use crate::identifier::generate_key;
+    use nymsphinx_types::{DestinationAddressBytes, NodeAddressBytes, PublicKey};
+    use rand::rngs::OsRng;
+    use std::convert::TryInto;
+    use std::net::SocketAddr;
+
+    fn sphinx_node(address: SocketAddr) -> SphinxNode {
+        SphinxNode::new(
+            NymNodeRoutingAddress::from(address).try_into().unwrap(),
+            PublicKey::from([42; 32]),
+        )
+    }
+
+    fn surb_ack_with_first_hop(first_hop: SocketAddr) -> SURBAck {
+        let recipient = Recipient::new(
+            DestinationAddressBytes::from_bytes([1; 32]),
+            NodeAddressBytes::from_bytes([2; 32]),
+        );
+        let route = vec![
+            sphinx_node(first_hop),
+            sphinx_node("10.0.0.1:1789".parse().unwrap()),
+        ];
+
+        SURBAck::construct_with_route(
+            &mut OsRng,
+            &recipient,
+            &generate_key(&mut OsRng),
+            [1, 2, 3, 4, 5],
+            &DelayParameters::new(Default::default()),
+            &route,
+        )
+    }
+
+    fn assert_roundtrip(first_hop: SocketAddr) {
+        let surb_ack = surb_ack_with_first_hop(first_hop);
+        let packet_bytes = surb_ack.surb_ack_packet.to_bytes();
+
+        let (_, surb_ack_bytes) = surb_ack.prepare_for_sending();
+        assert_eq!(
+            SURBAck::len_with_first_hop(&first_hop.into()),
+            surb_ack_bytes.len()
+        );
+        assert_eq!(
+            SURBAck::try_get_len(&surb_ack_bytes).unwrap(),
+            surb_ack_bytes.len()
+        );
+
+        let (address, packet) = SURBAck::try_recover_first_hop_packet(&surb_ack_bytes).unwrap();
+        assert_eq!(NymNodeRoutingAddress::from(first_hop), address);
+        assert_eq!(packet_bytes, packet.to_bytes());
+    }
+
+    #[test]
+    fn is_recovered_with_ipv4_first_hop() {
+        assert_roundtrip("127.0.0.1:1789".parse().unwrap())
+    }
+
+    #[test]
+    fn is_recovered_with_ipv6_first_hop() {
+        assert_roundtrip("[2001:db8::1]:1789".parse().unwrap())
+    }
+
+    #[test]
+    fn is_recovered_with_legacy_padded_first_hop() {
+        let first_hop: SocketAddr = "127.0.0.1:1789".parse().unwrap();
+        let surb_ack = surb_ack_with_first_hop(first_hop);
+        let packet_bytes = surb_ack.surb_ack_packet.to_bytes();
+
+        let legacy_bytes: Vec<_> = NymNodeRoutingAddress::from(first_hop)
+            .as_zero_padded_bytes(MAX_NODE_ADDRESS_UNPADDED_LEN)
+            .into_iter()
+            .chain(packet_bytes.iter().cloned())
+            .collect();
+
+        assert_eq!(
+            SURBAck::try_get_len(&legacy_bytes).unwrap(),
+            SURBAck::max_len()
+        );
+
+        let (address, packet) = SURBAck::try_recover_first_hop_packet(&legacy_bytes).unwrap();
+        assert_eq!(NymNodeRoutingAddress::from(first_hop), address);
+        assert_eq!(packet_bytes, packet.to_bytes());
     }
 }
@@ -25,6 +25,10 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 /// In this case it's an ipv6 socket address (with version prefix)
 pub const MAX_NODE_ADDRESS_UNPADDED_LEN: usize = 19;
 
+/// MIN_UNPADDED_LEN represents minimum length an unpadded address could have.
+/// In this case it's an ipv4 socket address (with version prefix)
+pub const MIN_NODE_ADDRESS_UNPADDED_LEN: usize = 7;
+
 #[derive(Debug)]
 pub enum NymNodeRoutingAddressError {
     InsufficientNumberOfBytesAvailableError,
@@ -42,8 +46,8 @@ impl NymNodeRoutingAddress {
     /// padded with zeroes to be exactly NODE_ADDRESS_LENGTH long.
     pub fn bytes_min_len(&self) -> usize {
         match self.0 {
-            SocketAddr::V4(_) => 7,
-            SocketAddr::V6(_) => 19,
+            SocketAddr::V4(_) => MIN_NODE_ADDRESS_UNPADDED_LEN,
+            SocketAddr::V6(_) => MAX_NODE_ADDRESS_UNPADDED_LEN,
         }
     }
 
@@ -84,7 +88,7 @@ impl NymNodeRoutingAddress {
     /// Does not care if it's zero-padded or not.
     pub fn try_from_bytes(b: &[u8]) -> Result<Self, NymNodeRoutingAddressError> {
         // the bare minimum to represent `Self` is 7 bytes (for the shorter V4 version)
-        if b.len() < 7 {
+        if b.len() < MIN_NODE_ADDRESS_UNPADDED_LEN {
             return Err(NymNodeRoutingAddressError::InsufficientNumberOfBytesAvailableError);
         }
 
@@ -93,13 +97,13 @@ impl NymNodeRoutingAddress {
         let ip = match ip_version {
             4 => IpAddr::V4(Ipv4Addr::new(b[3], b[4], b[5], b[6])),
             6 => {
-                if b.len() < 19 {
+                if b.len() < MAX_NODE_ADDRESS_UNPADDED_LEN {
                     return Err(
                         NymNodeRoutingAddressError::InsufficientNumberOfBytesAvailableError,
                     );
                 }
                 let mut address_octets = [0u8; 16];
-                address_octets.copy_from_slice(&b[3..19]);
+                address_octets.copy_from_slice(&b[3..MAX_NODE_ADDRESS_UNPADDED_LEN]);
                 IpAddr::V6(Ipv6Addr::from(address_octets))
             }
             _ => return Err(NymNodeRoutingAddressError::InvalidIPVersion),
@@ -217,6 +221,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn nym_node_routing_address_unpadded_bytes_have_exactly_min_len() {
+        let address_v4 = NymNodeRoutingAddress(SocketAddr::new(IpAddr::from([1, 2, 3, 4]), 42));
+        let address_v6 = NymNodeRoutingAddress(SocketAddr::new(
+            IpAddr::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+            42,
+        ));
+
+        assert_eq!(address_v4.as_bytes().len(), address_v4.bytes_min_len());
+        assert_eq!(address_v4.bytes_min_len(), MIN_NODE_ADDRESS_UNPADDED_LEN);
+        assert_eq!(address_v6.as_bytes().len(), address_v6.bytes_min_len());
+        assert_eq!(address_v6.bytes_min_len(), MAX_NODE_ADDRESS_UNPADDED_LEN);
+    }
+
     #[test]
     fn nym_node_routing_address_can_be_converted_to_and_from_node_address_bytes_with_no_data_loss()
     {
@@ -171,6 +171,19 @@ impl Fragment {
             .collect()
     }
 
+    /// Number of bytes this `Fragment` is going to occupy after being marshaled
+    /// with `into_bytes`.
+    pub fn serialized_size(&self) -> usize {
+        let is_linked =
+            self.previous_fragments_set_id().is_some() || self.next_fragments_set_id().is_some();
+        let header_len = if is_linked {
+            LINKED_FRAGMENTED_HEADER_LEN
+        } else {
+            UNLINKED_FRAGMENTED_HEADER_LEN
+        };
+        header_len + self.payload.len()
+    }
+
     /// Derive identifier unique for this particular fragment
     pub fn fragment_identifier(&self) -> FragmentIdentifier {
         FragmentIdentifier {
@@ -14,20 +14,19 @@
 
 use crate::fragment::{
     linked_fragment_payload_max_len, unlinked_fragment_payload_max_len, Fragment,
-    FragmentIdentifier,
 };
 use crate::set::split_into_sets;
 use nymsphinx_acknowledgements::identifier::AckAes128Key;
 use nymsphinx_acknowledgements::surb_ack::SURBAck;
 use nymsphinx_addressing::clients::Recipient;
 use nymsphinx_addressing::nodes::{NymNodeRoutingAddress, MAX_NODE_ADDRESS_UNPADDED_LEN};
+use nymsphinx_params::delays::DelayParameters;
 use nymsphinx_params::packet_sizes::PacketSize;
 use nymsphinx_types::builder::SphinxPacketBuilder;
-use nymsphinx_types::{delays, Delay, Destination, SphinxPacket};
+use nymsphinx_types::{Delay, Destination, Node as SphinxNode, SphinxPacket};
 use rand::{rngs::OsRng, CryptoRng, Rng};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
-use std::time::Duration;
 use topology::{NymTopology, NymTopologyError};
 
 // Future consideration: currently in a lot of places, the payloads have randomised content
@@ -48,6 +47,10 @@ pub mod set;
 type DefaultRng = OsRng;
 const DEFAULT_RNG: DefaultRng = OsRng;
 
+/// Maximum number of attempts at finding a SURB-ACK route that would fit alongside
+/// an already created fragment.
+const MAX_ACK_ROUTE_ATTEMPTS: usize = 10;
+
 /// The idea behind the process of chunking is to incur as little data overhead as possible due
 /// to very computationally costly sphinx encapsulation procedure.
 ///
@@ -88,6 +91,12 @@ pub enum ChunkingError {
     MalformedFragmentIdentifier,
 }
 
+impl From<NymTopologyError> for ChunkingError {
+    fn from(_: NymTopologyError) -> Self {
+        ChunkingError::InvalidTopologyError
+    }
+}
+
 // Note: `Rng` implies `RngCore`
 #[derive(Debug, Clone)]
 pub struct MessageChunker<R: CryptoRng + Rng> {
@@ -96,23 +105,24 @@ pub struct MessageChunker<R: CryptoRng + Rng> {
     packet_size: PacketSize,
     reply_surbs: bool,
     should_pad: bool,
-    average_packet_delay_duration: Duration,
-    average_ack_delay_duration: Duration,
+    route_length: Option<usize>,
+    packet_delay_parameters: DelayParameters,
+    ack_delay_parameters: DelayParameters,
 }
 
 impl MessageChunker<DefaultRng> {
     pub fn new(
         ack_recipient: Recipient,
         should_pad: bool,
-        average_packet_delay_duration: Duration,
-        average_ack_delay_duration: Duration,
+        packet_delay_parameters: DelayParameters,
+        ack_delay_parameters: DelayParameters,
     ) -> Self {
         Self::new_with_rng(
             DEFAULT_RNG,
             ack_recipient,
             should_pad,
-            average_packet_delay_duration,
-            average_ack_delay_duration,
+            packet_delay_parameters,
+            ack_delay_parameters,
         )
     }
 
@@ -128,8 +138,8 @@ impl MessageChunker<DefaultRng> {
         Self::new(
             empty_recipient,
             false,
-            Default::default(),
-            Default::default(),
+            DelayParameters::new(Default::default()),
+            DelayParameters::new(Default::default()),
         )
     }
 }
@@ -139,8 +149,8 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
         rng: R,
         ack_recipient: Recipient,
         should_pad: bool,
-        average_packet_delay_duration: Duration,
-        average_ack_delay_duration: Duration,
+        packet_delay_parameters: DelayParameters,
+        ack_delay_parameters: DelayParameters,
     ) -> Self {
         MessageChunker {
             rng,
@@ -148,23 +158,23 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
             should_pad,
             packet_size: Default::default(),
             reply_surbs: false,
-            average_packet_delay_duration,
-            average_ack_delay_duration,
+            route_length: None,
+            packet_delay_parameters,
+            ack_delay_parameters,
         }
     }
 
+    /// Plaintext available per fragment assuming the worst case, i.e. that the SURB-ACK
+    /// first hop address is going to have `MAX_NODE_ADDRESS_UNPADDED_LEN`.
     pub fn available_plaintext_size(&self) -> usize {
-        // we need to put first hop's destination alongside the actual ack
-        // TODO: a possible optimization way down the line: currently we're always assuming that
-        // the addresses will have `MAX_NODE_ADDRESS_UNPADDED_LEN`, i.e. be ipv6. In most cases
-        // they're actually going to be ipv4 hence wasting few bytes every packet.
-        // To fully utilise all available space, I guess first we'd need to generate routes for ACKs
-        // and only then perform the chunking with `available_plaintext_size` being called per chunk.
-        // However this will probably introduce bunch of complexity
-        // for relatively not a lot of gain, so it shouldn't be done just yet.
-        let available_size = self.packet_size.plaintext_size()
-            - PacketSize::ACKPacket.size()
-            - MAX_NODE_ADDRESS_UNPADDED_LEN;
+        self.available_plaintext_size_with_ack_address_len(MAX_NODE_ADDRESS_UNPADDED_LEN)
+    }
+
+    /// Plaintext available per fragment if the SURB-ACK first hop address,
+    /// which we need to put alongside the actual ack, is going to have the specified length.
+    pub fn available_plaintext_size_with_ack_address_len(&self, address_len: usize) -> usize {
+        let available_size =
+            self.packet_size.plaintext_size() - PacketSize::ACKPacket.size() - address_len;
         if self.reply_surbs {
             // TODO
             unimplemented!();
@@ -182,8 +192,18 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
         self
     }
 
+    /// Sets the number of mix nodes both packets and their SURB-ACKs are going to go through.
+    /// If not set, a single node from every layer of the topology is used.
+    pub fn with_route_length(mut self, route_length: Option<usize>) -> Self {
+        self.route_length = route_length;
+        self
+    }
+
     /// Tries to convert this `Fragment` into a `SphinxPacket` that can be sent through the Nym mix-network,
     /// such that it contains required SURB-ACK.
+    /// The route for the SURB-ACK is chosen such that its first hop address fits alongside
+    /// the `Fragment`, which might not be the case if the `Fragment` was created assuming a shorter
+    /// address, for example when it's being retransmitted.
     /// This method can fail if the provided network topology is invalid.
     /// It returns total expected delay as well as the `SphinxPacket` to be sent through the network.
     pub fn prepare_chunk_for_sending<T: NymTopology>(
@@ -192,10 +212,43 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
         topology: &T,
         ack_key: &AckAes128Key,
         packet_recipient: &Recipient,
-    ) -> Result<(Delay, (SocketAddr, SphinxPacket)), NymTopologyError> {
-        let (ack_delay, surb_bytes) = self
-            .generate_surb_ack(&fragment.fragment_identifier(), topology, ack_key)?
-            .prepare_for_sending();
+    ) -> Result<(Delay, (SocketAddr, SphinxPacket)), ChunkingError> {
+        let max_address_len = self.packet_size.plaintext_size()
+            - PacketSize::ACKPacket.size()
+            - fragment.serialized_size();
+        let ack_route = self.generate_fitting_ack_route(topology, max_address_len)?;
+
+        self.prepare_chunk_for_sending_with_ack_route(
+            fragment,
+            &ack_route,
+            topology,
+            ack_key,
+            packet_recipient,
+        )
+    }
+
+    /// Tries to convert this `Fragment` into a `SphinxPacket` that can be sent through the Nym mix-network,
+    /// such that it contains required SURB-ACK that is going to be sent through the provided route.
+    /// The route should have been obtained alongside the `Fragment` via `split_message_with_ack_routes`.
+    /// This method can fail if the provided network topology is invalid.
+    /// It returns total expected delay as well as the `SphinxPacket` to be sent through the network.
+    pub fn prepare_chunk_for_sending_with_ack_route<T: NymTopology>(
+        &mut self,
+        fragment: Fragment,
+        ack_route: &[SphinxNode],
+        topology: &T,
+        ack_key: &AckAes128Key,
+        packet_recipient: &Recipient,
+    ) -> Result<(Delay, (SocketAddr, SphinxPacket)), ChunkingError> {
+        let (ack_delay, surb_bytes) = SURBAck::construct_with_route(
+            &mut self.rng,
+            &self.ack_recipient,
+            ack_key,
+            fragment.fragment_identifier().to_bytes(),
+            &self.ack_delay_parameters,
+            ack_route,
+        )
+        .prepare_for_sending();
 
         // SURB_FIRST_HOP || SURB_ACK || CHUNK_DATA
         let packet_payload: Vec<_> = surb_bytes
@@ -203,9 +256,13 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
             .chain(fragment.into_bytes().into_iter())
             .collect();
 
-        let route = topology.random_route_to_gateway(&packet_recipient.gateway())?;
-        let delays =
-            delays::generate_from_average_duration(route.len(), self.average_packet_delay_duration);
+        if packet_payload.len() > self.packet_size.plaintext_size() {
+            return Err(ChunkingError::InvalidPayloadLengthError);
+        }
+
+        let route = topology
+            .random_route_to_gateway_with_length(&packet_recipient.gateway(), self.route_length)?;
+        let delays = self.packet_delay_parameters.generate_delays(route.len());
         let destination = Destination::new(packet_recipient.destination(), Default::default());
 
         // once merged, that's an easy rng injection point for sphinx packets : )
@@ -223,23 +280,29 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
         ))
     }
 
-    fn generate_surb_ack<T>(
-        &mut self,
-        fragment_id: &FragmentIdentifier,
+    fn generate_ack_route<T: NymTopology>(
+        &self,
         topology: &T,
-        ack_key: &AckAes128Key,
-    ) -> Result<SURBAck, NymTopologyError>
-    where
-        T: NymTopology,
-    {
-        SURBAck::construct(
-            &mut self.rng,
-            &self.ack_recipient,
-            ack_key,
-            fragment_id.to_bytes(),
-            self.average_ack_delay_duration,
-            topology,
-        )
+    ) -> Result<Vec<SphinxNode>, NymTopologyError> {
+        topology
+            .random_route_to_gateway_with_length(&self.ack_recipient.gateway(), self.route_length)
+    }
+
+    /// Generates a random route for a SURB-ACK whose first hop address is not going to be longer
+    /// than `max_address_len` bytes. Note that this is only ever restrictive if the fragment was
+    /// created assuming ipv4 first hops and the topology contains ipv6 nodes in the first layer.
+    fn generate_fitting_ack_route<T: NymTopology>(
+        &self,
+        topology: &T,
+        max_address_len: usize,
+    ) -> Result<Vec<SphinxNode>, ChunkingError> {
+        for _ in 0..MAX_ACK_ROUTE_ATTEMPTS {
+            let route = self.generate_ack_route(topology)?;
+            if route_first_hop_len(&route)? <= max_address_len {
+                return Ok(route);
+            }
+        }
+        Err(ChunkingError::NoValidRoutesAvailableError)
     }
 
     /// Returns number of fragments the message will be split to as well as number of available
@@ -314,7 +377,17 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
     /// to obtain the original message back.
     pub fn split_message_to_constant_length_chunks(&mut self, message: &[u8]) -> Vec<Fragment> {
         let available_plaintext_per_fragment = self.available_plaintext_size();
+        self.split_message_to_constant_length_chunks_with_plaintext_size(
+            message,
+            available_plaintext_per_fragment,
+        )
+    }
 
+    fn split_message_to_constant_length_chunks_with_plaintext_size(
+        &mut self,
+        message: &[u8],
+        available_plaintext_per_fragment: usize,
+    ) -> Vec<Fragment> {
         // 1 is added as there will always have to be at least a single byte of padding (1) added
         // to be able to later remove the padding
         let (_, space_left) =
@@ -341,23 +414,219 @@ impl<R: CryptoRng + Rng> MessageChunker<R> {
     /// After receiving they can be combined using `reconstruction::MessageReconstructor`
     /// to obtain the original message back.
     pub fn split_message(&mut self, message: &[u8]) -> Vec<Fragment> {
+        let available_plaintext_per_fragment = self.available_plaintext_size();
+        self.split_message_with_plaintext_size(message, available_plaintext_per_fragment)
+    }
+
+    fn split_message_with_plaintext_size(
+        &mut self,
+        message: &[u8],
+        available_plaintext_per_fragment: usize,
+    ) -> Vec<Fragment> {
         if self.should_pad {
-            self.split_message_to_constant_length_chunks(message)
+            self.split_message_to_constant_length_chunks_with_plaintext_size(
+                message,
+                available_plaintext_per_fragment,
+            )
         } else {
-            let available_plaintext_per_fragment = self.available_plaintext_size();
-
             split_into_sets(&mut self.rng, &message, available_plaintext_per_fragment)
                 .into_iter()
                 .flat_map(|fragment_set| fragment_set.into_iter())
                 .collect()
         }
     }
+
+    /// Generates routes for SURB-ACKs and only then splits the message into fragments,
+    /// so that the space available in each of them reflects actual length of the SURB-ACK first hop
+    /// addresses rather than always assuming the worst case. Each returned `Fragment` should
+    /// be sent with `prepare_chunk_for_sending_with_ack_route` using its corresponding route.
+    ///
+    /// Note that because all fragments of a message must share the same maximum plaintext size
+    /// (see `set.rs` for the rationale), it is determined by the longest first hop address
+    /// out of all generated routes.
+    pub fn split_message_with_ack_routes<T: NymTopology>(
+        &mut self,
+        message: &[u8],
+        topology: &T,
+    ) -> Result<Vec<(Fragment, Vec<SphinxNode>)>, ChunkingError> {
+        // we don't know the number of fragments until we know their size, and we don't know their
+        // size until we know the routes. However, assuming the worst case gives us an upper bound
+        // on the number of required routes as fragments can only get bigger.
+        let worst_case_plaintext_size = self.available_plaintext_size();
+        let message_len = if self.should_pad {
+            message.len() + 1
+        } else {
+            message.len()
+        };
+        let (max_fragments, _) =
+            Self::number_of_required_fragments(message_len, worst_case_plaintext_size);
+
+        let mut ack_routes = Vec::with_capacity(max_fragments);
+        let mut longest_address_len = 0;
+        for _ in 0..max_fragments {
+            let route = self.generate_ack_route(topology)?;
+            longest_address_len = std::cmp::max(longest_address_len, route_first_hop_len(&route)?);
+            ack_routes.push(route);
+        }
+
+        let available_plaintext_per_fragment =
+            self.available_plaintext_size_with_ack_address_len(longest_address_len);
+        let fragments =
+            self.split_message_with_plaintext_size(message, available_plaintext_per_fragment);
+
+        // there can't be more fragments than what we have assumed for the worst case
+        debug_assert!(fragments.len() <= ack_routes.len());
+        Ok(fragments.into_iter().zip(ack_routes.into_iter()).collect())
+    }
+}
+
+/// Length of the encoded first hop address of the provided route.
+fn route_first_hop_len(route: &[SphinxNode]) -> Result<usize, ChunkingError> {
+    let first_hop = route
+        .first()
+        .ok_or(ChunkingError::NoValidRoutesAvailableError)?;
+    let first_hop_address = NymNodeRoutingAddress::try_from(first_hop.address.clone())
+        .map_err(|_| ChunkingError::InvalidTopologyError)?;
+    Ok(first_hop_address.bytes_min_len())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::set::{max_one_way_linked_set_payload_length, two_way_linked_set_payload_length};
+    use nymsphinx_addressing::nodes::MIN_NODE_ADDRESS_UNPADDED_LEN;
+    use nymsphinx_types::NodeAddressBytes;
+    use topology::{coco, gateway, mix, provider};
+
+    #[derive(Debug, Clone)]
+    struct TestTopology {
+        mix_nodes: Vec<mix::Node>,
+        gateways: Vec<gateway::Node>,
+    }
+
+    impl TestTopology {
+        // two mix layers, all of them listening on the provided ip address, followed by
+        // the gateway of `MessageChunker::test_fixture()` recipient
+        fn with_mixes_on(ip: &str) -> Self {
+            let mix_node = |layer: u64| mix::Node {
+                location: "".to_string(),
+                host: format!("{}:1789", ip).parse().unwrap(),
+                pub_key: NodeAddressBytes::from_bytes([layer as u8; 32]).to_base58_string(),
+                layer,
+                last_seen: 0,
+                version: "".to_string(),
+            };
+
+            let gateway = gateway::Node {
+                location: "".to_string(),
+                client_listener: "".to_string(),
+                mixnet_listener: "10.0.0.1:1789".parse().unwrap(),
+                identity_key: NodeAddressBytes::from_bytes([0; 32]).to_base58_string(),
+                sphinx_key: NodeAddressBytes::from_bytes([42; 32]).to_base58_string(),
+                registered_clients: Vec::new(),
+                last_seen: 0,
+                version: "".to_string(),
+            };
+
+            TestTopology {
+                mix_nodes: vec![mix_node(1), mix_node(2)],
+                gateways: vec![gateway],
+            }
+        }
+    }
+
+    impl NymTopology for TestTopology {
+        fn new_from_nodes(
+            mix_nodes: Vec<mix::Node>,
+            _: Vec<provider::Node>,
+            _: Vec<coco::Node>,
+            gateways: Vec<gateway::Node>,
+        ) -> Self {
+            TestTopology {
+                mix_nodes,
+                gateways,
+            }
+        }
+
+        fn mix_nodes(&self) -> Vec<mix::Node> {
+            self.mix_nodes.clone()
+        }
+
+        fn providers(&self) -> Vec<provider::Node> {
+            Vec::new()
+        }
+
+        fn gateways(&self) -> Vec<gateway::Node> {
+            self.gateways.clone()
+        }
+
+        fn coco_nodes(&self) -> Vec<coco::Node> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn splitting_message_with_ack_routes_uses_space_saved_on_ipv4_first_hops() {
+        let mut message_chunker = MessageChunker::test_fixture();
+        let topology = TestTopology::with_mixes_on("127.0.0.1");
+
+        // exactly fits into a single fragment only if the shorter address is taken into account
+        let available_plaintext = message_chunker
+            .available_plaintext_size_with_ack_address_len(MIN_NODE_ADDRESS_UNPADDED_LEN);
+        let message = vec![42u8; unlinked_fragment_payload_max_len(available_plaintext)];
+
+        let fragments_with_routes = message_chunker
+            .split_message_with_ack_routes(&message, &topology)
+            .unwrap();
+        assert_eq!(fragments_with_routes.len(), 1);
+
+        let (fragment, ack_route) = &fragments_with_routes[0];
+        assert_eq!(ack_route.len(), 3);
+        assert_eq!(
+            route_first_hop_len(ack_route).unwrap(),
+            MIN_NODE_ADDRESS_UNPADDED_LEN
+        );
+        assert!(fragment.serialized_size() <= available_plaintext);
+    }
+
+    #[test]
+    fn splitting_message_with_ack_routes_assumes_longest_first_hop_address() {
+        let mut message_chunker = MessageChunker::test_fixture();
+        let topology = TestTopology::with_mixes_on("[2001:db8::1]");
+
+        let available_plaintext = message_chunker
+            .available_plaintext_size_with_ack_address_len(MIN_NODE_ADDRESS_UNPADDED_LEN);
+        let message = vec![42u8; unlinked_fragment_payload_max_len(available_plaintext)];
+
+        let fragments_with_routes = message_chunker
+            .split_message_with_ack_routes(&message, &topology)
+            .unwrap();
+        assert_eq!(fragments_with_routes.len(), 2);
+
+        for (fragment, ack_route) in fragments_with_routes {
+            assert_eq!(
+                route_first_hop_len(&ack_route).unwrap(),
+                MAX_NODE_ADDRESS_UNPADDED_LEN
+            );
+            assert!(fragment.serialized_size() <= message_chunker.available_plaintext_size());
+        }
+    }
+
+    #[test]
+    fn shorter_ack_first_hop_address_leaves_more_space_for_plaintext() {
+        let message_chunker = MessageChunker::test_fixture();
+        assert_eq!(
+            message_chunker.available_plaintext_size(),
+            message_chunker
+                .available_plaintext_size_with_ack_address_len(MAX_NODE_ADDRESS_UNPADDED_LEN)
+        );
+        assert_eq!(
+            message_chunker
+                .available_plaintext_size_with_ack_address_len(MIN_NODE_ADDRESS_UNPADDED_LEN)
+                - message_chunker.available_plaintext_size(),
+            MAX_NODE_ADDRESS_UNPADDED_LEN - MIN_NODE_ADDRESS_UNPADDED_LEN
+        );
+    }
 
     #[test]
     fn calculating_number_of_required_fragments() {
@@ -17,13 +17,13 @@ use nymsphinx_acknowledgements::AckAes128Key;
 use nymsphinx_addressing::clients::Recipient;
 use nymsphinx_addressing::nodes::{NymNodeRoutingAddress, NymNodeRoutingAddressError};
 use nymsphinx_chunking::fragment::COVER_FRAG_ID;
+use nymsphinx_params::delays::DelayParameters;
 use nymsphinx_params::packet_sizes::PacketSize;
 use nymsphinx_types::builder::SphinxPacketBuilder;
-use nymsphinx_types::{delays, Destination, Error as SphinxError, SphinxPacket};
+use nymsphinx_types::{Destination, Error as SphinxError, SphinxPacket};
 use rand::{CryptoRng, RngCore};
 use std::convert::TryFrom;
 use std::net::SocketAddr;
-use std::time;
 use topology::{NymTopology, NymTopologyError};
 
 pub const LOOP_COVER_MESSAGE_PAYLOAD: &[u8] = b"The cake is a lie!";
@@ -60,7 +60,8 @@ pub fn generate_loop_cover_surb_ack<R, T>(
     topology: &T,
     ack_key: &AckAes128Key,
     full_address: &Recipient,
-    average_ack_delay: time::Duration,
+    route_length: Option<usize>,
+    ack_delay_parameters: &DelayParameters,
 ) -> Result<SURBAck, CoverMessageError>
 where
     R: RngCore + CryptoRng,
@@ -71,7 +72,8 @@ where
         full_address,
         ack_key,
         COVER_FRAG_ID.to_bytes(),
-        average_ack_delay,
+        route_length,
+        ack_delay_parameters,
         topology,
     )?)
 }
@@ -81,17 +83,24 @@ pub fn generate_loop_cover_packet<R, T>(
     topology: &T,
     ack_key: &AckAes128Key,
     full_address: &Recipient,
-    average_ack_delay: time::Duration,
-    average_packet_delay: time::Duration,
+    route_length: Option<usize>,
+    ack_delay_parameters: &DelayParameters,
+    packet_delay_parameters: &DelayParameters,
 ) -> Result<(SocketAddr, SphinxPacket), CoverMessageError>
 where
     R: RngCore + CryptoRng,
     T: NymTopology,
 {
     // we don't care about total ack delay - we will not be retransmitting it anyway
-    let (_, ack_bytes) =
-        generate_loop_cover_surb_ack(rng, topology, ack_key, full_address, average_ack_delay)?
-            .prepare_for_sending();
+    let (_, ack_bytes) = generate_loop_cover_surb_ack(
+        rng,
+        topology,
+        ack_key,
+        full_address,
+        route_length,
+        ack_delay_parameters,
+    )?
+    .prepare_for_sending();
 
     let plaintext_size = PacketSize::default().plaintext_size();
 
@@ -105,8 +114,9 @@ where
         .take(plaintext_size)
         .collect();
 
-    let route = topology.random_route_to_gateway(&full_address.gateway())?;
-    let delays = delays::generate_from_average_duration(route.len(), average_packet_delay);
+    let route =
+        topology.random_route_to_gateway_with_length(&full_address.gateway(), route_length)?;
+    let delays = packet_delay_parameters.generate_delays(route.len());
     // in our design we don't care about SURB_ID
     let destination = Destination::new(full_address.destination(), Default::default());
 
@@ -19,10 +19,22 @@ use std::convert::TryFrom;
 use std::io;
 use tokio_util::codec::{Decoder, Encoder};
 
+/// Most significant bit of the first byte of a frame indicates whether it uses the versioned
+/// header. Legacy frames start with a `PacketSize` discriminant, which never has this bit set.
+const VERSIONED_FRAME_MARKER: u8 = 0b1000_0000;
+
+/// Length of the header of a legacy frame, i.e. just the `PacketSize` discriminant.
+const LEGACY_HEADER_LEN: usize = 1;
+
+/// Length of the header of a V1 frame: version || packet type || flags || packet size
+const V1_HEADER_LEN: usize = 4;
+
 #[derive(Debug)]
 pub enum SphinxCodecError {
     InvalidPacketSize,
     MalformedSphinxPacket,
+    UnsupportedFrameVersion(u8),
+    UnknownPacketType(u8),
     IoError(io::Error),
 }
 
@@ -41,6 +53,14 @@ impl Into<io::Error> for SphinxCodecError {
             SphinxCodecError::MalformedSphinxPacket => {
                 io::Error::new(io::ErrorKind::InvalidData, "malformed packet")
             }
+            SphinxCodecError::UnsupportedFrameVersion(version) => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported frame version {}", version),
+            ),
+            SphinxCodecError::UnknownPacketType(packet_type) => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown packet type {}", packet_type),
+            ),
             SphinxCodecError::IoError(err) => err,
         }
     }
@@ -52,9 +72,165 @@ impl From<InvalidPacketSize> for SphinxCodecError {
     }
 }
 
-// The SphinxCodec is an extremely simple one, u8 representing one of valid packet
-// lengths followed by the actual framed packet
-pub struct SphinxCodec;
+/// Version of the frame header put in front of each packet sent between nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameVersion {
+    /// Original framing consisting of a single byte representing one of valid packet lengths.
+    Legacy,
+
+    /// Framing including protocol version, packet type and flags.
+    V1,
+}
+
+impl FrameVersion {
+    fn header_len(self) -> usize {
+        match self {
+            FrameVersion::Legacy => LEGACY_HEADER_LEN,
+            FrameVersion::V1 => V1_HEADER_LEN,
+        }
+    }
+}
+
+/// Kind of the packet carried by the frame.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PacketType {
+    Sphinx = 0,
+}
+
+impl TryFrom<u8> for PacketType {
+    type Error = SphinxCodecError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            _ if value == (PacketType::Sphinx as u8) => Ok(Self::Sphinx),
+            _ => Err(SphinxCodecError::UnknownPacketType(value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Header {
+    version: FrameVersion,
+    packet_type: PacketType,
+    flags: u8,
+    packet_size: PacketSize,
+}
+
+impl Header {
+    fn new(version: FrameVersion, packet_size: PacketSize) -> Self {
+        Header {
+            version,
+            packet_type: PacketType::Sphinx,
+            flags: 0,
+            packet_size,
+        }
+    }
+
+    pub fn version(&self) -> FrameVersion {
+        self.version
+    }
+
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn packet_size(&self) -> PacketSize {
+        self.packet_size
+    }
+
+    /// Total length of the frame, including the header itself.
+    fn frame_len(&self) -> usize {
+        self.version.header_len() + self.packet_size.size()
+    }
+
+    fn encode(&self, dst: &mut BytesMut) {
+        match self.version {
+            FrameVersion::Legacy => dst.put_u8(self.packet_size as u8),
+            FrameVersion::V1 => {
+                dst.put_u8(VERSIONED_FRAME_MARKER | 1);
+                dst.put_u8(self.packet_type as u8);
+                dst.put_u8(self.flags);
+                dst.put_u8(self.packet_size as u8);
+            }
+        }
+    }
+
+    /// Tries to parse the header located at the beginning of the provided bytes. Returns `None`
+    /// if there are not enough bytes available to do so.
+    fn decode(src: &[u8]) -> Result<Option<Self>, SphinxCodecError> {
+        if src.is_empty() {
+            return Ok(None);
+        }
+
+        if src[0] & VERSIONED_FRAME_MARKER == 0 {
+            // legacy frames have no notion of version, they start with the packet size directly
+            let packet_size = PacketSize::try_from(src[0])?;
+            return Ok(Some(Header::new(FrameVersion::Legacy, packet_size)));
+        }
+
+        let version = match src[0] & !VERSIONED_FRAME_MARKER {
+            1 => FrameVersion::V1,
+            n => return Err(SphinxCodecError::UnsupportedFrameVersion(n)),
+        };
+
+        if src.len() < version.header_len() {
+            return Ok(None);
+        }
+
+        Ok(Some(Header {
+            version,
+            packet_type: PacketType::try_from(src[1])?,
+            flags: src[2],
+            packet_size: PacketSize::try_from(src[3])?,
+        }))
+    }
+}
+
+/// Received `SphinxPacket` alongside the header of the frame it was sent in.
+pub struct FramedSphinxPacket {
+    header: Header,
+    packet: SphinxPacket,
+}
+
+impl FramedSphinxPacket {
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn packet(&self) -> &SphinxPacket {
+        &self.packet
+    }
+
+    pub fn into_inner(self) -> SphinxPacket {
+        self.packet
+    }
+}
+
+// The SphinxCodec puts a header in front of the actual framed packet. The decoder accepts both
+// the legacy header, consisting of just u8 representing one of valid packet lengths,
+// and the versioned one, while the encoder uses the version the codec was created with.
+pub struct SphinxCodec {
+    version: FrameVersion,
+}
+
+impl SphinxCodec {
+    pub fn new(version: FrameVersion) -> Self {
+        SphinxCodec { version }
+    }
+}
+
+impl Default for SphinxCodec {
+    // the legacy framing is understood by every node in the network, the versioned one
+    // has to be explicitly opted into
+    fn default() -> Self {
+        SphinxCodec::new(FrameVersion::Legacy)
+    }
+}
 
 impl Encoder<SphinxPacket> for SphinxCodec {
     type Error = SphinxCodecError;
@@ -63,76 +239,103 @@ impl Encoder<SphinxPacket> for SphinxCodec {
         let packet_bytes = item.to_bytes();
         let packet_length = packet_bytes.len();
         let packet_size = PacketSize::get_type(packet_length)?;
-        dst.reserve(1 + packet_size.size());
-        dst.put_u8(packet_size as u8);
+        let header = Header::new(self.version, packet_size);
+        dst.reserve(header.frame_len());
+        header.encode(dst);
         dst.put(packet_bytes.as_ref());
         Ok(())
     }
 }
 
 impl Decoder for SphinxCodec {
-    type Item = SphinxPacket;
+    type Item = FramedSphinxPacket;
     type Error = SphinxCodecError;
 
     //https://docs.rs/tokio-util/0.3.1/tokio_util/codec/trait.Decoder.html
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.is_empty() {
-            // can't do anything if we have no bytes
-            return Ok(None);
-        }
-        // we at least have a single byte in the buffer, so we can read the expected
-        // length of the sphinx packet
-        let packet_len_flag = src[0];
-        let packet_len = PacketSize::try_from(packet_len_flag)?;
+        let header = match Header::decode(src)? {
+            Some(header) => header,
+            // can't do anything if we don't have enough bytes to read the header
+            None => return Ok(None),
+        };
 
-        let frame_len = packet_len.size() + 1; // one is due to the flag taking the space
+        let frame_len = header.frame_len();
         if src.len() < frame_len {
             // we don't have enough bytes to read the entire frame
             src.reserve(frame_len);
             return Ok(None);
         }
-        // we advance the buffer beyond the flag
-        src.advance(1);
-        let sphinx_packet_bytes = src.split_to(packet_len.size());
+        // we advance the buffer beyond the header
+        src.advance(header.version.header_len());
+        let sphinx_packet_bytes = src.split_to(header.packet_size.size());
         let sphinx_packet = match SphinxPacket::from_bytes(&sphinx_packet_bytes) {
             Ok(sphinx_packet) => sphinx_packet,
             // here it could be debatable whether stream is corrupt or not,
             // but let's go with the safer approach and assume it is.
             Err(_) => return Err(SphinxCodecError::MalformedSphinxPacket),
         };
+        let framed_packet = FramedSphinxPacket {
+            header,
+            packet: sphinx_packet,
+        };
 
         // As per docs:
         // Before returning from the function, implementations should ensure that the buffer
         // has appropriate capacity in anticipation of future calls to decode.
         // Failing to do so leads to inefficiency.
 
-        // if we have at least one more byte available, we can reserve enough bytes for
-        // the entire next frame
-        if !src.is_empty() {
-            let next_packet_len = match PacketSize::try_from(src[0]) {
-                Ok(next_packet_len) => next_packet_len,
-                // the next frame will be malformed but let's leave handling the error to the next
-                // call to 'decode', as presumably, the current sphinx packet is still valid
-                Err(_) => return Ok(Some(sphinx_packet)),
-            };
-            let next_frame_len = next_packet_len.size() + 1;
-            src.reserve(next_frame_len);
+        // if we have enough bytes available to read the next header, we can reserve enough bytes
+        // for the entire next frame
+        // (the next frame might be malformed but let's leave handling the error to the next
+        // call to 'decode', as presumably, the current sphinx packet is still valid)
+        if let Ok(Some(next_header)) = Header::decode(src) {
+            src.reserve(next_header.frame_len());
         }
 
-        Ok(Some(sphinx_packet))
+        Ok(Some(framed_packet))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nymsphinx_params::delays::DelayParameters;
+    use nymsphinx_types::builder::SphinxPacketBuilder;
+    use nymsphinx_types::{
+        Destination, DestinationAddressBytes, Node, NodeAddressBytes, PublicKey,
+    };
+
+    fn sphinx_packet(packet_size: PacketSize) -> SphinxPacket {
+        let route = [Node::new(
+            NodeAddressBytes::from_bytes([1; 32]),
+            PublicKey::from([42; 32]),
+        )];
+        let destination = Destination::new(
+            DestinationAddressBytes::from_bytes([2; 32]),
+            Default::default(),
+        );
+        let delays = DelayParameters::new(Default::default()).generate_delays(route.len());
+
+        SphinxPacketBuilder::new()
+            .with_payload_size(packet_size.payload_size())
+            .build_packet(b"foomp".to_vec(), &route, &destination, &delays)
+            .unwrap()
+    }
+
+    fn assert_framed_packet(
+        framed_packet: FramedSphinxPacket,
+        version: FrameVersion,
+        packet_bytes: &[u8],
+    ) {
+        assert_eq!(framed_packet.header().version(), version);
+        assert_eq!(framed_packet.header().packet_type(), PacketType::Sphinx);
+        assert_eq!(framed_packet.packet().to_bytes(), packet_bytes);
+    }
 
-    #[cfg(test)]
-    #[allow(dead_code)]
     fn consume(
         codec: &mut SphinxCodec,
         bytes: &mut BytesMut,
-    ) -> Vec<Result<Option<SphinxPacket>, SphinxCodecError>> {
+    ) -> Vec<Result<Option<FramedSphinxPacket>, SphinxCodecError>> {
         let mut result = Vec::new();
         loop {
             match codec.decode(bytes) {
@@ -144,4 +347,102 @@ mod tests {
         }
         return result;
     }
+
+    #[test]
+    fn legacy_header_is_decoded_from_single_byte() {
+        let header = Header::decode(&[PacketSize::ACKPacket as u8])
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.version(), FrameVersion::Legacy);
+        assert_eq!(header.packet_type(), PacketType::Sphinx);
+        assert_eq!(header.frame_len(), 1 + PacketSize::ACKPacket.size());
+    }
+
+    #[test]
+    fn v1_header_can_be_encoded_and_decoded() {
+        let header = Header::new(FrameVersion::V1, PacketSize::RegularPacket);
+        let mut bytes = BytesMut::new();
+        header.encode(&mut bytes);
+        assert_eq!(bytes.len(), V1_HEADER_LEN);
+        assert_eq!(header, Header::decode(&bytes).unwrap().unwrap());
+    }
+
+    #[test]
+    fn v1_header_is_not_decoded_until_fully_available() {
+        let header = Header::new(FrameVersion::V1, PacketSize::RegularPacket);
+        let mut bytes = BytesMut::new();
+        header.encode(&mut bytes);
+        assert!(Header::decode(&bytes[..V1_HEADER_LEN - 1])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn unknown_frame_version_is_rejected() {
+        match Header::decode(&[VERSIONED_FRAME_MARKER | 42, 0, 0, 1]) {
+            Err(SphinxCodecError::UnsupportedFrameVersion(42)) => (),
+            _ => panic!("expected unsupported frame version error"),
+        }
+    }
+
+    #[test]
+    fn unknown_packet_type_is_rejected() {
+        match Header::decode(&[VERSIONED_FRAME_MARKER | 1, 42, 0, 1]) {
+            Err(SphinxCodecError::UnknownPacketType(42)) => (),
+            _ => panic!("expected unknown packet type error"),
+        }
+    }
+
+    #[test]
+    fn sphinx_packet_survives_roundtrip_in_both_frame_versions() {
+        for &version in &[FrameVersion::Legacy, FrameVersion::V1] {
+            let packet = sphinx_packet(PacketSize::ACKPacket);
+            let packet_bytes = packet.to_bytes();
+
+            let mut codec = SphinxCodec::new(version);
+            let mut bytes = BytesMut::new();
+            codec.encode(packet, &mut bytes).unwrap();
+            assert_eq!(
+                bytes.len(),
+                version.header_len() + PacketSize::ACKPacket.size()
+            );
+
+            let framed_packet = codec.decode(&mut bytes).unwrap().unwrap();
+            assert_eq!(framed_packet.header().packet_size(), PacketSize::ACKPacket);
+            assert_framed_packet(framed_packet, version, &packet_bytes);
+            assert!(bytes.is_empty());
+        }
+    }
+
+    #[test]
+    fn stream_of_mixed_frame_versions_is_decoded() {
+        let versions = [FrameVersion::Legacy, FrameVersion::V1, FrameVersion::Legacy];
+        let packet_sizes = [
+            PacketSize::ACKPacket,
+            PacketSize::RegularPacket,
+            PacketSize::RegularPacket,
+        ];
+
+        let mut bytes = BytesMut::new();
+        let mut packets_bytes = Vec::new();
+        for (&version, &packet_size) in versions.iter().zip(packet_sizes.iter()) {
+            let packet = sphinx_packet(packet_size);
+            packets_bytes.push(packet.to_bytes());
+            SphinxCodec::new(version)
+                .encode(packet, &mut bytes)
+                .unwrap();
+        }
+
+        // the decoder accepts either version regardless of the one it encodes with
+        let decoded = consume(&mut SphinxCodec::new(FrameVersion::V1), &mut bytes);
+        assert_eq!(decoded.len(), versions.len());
+        for ((framed_packet, &version), packet_bytes) in decoded
+            .into_iter()
+            .zip(versions.iter())
+            .zip(packets_bytes.iter())
+        {
+            assert_framed_packet(framed_packet.unwrap().unwrap(), version, packet_bytes);
+        }
+        assert!(bytes.is_empty());
+    }
 }
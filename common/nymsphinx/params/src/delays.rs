@@ -0,0 +1,133 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nymsphinx_types::delays::{self, Delay};
+use std::time::Duration;
+
+/// Parameters determining how long, on average, a packet is going to be delayed at each hop
+/// of its route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DelayParameters {
+    /// Average delay used for any hop without an explicitly specified value.
+    average_delay: Duration,
+
+    /// Average delays for the consecutive hops of the route, taking precedence over `average_delay`.
+    per_hop_average_delays: Vec<Duration>,
+
+    /// If specified, the average delays are proportionally scaled down so that
+    /// the total expected delay of the route would not exceed this value.
+    max_total_expected_delay: Option<Duration>,
+}
+
+impl DelayParameters {
+    pub fn new(average_delay: Duration) -> Self {
+        DelayParameters {
+            average_delay,
+            per_hop_average_delays: Vec::new(),
+            max_total_expected_delay: None,
+        }
+    }
+
+    pub fn with_per_hop_average_delays(mut self, per_hop_average_delays: Vec<Duration>) -> Self {
+        self.per_hop_average_delays = per_hop_average_delays;
+        self
+    }
+
+    pub fn with_max_total_expected_delay(mut self, max_total_expected_delay: Duration) -> Self {
+        self.max_total_expected_delay = Some(max_total_expected_delay);
+        self
+    }
+
+    /// Average delays for each hop of a route consisting of `hops` nodes.
+    pub fn average_delays(&self, hops: usize) -> Vec<Duration> {
+        let average_delays: Vec<_> = (0..hops)
+            .map(|hop| {
+                self.per_hop_average_delays
+                    .get(hop)
+                    .cloned()
+                    .unwrap_or(self.average_delay)
+            })
+            .collect();
+
+        let total_expected_delay: Duration = average_delays.iter().sum();
+        match self.max_total_expected_delay {
+            Some(max_total) if total_expected_delay > max_total => {
+                let scale = max_total.as_secs_f64() / total_expected_delay.as_secs_f64();
+                average_delays
+                    .into_iter()
+                    .map(|delay| delay.mul_f64(scale))
+                    .collect()
+            }
+            _ => average_delays,
+        }
+    }
+
+    /// Generates actual delays for each hop of a route consisting of `hops` nodes.
+    pub fn generate_delays(&self, hops: usize) -> Vec<Delay> {
+        generate_from_average_durations(&self.average_delays(hops))
+    }
+}
+
+/// Generates delays for consecutive hops, where each of them is sampled from the distribution
+/// with the corresponding average duration.
+pub fn generate_from_average_durations(average_delays: &[Duration]) -> Vec<Delay> {
+    average_delays
+        .iter()
+        .flat_map(|&average_delay| delays::generate_from_average_duration(1, average_delay))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_hop_delays_take_precedence_over_the_default() {
+        let params =
+            DelayParameters::new(Duration::from_millis(100)).with_per_hop_average_delays(vec![
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+            ]);
+
+        assert_eq!(
+            params.average_delays(3),
+            vec![
+                Duration::from_millis(10),
+                Duration::from_millis(20),
+                Duration::from_millis(100)
+            ]
+        );
+        assert_eq!(params.average_delays(1), vec![Duration::from_millis(10)]);
+    }
+
+    #[test]
+    fn average_delays_are_scaled_down_to_the_cap() {
+        let params = DelayParameters::new(Duration::from_millis(100))
+            .with_max_total_expected_delay(Duration::from_millis(200));
+
+        let average_delays = params.average_delays(4);
+        assert_eq!(average_delays, vec![Duration::from_millis(50); 4]);
+    }
+
+    #[test]
+    fn average_delays_are_not_affected_by_unreached_cap() {
+        let params = DelayParameters::new(Duration::from_millis(100))
+            .with_max_total_expected_delay(Duration::from_millis(1000));
+
+        assert_eq!(
+            params.average_delays(3),
+            vec![Duration::from_millis(100); 3]
+        );
+    }
+}
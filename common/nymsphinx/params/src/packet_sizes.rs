@@ -24,7 +24,7 @@ const EXTENDED_PACKET_SIZE: usize = HEADER_SIZE + PAYLOAD_OVERHEAD_SIZE + 32 * 1
 pub struct InvalidPacketSize;
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PacketSize {
     RegularPacket = 1,  // for example instant messaging use case
     ACKPacket = 2,      // for sending SURB-ACKs
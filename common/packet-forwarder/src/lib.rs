@@ -0,0 +1,324 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::queue::PacketQueue;
+use crate::stats::PeerCounters;
+use futures::channel::mpsc;
+use futures::future::{abortable, AbortHandle};
+use futures::StreamExt;
+use link_encryption::InitiatorConfig;
+use log::*;
+use nymsphinx::framing::FrameVersion;
+use nymsphinx::SphinxPacket;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+mod peer_keys;
+mod queue;
+mod stats;
+
+pub use peer_keys::{KnownPeers, PeerKeysRefresher};
+pub use stats::{ForwardingStats, PeerForwardingStats};
+
+// how long the peer worker waits before trying to send another packet after a failure,
+// so that the packets accumulate in our bounded queue rather than in the connection buffer
+const SEND_FAILURE_BACKOFF: Duration = Duration::from_millis(500);
+
+const DEFAULT_IDLE_PEER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+pub type OutboundPacketSender = mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>;
+pub type OutboundPacketReceiver = mpsc::UnboundedReceiver<(SocketAddr, SphinxPacket)>;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum DropPolicy {
+    /// Once the queue of a destination is full, any new packets to it are dropped.
+    DropNewest,
+
+    /// Once the queue of a destination is full, the oldest packet in it is dropped
+    /// to make space for the new one.
+    DropOldest,
+}
+
+impl Default for DropPolicy {
+    fn default() -> Self {
+        DropPolicy::DropNewest
+    }
+}
+
+pub struct Config {
+    initial_reconnection_backoff: Duration,
+    maximum_reconnection_backoff: Duration,
+    initial_connection_timeout: Duration,
+    maximum_queue_length: usize,
+    drop_policy: DropPolicy,
+    link_encryption: Option<InitiatorConfig>,
+    frame_version: FrameVersion,
+    idle_peer_timeout: Duration,
+    known_peers: Option<KnownPeers>,
+}
+
+impl Config {
+    pub fn new(
+        initial_reconnection_backoff: Duration,
+        maximum_reconnection_backoff: Duration,
+        initial_connection_timeout: Duration,
+        maximum_queue_length: usize,
+        drop_policy: DropPolicy,
+    ) -> Self {
+        Config {
+            initial_reconnection_backoff,
+            maximum_reconnection_backoff,
+            initial_connection_timeout,
+            maximum_queue_length,
+            drop_policy,
+            link_encryption: None,
+            frame_version: FrameVersion::Legacy,
+            idle_peer_timeout: DEFAULT_IDLE_PEER_TIMEOUT,
+            known_peers: None,
+        }
+    }
+
+    /// Attempts to encrypt and authenticate links to all destinations with known static keys.
+    pub fn with_link_encryption(mut self, link_encryption: InitiatorConfig) -> Self {
+        self.link_encryption = Some(link_encryption);
+        self
+    }
+
+    /// Frames all forwarded packets with the specified version of the header.
+    pub fn with_frame_version(mut self, frame_version: FrameVersion) -> Self {
+        self.frame_version = frame_version;
+        self
+    }
+
+    /// Stops the worker, and closes the connection, of any destination that had no packets
+    /// to forward for the specified duration.
+    pub fn with_idle_peer_timeout(mut self, idle_peer_timeout: Duration) -> Self {
+        self.idle_peer_timeout = idle_peer_timeout;
+        self
+    }
+
+    /// Only forwards packets to the destinations present in the network topology,
+    /// any other packets are dropped.
+    pub fn with_known_peers(mut self, known_peers: KnownPeers) -> Self {
+        self.known_peers = Some(known_peers);
+        self
+    }
+
+    fn mixnet_client_config(&self) -> mixnet_client::Config {
+        let config = mixnet_client::Config::new(
+            self.initial_reconnection_backoff,
+            self.maximum_reconnection_backoff,
+            self.initial_connection_timeout,
+        )
+        .with_frame_version(self.frame_version);
+        match &self.link_encryption {
+            Some(link_encryption) => config.with_link_encryption(link_encryption.clone()),
+            None => config,
+        }
+    }
+}
+
+// keeps sending packets from the queue to the single destination, one at a time
+async fn run_peer_worker(
+    address: SocketAddr,
+    mut mixnet_client: mixnet_client::Client,
+    queue: Arc<PacketQueue<SphinxPacket>>,
+    counters: Arc<PeerCounters>,
+) {
+    loop {
+        let packet = queue.pop().await;
+        trace!("Going to forward packet to {:?}", address);
+        // waiting for the response makes sure the packet was actually written to the socket
+        // before we take the next one from our queue
+        match mixnet_client.send(address, packet, true).await {
+            Ok(_) => counters.increment_forwarded(),
+            Err(err) => {
+                debug!("Failed to forward packet to {} - {:?}", address, err);
+                counters.increment_failed();
+                tokio::time::delay_for(SEND_FAILURE_BACKOFF).await;
+            }
+        }
+    }
+}
+
+// everything needed for pushing packets to the worker of particular destination
+struct PeerWorker {
+    queue: Arc<PacketQueue<SphinxPacket>>,
+    counters: Arc<PeerCounters>,
+    abort_handle: AbortHandle,
+    last_used: Instant,
+}
+
+impl PeerWorker {
+    fn is_idle(&self, now: Instant, idle_timeout: Duration) -> bool {
+        self.queue.len() == 0 && now.duration_since(self.last_used) >= idle_timeout
+    }
+}
+
+/// Forwards sphinx packets to other nodes in the network. Each destination has its own bounded
+/// queue served by a dedicated task, so that a slow or unreachable node does not affect
+/// forwarding packets to any other one.
+pub struct PacketForwarder {
+    config: Config,
+    peers: HashMap<SocketAddr, PeerWorker>,
+    stats: ForwardingStats,
+    packet_tx: OutboundPacketSender,
+    packet_rx: OutboundPacketReceiver,
+}
+
+impl PacketForwarder {
+    pub fn new(config: Config) -> Self {
+        let (packet_tx, packet_rx) = mpsc::unbounded();
+
+        PacketForwarder {
+            config,
+            peers: HashMap::new(),
+            stats: Default::default(),
+            packet_tx,
+            packet_rx,
+        }
+    }
+
+    /// Returns handle to the statistics of all destinations of this forwarder.
+    pub fn stats(&self) -> ForwardingStats {
+        self.stats.clone()
+    }
+
+    // must be called within the context of a tokio runtime
+    fn start_peer_worker(
+        config: &Config,
+        stats: &ForwardingStats,
+        address: SocketAddr,
+    ) -> PeerWorker {
+        debug!("Starting packet forwarding worker for {}", address);
+        let queue = Arc::new(PacketQueue::new(
+            config.maximum_queue_length,
+            config.drop_policy,
+        ));
+        let counters = Arc::new(PeerCounters::default());
+        let mixnet_client = mixnet_client::Client::new(config.mixnet_client_config());
+
+        stats.register_peer(
+            address,
+            Arc::clone(&queue),
+            Arc::clone(&counters),
+            mixnet_client.connection_stats(),
+        );
+
+        // dropping the mixnet client, alongside the worker, closes the connection
+        let (worker, abort_handle) = abortable(run_peer_worker(
+            address,
+            mixnet_client,
+            Arc::clone(&queue),
+            Arc::clone(&counters),
+        ));
+        tokio::spawn(worker);
+
+        PeerWorker {
+            queue,
+            counters,
+            abort_handle,
+            last_used: Instant::now(),
+        }
+    }
+
+    fn forward(&mut self, address: SocketAddr, packet: SphinxPacket) {
+        if let Some(known_peers) = &self.config.known_peers {
+            if !known_peers.contains(&address) {
+                trace!("Dropping packet to {} - it's not a known node", address);
+                self.stats.increment_rejected();
+                return;
+            }
+        }
+
+        let config = &self.config;
+        let stats = &self.stats;
+        let worker = self
+            .peers
+            .entry(address)
+            .or_insert_with(|| Self::start_peer_worker(config, stats, address));
+        worker.last_used = Instant::now();
+
+        if worker.queue.push(packet) {
+            trace!("The queue to {} is full - a packet was dropped", address);
+            worker.counters.increment_dropped()
+        }
+    }
+
+    fn reap_idle_peers(&mut self) {
+        let now = Instant::now();
+        let idle_timeout = self.config.idle_peer_timeout;
+        let stats = &self.stats;
+        self.peers.retain(|address, worker| {
+            if !worker.is_idle(now, idle_timeout) {
+                return true;
+            }
+            debug!("Stopping idle packet forwarding worker for {}", address);
+            worker.abort_handle.abort();
+            stats.unregister_peer(address);
+            false
+        })
+    }
+
+    /// Starts the forwarder and returns the channel through which packets should be sent to it.
+    pub fn start(mut self, handle: &Handle) -> (JoinHandle<()>, OutboundPacketSender) {
+        let sender_channel = self.packet_tx.clone();
+        let join_handle = handle.spawn(async move {
+            let mut reaping_timer = tokio::time::interval(self.config.idle_peer_timeout);
+            loop {
+                tokio::select! {
+                    packet = self.packet_rx.next() => match packet {
+                        Some((address, packet)) => self.forward(address, packet),
+                        None => break,
+                    },
+                    _ = reaping_timer.tick() => self.reap_idle_peers(),
+                }
+            }
+            warn!(
+                "Packet forwarder's channel got closed - no more packets are going to be forwarded"
+            );
+        });
+        (join_handle, sender_channel)
+    }
+}
+
+#[cfg(test)]
+mod reaping_idle_peers {
+    use super::*;
+
+    fn worker_last_used_at(last_used: Instant) -> PeerWorker {
+        PeerWorker {
+            queue: Arc::new(PacketQueue::new(10, DropPolicy::DropNewest)),
+            counters: Default::default(),
+            abort_handle: AbortHandle::new_pair().0,
+            last_used,
+        }
+    }
+
+    #[test]
+    fn worker_is_idle_only_after_the_timeout() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(60);
+
+        assert!(!worker_last_used_at(now).is_idle(now, timeout));
+        assert!(!worker_last_used_at(now).is_idle(now + Duration::from_secs(59), timeout));
+        assert!(worker_last_used_at(now).is_idle(now + timeout, timeout));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crypto::asymmetric::encryption;
+use directory_client::{DirectoryClient, Topology};
+use link_encryption::{PeerKeys, KEY_LEN};
+use log::*;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+fn parse_peer_key(address: &str, key: &str) -> Option<(SocketAddr, [u8; KEY_LEN])> {
+    let address = address.parse().ok()?;
+    let key = encryption::PublicKey::from_base58_string(key).ok()?;
+    Some((address, key.to_bytes()))
+}
+
+// both mixnodes and gateways use their sphinx keys as the static keys of their links
+fn extract_peer_keys(topology: Topology) -> HashMap<SocketAddr, [u8; KEY_LEN]> {
+    let mix_keys = topology
+        .mix_nodes
+        .iter()
+        .filter_map(|node| parse_peer_key(&node.host, &node.pub_key));
+    let gateway_keys = topology
+        .gateway_nodes
+        .iter()
+        .filter_map(|node| parse_peer_key(&node.mixnet_listener, &node.sphinx_key));
+
+    mix_keys.chain(gateway_keys).collect()
+}
+
+fn extract_known_peers(topology: &Topology) -> HashSet<SocketAddr> {
+    let mix_addresses = topology.mix_nodes.iter().map(|node| node.host.parse());
+    let gateway_addresses = topology
+        .gateway_nodes
+        .iter()
+        .map(|node| node.mixnet_listener.parse());
+
+    mix_addresses
+        .chain(gateway_addresses)
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Addresses of all nodes present in the most recently obtained network topology.
+/// Until the topology is obtained for the first time, no address is known.
+#[derive(Clone, Default)]
+pub struct KnownPeers {
+    addresses: Arc<RwLock<HashSet<SocketAddr>>>,
+}
+
+impl KnownPeers {
+    fn update(&self, addresses: HashSet<SocketAddr>) {
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        *self.addresses.write().unwrap() = addresses;
+    }
+
+    pub fn contains(&self, address: &SocketAddr) -> bool {
+        self.addresses.read().unwrap().contains(address)
+    }
+}
+
+/// Periodically updates the static keys, and addresses, of all nodes in the network,
+/// as announced to the directory server, so that the links to them could be authenticated.
+pub struct PeerKeysRefresher {
+    directory_client: directory_client::Client,
+    peer_keys: PeerKeys,
+    known_peers: KnownPeers,
+    refresh_rate: Duration,
+}
+
+impl PeerKeysRefresher {
+    pub fn new(
+        directory_server: String,
+        peer_keys: PeerKeys,
+        known_peers: KnownPeers,
+        refresh_rate: Duration,
+    ) -> Self {
+        PeerKeysRefresher {
+            directory_client: directory_client::Client::new(directory_client::Config::new(
+                directory_server,
+            )),
+            peer_keys,
+            known_peers,
+            refresh_rate,
+        }
+    }
+
+    async fn refresh(&self) {
+        match self.directory_client.get_topology().await {
+            Ok(topology) => {
+                let addresses = extract_known_peers(&topology);
+                trace!("Obtained addresses of {} peers", addresses.len());
+                self.known_peers.update(addresses);
+
+                let keys = extract_peer_keys(topology);
+                trace!("Obtained static keys of {} peers", keys.len());
+                self.peer_keys.update(keys)
+            }
+            // keep using the keys we already know
+            Err(err) => warn!("Failed to refresh static keys of peers - {}", err),
+        }
+    }
+
+    pub fn start(self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                self.refresh().await;
+                tokio::time::delay_for(self.refresh_rate).await;
+            }
+        })
+    }
+}
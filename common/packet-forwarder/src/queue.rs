@@ -0,0 +1,129 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::DropPolicy;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Bounded queue of packets waiting to be sent to a single destination.
+/// Once it's full, the configured `DropPolicy` decides which packet is discarded.
+pub(crate) struct PacketQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    drop_policy: DropPolicy,
+    notify: Notify,
+}
+
+impl<T> PacketQueue<T> {
+    pub(crate) fn new(capacity: usize, drop_policy: DropPolicy) -> Self {
+        PacketQueue {
+            items: Mutex::new(VecDeque::new()),
+            capacity,
+            drop_policy,
+            notify: Notify::new(),
+        }
+    }
+
+    /// Pushes the item onto the queue. Returns `true` if, as a result, any item was dropped.
+    pub(crate) fn push(&self, item: T) -> bool {
+        let dropped = {
+            // the lock can only be poisoned if some thread panicked while holding it and we never
+            // do anything that could panic with it
+            let mut items = self.items.lock().unwrap();
+            if items.len() < self.capacity {
+                items.push_back(item);
+                false
+            } else {
+                match self.drop_policy {
+                    DropPolicy::DropNewest => true,
+                    DropPolicy::DropOldest => {
+                        // if capacity is 0, it just means the new item is dropped straight away
+                        if items.pop_front().is_some() {
+                            items.push_back(item);
+                        }
+                        true
+                    }
+                }
+            }
+        };
+
+        // a spurious notification is harmless as `pop` re-checks the queue anyway
+        self.notify.notify();
+        dropped
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        self.items.lock().unwrap().pop_front()
+    }
+
+    /// Waits until there's an item in the queue and removes it.
+    pub(crate) async fn pop(&self) -> T {
+        loop {
+            if let Some(item) = self.try_pop() {
+                return item;
+            }
+            // if `push` happened between `try_pop` and this call, the permit is already stored
+            // and the future resolves immediately
+            self.notify.notified().await;
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod packet_queue {
+    use super::*;
+
+    #[test]
+    fn drop_newest_policy_keeps_existing_items() {
+        let queue = PacketQueue::new(2, DropPolicy::DropNewest);
+        assert!(!queue.push(1));
+        assert!(!queue.push(2));
+        assert!(queue.push(3));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), Some(2));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[test]
+    fn drop_oldest_policy_replaces_existing_items() {
+        let queue = PacketQueue::new(2, DropPolicy::DropOldest);
+        assert!(!queue.push(1));
+        assert!(!queue.push(2));
+        assert!(queue.push(3));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.try_pop(), Some(2));
+        assert_eq!(queue.try_pop(), Some(3));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[tokio::test]
+    async fn pop_waits_for_pushed_item() {
+        let queue = std::sync::Arc::new(PacketQueue::new(2, DropPolicy::DropNewest));
+        let pusher_queue = std::sync::Arc::clone(&queue);
+        tokio::spawn(async move {
+            tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+            pusher_queue.push(42);
+        });
+
+        assert_eq!(queue.pop().await, 42);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::queue::PacketQueue;
+use mixnet_client::ConnectionStats;
+use nymsphinx::SphinxPacket;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+pub(crate) struct PeerCounters {
+    forwarded: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl PeerCounters {
+    pub(crate) fn increment_forwarded(&self) {
+        self.forwarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn increment_failed(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn increment_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// everything we know about forwarding packets to particular destination
+struct PeerEntry {
+    queue: Arc<PacketQueue<SphinxPacket>>,
+    counters: Arc<PeerCounters>,
+    connection_stats: ConnectionStats,
+}
+
+impl PeerEntry {
+    fn snapshot(&self, address: SocketAddr) -> PeerForwardingStats {
+        // there's only ever a single connection per peer
+        let connection = self
+            .connection_stats
+            .snapshot()
+            .into_iter()
+            .find(|(peer, _)| *peer == address)
+            .map(|(_, stats)| stats);
+
+        PeerForwardingStats {
+            queued_packets: self.queue.len(),
+            buffered_packets: connection
+                .map(|stats| stats.queued_packets + stats.buffered_packets)
+                .unwrap_or_default(),
+            forwarded: self.counters.forwarded.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            connection_attempts: connection
+                .map(|stats| stats.connection_attempts)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Current state of forwarding packets to particular destination.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerForwardingStats {
+    /// Number of packets waiting in the queue of the forwarder.
+    pub queued_packets: usize,
+    /// Number of packets already handed to the connection, but not yet written to the socket,
+    /// for example because the connection is being re-established.
+    pub buffered_packets: usize,
+    /// Total number of packets successfully written to the connection.
+    pub forwarded: u64,
+    /// Total number of failed attempts to write a packet to the connection. Note that
+    /// the connection might still retry sending the packet once it gets re-established.
+    pub failed: u64,
+    /// Total number of packets dropped due to the queue being full.
+    pub dropped: u64,
+    /// Total number of attempts to (re)establish the connection.
+    pub connection_attempts: u64,
+}
+
+impl Display for PeerForwardingStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "queued: {}, buffered: {}, forwarded: {}, failed: {}, dropped: {}, connection attempts: {}",
+            self.queued_packets,
+            self.buffered_packets,
+            self.forwarded,
+            self.failed,
+            self.dropped,
+            self.connection_attempts
+        )
+    }
+}
+
+/// Shared handle to the statistics of all destinations the `PacketForwarder` has sent packets to.
+#[derive(Clone, Default)]
+pub struct ForwardingStats {
+    peers: Arc<Mutex<HashMap<SocketAddr, PeerEntry>>>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl ForwardingStats {
+    pub(crate) fn register_peer(
+        &self,
+        address: SocketAddr,
+        queue: Arc<PacketQueue<SphinxPacket>>,
+        counters: Arc<PeerCounters>,
+        connection_stats: ConnectionStats,
+    ) {
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        self.peers.lock().unwrap().insert(
+            address,
+            PeerEntry {
+                queue,
+                counters,
+                connection_stats,
+            },
+        );
+    }
+
+    pub(crate) fn unregister_peer(&self, address: &SocketAddr) {
+        self.peers.lock().unwrap().remove(address);
+    }
+
+    pub(crate) fn increment_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Total number of packets dropped as their destinations were not present in the topology.
+    pub fn rejected_packets(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Total number of packets that are yet to be written to any of the connections.
+    pub fn pending_packets(&self) -> usize {
+        self.snapshot()
+            .iter()
+            .map(|(_, stats)| stats.queued_packets + stats.buffered_packets)
+            .sum()
+    }
+
+    pub fn snapshot(&self) -> Vec<(SocketAddr, PeerForwardingStats)> {
+        let peers = self.peers.lock().unwrap();
+        peers
+            .iter()
+            .map(|(address, entry)| (*address, entry.snapshot(*address)))
+            .collect()
+    }
+}
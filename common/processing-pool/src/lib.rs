@@ -0,0 +1,320 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::channel::oneshot;
+use log::*;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum ProcessingPoolError {
+    /// The input queue is at its full capacity and hence the item was dropped.
+    QueueFull,
+    /// All workers have stopped running and hence the item can't be processed.
+    PoolShutdown,
+}
+
+struct Job<I, O> {
+    input: I,
+    response_sender: oneshot::Sender<O>,
+    enqueued_at: Instant,
+}
+
+#[derive(Default)]
+struct PoolMetrics {
+    queue_depth: AtomicUsize,
+    in_progress: AtomicUsize,
+    processed: AtomicU64,
+    dropped: AtomicU64,
+    total_queue_latency_nanos: AtomicU64,
+    total_processing_latency_nanos: AtomicU64,
+}
+
+impl PoolMetrics {
+    fn snapshot(&self) -> PoolStats {
+        let processed = self.processed.load(Ordering::Relaxed);
+        let average = |total_nanos: u64| {
+            total_nanos
+                .checked_div(processed)
+                .map(Duration::from_nanos)
+                .unwrap_or_default()
+        };
+
+        PoolStats {
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            processed,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            average_queue_latency: average(self.total_queue_latency_nanos.load(Ordering::Relaxed)),
+            average_processing_latency: average(
+                self.total_processing_latency_nanos.load(Ordering::Relaxed),
+            ),
+        }
+    }
+}
+
+/// Snapshot of the metrics of a `ProcessingPool` since its creation.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    /// Number of items currently waiting in the input queue.
+    pub queue_depth: usize,
+    /// Total number of processed items.
+    pub processed: u64,
+    /// Total number of items dropped due to the input queue being full.
+    pub dropped: u64,
+    /// Average time an item spent in the input queue before being picked up by a worker.
+    pub average_queue_latency: Duration,
+    /// Average time it took a worker to process an item.
+    pub average_processing_latency: Duration,
+}
+
+impl Display for PoolStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "queue depth: {}, processed: {}, dropped: {}, average queue latency: {:?}, average processing latency: {:?}",
+            self.queue_depth,
+            self.processed,
+            self.dropped,
+            self.average_queue_latency,
+            self.average_processing_latency
+        )
+    }
+}
+
+/// Fixed-size pool of OS threads dedicated to CPU-heavy work (such as unwrapping sphinx packets),
+/// so that it does not block the async executor. Items are submitted through a bounded queue
+/// and if it's full, they are rejected straight away rather than buffered.
+pub struct ProcessingPool<I, O> {
+    job_sender: SyncSender<Job<I, O>>,
+    metrics: Arc<PoolMetrics>,
+}
+
+// explicit implementation as derive would have required `I: Clone` and `O: Clone`
+impl<I, O> Clone for ProcessingPool<I, O> {
+    fn clone(&self) -> Self {
+        ProcessingPool {
+            job_sender: self.job_sender.clone(),
+            metrics: Arc::clone(&self.metrics),
+        }
+    }
+}
+
+impl<I, O> ProcessingPool<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    /// Spawns `num_workers` threads running `processing_fn` on the submitted items.
+    /// The workers are stopped once all instances of the pool are dropped.
+    pub fn new<F>(num_workers: usize, queue_capacity: usize, processing_fn: F) -> Self
+    where
+        F: Fn(I) -> O + Send + Sync + 'static,
+    {
+        assert!(
+            num_workers > 0,
+            "processing pool requires at least one worker"
+        );
+
+        let (job_sender, job_receiver) = mpsc::sync_channel(queue_capacity);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let processing_fn = Arc::new(processing_fn);
+        let metrics = Arc::new(PoolMetrics::default());
+
+        for i in 0..num_workers {
+            let job_receiver = Arc::clone(&job_receiver);
+            let processing_fn = Arc::clone(&processing_fn);
+            let metrics = Arc::clone(&metrics);
+            thread::Builder::new()
+                .name(format!("processing-worker-{}", i))
+                .spawn(move || Self::run_worker(job_receiver, processing_fn, metrics))
+                .expect("failed to spawn processing pool worker");
+        }
+
+        ProcessingPool {
+            job_sender,
+            metrics,
+        }
+    }
+
+    fn run_worker<F>(
+        job_receiver: Arc<Mutex<Receiver<Job<I, O>>>>,
+        processing_fn: Arc<F>,
+        metrics: Arc<PoolMetrics>,
+    ) where
+        F: Fn(I) -> O,
+    {
+        loop {
+            // the lock is released as soon as we get the job so that other workers could
+            // receive next jobs while this one is being processed
+            let job = match job_receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                // another worker has panicked while holding the lock
+                Err(_) => break,
+            };
+            let job = match job {
+                Ok(job) => job,
+                // all senders are gone - nobody is going to submit any more work
+                Err(_) => break,
+            };
+
+            // the item has to be accounted for at all times, until its processing is done
+            metrics.in_progress.fetch_add(1, Ordering::SeqCst);
+            metrics.queue_depth.fetch_sub(1, Ordering::SeqCst);
+            let processing_start = Instant::now();
+            let queue_latency = processing_start - job.enqueued_at;
+
+            let output = processing_fn(job.input);
+
+            let processing_latency = processing_start.elapsed();
+            metrics
+                .total_queue_latency_nanos
+                .fetch_add(queue_latency.as_nanos() as u64, Ordering::Relaxed);
+            metrics
+                .total_processing_latency_nanos
+                .fetch_add(processing_latency.as_nanos() as u64, Ordering::Relaxed);
+            metrics.processed.fetch_add(1, Ordering::Relaxed);
+
+            metrics.in_progress.fetch_sub(1, Ordering::SeqCst);
+            // the caller might have given up on waiting for the result, but it's not our concern
+            let _ = job.response_sender.send(output);
+        }
+        debug!(
+            "{:?} is shutting down",
+            thread::current().name().unwrap_or("processing worker")
+        );
+    }
+
+    /// Submits the item to the pool and waits for the result of its processing.
+    pub async fn process(&self, input: I) -> Result<O, ProcessingPoolError> {
+        let (response_sender, response_receiver) = oneshot::channel();
+        let job = Job {
+            input,
+            response_sender,
+            enqueued_at: Instant::now(),
+        };
+
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        if let Err(err) = self.job_sender.try_send(job) {
+            self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+            return match err {
+                TrySendError::Full(_) => {
+                    self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    Err(ProcessingPoolError::QueueFull)
+                }
+                TrySendError::Disconnected(_) => Err(ProcessingPoolError::PoolShutdown),
+            };
+        }
+
+        // the sender can only be dropped without sending if the worker has panicked
+        response_receiver
+            .await
+            .map_err(|_| ProcessingPoolError::PoolShutdown)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.metrics.snapshot()
+    }
+
+    /// Number of items that were submitted, but whose processing is not done yet,
+    /// either because they are waiting in the input queue or are being processed right now.
+    pub fn pending(&self) -> usize {
+        // workers count the item as in progress before removing it from the queue, so if it got
+        // picked up in between the reads, it could only be counted twice rather than missed
+        let queue_depth = self.metrics.queue_depth.load(Ordering::SeqCst);
+        queue_depth + self.metrics.in_progress.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod processing_pool {
+    use super::*;
+    use std::sync::Barrier;
+
+    #[tokio::test]
+    async fn returns_results_of_processing_function() {
+        let pool = ProcessingPool::new(2, 10, |x: u64| x * 2);
+        for i in 0..20 {
+            assert_eq!(pool.process(i).await.unwrap(), i * 2);
+        }
+
+        let stats = pool.stats();
+        assert_eq!(stats.processed, 20);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!(stats.queue_depth, 0);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn drops_items_when_queue_is_full() {
+        // the only worker is going to be stuck on the first item until we release it
+        let started = Arc::new(Barrier::new(2));
+        let release = Arc::new(Barrier::new(2));
+        let worker_started = Arc::clone(&started);
+        let worker_release = Arc::clone(&release);
+        let pool = ProcessingPool::new(1, 1, move |x: u64| {
+            if x == 0 {
+                worker_started.wait();
+                worker_release.wait();
+            }
+            x
+        });
+
+        let blocked_pool = pool.clone();
+        let blocked = tokio::spawn(async move { blocked_pool.process(0).await });
+        // once the worker has picked up the first item, the queue can take a single one
+        started.wait();
+
+        let pool1 = pool.clone();
+        let pool2 = pool.clone();
+        let first = tokio::spawn(async move { pool1.process(1).await });
+        let second = tokio::spawn(async move { pool2.process(2).await });
+
+        while pool.stats().dropped == 0 {
+            tokio::time::delay_for(Duration::from_millis(1)).await;
+        }
+        release.wait();
+
+        assert_eq!(blocked.await.unwrap().unwrap(), 0);
+        let results = [first.await.unwrap(), second.await.unwrap()];
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+        assert!(results.contains(&Err(ProcessingPoolError::QueueFull)));
+        assert_eq!(pool.stats().dropped, 1);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn items_being_processed_are_pending() {
+        let started = Arc::new(Barrier::new(2));
+        let release = Arc::new(Barrier::new(2));
+        let worker_started = Arc::clone(&started);
+        let worker_release = Arc::clone(&release);
+        let pool = ProcessingPool::new(1, 1, move |x: u64| {
+            worker_started.wait();
+            worker_release.wait();
+            x
+        });
+
+        let blocked_pool = pool.clone();
+        let blocked = tokio::spawn(async move { blocked_pool.process(0).await });
+        started.wait();
+        assert_eq!(pool.stats().queue_depth, 0);
+        assert_eq!(pool.pending(), 1);
+
+        release.wait();
+        assert_eq!(blocked.await.unwrap().unwrap(), 0);
+        assert_eq!(pool.pending(), 0);
+    }
+}
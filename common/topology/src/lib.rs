@@ -72,14 +72,42 @@ pub trait NymTopology: Sized + std::fmt::Debug + Send + Sync + Clone {
 
     // Tries to get a route through the mix network
     fn random_mix_route(&self) -> Result<Vec<SphinxNode>, NymTopologyError> {
-        let mut layered_topology = self.make_layered_topology()?;
+        self.random_mix_route_with_length(None)
+    }
+
+    // Tries to get a route through the mix network consisting of the specified number of mix nodes.
+    // If the length is not specified, a single node from each layer is used. Otherwise the route
+    // goes through that many first layers in order. Routes longer than the number of layers
+    // are rejected as they would have to revisit a layer, which layer-filtering nodes refuse.
+    // Note that shorter routes don't end at the final layer either, so their packets are going
+    // to be dropped by nodes with layer filtering enabled, as only the final layer is allowed
+    // to forward traffic to gateways.
+    fn random_mix_route_with_length(
+        &self,
+        route_length: Option<usize>,
+    ) -> Result<Vec<SphinxNode>, NymTopologyError> {
+        let layered_topology = self.make_layered_topology()?;
         let num_layers = layered_topology.len();
-        let route = (1..=num_layers as u64)
-            // unwrap is safe for 'remove' as it it failed, it implied the entry never existed
-            // in the map in the first place which would contradict what we've just done
-            .map(|layer| layered_topology.remove(&layer).unwrap()) // for each layer
-            .map(|nodes| nodes.into_iter().choose(&mut rand::thread_rng()).unwrap()) // choose random node
-            .map(|random_node| random_node.into()) // and convert it into sphinx specific node format
+        if num_layers == 0 {
+            return Err(NymTopologyError::MissingLayerError(vec![1]));
+        }
+        let route_length = route_length.unwrap_or(num_layers);
+
+        // we need to have extra space for the gateway
+        if route_length == 0
+            || route_length > num_layers
+            || route_length >= nymsphinx_types::MAX_PATH_LENGTH
+        {
+            return Err(NymTopologyError::InvalidRouteLengthError(route_length));
+        }
+
+        let route = (0..route_length)
+            .map(|hop| hop as u64 + 1)
+            // indexing can't fail as `make_layered_topology` guarantees there are no gaps
+            // between the layers and each of them contains at least a single node
+            .map(|layer| &layered_topology[&layer]) // for each layer
+            .map(|nodes| nodes.iter().choose(&mut rand::thread_rng()).unwrap()) // choose random node
+            .map(|random_node| random_node.clone().into()) // and convert it into sphinx specific node format
             .collect();
 
         Ok(route)
@@ -96,6 +124,14 @@ pub trait NymTopology: Sized + std::fmt::Debug + Send + Sync + Clone {
     fn random_route_to_gateway(
         &self,
         gateway_address: &NodeAddressBytes,
+    ) -> Result<Vec<SphinxNode>, NymTopologyError> {
+        self.random_route_to_gateway_with_length(gateway_address, None)
+    }
+
+    fn random_route_to_gateway_with_length(
+        &self,
+        gateway_address: &NodeAddressBytes,
+        route_length: Option<usize>,
     ) -> Result<Vec<SphinxNode>, NymTopologyError> {
         let b58_address = gateway_address.to_base58_string();
 
@@ -107,7 +143,7 @@ pub trait NymTopology: Sized + std::fmt::Debug + Send + Sync + Clone {
             .clone();
 
         Ok(self
-            .random_mix_route()?
+            .random_mix_route_with_length(route_length)?
             .into_iter()
             .chain(std::iter::once(gateway.into()))
             .collect())
@@ -171,4 +207,5 @@ pub enum NymTopologyError {
     InvalidMixLayerError,
     MissingLayerError(Vec<u64>),
     NonExistentGatewayError,
+    InvalidRouteLengthError(usize),
 }
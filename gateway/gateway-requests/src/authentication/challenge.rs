@@ -0,0 +1,330 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crypto::asymmetric::identity;
+use nymsphinx::DestinationAddressBytes;
+use rand::{CryptoRng, RngCore};
+use std::fmt::{self, Display, Formatter};
+
+pub const CHALLENGE_LENGTH: usize = 32;
+
+// prevent the signatures from being valid in any other context, including each other's
+const AUTHENTICATION_DOMAIN_SEPARATOR: &[u8] = b"NYM_GATEWAY_AUTHENTICATION";
+const SESSION_RESUMPTION_DOMAIN_SEPARATOR: &[u8] = b"NYM_GATEWAY_SESSION_RESUMPTION";
+
+/// Random value chosen by the gateway that the client has to sign in order to prove
+/// it holds the identity key of the address it is authenticating as.
+/// Each challenge is only ever accepted once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuthenticationChallenge([u8; CHALLENGE_LENGTH]);
+
+/// Challenge issued to the client in advance, upon successful authentication, so that after
+/// losing the connection it could resume its session in a single request.
+pub type SessionToken = AuthenticationChallenge;
+
+#[derive(Debug)]
+pub enum ChallengeConversionError {
+    DecodeError(bs58::decode::Error),
+    StringOfInvalidLengthError,
+}
+
+impl AuthenticationChallenge {
+    pub fn new_random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        rng.fill_bytes(&mut challenge);
+        AuthenticationChallenge(challenge)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn try_from_base58_string<S: Into<String>>(
+        val: S,
+    ) -> Result<Self, ChallengeConversionError> {
+        let decoded = match bs58::decode(val.into()).into_vec() {
+            Ok(decoded) => decoded,
+            Err(err) => return Err(ChallengeConversionError::DecodeError(err)),
+        };
+
+        if decoded.len() != CHALLENGE_LENGTH {
+            return Err(ChallengeConversionError::StringOfInvalidLengthError);
+        }
+
+        let mut challenge = [0u8; CHALLENGE_LENGTH];
+        challenge.copy_from_slice(&decoded);
+        Ok(AuthenticationChallenge(challenge))
+    }
+
+    pub fn to_base58_string(&self) -> String {
+        bs58::encode(&self.0).into_string()
+    }
+}
+
+impl Into<String> for AuthenticationChallenge {
+    fn into(self) -> String {
+        self.to_base58_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChallengePurpose {
+    /// Challenge was obtained from the gateway right before answering it.
+    Authentication,
+    /// Challenge is a session token obtained during some earlier session.
+    SessionResumption,
+}
+
+impl ChallengePurpose {
+    fn domain_separator(self) -> &'static [u8] {
+        match self {
+            ChallengePurpose::Authentication => AUTHENTICATION_DOMAIN_SEPARATOR,
+            ChallengePurpose::SessionResumption => SESSION_RESUMPTION_DOMAIN_SEPARATOR,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ChallengeError {
+    MalformedIdentity,
+    MalformedSignature,
+    InvalidSignature,
+}
+
+impl Display for ChallengeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ChallengeError::MalformedIdentity => write!(f, "client identity is malformed"),
+            ChallengeError::MalformedSignature => write!(f, "signature is malformed"),
+            ChallengeError::InvalidSignature => write!(f, "signature is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for ChallengeError {}
+
+/// Signature of the client over the challenge, bound to the particular gateway,
+/// the address of the client and the purpose of the challenge.
+#[derive(Debug)]
+pub struct ChallengeResponse {
+    pub client_identity: identity::PublicKey,
+    pub signature: identity::Signature,
+}
+
+impl ChallengeResponse {
+    fn signed_message(
+        purpose: ChallengePurpose,
+        gateway_identity: &identity::PublicKey,
+        client_identity: &identity::PublicKey,
+        challenge: &AuthenticationChallenge,
+    ) -> Vec<u8> {
+        purpose
+            .domain_separator()
+            .iter()
+            .cloned()
+            .chain(gateway_identity.to_bytes().iter().cloned())
+            .chain(client_identity.to_bytes().iter().cloned())
+            .chain(challenge.as_bytes().iter().cloned())
+            .collect()
+    }
+
+    pub fn new_signed(
+        client_identity: &identity::KeyPair,
+        gateway_identity: &identity::PublicKey,
+        challenge: &AuthenticationChallenge,
+        purpose: ChallengePurpose,
+    ) -> Self {
+        let message = Self::signed_message(
+            purpose,
+            gateway_identity,
+            client_identity.public_key(),
+            challenge,
+        );
+        ChallengeResponse {
+            client_identity: *client_identity.public_key(),
+            signature: client_identity.private_key().sign(&message),
+        }
+    }
+
+    /// Recovers the response from the address of the client, i.e. the encoding
+    /// of its identity key, and the signature.
+    pub fn try_from_base58_fields(address: &str, signature: &str) -> Result<Self, ChallengeError> {
+        let client_identity = identity::PublicKey::from_base58_string(address)
+            .map_err(|_| ChallengeError::MalformedIdentity)?;
+        let signature_bytes = bs58::decode(signature)
+            .into_vec()
+            .map_err(|_| ChallengeError::MalformedSignature)?;
+        let signature = identity::Signature::from_bytes(&signature_bytes)
+            .map_err(|_| ChallengeError::MalformedSignature)?;
+
+        Ok(ChallengeResponse {
+            client_identity,
+            signature,
+        })
+    }
+
+    pub fn client_address(&self) -> DestinationAddressBytes {
+        self.client_identity.derive_address()
+    }
+
+    pub fn signature_to_base58_string(&self) -> String {
+        bs58::encode(self.signature.to_bytes().to_vec()).into_string()
+    }
+
+    /// Checks whether the challenge was signed by the client for this gateway and purpose.
+    pub fn verify(
+        &self,
+        gateway_identity: &identity::PublicKey,
+        challenge: &AuthenticationChallenge,
+        purpose: ChallengePurpose,
+    ) -> Result<(), ChallengeError> {
+        let message =
+            Self::signed_message(purpose, gateway_identity, &self.client_identity, challenge);
+        self.client_identity
+            .verify(&message, &self.signature)
+            .map_err(|_| ChallengeError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod challenge_response {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn is_valid_for_the_challenge_it_was_made_for() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let challenge = AuthenticationChallenge::new_random(&mut OsRng);
+
+        let response = ChallengeResponse::new_signed(
+            &client,
+            gateway.public_key(),
+            &challenge,
+            ChallengePurpose::Authentication,
+        );
+        assert!(response
+            .verify(
+                gateway.public_key(),
+                &challenge,
+                ChallengePurpose::Authentication
+            )
+            .is_ok());
+
+        let other_challenge = AuthenticationChallenge::new_random(&mut OsRng);
+        assert!(response
+            .verify(
+                gateway.public_key(),
+                &other_challenge,
+                ChallengePurpose::Authentication
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn is_invalid_for_other_gateways() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let other_gateway = identity::KeyPair::new();
+        let challenge = AuthenticationChallenge::new_random(&mut OsRng);
+
+        let response = ChallengeResponse::new_signed(
+            &client,
+            gateway.public_key(),
+            &challenge,
+            ChallengePurpose::Authentication,
+        );
+        assert!(response
+            .verify(
+                other_gateway.public_key(),
+                &challenge,
+                ChallengePurpose::Authentication
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn is_invalid_for_other_purposes() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let token = SessionToken::new_random(&mut OsRng);
+
+        let response = ChallengeResponse::new_signed(
+            &client,
+            gateway.public_key(),
+            &token,
+            ChallengePurpose::SessionResumption,
+        );
+        assert!(response
+            .verify(
+                gateway.public_key(),
+                &token,
+                ChallengePurpose::Authentication
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn cannot_be_claimed_by_other_clients() {
+        let client = identity::KeyPair::new();
+        let other_client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let challenge = AuthenticationChallenge::new_random(&mut OsRng);
+
+        let mut response = ChallengeResponse::new_signed(
+            &client,
+            gateway.public_key(),
+            &challenge,
+            ChallengePurpose::Authentication,
+        );
+        response.client_identity = *other_client.public_key();
+        assert!(response
+            .verify(
+                gateway.public_key(),
+                &challenge,
+                ChallengePurpose::Authentication
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn survives_conversion_through_base58_fields() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let challenge = AuthenticationChallenge::new_random(&mut OsRng);
+
+        let response = ChallengeResponse::new_signed(
+            &client,
+            gateway.public_key(),
+            &challenge,
+            ChallengePurpose::Authentication,
+        );
+        let recovered = ChallengeResponse::try_from_base58_fields(
+            &response.client_address().to_base58_string(),
+            &response.signature_to_base58_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            client.public_key().derive_address(),
+            recovered.client_address()
+        );
+        assert!(recovered
+            .verify(
+                gateway.public_key(),
+                &challenge,
+                ChallengePurpose::Authentication
+            )
+            .is_ok());
+    }
+}
@@ -12,5 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-pub mod encrypted_address;
-pub mod iv;
+pub mod challenge;
+
+/// The original authentication, in which clients encrypted their address with the shared key.
+/// It is no longer supported, but clients that predate authentication versioning still
+/// implicitly use it, so that they could be told to upgrade.
+pub const LEGACY_AUTHENTICATION_VERSION: u8 = 1;
+
+/// Authentication in which clients sign a challenge issued by the gateway.
+pub const CHALLENGE_AUTHENTICATION_VERSION: u8 = 2;
+
+pub const CURRENT_AUTHENTICATION_VERSION: u8 = CHALLENGE_AUTHENTICATION_VERSION;
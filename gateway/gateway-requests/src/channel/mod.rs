@@ -0,0 +1,295 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::registration::handshake::{SharedKey, SharedKeySize};
+use crypto::kdf::blake3_hkdf;
+use crypto::symmetric::aes_ctr::{
+    generic_array::{typenum::Unsigned, GenericArray},
+    Aes128Key,
+};
+use crypto::symmetric::aes_gcm;
+use std::convert::TryInto;
+use std::fmt::{self, Display, Formatter};
+
+pub use session_nonce::{SessionNonce, SessionNonceConversionError, SESSION_NONCE_LENGTH};
+
+mod session_nonce;
+
+// Each message sent over the channel has the following format:
+// COUNTER || AES_GCM(k, DIRECTION || COUNTER, data)
+// where k is derived from the shared key and the session nonce, and COUNTER is a big-endian u64
+// incremented with each message sent in given direction.
+// Both directions use the same key, but their nonces are disjoint thanks to the DIRECTION prefix.
+
+const COUNTER_LENGTH: usize = 8;
+const DIRECTION_LENGTH: usize = 4;
+const CLIENT_TO_GATEWAY: [u8; DIRECTION_LENGTH] = [0, 0, 0, 1];
+const GATEWAY_TO_CLIENT: [u8; DIRECTION_LENGTH] = [0, 0, 0, 2];
+
+const CHANNEL_KEY_INFO: &[u8] = b"NYM_CLIENT_GATEWAY_CHANNEL";
+
+/// Side of the channel the local party is at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelSide {
+    Client,
+    Gateway,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChannelError {
+    MessageTooShort,
+    /// The message was either not encrypted with the key of the channel or it was tampered with.
+    DecryptionFailure,
+    /// The message was already received or is older than the last received one.
+    ReplayedMessage(u64),
+}
+
+impl Display for ChannelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::MessageTooShort => write!(f, "received message was too short"),
+            ChannelError::DecryptionFailure => write!(f, "failed to decrypt received message"),
+            ChannelError::ReplayedMessage(counter) => {
+                write!(f, "received replayed message (counter {})", counter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+/// Encrypts messages sent by the local party.
+pub struct ChannelEncryptor {
+    key: Aes128Key,
+    direction: [u8; DIRECTION_LENGTH],
+    next_counter: u64,
+}
+
+/// Decrypts messages sent by the remote party, rejecting the ones that were replayed.
+pub struct ChannelDecryptor {
+    key: Aes128Key,
+    direction: [u8; DIRECTION_LENGTH],
+    last_counter: Option<u64>,
+}
+
+fn make_nonce(direction: &[u8; DIRECTION_LENGTH], counter: u64) -> Vec<u8> {
+    direction
+        .iter()
+        .cloned()
+        .chain(counter.to_be_bytes().iter().cloned())
+        .collect()
+}
+
+/// Derives both halves of the channel for the session identified by the nonce.
+/// Note that the channel must be derived anew whenever the shared key changes.
+pub fn derive_channel(
+    shared_key: &SharedKey,
+    session_nonce: &SessionNonce,
+    side: ChannelSide,
+) -> (ChannelEncryptor, ChannelDecryptor) {
+    // there is no reason for this to fail as our okm is expected to be only 16 bytes
+    let okm = blake3_hkdf::extract_then_expand(
+        Some(session_nonce.as_bytes()),
+        shared_key.as_bytes(),
+        Some(CHANNEL_KEY_INFO),
+        SharedKeySize::to_usize(),
+    )
+    .expect("somehow too long okm was provided");
+    let key = GenericArray::clone_from_slice(&okm);
+
+    let (sending_direction, receiving_direction) = match side {
+        ChannelSide::Client => (CLIENT_TO_GATEWAY, GATEWAY_TO_CLIENT),
+        ChannelSide::Gateway => (GATEWAY_TO_CLIENT, CLIENT_TO_GATEWAY),
+    };
+
+    (
+        ChannelEncryptor {
+            key,
+            direction: sending_direction,
+            next_counter: 0,
+        },
+        ChannelDecryptor {
+            key,
+            direction: receiving_direction,
+            last_counter: None,
+        },
+    )
+}
+
+impl ChannelEncryptor {
+    pub fn encrypt(&mut self, data: &[u8]) -> Vec<u8> {
+        let counter = self.next_counter;
+        // realistically we are never going to send 2^64 messages in a single session
+        self.next_counter = counter
+            .checked_add(1)
+            .expect("exhausted the message counter of the channel");
+
+        let nonce = make_nonce(&self.direction, counter);
+        let ciphertext = aes_gcm::encrypt(&self.key, aes_gcm::nonce_from_slice(&nonce), data);
+        counter
+            .to_be_bytes()
+            .iter()
+            .cloned()
+            .chain(ciphertext.into_iter())
+            .collect()
+    }
+}
+
+impl ChannelDecryptor {
+    pub fn decrypt(&mut self, message: &[u8]) -> Result<Vec<u8>, ChannelError> {
+        if message.len() < COUNTER_LENGTH + aes_gcm::TAG_SIZE {
+            return Err(ChannelError::MessageTooShort);
+        }
+
+        // this can't fail as we've just checked the length
+        let counter = u64::from_be_bytes(message[..COUNTER_LENGTH].try_into().unwrap());
+        // the channel runs on top of an ordered connection, so the counter must always increase
+        if let Some(last_counter) = self.last_counter {
+            if counter <= last_counter {
+                return Err(ChannelError::ReplayedMessage(counter));
+            }
+        }
+
+        let nonce = make_nonce(&self.direction, counter);
+        let data = aes_gcm::decrypt(
+            &self.key,
+            aes_gcm::nonce_from_slice(&nonce),
+            &message[COUNTER_LENGTH..],
+        )
+        .map_err(|_| ChannelError::DecryptionFailure)?;
+
+        // only authentic messages can move the counter forward
+        self.last_counter = Some(counter);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod channel_encryption {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    fn channels() -> (
+        (ChannelEncryptor, ChannelDecryptor),
+        (ChannelEncryptor, ChannelDecryptor),
+    ) {
+        let shared_key = SharedKey::new_with_rng(&mut OsRng);
+        let session_nonce = SessionNonce::new_random(&mut OsRng);
+        (
+            derive_channel(&shared_key, &session_nonce, ChannelSide::Client),
+            derive_channel(&shared_key, &session_nonce, ChannelSide::Gateway),
+        )
+    }
+
+    #[test]
+    fn messages_can_be_exchanged_in_both_directions() {
+        let ((mut client_enc, mut client_dec), (mut gateway_enc, mut gateway_dec)) = channels();
+
+        for i in 0..5u8 {
+            let request = client_enc.encrypt(&[i; 100]);
+            assert_eq!(vec![i; 100], gateway_dec.decrypt(&request).unwrap());
+
+            let response = gateway_enc.encrypt(&[i; 50]);
+            assert_eq!(vec![i; 50], client_dec.decrypt(&response).unwrap());
+        }
+    }
+
+    #[test]
+    fn identical_messages_are_encrypted_differently() {
+        let ((mut client_enc, _), _) = channels();
+        assert_ne!(
+            client_enc.encrypt(&[42; 100]),
+            client_enc.encrypt(&[42; 100])
+        );
+    }
+
+    #[test]
+    fn replayed_messages_are_rejected() {
+        let ((mut client_enc, _), (_, mut gateway_dec)) = channels();
+
+        let first = client_enc.encrypt(&[1; 100]);
+        let second = client_enc.encrypt(&[2; 100]);
+        assert!(gateway_dec.decrypt(&first).is_ok());
+        assert!(gateway_dec.decrypt(&second).is_ok());
+
+        assert_eq!(
+            ChannelError::ReplayedMessage(1),
+            gateway_dec.decrypt(&second).unwrap_err()
+        );
+        assert_eq!(
+            ChannelError::ReplayedMessage(0),
+            gateway_dec.decrypt(&first).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let ((mut client_enc, _), (_, mut gateway_dec)) = channels();
+
+        let mut message = client_enc.encrypt(&[1; 100]);
+        message[20] ^= 1;
+        assert_eq!(
+            ChannelError::DecryptionFailure,
+            gateway_dec.decrypt(&message).unwrap_err()
+        );
+
+        // the counter is authenticated as well
+        let mut message = client_enc.encrypt(&[1; 100]);
+        message[COUNTER_LENGTH - 1] ^= 1;
+        assert_eq!(
+            ChannelError::DecryptionFailure,
+            gateway_dec.decrypt(&message).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn messages_cannot_be_reflected_back_to_their_sender() {
+        let ((mut client_enc, mut client_dec), _) = channels();
+
+        let message = client_enc.encrypt(&[1; 100]);
+        assert_eq!(
+            ChannelError::DecryptionFailure,
+            client_dec.decrypt(&message).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn messages_from_other_sessions_are_rejected() {
+        let shared_key = SharedKey::new_with_rng(&mut OsRng);
+        let (mut old_enc, _) = derive_channel(
+            &shared_key,
+            &SessionNonce::new_random(&mut OsRng),
+            ChannelSide::Client,
+        );
+        let (_, mut new_dec) = derive_channel(
+            &shared_key,
+            &SessionNonce::new_random(&mut OsRng),
+            ChannelSide::Gateway,
+        );
+
+        assert_eq!(
+            ChannelError::DecryptionFailure,
+            new_dec.decrypt(&old_enc.encrypt(&[1; 100])).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn session_nonce_survives_base58_conversion() {
+        let nonce = SessionNonce::new_random(&mut OsRng);
+        assert_eq!(
+            nonce,
+            SessionNonce::try_from_base58_string(nonce.to_base58_string()).unwrap()
+        );
+    }
+}
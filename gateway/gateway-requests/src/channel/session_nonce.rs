@@ -0,0 +1,67 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use rand::{CryptoRng, RngCore};
+
+pub const SESSION_NONCE_LENGTH: usize = 16;
+
+/// Random value chosen by the gateway for each authenticated session. Keys of the channel are
+/// derived from it, so that they are never reused between sessions with the same shared key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionNonce([u8; SESSION_NONCE_LENGTH]);
+
+#[derive(Debug)]
+pub enum SessionNonceConversionError {
+    DecodeError(bs58::decode::Error),
+    StringOfInvalidLengthError,
+}
+
+impl SessionNonce {
+    pub fn new_random<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut nonce = [0u8; SESSION_NONCE_LENGTH];
+        rng.fill_bytes(&mut nonce);
+        SessionNonce(nonce)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn try_from_base58_string<S: Into<String>>(
+        val: S,
+    ) -> Result<Self, SessionNonceConversionError> {
+        let decoded = match bs58::decode(val.into()).into_vec() {
+            Ok(decoded) => decoded,
+            Err(err) => return Err(SessionNonceConversionError::DecodeError(err)),
+        };
+
+        if decoded.len() != SESSION_NONCE_LENGTH {
+            return Err(SessionNonceConversionError::StringOfInvalidLengthError);
+        }
+
+        let mut nonce = [0u8; SESSION_NONCE_LENGTH];
+        nonce.copy_from_slice(&decoded);
+        Ok(SessionNonce(nonce))
+    }
+
+    pub fn to_base58_string(&self) -> String {
+        bs58::encode(&self.0).into_string()
+    }
+}
+
+impl Into<String> for SessionNonce {
+    fn into(self) -> String {
+        self.to_base58_string()
+    }
+}
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 pub mod authentication;
+pub mod channel;
+pub mod publication;
 pub mod registration;
 pub mod types;
 
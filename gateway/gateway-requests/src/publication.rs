@@ -0,0 +1,208 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crypto::asymmetric::identity;
+use std::fmt::{self, Display, Formatter};
+
+// prevents the signature from being valid in any other context
+const PUBLICATION_DOMAIN_SEPARATOR: &[u8] = b"NYM_GATEWAY_ADDRESS_PUBLICATION";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PublicationAction {
+    /// Makes the address of the client discoverable via the presence of the gateway.
+    Publish,
+    /// Revokes any earlier publication of the address.
+    Revoke,
+}
+
+impl PublicationAction {
+    fn as_byte(self) -> u8 {
+        match self {
+            PublicationAction::Publish => 1,
+            PublicationAction::Revoke => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PublicationError {
+    MalformedIdentity,
+    MalformedSignature,
+    InvalidSignature,
+}
+
+impl Display for PublicationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PublicationError::MalformedIdentity => write!(f, "client identity is malformed"),
+            PublicationError::MalformedSignature => write!(f, "signature is malformed"),
+            PublicationError::InvalidSignature => write!(f, "signature is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for PublicationError {}
+
+/// Statement, signed by a client, on whether its address should be listed by its gateway.
+/// It is bound to a particular gateway and the gateway only accepts a statement with
+/// a timestamp greater than that of the last one it accepted, so that an older statement
+/// could not be replayed after being superseded.
+#[derive(Debug)]
+pub struct AddressPublication {
+    pub client_identity: identity::PublicKey,
+    pub action: PublicationAction,
+    /// Unix timestamp, in milliseconds, of when the statement was made.
+    pub timestamp: u64,
+    pub signature: identity::Signature,
+}
+
+impl AddressPublication {
+    fn signed_message(
+        gateway_identity: &identity::PublicKey,
+        action: PublicationAction,
+        timestamp: u64,
+    ) -> Vec<u8> {
+        PUBLICATION_DOMAIN_SEPARATOR
+            .iter()
+            .cloned()
+            .chain(gateway_identity.to_bytes().iter().cloned())
+            .chain(std::iter::once(action.as_byte()))
+            .chain(timestamp.to_be_bytes().iter().cloned())
+            .collect()
+    }
+
+    pub fn new_signed(
+        client_identity: &identity::KeyPair,
+        gateway_identity: &identity::PublicKey,
+        action: PublicationAction,
+        timestamp: u64,
+    ) -> Self {
+        let message = Self::signed_message(gateway_identity, action, timestamp);
+        AddressPublication {
+            client_identity: *client_identity.public_key(),
+            action,
+            timestamp,
+            signature: client_identity.private_key().sign(&message),
+        }
+    }
+
+    pub fn try_from_base58_fields(
+        client_identity: &str,
+        publish: bool,
+        timestamp: u64,
+        signature: &str,
+    ) -> Result<Self, PublicationError> {
+        let client_identity = identity::PublicKey::from_base58_string(client_identity)
+            .map_err(|_| PublicationError::MalformedIdentity)?;
+        let signature_bytes = bs58::decode(signature)
+            .into_vec()
+            .map_err(|_| PublicationError::MalformedSignature)?;
+        let signature = identity::Signature::from_bytes(&signature_bytes)
+            .map_err(|_| PublicationError::MalformedSignature)?;
+        let action = if publish {
+            PublicationAction::Publish
+        } else {
+            PublicationAction::Revoke
+        };
+
+        Ok(AddressPublication {
+            client_identity,
+            action,
+            timestamp,
+            signature,
+        })
+    }
+
+    /// Checks whether the statement was signed by the client and is meant for this gateway.
+    pub fn verify(&self, gateway_identity: &identity::PublicKey) -> Result<(), PublicationError> {
+        let message = Self::signed_message(gateway_identity, self.action, self.timestamp);
+        self.client_identity
+            .verify(&message, &self.signature)
+            .map_err(|_| PublicationError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod address_publication {
+    use super::*;
+
+    #[test]
+    fn is_valid_for_the_gateway_it_was_made_for() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+
+        let publication = AddressPublication::new_signed(
+            &client,
+            gateway.public_key(),
+            PublicationAction::Publish,
+            42,
+        );
+        assert!(publication.verify(gateway.public_key()).is_ok());
+    }
+
+    #[test]
+    fn is_invalid_for_other_gateways() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+        let other_gateway = identity::KeyPair::new();
+
+        let publication = AddressPublication::new_signed(
+            &client,
+            gateway.public_key(),
+            PublicationAction::Publish,
+            42,
+        );
+        assert!(publication.verify(other_gateway.public_key()).is_err());
+    }
+
+    #[test]
+    fn cannot_have_its_action_or_timestamp_altered() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+
+        let mut publication = AddressPublication::new_signed(
+            &client,
+            gateway.public_key(),
+            PublicationAction::Revoke,
+            42,
+        );
+        publication.action = PublicationAction::Publish;
+        assert!(publication.verify(gateway.public_key()).is_err());
+
+        publication.action = PublicationAction::Revoke;
+        publication.timestamp = 43;
+        assert!(publication.verify(gateway.public_key()).is_err());
+    }
+
+    #[test]
+    fn survives_conversion_through_base58_fields() {
+        let client = identity::KeyPair::new();
+        let gateway = identity::KeyPair::new();
+
+        let publication = AddressPublication::new_signed(
+            &client,
+            gateway.public_key(),
+            PublicationAction::Revoke,
+            42,
+        );
+        let recovered = AddressPublication::try_from_base58_fields(
+            &publication.client_identity.to_base58_string(),
+            false,
+            publication.timestamp,
+            &bs58::encode(publication.signature.to_bytes().to_vec()).into_string(),
+        )
+        .unwrap();
+        assert!(recovered.verify(gateway.public_key()).is_ok());
+    }
+}
@@ -25,6 +25,9 @@ pub enum HandshakeError {
     MalformedResponse,
     MalformedRequest,
     HandshakeFailure,
+    UnsupportedVersion(u8),
+    MismatchedIdentity,
+    NoiseError(snow::Error),
 }
 
 impl Display for HandshakeError {
@@ -45,8 +48,22 @@ impl Display for HandshakeError {
             HandshakeError::MalformedResponse => write!(f, "received response was malformed:"),
             HandshakeError::MalformedRequest => write!(f, "sent request was malformed"),
             HandshakeError::HandshakeFailure => write!(f, "unknown handshake failure"),
+            HandshakeError::UnsupportedVersion(version) => {
+                write!(f, "handshake version {} is not supported", version)
+            }
+            HandshakeError::MismatchedIdentity => write!(
+                f,
+                "the presented identity does not match the key used during the handshake"
+            ),
+            HandshakeError::NoiseError(err) => write!(f, "noise protocol failure - {}", err),
         }
     }
 }
 
 impl std::error::Error for HandshakeError {}
+
+impl From<snow::Error> for HandshakeError {
+    fn from(err: snow::Error) -> Self {
+        HandshakeError::NoiseError(err)
+    }
+}
@@ -15,7 +15,7 @@
 use crate::registration::handshake::shared_key::SharedKey;
 use crate::registration::handshake::state::State;
 use crate::registration::handshake::{error::HandshakeError, WsItem};
-use crypto::asymmetric::encryption;
+use crypto::asymmetric::{encryption, identity};
 use futures::future::BoxFuture;
 use futures::task::{Context, Poll};
 use futures::{Future, Sink, Stream};
@@ -24,14 +24,14 @@ use std::pin::Pin;
 use tokio_tungstenite::tungstenite::Message as WsMessage;
 
 pub(crate) struct GatewayHandshake<'a> {
-    handshake_future: BoxFuture<'a, Result<SharedKey, HandshakeError>>,
+    handshake_future: BoxFuture<'a, Result<(identity::PublicKey, SharedKey), HandshakeError>>,
 }
 
 impl<'a> GatewayHandshake<'a> {
     pub(crate) fn new<S>(
         rng: &mut (impl RngCore + CryptoRng),
         ws_stream: &'a mut S,
-        identity: &'a crypto::asymmetric::identity::KeyPair,
+        identity: &'a identity::KeyPair,
         received_init_payload: Vec<u8>,
     ) -> Self
     where
@@ -91,7 +91,7 @@ impl<'a> GatewayHandshake<'a> {
 
                 // -> Ok
                 state.send_handshake_data(finalizer).await?;
-                Ok(state.finalize_handshake())
+                Ok((remote_identity, state.finalize_handshake()))
             }),
         }
     }
@@ -115,7 +115,7 @@ impl<'a> GatewayHandshake<'a> {
 }
 
 impl<'a> Future for GatewayHandshake<'a> {
-    type Output = Result<SharedKey, HandshakeError>;
+    type Output = Result<(identity::PublicKey, SharedKey), HandshakeError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         Pin::new(&mut self.handshake_future).poll(cx)
@@ -0,0 +1,99 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::registration::handshake::error::HandshakeError;
+use crate::registration::handshake::WsItem;
+use crate::types;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use log::*;
+use std::convert::{TryFrom, TryInto};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+// Helpers for exchanging messages of the registration handshake, regardless of its version.
+
+pub(crate) async fn receive_handshake_message<S>(
+    ws_stream: &mut S,
+) -> Result<Vec<u8>, HandshakeError>
+where
+    S: Stream<Item = WsItem> + Unpin,
+{
+    loop {
+        if let Some(msg) = ws_stream.next().await {
+            if let Ok(msg) = msg {
+                match msg {
+                    WsMessage::Text(ws_msg) => match types::RegistrationHandshake::try_from(ws_msg) {
+                        Ok(reg_handshake_msg) => return match reg_handshake_msg {
+                            types::RegistrationHandshake::HandshakePayload { data } => Ok(data),
+                            types::RegistrationHandshake::HandshakeError { message } => Err(HandshakeError::RemoteError(message)),
+                        },
+                        Err(_) => error!("Received a non-handshake message during the registration handshake! It's getting dropped."),
+                    },
+                    _ => error!("Received non-text message during registration handshake"),
+                }
+            } else {
+                return Err(HandshakeError::NetworkError);
+            }
+        } else {
+            return Err(HandshakeError::ClosedStream);
+        }
+    }
+}
+
+// upon receiving this, the receiver should terminate the handshake
+pub(crate) async fn send_handshake_error<S, M>(
+    ws_stream: &mut S,
+    message: M,
+) -> Result<(), HandshakeError>
+where
+    S: Sink<WsMessage> + Unpin,
+    M: Into<String>,
+{
+    let handshake_message = types::RegistrationHandshake::new_error(message);
+    ws_stream
+        .send(WsMessage::Text(handshake_message.try_into().unwrap()))
+        .await
+        .map_err(|_| HandshakeError::ClosedStream)
+}
+
+pub(crate) async fn send_handshake_data<S>(
+    ws_stream: &mut S,
+    payload: Vec<u8>,
+) -> Result<(), HandshakeError>
+where
+    S: Sink<WsMessage> + Unpin,
+{
+    let handshake_message = types::RegistrationHandshake::new_payload(payload);
+    ws_stream
+        .send(WsMessage::Text(handshake_message.try_into().unwrap()))
+        .await
+        .map_err(|_| HandshakeError::ClosedStream)
+}
+
+/// If any step along the way failed (that are non-network related), tries to send 'error' message
+/// to the remote party to indicate handshake should be terminated.
+pub(crate) async fn check_processing_error<T, S>(
+    result: Result<T, HandshakeError>,
+    ws_stream: &mut S,
+) -> Result<T, HandshakeError>
+where
+    S: Sink<WsMessage> + Unpin,
+{
+    match result {
+        Ok(ok) => Ok(ok),
+        Err(err) => {
+            send_handshake_error(ws_stream, err.to_string()).await?;
+            Err(err)
+        }
+    }
+}
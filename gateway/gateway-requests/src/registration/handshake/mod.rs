@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use self::client::ClientHandshake;
 use self::error::HandshakeError;
 use self::gateway::GatewayHandshake;
 pub use self::shared_key::{SharedKey, SharedKeySize};
 use crypto::asymmetric::identity;
+use futures::future::BoxFuture;
 use futures::{Sink, Stream};
 use rand::rngs::OsRng;
 use rand::{CryptoRng, RngCore};
@@ -25,11 +25,26 @@ use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
 // for ease of use
 pub const DEFAULT_RNG: OsRng = OsRng;
 
+/// The original, STS-based, handshake. Gateways can keep accepting it only for as long as it takes
+/// the clients to migrate to the newer version. Clients that predate handshake versioning
+/// implicitly request it.
+pub const LEGACY_HANDSHAKE_VERSION: u8 = 1;
+
+/// Handshake based on the Noise XK pattern.
+pub const NOISE_HANDSHAKE_VERSION: u8 = 2;
+
+pub const CURRENT_HANDSHAKE_VERSION: u8 = NOISE_HANDSHAKE_VERSION;
+
 pub(crate) type WsItem = Result<WsMessage, WsError>;
 
+// clients no longer perform the legacy handshake, its client side is only kept to make sure
+// gateways still accept it during the migration
+#[cfg(test)]
 mod client;
 pub mod error;
 mod gateway;
+mod messages;
+mod noise;
 pub mod shared_key;
 mod state;
 
@@ -38,8 +53,8 @@ mod state;
 // AsyncWrite and AsyncRead and slightly adjusting the implementation. But right now
 // we do not need to worry about that.
 
+/// Performs the client side of the current version of the handshake.
 pub async fn client_handshake<'a, S>(
-    rng: &mut (impl RngCore + CryptoRng),
     ws_stream: &'a mut S,
     identity: &'a identity::KeyPair,
     gateway_pubkey: identity::PublicKey,
@@ -47,24 +62,85 @@ pub async fn client_handshake<'a, S>(
 where
     S: Stream<Item = WsItem> + Sink<WsMessage> + Unpin + Send + 'a,
 {
-    ClientHandshake::new(rng, ws_stream, identity, gateway_pubkey).await
+    noise::client_handshake(ws_stream, identity, gateway_pubkey).await
 }
 
-pub async fn gateway_handshake<'a, S>(
+/// Performs the gateway side of the handshake in the version chosen by the client,
+/// yielding the authenticated identity of the client alongside the shared key.
+pub fn gateway_handshake<'a, S>(
     rng: &mut (impl RngCore + CryptoRng),
     ws_stream: &'a mut S,
     identity: &'a identity::KeyPair,
+    version: u8,
     received_init_payload: Vec<u8>,
-) -> Result<SharedKey, HandshakeError>
+    accept_legacy: bool,
+) -> BoxFuture<'a, Result<(identity::PublicKey, SharedKey), HandshakeError>>
 where
     S: Stream<Item = WsItem> + Sink<WsMessage> + Unpin + Send + 'a,
 {
-    GatewayHandshake::new(rng, ws_stream, identity, received_init_payload).await
+    match version {
+        NOISE_HANDSHAKE_VERSION => Box::pin(noise::gateway_handshake(
+            rng,
+            ws_stream,
+            identity,
+            received_init_payload,
+        )),
+        LEGACY_HANDSHAKE_VERSION if accept_legacy => Box::pin(GatewayHandshake::new(
+            rng,
+            ws_stream,
+            identity,
+            received_init_payload,
+        )),
+        _ => Box::pin(reject_unsupported_version(
+            ws_stream,
+            version,
+            accept_legacy,
+        )),
+    }
+}
+
+// let the client know which versions it could use instead
+async fn reject_unsupported_version<S>(
+    ws_stream: &mut S,
+    version: u8,
+    accept_legacy: bool,
+) -> Result<(identity::PublicKey, SharedKey), HandshakeError>
+where
+    S: Sink<WsMessage> + Unpin,
+{
+    let supported_versions = if accept_legacy {
+        vec![LEGACY_HANDSHAKE_VERSION, NOISE_HANDSHAKE_VERSION]
+    } else {
+        vec![NOISE_HANDSHAKE_VERSION]
+    };
+    let err = HandshakeError::UnsupportedVersion(version);
+    messages::send_handshake_error(
+        ws_stream,
+        format!("{}. Supported versions: {:?}", err, supported_versions),
+    )
+    .await?;
+    Err(err)
 }
 
 /*
 
-Messages exchanged:
+Messages exchanged (version 2, Noise_XK_25519_ChaChaPoly_BLAKE2s with static keys
+derived from the identities; prologue binds it to the version):
+
+CLIENT -> GATEWAY:
+VERSION || e, es
+
+GATEWAY -> CLIENT
+e, ee
+
+CLIENT -> GATEWAY
+s, se || AEAD(k, CLIENT_ID_KEY)
+
+GATEWAY -> CLIENT
+AEAD(k, SHARED_KEY)
+
+
+Messages exchanged (version 1, legacy):
 
 CLIENT -> GATEWAY:
 CLIENT_ID_KEY || G^x
@@ -79,3 +155,180 @@ GATEWAY -> CLIENT
 DONE(status)
 
 */
+
+#[cfg(test)]
+mod handshake_versions {
+    use super::*;
+    use crate::registration::handshake::client::ClientHandshake;
+    use crate::types::ClientControlRequest;
+    use futures::channel::mpsc;
+    use futures::task::{Context, Poll};
+    use futures::{executor, future, StreamExt};
+    use std::convert::TryFrom;
+    use std::pin::Pin;
+
+    struct MockWsStream {
+        tx: mpsc::UnboundedSender<WsMessage>,
+        rx: mpsc::UnboundedReceiver<WsMessage>,
+    }
+
+    impl MockWsStream {
+        fn pair() -> (Self, Self) {
+            let (client_tx, gateway_rx) = mpsc::unbounded();
+            let (gateway_tx, client_rx) = mpsc::unbounded();
+            (
+                MockWsStream {
+                    tx: client_tx,
+                    rx: client_rx,
+                },
+                MockWsStream {
+                    tx: gateway_tx,
+                    rx: gateway_rx,
+                },
+            )
+        }
+    }
+
+    impl Stream for MockWsStream {
+        type Item = WsItem;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Pin::new(&mut self.rx).poll_next(cx).map(|msg| msg.map(Ok))
+        }
+    }
+
+    impl Sink<WsMessage> for MockWsStream {
+        type Error = WsError;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: WsMessage) -> Result<(), Self::Error> {
+            self.tx
+                .unbounded_send(item)
+                .map_err(|_| WsError::ConnectionClosed)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    // does what the gateway does upon receiving the first message of the handshake
+    async fn run_gateway(
+        ws_stream: &mut MockWsStream,
+        identity: &identity::KeyPair,
+        accept_legacy: bool,
+    ) -> Result<(identity::PublicKey, SharedKey), HandshakeError> {
+        let init = match ws_stream.next().await {
+            Some(Ok(WsMessage::Text(text))) => ClientControlRequest::try_from(text).unwrap(),
+            _ => panic!("did not receive handshake init"),
+        };
+        match init {
+            ClientControlRequest::RegisterHandshakeInitRequest { version, data } => {
+                gateway_handshake(
+                    &mut OsRng,
+                    ws_stream,
+                    identity,
+                    version,
+                    data,
+                    accept_legacy,
+                )
+                .await
+            }
+            _ => panic!("received unexpected request"),
+        }
+    }
+
+    #[test]
+    fn noise_handshake_results_in_same_key_and_authenticated_client() {
+        let client_keys = identity::KeyPair::new();
+        let gateway_keys = identity::KeyPair::new();
+        let (mut client_ws, mut gateway_ws) = MockWsStream::pair();
+
+        let (client_res, gateway_res) = executor::block_on(future::join(
+            client_handshake(&mut client_ws, &client_keys, *gateway_keys.public_key()),
+            run_gateway(&mut gateway_ws, &gateway_keys, false),
+        ));
+
+        let client_key = client_res.unwrap();
+        let (client_identity, gateway_key) = gateway_res.unwrap();
+        assert_eq!(client_key.to_bytes(), gateway_key.to_bytes());
+        assert_eq!(&client_identity, client_keys.public_key());
+    }
+
+    #[test]
+    fn noise_handshake_fails_for_unexpected_gateway_key() {
+        let client_keys = identity::KeyPair::new();
+        let gateway_keys = identity::KeyPair::new();
+        let other_gateway_keys = identity::KeyPair::new();
+        let (mut client_ws, mut gateway_ws) = MockWsStream::pair();
+
+        let (client_res, gateway_res) = executor::block_on(future::join(
+            client_handshake(
+                &mut client_ws,
+                &client_keys,
+                *other_gateway_keys.public_key(),
+            ),
+            run_gateway(&mut gateway_ws, &gateway_keys, true),
+        ));
+
+        assert!(client_res.is_err());
+        assert!(gateway_res.is_err());
+    }
+
+    #[test]
+    fn legacy_handshake_is_accepted_during_migration() {
+        let client_keys = identity::KeyPair::new();
+        let gateway_keys = identity::KeyPair::new();
+        let (mut client_ws, mut gateway_ws) = MockWsStream::pair();
+
+        let (client_res, gateway_res) = executor::block_on(future::join(
+            // clients that predate handshake versioning do not specify it
+            ClientHandshake::new(
+                &mut OsRng,
+                &mut client_ws,
+                &client_keys,
+                *gateway_keys.public_key(),
+            ),
+            run_gateway(&mut gateway_ws, &gateway_keys, true),
+        ));
+
+        let client_key = client_res.unwrap();
+        let (client_identity, gateway_key) = gateway_res.unwrap();
+        assert_eq!(client_key.to_bytes(), gateway_key.to_bytes());
+        assert_eq!(&client_identity, client_keys.public_key());
+    }
+
+    #[test]
+    fn legacy_handshake_is_rejected_after_migration() {
+        let client_keys = identity::KeyPair::new();
+        let gateway_keys = identity::KeyPair::new();
+        let (mut client_ws, mut gateway_ws) = MockWsStream::pair();
+
+        let (client_res, gateway_res) = executor::block_on(future::join(
+            // clients that predate handshake versioning do not specify it
+            ClientHandshake::new(
+                &mut OsRng,
+                &mut client_ws,
+                &client_keys,
+                *gateway_keys.public_key(),
+            ),
+            run_gateway(&mut gateway_ws, &gateway_keys, false),
+        ));
+
+        match client_res {
+            Err(HandshakeError::RemoteError(_)) => (),
+            _ => panic!("expected the gateway to reject the handshake"),
+        }
+        match gateway_res {
+            Err(HandshakeError::UnsupportedVersion(LEGACY_HANDSHAKE_VERSION)) => (),
+            _ => panic!("expected unsupported version"),
+        }
+    }
+}
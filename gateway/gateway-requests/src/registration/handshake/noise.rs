@@ -0,0 +1,184 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::registration::handshake::error::HandshakeError;
+use crate::registration::handshake::messages::{
+    check_processing_error, receive_handshake_message, send_handshake_data,
+};
+use crate::registration::handshake::shared_key::SharedKey;
+use crate::registration::handshake::{WsItem, NOISE_HANDSHAKE_VERSION};
+use crate::types::ClientControlRequest;
+use crypto::asymmetric::identity;
+use futures::{Future, Sink, SinkExt, Stream};
+use rand::{CryptoRng, RngCore};
+use snow::{Builder, HandshakeState};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// With the XK pattern the client knows the static key of the gateway (derived from its identity)
+/// in advance, while its own static key is only ever sent encrypted.
+const NOISE_PARAMS: &str = "Noise_XK_25519_ChaChaPoly_BLAKE2s";
+
+/// Binds the handshake to its purpose. The handshake version gets appended to it.
+const PROLOGUE: &[u8] = b"NYM_GATEWAY_REGISTRATION";
+
+const MAX_NOISE_MESSAGE_LENGTH: usize = 65535;
+
+fn prologue() -> Vec<u8> {
+    PROLOGUE
+        .iter()
+        .cloned()
+        .chain(std::iter::once(NOISE_HANDSHAKE_VERSION))
+        .collect()
+}
+
+fn builder<'a>(prologue: &'a [u8], local_identity: &'a [u8]) -> Builder<'a> {
+    // the params are hardcoded, so parsing them can only fail if they are malformed
+    Builder::new(NOISE_PARAMS.parse().unwrap())
+        .prologue(prologue)
+        .local_private_key(local_identity)
+}
+
+fn write_handshake_message(
+    noise: &mut HandshakeState,
+    payload: &[u8],
+) -> Result<Vec<u8>, HandshakeError> {
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let len = noise.write_message(payload, &mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn read_handshake_message(
+    noise: &mut HandshakeState,
+    message: &[u8],
+) -> Result<Vec<u8>, HandshakeError> {
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let len = noise.read_message(message, &mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+// the identity sent by the client must be the one its static noise key got derived from
+fn parse_client_identity(
+    noise: &HandshakeState,
+    payload: &[u8],
+) -> Result<identity::PublicKey, HandshakeError> {
+    let client_identity =
+        identity::PublicKey::from_bytes(payload).map_err(|_| HandshakeError::MalformedRequest)?;
+    match noise.get_remote_static() {
+        Some(remote_static) if remote_static == client_identity.to_x25519().to_bytes() => {
+            Ok(client_identity)
+        }
+        _ => Err(HandshakeError::MismatchedIdentity),
+    }
+}
+
+pub(crate) async fn client_handshake<S>(
+    ws_stream: &mut S,
+    identity: &identity::KeyPair,
+    gateway_pubkey: identity::PublicKey,
+) -> Result<SharedKey, HandshakeError>
+where
+    S: Stream<Item = WsItem> + Sink<WsMessage> + Unpin,
+{
+    let prologue = prologue();
+    let local_static = identity.private_key().to_x25519().to_bytes();
+    let gateway_static = gateway_pubkey.to_x25519().to_bytes();
+    let mut noise = builder(&prologue, &local_static)
+        .remote_public_key(&gateway_static)
+        .build_initiator()?;
+
+    // -> e, es
+    let init_message = write_handshake_message(&mut noise, &[])?;
+    ws_stream
+        .send(
+            ClientControlRequest::new_register_handshake_init(
+                NOISE_HANDSHAKE_VERSION,
+                init_message,
+            )
+            .into(),
+        )
+        .await
+        .map_err(|_| HandshakeError::ClosedStream)?;
+
+    // <- e, ee
+    let mid_res = receive_handshake_message(ws_stream).await?;
+    check_processing_error(read_handshake_message(&mut noise, &mid_res), ws_stream).await?;
+
+    // -> s, se (with our identity as payload)
+    let material = write_handshake_message(&mut noise, &identity.public_key().to_bytes())?;
+    send_handshake_data(ws_stream, material).await?;
+
+    // <- AEAD(k, shared_key)
+    // being able to decrypt it confirms the gateway derived the same keys from the same transcript
+    let mut transport = noise.into_transport_mode()?;
+    let finalization = receive_handshake_message(ws_stream).await?;
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let read_res = transport
+        .read_message(&finalization, &mut buf)
+        .map_err(HandshakeError::from)
+        .and_then(|len| {
+            SharedKey::try_from_bytes(&buf[..len]).map_err(|_| HandshakeError::MalformedResponse)
+        });
+    check_processing_error(read_res, ws_stream).await
+}
+
+// The shared key is generated before anything is awaited, so that the returned future
+// would not need to hold onto the rng.
+pub(crate) fn gateway_handshake<'a, S>(
+    rng: &mut (impl RngCore + CryptoRng),
+    ws_stream: &'a mut S,
+    identity: &'a identity::KeyPair,
+    received_init_payload: Vec<u8>,
+) -> impl Future<Output = Result<(identity::PublicKey, SharedKey), HandshakeError>> + 'a
+where
+    S: Stream<Item = WsItem> + Sink<WsMessage> + Unpin + 'a,
+{
+    let shared_key = SharedKey::new_with_rng(rng);
+
+    async move {
+        let prologue = prologue();
+        let local_static = identity.private_key().to_x25519().to_bytes();
+        let mut noise = builder(&prologue, &local_static).build_responder()?;
+
+        // <- e, es
+        check_processing_error(
+            read_handshake_message(&mut noise, &received_init_payload),
+            ws_stream,
+        )
+        .await?;
+
+        // -> e, ee
+        let mid_res = write_handshake_message(&mut noise, &[])?;
+        send_handshake_data(ws_stream, mid_res).await?;
+
+        // <- s, se (with client's identity as payload)
+        let remote_material = receive_handshake_message(ws_stream).await?;
+        let client_identity = check_processing_error(
+            read_handshake_message(&mut noise, &remote_material)
+                .and_then(|payload| parse_client_identity(&noise, &payload)),
+            ws_stream,
+        )
+        .await?;
+
+        // -> AEAD(k, shared_key)
+        let mut transport = noise.into_transport_mode()?;
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+        let len = transport.write_message(shared_key.as_bytes(), &mut buf)?;
+        buf.truncate(len);
+        send_handshake_data(ws_stream, buf).await?;
+
+        Ok((client_identity, shared_key))
+    }
+}
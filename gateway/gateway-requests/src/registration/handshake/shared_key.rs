@@ -13,9 +13,11 @@
 // limitations under the License.
 
 use crypto::symmetric::aes_ctr::{
+    generate_key,
     generic_array::{typenum::Unsigned, GenericArray},
     Aes128Key, Aes128KeySize,
 };
+use rand::{CryptoRng, RngCore};
 use std::ops::Deref;
 
 pub type SharedKeySize = Aes128KeySize;
@@ -31,6 +33,10 @@ pub enum SharedKeyConversionError {
 }
 
 impl SharedKey {
+    pub fn new_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        SharedKey(generate_key(rng))
+    }
+
     pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, SharedKeyConversionError> {
         if bytes.len() != SharedKeySize::to_usize() {
             return Err(SharedKeyConversionError::BytesOfInvalidLengthError);
@@ -13,18 +13,16 @@
 // limitations under the License.
 
 use crate::registration::handshake::error::HandshakeError;
+use crate::registration::handshake::messages;
 use crate::registration::handshake::shared_key::{SharedKey, SharedKeySize};
 use crate::registration::handshake::WsItem;
-use crate::types;
 use crypto::{
     asymmetric::{encryption, identity},
     kdf::blake3_hkdf,
     symmetric::aes_ctr::{self, generic_array::typenum::Unsigned},
 };
-use futures::{Sink, SinkExt, Stream, StreamExt};
-use log::*;
+use futures::{Sink, Stream};
 use rand::{CryptoRng, RngCore};
-use std::convert::{TryFrom, TryInto};
 use tokio_tungstenite::tungstenite::Message as WsMessage;
 
 /// Handshake state.
@@ -67,6 +65,7 @@ impl<'a, S> State<'a, S> {
     // LOCAL_ID_PUBKEY || EPHEMERAL_KEY
     // Eventually the ID_PUBKEY prefix will get removed and recipient will know
     // initializer's identity from another source.
+    #[cfg(test)]
     pub(crate) fn init_message(&self) -> Vec<u8> {
         self.identity
             .public_key()
@@ -197,26 +196,7 @@ impl<'a, S> State<'a, S> {
     where
         S: Stream<Item = WsItem> + Unpin,
     {
-        loop {
-            if let Some(msg) = self.ws_stream.next().await {
-                if let Ok(msg) = msg {
-                    match msg {
-                        WsMessage::Text(ws_msg) => match types::RegistrationHandshake::try_from(ws_msg) {
-                            Ok(reg_handshake_msg) => return match reg_handshake_msg {
-                                types::RegistrationHandshake::HandshakePayload { data } => Ok(data),
-                                types::RegistrationHandshake::HandshakeError { message } => Err(HandshakeError::RemoteError(message)),
-                            },
-                            Err(_) => error!("Received a non-handshake message during the registration handshake! It's getting dropped."),
-                        },
-                        _ => error!("Received non-text message during registration handshake"),
-                    }
-                } else {
-                    return Err(HandshakeError::NetworkError);
-                }
-            } else {
-                return Err(HandshakeError::ClosedStream);
-            }
-        }
+        messages::receive_handshake_message(self.ws_stream).await
     }
 
     // upon receiving this, the receiver should terminate the handshake
@@ -227,11 +207,7 @@ impl<'a, S> State<'a, S> {
     where
         S: Sink<WsMessage> + Unpin,
     {
-        let handshake_message = types::RegistrationHandshake::new_error(message);
-        self.ws_stream
-            .send(WsMessage::Text(handshake_message.try_into().unwrap()))
-            .await
-            .map_err(|_| HandshakeError::ClosedStream)
+        messages::send_handshake_error(self.ws_stream, message).await
     }
 
     pub(crate) async fn send_handshake_data(
@@ -241,11 +217,7 @@ impl<'a, S> State<'a, S> {
     where
         S: Sink<WsMessage> + Unpin,
     {
-        let handshake_message = types::RegistrationHandshake::new_payload(payload);
-        self.ws_stream
-            .send(WsMessage::Text(handshake_message.try_into().unwrap()))
-            .await
-            .map_err(|_| HandshakeError::ClosedStream)
+        messages::send_handshake_data(self.ws_stream, payload).await
     }
 
     /// Finish the handshake, yielding the derived shared key and implicitly dropping all borrowed
@@ -14,7 +14,6 @@
 
 pub mod handshake;
 
-// TODO: is it perhaps possible to replace the 'custom' handshake with an existing
-// implementation like with one of the variants on the Noise framework?
-
-// Right now it's based on the STS (Station-to-Station) Protocol.
+// The handshake is based on the Noise XK pattern, with the static keys derived from the
+// identities of the parties. The previous, STS (Station-to-Station) based, version
+// can still be accepted by the gateways during the migration period.
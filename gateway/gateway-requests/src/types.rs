@@ -12,13 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::authentication::encrypted_address::EncryptedAddressBytes;
-use crate::authentication::iv::AuthenticationIV;
-use crate::registration::handshake::SharedKey;
-use crypto::symmetric::aes_ctr;
+use crate::authentication::challenge::{AuthenticationChallenge, ChallengeResponse, SessionToken};
+use crate::authentication::{CURRENT_AUTHENTICATION_VERSION, LEGACY_AUTHENTICATION_VERSION};
+use crate::channel::{ChannelDecryptor, ChannelEncryptor, ChannelError, SessionNonce};
+use crate::publication::{AddressPublication, PublicationAction};
+use crate::registration::handshake::LEGACY_HANDSHAKE_VERSION;
 use nymsphinx::addressing::nodes::{NymNodeRoutingAddress, NymNodeRoutingAddressError};
 use nymsphinx::params::packet_sizes::PacketSize;
-use nymsphinx::{DestinationAddressBytes, SphinxPacket};
+use nymsphinx::SphinxPacket;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{TryFrom, TryInto},
@@ -67,7 +68,8 @@ pub enum GatewayRequestsError {
     IncorrectlyEncodedAddress,
     RequestOfInvalidSize(usize),
     MalformedSphinxPacket,
-    MalformedEncryption,
+    ChannelFailure(ChannelError),
+    MalformedServerData,
 }
 
 // to use it as `std::error::Error`, and we don't want to just derive is because we want
@@ -83,11 +85,18 @@ impl fmt::Display for GatewayRequestsError {
                 actual, PacketSize::ACKPacket.size(), PacketSize::RegularPacket.size(), PacketSize::ExtendedPacket.size()
             ),
             MalformedSphinxPacket => write!(f, "received sphinx packet was malformed"),
-            MalformedEncryption => write!(f, "the received encrypted data was malformed"),
+            ChannelFailure(err) => write!(f, "the received encrypted data was invalid - {}", err),
+            MalformedServerData => write!(f, "the received server data was malformed"),
         }
     }
 }
 
+impl From<ChannelError> for GatewayRequestsError {
+    fn from(err: ChannelError) -> Self {
+        GatewayRequestsError::ChannelFailure(err)
+    }
+}
+
 impl From<NymNodeRoutingAddressError> for GatewayRequestsError {
     fn from(_: NymNodeRoutingAddressError) -> Self {
         GatewayRequestsError::IncorrectlyEncodedAddress
@@ -97,25 +106,115 @@ impl From<NymNodeRoutingAddressError> for GatewayRequestsError {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum ClientControlRequest {
+    /// Asks the gateway for a challenge to be signed in the subsequent `Authenticate` request.
+    RequestChallenge,
+    /// Signature of the client over the most recently obtained challenge. Clients that predate
+    /// authentication versioning send neither the version nor the signature, which implies
+    /// the legacy version.
     Authenticate {
+        #[serde(default = "legacy_authentication_version")]
+        version: u8,
         address: String,
-        enc_address: String,
-        iv: String,
+        #[serde(default)]
+        signature: String,
+        #[serde(default)]
+        retrieval_mode: RetrievalMode,
     },
+    /// Signature of the client over a session token obtained during an earlier session.
+    /// It lets the client skip requesting a challenge when reconnecting.
+    ResumeSession {
+        version: u8,
+        address: String,
+        token: String,
+        signature: String,
+        #[serde(default)]
+        retrieval_mode: RetrievalMode,
+    },
+    /// Initial message of the registration handshake. Clients that predate handshake versioning
+    /// send it as a plain handshake payload, which implies the legacy version.
     #[serde(alias = "handshakePayload")]
-    RegisterHandshakeInitRequest { data: Vec<u8> },
+    RegisterHandshakeInitRequest {
+        #[serde(default = "legacy_handshake_version")]
+        version: u8,
+        data: Vec<u8>,
+    },
+    /// Confirms receipt of the pushed or fetched stored messages, so that the gateway
+    /// could remove them.
+    AckMessages { ids: Vec<u64> },
+    /// Asks for the oldest stored messages, at most `max_count` of them and of at most
+    /// `max_bytes` in total, unless the oldest one alone is larger than that.
+    /// Only available in the pull retrieval mode.
+    FetchMessages { max_count: u32, max_bytes: u64 },
+    /// Asks how many messages are currently stored for the client.
+    InboxStatus,
+    /// Signed statement on whether the address of the client should be listed
+    /// in the presence of the gateway. By default it is not listed.
+    AddressPublication {
+        identity: String,
+        publish: bool,
+        timestamp: u64,
+        signature: String,
+    },
+    /// Asks the gateway to forget the client, i.e. its shared key and all of its stored messages.
+    /// The gateway closes the connection afterwards.
+    Deregister,
+    /// Announces that the registration handshake is about to be performed again
+    /// in order to replace the current shared key.
+    RotateKey,
+    /// Confirms the client has switched to the key established by the preceding rotation
+    /// handshake. Until then, the gateway keeps the previous key.
+    ConfirmKeyRotation,
+    /// Chooses how messages are handed over to the sessions of the client if it has more than
+    /// a single one open at the same time. It applies to all current and future sessions.
+    SetDeliveryPolicy { policy: DeliveryPolicy },
+}
+
+fn legacy_handshake_version() -> u8 {
+    LEGACY_HANDSHAKE_VERSION
+}
+
+fn legacy_authentication_version() -> u8 {
+    LEGACY_AUTHENTICATION_VERSION
 }
 
 impl ClientControlRequest {
-    pub fn new_authenticate(
-        address: DestinationAddressBytes,
-        enc_address: EncryptedAddressBytes,
-        iv: AuthenticationIV,
-    ) -> Self {
+    pub fn new_register_handshake_init(version: u8, data: Vec<u8>) -> Self {
+        ClientControlRequest::RegisterHandshakeInitRequest { version, data }
+    }
+
+    pub fn new_authenticate(response: ChallengeResponse, retrieval_mode: RetrievalMode) -> Self {
         ClientControlRequest::Authenticate {
-            address: address.to_base58_string(),
-            enc_address: enc_address.to_base58_string(),
-            iv: iv.to_base58_string(),
+            version: CURRENT_AUTHENTICATION_VERSION,
+            address: response.client_address().to_base58_string(),
+            signature: response.signature_to_base58_string(),
+            retrieval_mode,
+        }
+    }
+
+    pub fn new_resume_session(
+        token: SessionToken,
+        response: ChallengeResponse,
+        retrieval_mode: RetrievalMode,
+    ) -> Self {
+        ClientControlRequest::ResumeSession {
+            version: CURRENT_AUTHENTICATION_VERSION,
+            address: response.client_address().to_base58_string(),
+            token: token.to_base58_string(),
+            signature: response.signature_to_base58_string(),
+            retrieval_mode,
+        }
+    }
+
+    pub fn new_ack_messages(ids: Vec<u64>) -> Self {
+        ClientControlRequest::AckMessages { ids }
+    }
+
+    pub fn new_address_publication(publication: AddressPublication) -> Self {
+        ClientControlRequest::AddressPublication {
+            identity: publication.client_identity.to_base58_string(),
+            publish: publication.action == PublicationAction::Publish,
+            timestamp: publication.timestamp,
+            signature: bs58::encode(publication.signature.to_bytes().to_vec()).into_string(),
         }
     }
 }
@@ -148,13 +247,133 @@ impl TryInto<String> for ClientControlRequest {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum ServerResponse {
-    Authenticate { status: bool },
-    Register { status: bool },
-    Send { status: bool },
-    Error { message: String },
+    Challenge {
+        challenge: String,
+    },
+    /// Response to either authentication or session resumption. Upon success, carries the nonce
+    /// of the session the encrypted channel is derived for and the token to resume it with.
+    Authenticate {
+        status: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_nonce: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_token: Option<String>,
+    },
+    Register {
+        status: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_nonce: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_token: Option<String>,
+    },
+    Send {
+        status: bool,
+    },
+    AddressPublication {
+        status: bool,
+    },
+    Deregister {
+        status: bool,
+    },
+    RotateKey {
+        status: bool,
+    },
+    ConfirmKeyRotation {
+        status: bool,
+    },
+    SetDeliveryPolicy {
+        status: bool,
+    },
+    /// Sent once the fetched messages, if any, were sent over the channel.
+    FetchMessages {
+        count: usize,
+    },
+    InboxStatus {
+        count: usize,
+        bytes: usize,
+    },
+    Error {
+        message: String,
+    },
 }
 
 impl ServerResponse {
+    pub fn new_challenge(challenge: AuthenticationChallenge) -> Self {
+        ServerResponse::Challenge {
+            challenge: challenge.to_base58_string(),
+        }
+    }
+
+    pub fn new_authenticate(session_nonce: Option<SessionNonce>) -> Self {
+        ServerResponse::Authenticate {
+            status: session_nonce.is_some(),
+            session_nonce: session_nonce.map(|nonce| nonce.to_base58_string()),
+            session_token: None,
+        }
+    }
+
+    pub fn new_register(session_nonce: Option<SessionNonce>) -> Self {
+        ServerResponse::Register {
+            status: session_nonce.is_some(),
+            session_nonce: session_nonce.map(|nonce| nonce.to_base58_string()),
+            session_token: None,
+        }
+    }
+
+    /// Attaches the token the established session can be resumed with.
+    /// It has no effect on responses other than to successful authentication or registration.
+    pub fn with_session_token(mut self, token: SessionToken) -> Self {
+        match &mut self {
+            ServerResponse::Authenticate {
+                status: true,
+                session_token,
+                ..
+            }
+            | ServerResponse::Register {
+                status: true,
+                session_token,
+                ..
+            } => *session_token = Some(token.to_base58_string()),
+            _ => (),
+        }
+        self
+    }
+
+    /// Nonce of the session established by successful authentication or registration.
+    pub fn session_nonce(&self) -> Option<SessionNonce> {
+        match self {
+            ServerResponse::Authenticate {
+                status: true,
+                session_nonce: Some(nonce),
+                ..
+            }
+            | ServerResponse::Register {
+                status: true,
+                session_nonce: Some(nonce),
+                ..
+            } => SessionNonce::try_from_base58_string(nonce.as_str()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Token the session established by successful authentication or registration can be
+    /// resumed with.
+    pub fn session_token(&self) -> Option<SessionToken> {
+        match self {
+            ServerResponse::Authenticate {
+                status: true,
+                session_token: Some(token),
+                ..
+            }
+            | ServerResponse::Register {
+                status: true,
+                session_token: Some(token),
+                ..
+            } => SessionToken::try_from_base58_string(token.as_str()).ok(),
+            _ => None,
+        }
+    }
+
     pub fn new_error<S: Into<String>>(msg: S) -> Self {
         ServerResponse::Error {
             message: msg.into(),
@@ -194,6 +413,132 @@ impl TryFrom<String> for ServerResponse {
     }
 }
 
+/// Specifies how messages received from the mix network are handed over to the client
+/// that has several sessions open with the gateway, for example when it is used
+/// on multiple devices at once.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryPolicy {
+    /// Every message is pushed to all of the sessions open at the time of its arrival.
+    FanOut,
+    /// Every message is handed to only one of the sessions at a time and belongs to the first
+    /// session that acknowledges it. If that session is gone before acknowledging it,
+    /// the message is handed to one of the remaining ones.
+    FirstAcknowledged,
+}
+
+impl Default for DeliveryPolicy {
+    fn default() -> Self {
+        DeliveryPolicy::FanOut
+    }
+}
+
+/// Specifies how the messages stored by the gateway are handed over to the client.
+/// Messages received while the client is connected are pushed to it regardless.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RetrievalMode {
+    /// Stored messages are pushed as soon as the client authenticates and whenever
+    /// new ones arrive.
+    Push,
+    /// Stored messages are only sent when the client explicitly fetches them.
+    Pull,
+}
+
+impl Default for RetrievalMode {
+    fn default() -> Self {
+        RetrievalMode::Push
+    }
+}
+
+/// Message that was stored by the gateway while its recipient was offline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    /// Identifier used for acknowledging the receipt of the message.
+    pub id: u64,
+    /// Unix timestamp, in milliseconds, of when the message was received by the gateway.
+    pub arrival_timestamp: u64,
+    pub data: Vec<u8>,
+}
+
+impl StoredMessage {
+    pub fn new(id: u64, arrival_timestamp: u64, data: Vec<u8>) -> Self {
+        StoredMessage {
+            id,
+            arrival_timestamp,
+            data,
+        }
+    }
+}
+
+/// Data sent by the gateway on its own accord, rather than in response to a client request.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerPush {
+    /// Batch of messages from the inbox of the client. In the push retrieval mode, the next batch
+    /// is only pushed after all messages of this one got acknowledged with
+    /// `ClientControlRequest::AckMessages`. In the pull mode, it is sent in response to
+    /// `ClientControlRequest::FetchMessages`.
+    StoredMessages { messages: Vec<StoredMessage> },
+}
+
+impl TryFrom<String> for ServerPush {
+    type Error = serde_json::Error;
+
+    fn try_from(msg: String) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(&msg)
+    }
+}
+
+const MIX_MESSAGE_TAG: u8 = 0;
+const PUSH_TAG: u8 = 1;
+
+/// Data sent by the gateway to the authenticated client. It is always encrypted
+/// over the channel established upon authentication.
+#[derive(Debug)]
+pub enum ServerData {
+    /// Message received from the mixnet while the client was connected.
+    MixMessage(Vec<u8>),
+    Push(ServerPush),
+}
+
+impl ServerData {
+    pub fn try_from_encrypted_bytes(
+        raw_data: &[u8],
+        decryptor: &mut ChannelDecryptor,
+    ) -> Result<Self, GatewayRequestsError> {
+        let mut data = decryptor.decrypt(raw_data)?;
+        if data.is_empty() {
+            return Err(GatewayRequestsError::MalformedServerData);
+        }
+
+        let content = data.split_off(1);
+        match data[0] {
+            MIX_MESSAGE_TAG => Ok(ServerData::MixMessage(content)),
+            PUSH_TAG => serde_json::from_slice(&content)
+                .map(ServerData::Push)
+                .map_err(|_| GatewayRequestsError::MalformedServerData),
+            _ => Err(GatewayRequestsError::MalformedServerData),
+        }
+    }
+
+    pub fn into_encrypted_bytes(self, encryptor: &mut ChannelEncryptor) -> Vec<u8> {
+        let (tag, content) = match self {
+            ServerData::MixMessage(message) => (MIX_MESSAGE_TAG, message),
+            // it should be safe to call `unwrap` here as the push is generated by the server
+            // so if it fails (and consequently panics) it's a bug that should be resolved
+            ServerData::Push(push) => (PUSH_TAG, serde_json::to_vec(&push).unwrap()),
+        };
+        let data: Vec<_> = std::iter::once(tag).chain(content.into_iter()).collect();
+        encryptor.encrypt(&data)
+    }
+
+    pub fn into_ws_message(self, encryptor: &mut ChannelEncryptor) -> Message {
+        Message::Binary(self.into_encrypted_bytes(encryptor))
+    }
+}
+
 pub enum BinaryRequest {
     ForwardSphinx {
         address: SocketAddr,
@@ -201,31 +546,22 @@ pub enum BinaryRequest {
     },
 }
 
-// TODO: ask @AP if that's sufficient
-const PADDING_LEN: usize = 16;
-
 // Right now the only valid `BinaryRequest` is a request to forward a sphinx packet.
-// It is encrypted using the derived shared key between client and the gateway. Thanks to
-// randomness inside the sphinx packet themselves (even via the same route), the 0s IV can be used here.
-// HOWEVER, NOTE: If we introduced another 'BinaryRequest', we must carefully examine if a 0s IV
-// would work there.
+// It is encrypted over the channel established between the client and the gateway
+// upon authentication.
 impl BinaryRequest {
     pub fn try_from_encrypted_bytes(
-        mut raw_req: Vec<u8>,
-        shared_key: &SharedKey,
+        raw_req: &[u8],
+        decryptor: &mut ChannelDecryptor,
     ) -> Result<Self, GatewayRequestsError> {
-        aes_ctr::decrypt_in_place(shared_key, &aes_ctr::zero_iv(), &mut raw_req);
-        // see if the padding is retained
-        if !raw_req.iter().rev().take(PADDING_LEN).all(|&x| x == 0) {
-            return Err(GatewayRequestsError::MalformedEncryption);
-        }
+        let raw_req = decryptor.decrypt(raw_req)?;
 
         // right now there's only a single option possible which significantly simplifies the logic
         // if we decided to allow for more 'binary' messages, the API wouldn't need to change
         let address = NymNodeRoutingAddress::try_from_bytes(&raw_req)?;
         let addr_offset = address.bytes_min_len();
 
-        let sphinx_packet_data = &raw_req[addr_offset..raw_req.len() - PADDING_LEN];
+        let sphinx_packet_data = &raw_req[addr_offset..];
         let packet_size = sphinx_packet_data.len();
         if let Err(_) = PacketSize::get_type(packet_size) {
             // TODO: should this allow AckPacket sizes?
@@ -244,7 +580,7 @@ impl BinaryRequest {
         }
     }
 
-    pub fn into_encrypted_bytes(self, shared_key: &SharedKey) -> Vec<u8> {
+    pub fn into_encrypted_bytes(self, encryptor: &mut ChannelEncryptor) -> Vec<u8> {
         match self {
             BinaryRequest::ForwardSphinx {
                 address,
@@ -255,21 +591,17 @@ impl BinaryRequest {
                 // dependant on what it does
                 let wrapped_address = NymNodeRoutingAddress::from(address);
 
-                // add 16 bytes of padding so that the gateway could catch incorrect encryption
-                let mut gateway_data: Vec<_> = wrapped_address
+                let gateway_data: Vec<_> = wrapped_address
                     .as_bytes()
                     .into_iter()
                     .chain(sphinx_packet.to_bytes().into_iter())
-                    .chain(std::iter::repeat(0).take(PADDING_LEN))
                     .collect();
 
-                aes_ctr::encrypt_in_place(shared_key, &aes_ctr::zero_iv(), &mut gateway_data);
-                gateway_data
+                encryptor.encrypt(&gateway_data)
             }
         }
     }
 
-    // TODO: this will be encrypted, etc.
     pub fn new_forward_request(address: SocketAddr, sphinx_packet: SphinxPacket) -> BinaryRequest {
         BinaryRequest::ForwardSphinx {
             address,
@@ -277,14 +609,91 @@ impl BinaryRequest {
         }
     }
 
-    pub fn into_ws_message(self, shared_key: &SharedKey) -> Message {
-        Message::Binary(self.into_encrypted_bytes(shared_key))
+    pub fn into_ws_message(self, encryptor: &mut ChannelEncryptor) -> Message {
+        Message::Binary(self.into_encrypted_bytes(encryptor))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::channel::{derive_channel, ChannelSide};
+    use crate::registration::handshake::SharedKey;
+    use rand::rngs::OsRng;
+
+    fn channel_halves() -> (ChannelEncryptor, ChannelDecryptor) {
+        let shared_key = SharedKey::new_with_rng(&mut OsRng);
+        let session_nonce = SessionNonce::new_random(&mut OsRng);
+        let (gateway_encryptor, _) =
+            derive_channel(&shared_key, &session_nonce, ChannelSide::Gateway);
+        let (_, client_decryptor) =
+            derive_channel(&shared_key, &session_nonce, ChannelSide::Client);
+        (gateway_encryptor, client_decryptor)
+    }
+
+    #[test]
+    fn server_data_survives_channel_encryption() {
+        let (mut encryptor, mut decryptor) = channel_halves();
+
+        let mix_message =
+            ServerData::MixMessage(vec![1, 2, 3]).into_encrypted_bytes(&mut encryptor);
+        match ServerData::try_from_encrypted_bytes(&mix_message, &mut decryptor).unwrap() {
+            ServerData::MixMessage(message) => assert_eq!(message, vec![1, 2, 3]),
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+
+        let push = ServerData::Push(ServerPush::StoredMessages {
+            messages: vec![StoredMessage::new(1, 42, vec![1, 2, 3])],
+        })
+        .into_encrypted_bytes(&mut encryptor);
+        match ServerData::try_from_encrypted_bytes(&push, &mut decryptor).unwrap() {
+            ServerData::Push(ServerPush::StoredMessages { messages }) => {
+                assert_eq!(messages, vec![StoredMessage::new(1, 42, vec![1, 2, 3])])
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+
+        // and it can't be delivered twice
+        assert!(ServerData::try_from_encrypted_bytes(&push, &mut decryptor).is_err());
+    }
+
+    #[test]
+    fn session_nonce_is_only_present_in_successful_responses() {
+        let nonce = SessionNonce::new_random(&mut OsRng);
+        let response = ServerResponse::new_authenticate(Some(nonce));
+        let serialized = serde_json::to_string(&response).unwrap();
+        let deserialized = ServerResponse::try_from(serialized).unwrap();
+        assert!(deserialized.implies_successful_authentication());
+        assert_eq!(Some(nonce), deserialized.session_nonce());
+
+        let failure = serde_json::to_string(&ServerResponse::new_register(None)).unwrap();
+        assert_eq!(r#"{"type":"register","status":false}"#, failure);
+        assert!(ServerResponse::try_from(failure)
+            .unwrap()
+            .session_nonce()
+            .is_none());
+    }
+
+    #[test]
+    fn session_token_is_only_attached_to_successful_responses() {
+        let nonce = SessionNonce::new_random(&mut OsRng);
+        let token = SessionToken::new_random(&mut OsRng);
+        let response = ServerResponse::new_register(Some(nonce)).with_session_token(token);
+        let serialized = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            Some(token),
+            ServerResponse::try_from(serialized)
+                .unwrap()
+                .session_token()
+        );
+
+        let failure = ServerResponse::new_authenticate(None).with_session_token(token);
+        assert!(failure.session_token().is_none());
+        assert_eq!(
+            r#"{"type":"authenticate","status":false}"#,
+            serde_json::to_string(&failure).unwrap()
+        );
+    }
 
     #[test]
     fn handshake_payload_can_be_deserialized_into_register_handshake_init_request() {
@@ -296,10 +705,110 @@ mod tests {
         let deserialized = ClientControlRequest::try_from(serialized).unwrap();
 
         match deserialized {
-            ClientControlRequest::RegisterHandshakeInitRequest { data } => {
+            ClientControlRequest::RegisterHandshakeInitRequest { version, data } => {
+                assert_eq!(version, LEGACY_HANDSHAKE_VERSION);
                 assert_eq!(data, handshake_data)
             }
             _ => unreachable!("this branch shouldn't have been reached!"),
         }
     }
+
+    #[test]
+    fn authenticate_request_of_legacy_client_implies_legacy_version() {
+        let legacy_request =
+            r#"{"type":"authenticate","address":"foo","enc_address":"bar","iv":"baz"}"#;
+
+        match ClientControlRequest::try_from(legacy_request.to_string()).unwrap() {
+            ClientControlRequest::Authenticate { version, .. } => {
+                assert_eq!(version, LEGACY_AUTHENTICATION_VERSION)
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+    }
+
+    #[test]
+    fn register_handshake_init_request_keeps_its_version() {
+        let init = ClientControlRequest::new_register_handshake_init(42, vec![1, 2, 3]);
+        let serialized: String = init.try_into().unwrap();
+        let deserialized = ClientControlRequest::try_from(serialized).unwrap();
+
+        match deserialized {
+            ClientControlRequest::RegisterHandshakeInitRequest { version, data } => {
+                assert_eq!(version, 42);
+                assert_eq!(data, vec![1, 2, 3])
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+    }
+
+    #[test]
+    fn server_push_is_not_mistaken_for_response() {
+        let push = ServerPush::StoredMessages {
+            messages: vec![StoredMessage::new(1, 42, vec![1, 2, 3])],
+        };
+        let serialized = serde_json::to_string(&push).unwrap();
+        assert!(ServerResponse::try_from(serialized.clone()).is_err());
+
+        match ServerPush::try_from(serialized).unwrap() {
+            ServerPush::StoredMessages { messages } => {
+                assert_eq!(messages, vec![StoredMessage::new(1, 42, vec![1, 2, 3])])
+            }
+        }
+
+        let response = serde_json::to_string(&ServerResponse::Send { status: true }).unwrap();
+        assert!(ServerPush::try_from(response).is_err());
+    }
+
+    #[test]
+    fn requests_without_content_are_tagged_with_their_type_only() {
+        let serialized = serde_json::to_string(&ClientControlRequest::RotateKey).unwrap();
+        assert_eq!(r#"{"type":"rotateKey"}"#, serialized);
+
+        let serialized = serde_json::to_string(&ClientControlRequest::ConfirmKeyRotation).unwrap();
+        assert_eq!(r#"{"type":"confirmKeyRotation"}"#, serialized);
+
+        match ClientControlRequest::try_from(r#"{"type":"deregister"}"#.to_string()).unwrap() {
+            ClientControlRequest::Deregister => (),
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+    }
+
+    #[test]
+    fn delivery_policy_is_serialized_in_camel_case() {
+        let request = ClientControlRequest::SetDeliveryPolicy {
+            policy: DeliveryPolicy::FirstAcknowledged,
+        };
+        let serialized = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            r#"{"type":"setDeliveryPolicy","policy":"firstAcknowledged"}"#,
+            serialized
+        );
+
+        match ClientControlRequest::try_from(serialized).unwrap() {
+            ClientControlRequest::SetDeliveryPolicy { policy } => {
+                assert_eq!(DeliveryPolicy::FirstAcknowledged, policy)
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+    }
+
+    #[test]
+    fn retrieval_mode_defaults_to_push_if_not_specified() {
+        let request = r#"{"type":"authenticate","address":"foo","signature":"bar"}"#;
+        match ClientControlRequest::try_from(request.to_string()).unwrap() {
+            ClientControlRequest::Authenticate { retrieval_mode, .. } => {
+                assert_eq!(RetrievalMode::Push, retrieval_mode)
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+
+        let request =
+            r#"{"type":"authenticate","address":"foo","signature":"bar","retrieval_mode":"pull"}"#;
+        match ClientControlRequest::try_from(request.to_string()).unwrap() {
+            ClientControlRequest::Authenticate { retrieval_mode, .. } => {
+                assert_eq!(RetrievalMode::Pull, retrieval_mode)
+            }
+            _ => unreachable!("this branch shouldn't have been reached!"),
+        }
+    }
 }
@@ -0,0 +1,138 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::Config;
+use crate::node::admin::{CLIENTS_PATH, INBOX_PATH_SUFFIX, REVOCATION_PATH_SUFFIX, STATUS_PATH};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use config::NymConfig;
+use http_endpoint::token::{load_token, send_authorized_request};
+use hyper::Method;
+
+fn address_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("address")
+        .long("address")
+        .help("Base58 encoded address of the client")
+        .takes_value(true)
+        .required(true)
+}
+
+pub fn command_args<'a, 'b>() -> App<'a, 'b> {
+    App::new("admin")
+        .about("Manages clients of the running gateway through its admin endpoint")
+        .arg(
+            Arg::with_name("id")
+                .long("id")
+                .help("Id of the nym-gateway we want to manage")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("admin-address")
+                .long("admin-address")
+                .help("Address of the admin endpoint of the node, if different than the one in its config")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Shows the number of connected clients and the depth of the forwarding queue"),
+        )
+        .subcommand(
+            SubCommand::with_name("list-clients")
+                .about("Lists registered clients alongside their last seen time and inbox size"),
+        )
+        .subcommand(
+            SubCommand::with_name("revoke")
+                .about("Revokes the shared key of the client, disconnecting it if it's online. The client is not allowed to register again until reinstated")
+                .arg(address_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("reinstate")
+                .about("Allows the previously revoked client to register again")
+                .arg(address_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("purge-inbox")
+                .about("Removes all messages stored for the client")
+                .arg(address_arg()),
+        )
+}
+
+pub fn execute(matches: &ArgMatches) {
+    let id = matches.value_of("id").unwrap();
+
+    let config = Config::load_from_file(None, Some(id)).expect("Failed to load config file");
+    let admin_address = match matches.value_of("admin-address") {
+        Some(address) => address.parse().expect("Invalid admin address provided"),
+        None => match config.get_admin_address() {
+            Some(address) => address,
+            None => {
+                println!("The admin endpoint of gateway {} is not enabled - set 'admin_address' in its config", id);
+                return;
+            }
+        },
+    };
+
+    let (method, path) = match matches.subcommand() {
+        ("status", Some(_)) => (Method::GET, STATUS_PATH.to_string()),
+        ("list-clients", Some(_)) => (Method::GET, CLIENTS_PATH.to_string()),
+        ("revoke", Some(m)) => (
+            Method::DELETE,
+            format!("{}/{}", CLIENTS_PATH, m.value_of("address").unwrap()),
+        ),
+        ("reinstate", Some(m)) => (
+            Method::DELETE,
+            format!(
+                "{}/{}{}",
+                CLIENTS_PATH,
+                m.value_of("address").unwrap(),
+                REVOCATION_PATH_SUFFIX
+            ),
+        ),
+        ("purge-inbox", Some(m)) => (
+            Method::DELETE,
+            format!(
+                "{}/{}{}",
+                CLIENTS_PATH,
+                m.value_of("address").unwrap(),
+                INBOX_PATH_SUFFIX
+            ),
+        ),
+        _ => {
+            println!("usage: --help to see available admin operations");
+            return;
+        }
+    };
+
+    // the token gets created by the gateway itself when it starts its admin endpoint
+    let token = match load_token(&config.get_admin_token_file()) {
+        Ok(token) => token,
+        Err(err) => {
+            println!(
+                "Failed to read the admin token of gateway {} (was it started with the admin endpoint enabled?) - {}",
+                id, err
+            );
+            return;
+        }
+    };
+
+    let uri = format!("http://{}{}", admin_address, path);
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    match runtime.block_on(send_authorized_request(method, uri, token)) {
+        Ok(response) => println!("The node responded with: {}", response),
+        Err(err) => println!(
+            "Failed to reach the admin endpoint at {} - {}",
+            admin_address, err
+        ),
+    }
+}
@@ -15,6 +15,7 @@
 use crate::config::Config;
 use clap::ArgMatches;
 
+pub mod admin;
 pub mod init;
 pub mod run;
 
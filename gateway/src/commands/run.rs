@@ -161,6 +161,11 @@ pub fn execute(matches: &ArgMatches) {
 
     config = override_config(config, matches);
 
+    if let Err(err) = config.validate() {
+        println!("Invalid configuration - {}", err);
+        return;
+    }
+
     let pemstore = PemStore::new(GatewayPathfinder::new_from_config(&config));
     let sphinx_keypair = load_sphinx_keys(&pemstore);
     let identity = load_identity_keys(&pemstore);
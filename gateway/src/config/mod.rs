@@ -15,7 +15,10 @@
 use crate::config::template::config_template;
 use config::NymConfig;
 use log::*;
+use nymsphinx::framing::FrameVersion;
+use packet_forwarder::DropPolicy;
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
 use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -34,10 +37,58 @@ const DEFAULT_DIRECTORY_SERVER: &str = "https://directory.nymtech.net";
 const DEFAULT_PRESENCE_SENDING_DELAY: u64 = 1500; // 1.5s
 const DEFAULT_PACKET_FORWARDING_INITIAL_BACKOFF: u64 = 10_000; // 10s
 const DEFAULT_PACKET_FORWARDING_MAXIMUM_BACKOFF: u64 = 300_000; // 5min
+const DEFAULT_PACKET_FORWARDING_QUEUE_LENGTH: usize = 1_000;
+const DEFAULT_PACKET_FORWARDING_IDLE_PEER_TIMEOUT: u64 = 600_000; // 10min
 const DEFAULT_INITIAL_CONNECTION_TIMEOUT: u64 = 1_500; // 1.5s
+const DEFAULT_LINK_HANDSHAKE_TIMEOUT: u64 = 1_500; // 1.5s
+const DEFAULT_LINK_KEYS_REFRESH_RATE: u64 = 60_000; // 1min
+const DEFAULT_LAYER_FILTERING_REFRESH_RATE: u64 = 60_000; // 1min
+const DEFAULT_PACKET_PROCESSING_WORKERS: usize = 4;
+const DEFAULT_PACKET_PROCESSING_QUEUE_CAPACITY: usize = 10_000;
 
-const DEFAULT_STORED_MESSAGE_FILENAME_LENGTH: u16 = 16;
 const DEFAULT_MESSAGE_RETRIEVAL_LIMIT: u16 = 5;
+const DEFAULT_INBOX_MAX_MESSAGES: usize = 10_000;
+const DEFAULT_INBOX_MAX_BYTES: usize = 32 * 1024 * 1024; // 32MB
+const DEFAULT_STORED_MESSAGE_TTL: u64 = 7 * 24 * 60 * 60 * 1000; // 7 days
+const DEFAULT_EXPIRED_MESSAGES_SWEEP_INTERVAL: u64 = 600_000; // 10min
+const DEFAULT_MAX_CLIENT_CONNECTIONS: usize = 10_000;
+const DEFAULT_MAX_PENDING_CLIENT_HANDSHAKES: usize = 1_000;
+const DEFAULT_CLIENT_HANDSHAKE_TIMEOUT: u64 = 30_000; // 30s
+const DEFAULT_MAX_CLIENT_CONNECTIONS_PER_IP: usize = 32;
+const DEFAULT_CLIENT_PACKET_RATE_LIMIT: u32 = 500;
+const DEFAULT_CLIENT_PACKET_BURST: u32 = 1_000;
+const DEFAULT_SESSION_TOKEN_VALIDITY: u64 = 600_000; // 10min
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum InboxFullPolicy {
+    /// New messages for clients with full inboxes are dropped.
+    Reject,
+
+    /// The oldest messages in the inbox are removed to make space for the new one.
+    EvictOldest,
+}
+
+impl Default for InboxFullPolicy {
+    fn default() -> Self {
+        InboxFullPolicy::Reject
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigValidationError {
+    ZeroPacketProcessingWorkers,
+}
+
+impl Display for ConfigValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValidationError::ZeroPacketProcessingWorkers => {
+                write!(f, "at least a single packet processing worker is required")
+            }
+        }
+    }
+}
 
 #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -321,6 +372,21 @@ impl Config {
         self
     }
 
+    pub fn with_admin_address(mut self, address: SocketAddr) -> Self {
+        self.gateway.admin_address = Some(address);
+        self
+    }
+
+    /// Checks values that can't be represented by their types alone, so that invalid
+    /// configuration is rejected at startup rather than misbehaving at runtime.
+    pub fn validate(&self) -> Result<(), ConfigValidationError> {
+        if self.debug.packet_processing_workers == 0 {
+            return Err(ConfigValidationError::ZeroPacketProcessingWorkers);
+        }
+
+        Ok(())
+    }
+
     // getters
     pub fn get_config_file_save_location(&self) -> PathBuf {
         self.config_directory().join(Self::config_file_name())
@@ -346,6 +412,14 @@ impl Config {
         self.gateway.public_sphinx_key_file.clone()
     }
 
+    pub fn get_admin_address(&self) -> Option<SocketAddr> {
+        self.gateway.admin_address
+    }
+
+    pub fn get_admin_token_file(&self) -> PathBuf {
+        self.data_directory().join("admin_token")
+    }
+
     pub fn get_presence_directory_server(&self) -> String {
         self.gateway.presence_directory_server.clone()
     }
@@ -386,16 +460,116 @@ impl Config {
         time::Duration::from_millis(self.debug.packet_forwarding_maximum_backoff)
     }
 
+    pub fn get_packet_forwarding_queue_length(&self) -> usize {
+        self.debug.packet_forwarding_queue_length
+    }
+
+    pub fn get_packet_forwarding_drop_policy(&self) -> DropPolicy {
+        self.debug.packet_forwarding_drop_policy
+    }
+
+    pub fn get_packet_forwarding_idle_peer_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.packet_forwarding_idle_peer_timeout)
+    }
+
     pub fn get_initial_connection_timeout(&self) -> time::Duration {
         time::Duration::from_millis(self.debug.initial_connection_timeout)
     }
 
+    pub fn get_frame_version(&self) -> FrameVersion {
+        if self.debug.versioned_framing {
+            FrameVersion::V1
+        } else {
+            FrameVersion::Legacy
+        }
+    }
+
+    pub fn get_link_encryption(&self) -> bool {
+        self.debug.link_encryption
+    }
+
+    pub fn get_link_encryption_plaintext_fallback(&self) -> bool {
+        self.debug.link_encryption_plaintext_fallback
+    }
+
+    pub fn get_link_handshake_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.link_handshake_timeout)
+    }
+
+    pub fn get_link_keys_refresh_rate(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.link_keys_refresh_rate)
+    }
+
+    pub fn get_layer_filtering(&self) -> bool {
+        self.debug.layer_filtering
+    }
+
+    pub fn get_layer_filtering_refresh_rate(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.layer_filtering_refresh_rate)
+    }
+
+    pub fn get_packet_processing_workers(&self) -> usize {
+        self.debug.packet_processing_workers
+    }
+
+    pub fn get_packet_processing_queue_capacity(&self) -> usize {
+        self.debug.packet_processing_queue_capacity
+    }
+
     pub fn get_message_retrieval_limit(&self) -> u16 {
         self.debug.message_retrieval_limit
     }
 
-    pub fn get_stored_messages_filename_length(&self) -> u16 {
-        self.debug.stored_messages_filename_length
+    pub fn get_inbox_max_messages(&self) -> usize {
+        self.debug.inbox_max_messages
+    }
+
+    pub fn get_inbox_max_bytes(&self) -> usize {
+        self.debug.inbox_max_bytes
+    }
+
+    pub fn get_inbox_full_policy(&self) -> InboxFullPolicy {
+        self.debug.inbox_full_policy
+    }
+
+    pub fn get_stored_message_ttl(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.stored_message_ttl)
+    }
+
+    pub fn get_expired_messages_sweep_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.expired_messages_sweep_interval)
+    }
+
+    pub fn get_max_client_connections(&self) -> usize {
+        self.debug.max_client_connections
+    }
+
+    pub fn get_max_pending_client_handshakes(&self) -> usize {
+        self.debug.max_pending_client_handshakes
+    }
+
+    pub fn get_client_handshake_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.client_handshake_timeout)
+    }
+
+    pub fn get_max_client_connections_per_ip(&self) -> usize {
+        self.debug.max_client_connections_per_ip
+    }
+
+    pub fn get_client_packet_rate_limit(&self) -> u32 {
+        self.debug.client_packet_rate_limit
+    }
+
+    pub fn get_client_packet_burst(&self) -> u32 {
+        self.debug.client_packet_burst
+    }
+
+    pub fn get_legacy_client_registration(&self) -> bool {
+        self.debug.legacy_client_registration
+    }
+
+    pub fn get_session_token_validity(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.session_token_validity)
     }
 }
 
@@ -426,6 +600,13 @@ pub struct Gateway {
     /// Directory server to which the server will be reporting their presence data.
     presence_directory_server: String,
 
+    /// Optional socket address on which the gateway will expose its admin endpoint, used by
+    /// the `admin` command. Requests to it have to carry the token stored in the `admin_token`
+    /// file of the data directory, but it should still only ever be bound to a local interface.
+    /// If not set, the endpoint is disabled.
+    #[serde(default)]
+    admin_address: Option<SocketAddr>,
+
     /// nym_home_directory specifies absolute path to the home nym gateways directory.
     /// It is expected to use default value and hence .toml file should not redefine this field.
     nym_root_directory: PathBuf,
@@ -463,6 +644,7 @@ impl Default for Gateway {
             private_sphinx_key_file: Default::default(),
             public_sphinx_key_file: Default::default(),
             presence_directory_server: DEFAULT_DIRECTORY_SERVER.to_string(),
+            admin_address: None,
             nym_root_directory: Config::default_root_directory(),
         }
     }
@@ -564,20 +746,122 @@ pub struct Debug {
     /// The provided value is interpreted as milliseconds.
     packet_forwarding_maximum_backoff: u64,
 
+    /// Maximum number of packets waiting to get forwarded to a single destination.
+    packet_forwarding_queue_length: usize,
+
+    /// Policy applied once the queue of particular destination is full,
+    /// either `DropNewest` or `DropOldest`.
+    packet_forwarding_drop_policy: DropPolicy,
+
+    /// Duration after which the connection to a destination without any packets to forward
+    /// is closed.
+    /// The provided value is interpreted as milliseconds.
+    packet_forwarding_idle_peer_timeout: u64,
+
     /// Timeout for establishing initial connection when trying to forward a sphinx packet.
     /// The provider value is interpreted as milliseconds.
     initial_connection_timeout: u64,
 
+    /// Whether forwarded packets should be framed with the versioned header, carrying the packet
+    /// type and flags, rather than the legacy one. It should only be enabled once all nodes
+    /// in the network are able to decode it. Both are always accepted.
+    versioned_framing: bool,
+
+    /// Whether links to other nodes should be encrypted and authenticated with their sphinx keys,
+    /// as announced to the directory server. When enabled, encrypted links from other nodes
+    /// are accepted as well. Plaintext links are always accepted.
+    link_encryption: bool,
+
+    /// Whether the link to a node that failed to complete the encryption handshake, for example
+    /// because it's running an older version, should be re-established in plaintext.
+    /// Encryption is attempted again after a while. Note that enabling it allows anyone able
+    /// to interfere with the handshake to downgrade the link.
+    link_encryption_plaintext_fallback: bool,
+
+    /// Timeout for completing the link encryption handshake.
+    /// The provided value is interpreted as milliseconds.
+    link_handshake_timeout: u64,
+
+    /// Delay between each subsequent refresh of the keys of other nodes used for authenticating
+    /// the links to them, alongside their addresses packets are allowed to be forwarded to.
+    /// The provided value is interpreted as milliseconds.
+    link_keys_refresh_rate: u64,
+
+    /// Whether mix packets should only be accepted from the mixnodes at the final layer,
+    /// according to the topology announced to the directory server.
+    /// Any violating packets are dropped. Note that this is incompatible with routes that
+    /// do not go through the layers in order.
+    layer_filtering: bool,
+
+    /// Delay between each subsequent refresh of the topology used for layer filtering.
+    /// The provided value is interpreted as milliseconds.
+    layer_filtering_refresh_rate: u64,
+
+    /// Number of worker threads dedicated to unwrapping received sphinx packets.
+    packet_processing_workers: usize,
+
+    /// Maximum number of received packets waiting to get unwrapped by the workers.
+    /// Any packets received while the queue is full are dropped.
+    packet_processing_queue_capacity: usize,
+
     /// Delay between each subsequent presence data being sent.
     presence_sending_delay: u64,
 
-    /// Length of filenames for new client messages.
-    stored_messages_filename_length: u16,
-
-    /// Number of messages client gets on each request
-    /// if there are no real messages, dummy ones are create to always return  
-    /// `message_retrieval_limit` total messages
+    /// Maximum number of stored messages pushed to the client in a single batch.
+    /// The next batch is only pushed after the client acknowledges receiving the previous one.
     message_retrieval_limit: u16,
+
+    /// Maximum number of messages stored in the inbox of a single client.
+    inbox_max_messages: usize,
+
+    /// Maximum total size, in bytes, of messages stored in the inbox of a single client.
+    inbox_max_bytes: usize,
+
+    /// Specifies what happens to new messages for a client whose inbox is full.
+    inbox_full_policy: InboxFullPolicy,
+
+    /// Duration for which messages are kept in the inbox of a client. Messages that were not
+    /// retrieved by then are removed.
+    /// The provided value is interpreted as milliseconds.
+    stored_message_ttl: u64,
+
+    /// Delay between each subsequent removal of expired stored messages.
+    /// The provided value is interpreted as milliseconds.
+    expired_messages_sweep_interval: u64,
+
+    /// Maximum number of simultaneous client connections. Any connection above the limit
+    /// is rejected with an error response.
+    max_client_connections: usize,
+
+    /// Maximum number of client connections that have not completed authentication
+    /// (or registration) yet.
+    max_pending_client_handshakes: usize,
+
+    /// Time the client has to complete the websocket handshake and then authenticate
+    /// (or register) before its connection is closed and its slot freed.
+    /// The provided value is interpreted as milliseconds.
+    client_handshake_timeout: u64,
+
+    /// Maximum number of simultaneous client connections originating from a single IP address.
+    max_client_connections_per_ip: usize,
+
+    /// Sustained number of sphinx packets per second each client is allowed to send
+    /// into the mixnet. Packets above the limit are dropped.
+    /// Setting it to 0 disables the rate limiting.
+    client_packet_rate_limit: u32,
+
+    /// Number of sphinx packets each client is allowed to send in a single burst
+    /// before the rate limit kicks in.
+    client_packet_burst: u32,
+
+    /// Whether clients are still allowed to register using the legacy, pre-Noise, handshake.
+    /// It should be disabled once the clients have migrated to the current version.
+    legacy_client_registration: bool,
+
+    /// Duration for which the client can use the session token it received upon authentication
+    /// to resume its session without requesting a new authentication challenge.
+    /// The provided value is interpreted as milliseconds.
+    session_token_validity: u64,
 }
 
 impl Default for Debug {
@@ -585,10 +869,34 @@ impl Default for Debug {
         Debug {
             packet_forwarding_initial_backoff: DEFAULT_PACKET_FORWARDING_INITIAL_BACKOFF,
             packet_forwarding_maximum_backoff: DEFAULT_PACKET_FORWARDING_MAXIMUM_BACKOFF,
+            packet_forwarding_queue_length: DEFAULT_PACKET_FORWARDING_QUEUE_LENGTH,
+            packet_forwarding_drop_policy: Default::default(),
+            packet_forwarding_idle_peer_timeout: DEFAULT_PACKET_FORWARDING_IDLE_PEER_TIMEOUT,
             initial_connection_timeout: DEFAULT_INITIAL_CONNECTION_TIMEOUT,
+            versioned_framing: false,
+            link_encryption: false,
+            link_encryption_plaintext_fallback: false,
+            link_handshake_timeout: DEFAULT_LINK_HANDSHAKE_TIMEOUT,
+            link_keys_refresh_rate: DEFAULT_LINK_KEYS_REFRESH_RATE,
+            layer_filtering: false,
+            layer_filtering_refresh_rate: DEFAULT_LAYER_FILTERING_REFRESH_RATE,
+            packet_processing_workers: DEFAULT_PACKET_PROCESSING_WORKERS,
+            packet_processing_queue_capacity: DEFAULT_PACKET_PROCESSING_QUEUE_CAPACITY,
             presence_sending_delay: DEFAULT_PRESENCE_SENDING_DELAY,
-            stored_messages_filename_length: DEFAULT_STORED_MESSAGE_FILENAME_LENGTH,
             message_retrieval_limit: DEFAULT_MESSAGE_RETRIEVAL_LIMIT,
+            inbox_max_messages: DEFAULT_INBOX_MAX_MESSAGES,
+            inbox_max_bytes: DEFAULT_INBOX_MAX_BYTES,
+            inbox_full_policy: Default::default(),
+            stored_message_ttl: DEFAULT_STORED_MESSAGE_TTL,
+            expired_messages_sweep_interval: DEFAULT_EXPIRED_MESSAGES_SWEEP_INTERVAL,
+            max_client_connections: DEFAULT_MAX_CLIENT_CONNECTIONS,
+            max_pending_client_handshakes: DEFAULT_MAX_PENDING_CLIENT_HANDSHAKES,
+            client_handshake_timeout: DEFAULT_CLIENT_HANDSHAKE_TIMEOUT,
+            max_client_connections_per_ip: DEFAULT_MAX_CLIENT_CONNECTIONS_PER_IP,
+            client_packet_rate_limit: DEFAULT_CLIENT_PACKET_RATE_LIMIT,
+            client_packet_burst: DEFAULT_CLIENT_PACKET_BURST,
+            legacy_client_registration: true,
+            session_token_validity: DEFAULT_SESSION_TOKEN_VALIDITY,
         }
     }
 }
@@ -611,4 +919,19 @@ mod gateway_config {
 
         assert_eq!(default_config, loaded_config);
     }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn zero_packet_processing_workers_are_rejected() {
+        let mut config = Config::default();
+        config.debug.packet_processing_workers = 0;
+        assert_eq!(
+            Err(ConfigValidationError::ZeroPacketProcessingWorkers),
+            config.validate()
+        );
+    }
 }
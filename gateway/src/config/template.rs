@@ -48,6 +48,14 @@ public_sphinx_key_file = '{{ gateway.public_sphinx_key_file }}'
 # Directory server to which the server will be reporting their presence data.
 presence_directory_server = '{{ gateway.presence_directory_server }}'
 
+# Optional socket address on which the gateway will expose its admin endpoint, used by
+# the `admin` command. Requests to it have to carry the token stored in the `admin_token`
+# file of the data directory, but it should still only ever be bound to a local interface.
+# If not set, the endpoint is disabled.
+{{#if gateway.admin_address }}
+admin_address = '{{ gateway.admin_address }}'
+{{/if}}
+
 # nym_home_directory specifies absolute path to the home nym gateway directory.
 # It is expected to use default value and hence .toml file should not redefine this field.
 nym_root_directory = '{{ gateway.nym_root_directory }}'
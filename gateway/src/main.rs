@@ -28,6 +28,7 @@ fn main() {
         .version(built_info::PKG_VERSION)
         .author("Nymtech")
         .about("Implementation of the Nym Mixnet Gateway")
+        .subcommand(commands::admin::command_args())
         .subcommand(commands::init::command_args())
         .subcommand(commands::run::command_args())
         .get_matches();
@@ -37,6 +38,7 @@ fn main() {
 
 fn execute(matches: ArgMatches) {
     match matches.subcommand() {
+        ("admin", Some(m)) => commands::admin::execute(m),
         ("init", Some(m)) => commands::init::execute(m),
         ("run", Some(m)) => commands::run::execute(m),
         _ => println!("{}", usage()),
@@ -0,0 +1,366 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::client_handling::clients_handler::{
+    ClientsHandlerRequest, ClientsHandlerRequestSender, ClientsHandlerResponse,
+};
+use crate::node::client_handling::websocket::limits::ConnectionLimiter;
+use crate::node::mixnet_handling::PacketProcessor;
+use crate::node::storage::inboxes::ClientStorage;
+use futures::channel::oneshot;
+use http_endpoint::response;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use layer_filter::LayerFilter;
+use log::*;
+use nymsphinx::DestinationAddressBytes;
+use packet_forwarder::ForwardingStats;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+pub(crate) const CLIENTS_PATH: &str = "/clients";
+pub(crate) const STATUS_PATH: &str = "/status";
+pub(crate) const INBOX_PATH_SUFFIX: &str = "/inbox";
+pub(crate) const REVOCATION_PATH_SUFFIX: &str = "/revocation";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClientStatus {
+    address: String,
+    online: bool,
+    last_seen: Option<u64>,
+    inbox_messages: usize,
+    inbox_bytes: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GatewayStatus {
+    connected_clients: usize,
+    pending_handshakes: usize,
+    forwarding_queue_depth: usize,
+    unwrapping_pool: UnwrappingPoolStatus,
+    forwarding: HashMap<String, PeerForwardingStatus>,
+    // packets to destinations that are not present in the network topology
+    rejected_forwarding_packets: u64,
+    // only present if layer filtering is enabled
+    rejected_ingress_packets: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UnwrappingPoolStatus {
+    queue_depth: usize,
+    processed: u64,
+    dropped: u64,
+    average_queue_latency_micros: u128,
+    average_processing_latency_micros: u128,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PeerForwardingStatus {
+    queued_packets: usize,
+    buffered_packets: usize,
+    forwarded: u64,
+    failed: u64,
+    dropped: u64,
+    connection_attempts: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeResult {
+    revoked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReinstateResult {
+    reinstated: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PurgeResult {
+    removed_messages: usize,
+}
+
+// operations on a particular client, all of which are requested with the DELETE method
+enum ClientOperation {
+    Revoke,
+    PurgeInbox,
+    // deleting the revocation of the client
+    Reinstate,
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            let mut response = Response::new(Body::from(json));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+            response
+        }
+        Err(err) => {
+            error!("Failed to serialize admin response - {}", err);
+            response(StatusCode::INTERNAL_SERVER_ERROR, "")
+        }
+    }
+}
+
+/// Handles to the statistics of processing and forwarding mix packets.
+pub(crate) struct MixnetStats {
+    pub(crate) packet_processor: PacketProcessor,
+    pub(crate) forwarding_stats: ForwardingStats,
+    pub(crate) layer_filter: Option<LayerFilter>,
+}
+
+/// Optional HTTP endpoint for inspecting and managing clients of the running gateway.
+/// Each request has to carry the admin token as `Authorization: Bearer <token>`.
+/// It should still only ever be exposed on a local interface.
+pub(crate) struct AdminEndpoint {
+    address: SocketAddr,
+    token: String,
+    clients_handler_sender: ClientsHandlerRequestSender,
+    client_storage: ClientStorage,
+    connection_limiter: ConnectionLimiter,
+    mixnet_stats: MixnetStats,
+}
+
+impl AdminEndpoint {
+    pub(crate) fn new(
+        address: SocketAddr,
+        token: String,
+        clients_handler_sender: ClientsHandlerRequestSender,
+        client_storage: ClientStorage,
+        connection_limiter: ConnectionLimiter,
+        mixnet_stats: MixnetStats,
+    ) -> Self {
+        AdminEndpoint {
+            address,
+            token,
+            clients_handler_sender,
+            client_storage,
+            connection_limiter,
+            mixnet_stats,
+        }
+    }
+
+    async fn clients_handler_request(
+        &self,
+        make_request: impl FnOnce(oneshot::Sender<ClientsHandlerResponse>) -> ClientsHandlerRequest,
+    ) -> ClientsHandlerResponse {
+        let (res_sender, res_receiver) = oneshot::channel();
+        self.clients_handler_sender
+            .unbounded_send(make_request(res_sender))
+            .unwrap(); // the receiver MUST BE alive
+        res_receiver.await.unwrap()
+    }
+
+    async fn list_clients(&self) -> Response<Body> {
+        let clients = match self
+            .clients_handler_request(ClientsHandlerRequest::ListClients)
+            .await
+        {
+            ClientsHandlerResponse::ListClients(clients) => clients,
+            ClientsHandlerResponse::Error(err) => {
+                error!("Failed to list registered clients - {}", err);
+                return response(StatusCode::INTERNAL_SERVER_ERROR, "");
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        };
+
+        let mut statuses = Vec::with_capacity(clients.len());
+        for client in clients {
+            let usage = match self.client_storage.inbox_usage(&client.address).await {
+                Ok(usage) => usage,
+                Err(err) => {
+                    error!("Failed to obtain inbox usage - {}", err);
+                    return response(StatusCode::INTERNAL_SERVER_ERROR, "");
+                }
+            };
+            statuses.push(ClientStatus {
+                address: client.address.to_base58_string(),
+                online: client.online,
+                last_seen: client.last_seen,
+                inbox_messages: usage.messages,
+                inbox_bytes: usage.bytes,
+            })
+        }
+        json_response(&statuses)
+    }
+
+    async fn revoke_client(&self, address: DestinationAddressBytes) -> Response<Body> {
+        let revoked = match self
+            .clients_handler_request(|res_sender| {
+                ClientsHandlerRequest::Revoke(address.clone(), res_sender)
+            })
+            .await
+        {
+            ClientsHandlerResponse::Revoke(revoked) => revoked,
+            ClientsHandlerResponse::Error(err) => {
+                error!("Failed to revoke the client - {}", err);
+                return response(StatusCode::INTERNAL_SERVER_ERROR, "");
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        };
+
+        if revoked {
+            info!(
+                "Revoked client {:?} via the admin endpoint",
+                address.to_base58_string()
+            );
+        }
+        json_response(&RevokeResult { revoked })
+    }
+
+    async fn reinstate_client(&self, address: DestinationAddressBytes) -> Response<Body> {
+        let reinstated = match self
+            .clients_handler_request(|res_sender| {
+                ClientsHandlerRequest::Reinstate(address.clone(), res_sender)
+            })
+            .await
+        {
+            ClientsHandlerResponse::Reinstate(reinstated) => reinstated,
+            ClientsHandlerResponse::Error(err) => {
+                error!("Failed to reinstate the client - {}", err);
+                return response(StatusCode::INTERNAL_SERVER_ERROR, "");
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        };
+
+        if reinstated {
+            info!(
+                "Reinstated client {:?} via the admin endpoint",
+                address.to_base58_string()
+            );
+        }
+        json_response(&ReinstateResult { reinstated })
+    }
+
+    async fn purge_inbox(&self, address: DestinationAddressBytes) -> Response<Body> {
+        match self.client_storage.purge_inbox(&address).await {
+            Ok(removed_messages) => {
+                info!(
+                    "Purged {} messages from the inbox of {:?} via the admin endpoint",
+                    removed_messages,
+                    address.to_base58_string()
+                );
+                json_response(&PurgeResult { removed_messages })
+            }
+            Err(err) => {
+                error!("Failed to purge the inbox - {}", err);
+                response(StatusCode::INTERNAL_SERVER_ERROR, "")
+            }
+        }
+    }
+
+    fn status(&self) -> Response<Body> {
+        let pool_stats = self.mixnet_stats.packet_processor.unwrapping_pool_stats();
+        let forwarding = self
+            .mixnet_stats
+            .forwarding_stats
+            .snapshot()
+            .into_iter()
+            .map(|(peer, stats)| {
+                let status = PeerForwardingStatus {
+                    queued_packets: stats.queued_packets,
+                    buffered_packets: stats.buffered_packets,
+                    forwarded: stats.forwarded,
+                    failed: stats.failed,
+                    dropped: stats.dropped,
+                    connection_attempts: stats.connection_attempts,
+                };
+                (peer.to_string(), status)
+            })
+            .collect();
+
+        json_response(&GatewayStatus {
+            connected_clients: self.connection_limiter.connections(),
+            pending_handshakes: self.connection_limiter.pending_handshakes(),
+            forwarding_queue_depth: self.mixnet_stats.forwarding_stats.pending_packets(),
+            unwrapping_pool: UnwrappingPoolStatus {
+                queue_depth: pool_stats.queue_depth,
+                processed: pool_stats.processed,
+                dropped: pool_stats.dropped,
+                average_queue_latency_micros: pool_stats.average_queue_latency.as_micros(),
+                average_processing_latency_micros: pool_stats
+                    .average_processing_latency
+                    .as_micros(),
+            },
+            forwarding,
+            rejected_forwarding_packets: self.mixnet_stats.forwarding_stats.rejected_packets(),
+            rejected_ingress_packets: self
+                .mixnet_stats
+                .layer_filter
+                .as_ref()
+                .map(|layer_filter| layer_filter.rejected_ingress()),
+        })
+    }
+
+    async fn handle_request(&self, request: Request<Body>) -> Response<Body> {
+        if !http_endpoint::token::is_authorized(&request, &self.token) {
+            return response(StatusCode::UNAUTHORIZED, "");
+        }
+
+        let path = request.uri().path();
+        match (request.method(), path) {
+            (&Method::GET, STATUS_PATH) => return self.status(),
+            (&Method::GET, CLIENTS_PATH) => return self.list_clients().await,
+            _ => (),
+        }
+
+        // the remaining requests are about particular client
+        let client_path = match path
+            .strip_prefix(CLIENTS_PATH)
+            .and_then(|path| path.strip_prefix('/'))
+        {
+            Some(client_path) if request.method() == Method::DELETE => client_path,
+            _ => return response(StatusCode::NOT_FOUND, ""),
+        };
+        let (raw_address, operation) =
+            if let Some(raw_address) = client_path.strip_suffix(INBOX_PATH_SUFFIX) {
+                (raw_address, ClientOperation::PurgeInbox)
+            } else if let Some(raw_address) = client_path.strip_suffix(REVOCATION_PATH_SUFFIX) {
+                (raw_address, ClientOperation::Reinstate)
+            } else {
+                (client_path, ClientOperation::Revoke)
+            };
+        let address = match DestinationAddressBytes::try_from_base58_string(raw_address) {
+            Ok(address) => address,
+            Err(_) => return response(StatusCode::BAD_REQUEST, "malformed client address\n"),
+        };
+
+        match operation {
+            ClientOperation::Revoke => self.revoke_client(address).await,
+            ClientOperation::PurgeInbox => self.purge_inbox(address).await,
+            ClientOperation::Reinstate => self.reinstate_client(address).await,
+        }
+    }
+
+    pub(crate) fn start(self, handle: &Handle) -> JoinHandle<()> {
+        let address = self.address;
+        let endpoint = Arc::new(self);
+        http_endpoint::start(handle, address, "admin endpoint", move |request| {
+            let endpoint = Arc::clone(&endpoint);
+            async move { endpoint.handle_request(request).await }
+        })
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::client_handling::websocket::message_receiver::{MixMessageSender, MixMessages};
+use gateway_requests::DeliveryPolicy;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct Sessions {
+    senders: Vec<MixMessageSender>,
+    policy: DeliveryPolicy,
+}
+
+/// All sessions the client currently has open with the gateway, e.g. one per each of its
+/// devices, alongside the policy of handing messages over to them. It is shared with
+/// the packet processors, so that sessions opened later on become visible to them immediately.
+#[derive(Clone, Debug)]
+pub(crate) struct ClientSessions {
+    inner: Arc<Mutex<Sessions>>,
+}
+
+impl ClientSessions {
+    pub(crate) fn new(sender: MixMessageSender, policy: DeliveryPolicy) -> Self {
+        ClientSessions {
+            inner: Arc::new(Mutex::new(Sessions {
+                senders: vec![sender],
+                policy,
+            })),
+        }
+    }
+
+    pub(crate) fn add(&self, sender: MixMessageSender) {
+        self.inner.lock().unwrap().senders.push(sender);
+    }
+
+    /// Forgets about the sessions that were closed. Returns whether any session is still open.
+    pub(crate) fn remove_closed(&self) -> bool {
+        let mut sessions = self.inner.lock().unwrap();
+        sessions.senders.retain(|sender| !sender.is_closed());
+        !sessions.senders.is_empty()
+    }
+
+    /// Indicates none of the sessions can receive messages anymore.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .senders
+            .iter()
+            .all(|sender| sender.is_closed())
+    }
+
+    pub(crate) fn set_policy(&self, policy: DeliveryPolicy) {
+        self.inner.lock().unwrap().policy = policy;
+    }
+
+    /// Pushes the message according to the delivery policy. If it is not pushed to any of
+    /// the sessions, it is given back, so that it could be stored in the inbox instead.
+    pub(crate) fn deliver(&self, message: Vec<u8>) -> Result<(), Vec<u8>> {
+        let sessions = self.inner.lock().unwrap();
+        match sessions.policy {
+            // with a single session there is nobody to compete for the message with
+            DeliveryPolicy::FirstAcknowledged if sessions.senders.len() > 1 => {
+                // only messages handed over through the inbox get acknowledged
+                Err(message)
+            }
+            _ => Self::push_to_all(&sessions.senders, message),
+        }
+    }
+
+    /// Pushes the message to all of the sessions regardless of the delivery policy.
+    pub(crate) fn deliver_to_all(&self, message: Vec<u8>) -> Result<(), Vec<u8>> {
+        Self::push_to_all(&self.inner.lock().unwrap().senders, message)
+    }
+
+    fn push_to_all(senders: &[MixMessageSender], message: Vec<u8>) -> Result<(), Vec<u8>> {
+        let mut pushed = false;
+        for sender in senders {
+            // the session might be just closing
+            pushed |= sender
+                .unbounded_send(MixMessages::Received(vec![message.clone()]))
+                .is_ok();
+        }
+        if pushed {
+            Ok(())
+        } else {
+            Err(message)
+        }
+    }
+
+    /// Lets all of the sessions know there might be something new for them in the inbox.
+    pub(crate) fn notify_inbox_updated(&self) {
+        self.broadcast(|| MixMessages::InboxUpdated)
+    }
+
+    /// Closes all of the sessions.
+    pub(crate) fn revoke(&self) {
+        self.broadcast(|| MixMessages::Revoked)
+    }
+
+    fn broadcast<F>(&self, make_message: F)
+    where
+        F: Fn() -> MixMessages,
+    {
+        for sender in self.inner.lock().unwrap().senders.iter() {
+            // if the session is gone, there is nobody to notify
+            let _ = sender.unbounded_send(make_message());
+        }
+    }
+}
+
+#[cfg(test)]
+mod delivering_to_sessions {
+    use super::*;
+    use crate::node::client_handling::websocket::message_receiver::MixMessageReceiver;
+    use futures::channel::mpsc;
+
+    fn session() -> (MixMessageSender, MixMessageReceiver) {
+        mpsc::unbounded()
+    }
+
+    fn received(receiver: &mut MixMessageReceiver) -> Option<Vec<Vec<u8>>> {
+        match receiver.try_next() {
+            Ok(Some(MixMessages::Received(messages))) => Some(messages),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn fan_out_pushes_message_to_every_session() {
+        let (first_sender, mut first_receiver) = session();
+        let (second_sender, mut second_receiver) = session();
+        let sessions = ClientSessions::new(first_sender, DeliveryPolicy::FanOut);
+        sessions.add(second_sender);
+
+        assert!(sessions.deliver(vec![42]).is_ok());
+        assert_eq!(Some(vec![vec![42]]), received(&mut first_receiver));
+        assert_eq!(Some(vec![vec![42]]), received(&mut second_receiver));
+    }
+
+    #[test]
+    fn first_acknowledged_hands_message_back_if_there_are_multiple_sessions() {
+        let (first_sender, mut first_receiver) = session();
+        let (second_sender, mut second_receiver) = session();
+        let sessions = ClientSessions::new(first_sender, DeliveryPolicy::FirstAcknowledged);
+
+        assert!(sessions.deliver(vec![1]).is_ok());
+        assert_eq!(Some(vec![vec![1]]), received(&mut first_receiver));
+
+        sessions.add(second_sender);
+        assert_eq!(Err(vec![2]), sessions.deliver(vec![2]));
+        assert!(received(&mut first_receiver).is_none());
+        assert!(received(&mut second_receiver).is_none());
+
+        assert!(sessions.deliver_to_all(vec![3]).is_ok());
+        assert_eq!(Some(vec![vec![3]]), received(&mut first_receiver));
+        assert_eq!(Some(vec![vec![3]]), received(&mut second_receiver));
+    }
+
+    #[test]
+    fn message_is_handed_back_once_all_sessions_are_closed() {
+        let (first_sender, first_receiver) = session();
+        let (second_sender, mut second_receiver) = session();
+        let sessions = ClientSessions::new(first_sender, DeliveryPolicy::FanOut);
+        sessions.add(second_sender);
+
+        drop(first_receiver);
+        assert!(!sessions.is_closed());
+        assert!(sessions.deliver(vec![1]).is_ok());
+        assert_eq!(Some(vec![vec![1]]), received(&mut second_receiver));
+
+        drop(second_receiver);
+        assert!(sessions.is_closed());
+        assert_eq!(Err(vec![2]), sessions.deliver(vec![2]));
+    }
+
+    #[test]
+    fn closed_sessions_are_forgotten() {
+        let (first_sender, first_receiver) = session();
+        let (second_sender, second_receiver) = session();
+        let sessions = ClientSessions::new(first_sender, DeliveryPolicy::FanOut);
+        sessions.add(second_sender);
+
+        drop(first_receiver);
+        assert!(sessions.remove_closed());
+        assert_eq!(1, sessions.inner.lock().unwrap().senders.len());
+
+        drop(second_receiver);
+        assert!(!sessions.remove_closed());
+    }
+}
@@ -12,22 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::node::{
-    client_handling::websocket::message_receiver::MixMessageSender,
-    storage::{inboxes::ClientStorage, ClientLedger},
-};
+use crate::node::client_handling::client_sessions::ClientSessions;
+use crate::node::client_handling::session_tokens::SessionTokens;
+use crate::node::client_handling::websocket::message_receiver::MixMessageSender;
+use crate::node::storage::inboxes::{current_timestamp, Timestamp};
+use crate::node::storage::ClientLedger;
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
-use gateway_requests::authentication::encrypted_address::EncryptedAddressBytes;
-use gateway_requests::authentication::iv::AuthenticationIV;
+use gateway_requests::authentication::challenge::SessionToken;
 use gateway_requests::registration::handshake::SharedKey;
+use gateway_requests::DeliveryPolicy;
 use log::*;
 use nymsphinx::DestinationAddressBytes;
 use std::collections::HashMap;
+use std::time::Duration;
 use tokio::task::JoinHandle;
 
+// how often the session tokens that expired without being redeemed are forgotten
+const SESSION_TOKENS_PRUNING_INTERVAL: Duration = Duration::from_secs(60);
+
 pub(crate) type ClientsHandlerRequestSender = mpsc::UnboundedSender<ClientsHandlerRequest>;
 pub(crate) type ClientsHandlerRequestReceiver = mpsc::UnboundedReceiver<ClientsHandlerRequest>;
 
@@ -42,39 +47,86 @@ pub(crate) enum ClientsHandlerRequest {
         MixMessageSender,
         ClientsHandlerResponseSender,
     ),
+    // the identity of the client must have already been verified
     Authenticate(
         DestinationAddressBytes,
-        EncryptedAddressBytes,
-        AuthenticationIV,
         MixMessageSender,
         ClientsHandlerResponseSender,
     ),
+    ResumeSession(
+        DestinationAddressBytes,
+        SessionToken,
+        MixMessageSender,
+        ClientsHandlerResponseSender,
+    ),
+    IssueSessionToken(DestinationAddressBytes, ClientsHandlerResponseSender),
+    // sent once the receiving end of the session's channel is already gone
     Disconnect(DestinationAddressBytes),
+    UpdateAddressPublication(
+        DestinationAddressBytes,
+        bool,
+        u64,
+        ClientsHandlerResponseSender,
+    ),
+    Deregister(DestinationAddressBytes, ClientsHandlerResponseSender),
+    RotateKey(
+        DestinationAddressBytes,
+        SharedKey,
+        ClientsHandlerResponseSender,
+    ),
+    SetDeliveryPolicy(
+        DestinationAddressBytes,
+        DeliveryPolicy,
+        ClientsHandlerResponseSender,
+    ),
 
     // mix
     IsOnline(DestinationAddressBytes, ClientsHandlerResponseSender),
+
+    // admin
+    ListClients(ClientsHandlerResponseSender),
+    Revoke(DestinationAddressBytes, ClientsHandlerResponseSender),
+    Reinstate(DestinationAddressBytes, ClientsHandlerResponseSender),
 }
 
 #[derive(Debug)]
 pub(crate) enum ClientsHandlerResponse {
     Register(bool),
     Authenticate(Option<SharedKey>),
-    IsOnline(Option<MixMessageSender>),
+    IssueSessionToken(SessionToken),
+    IsOnline(Option<ClientSessions>),
+    UpdateAddressPublication(bool),
+    Deregister(bool),
+    RotateKey,
+    SetDeliveryPolicy,
+    ListClients(Vec<RegisteredClient>),
+    Revoke(bool),
+    Reinstate(bool),
+    /// The client was revoked, so it can neither register nor authenticate.
+    Revoked,
     Error(Box<dyn std::error::Error + Send + Sync>),
 }
 
+#[derive(Debug)]
+pub(crate) struct RegisteredClient {
+    pub(crate) address: DestinationAddressBytes,
+    pub(crate) online: bool,
+    /// Time at which the client was last connected, if ever.
+    pub(crate) last_seen: Option<Timestamp>,
+}
+
 pub(crate) struct ClientsHandler {
-    open_connections: HashMap<DestinationAddressBytes, MixMessageSender>,
+    open_connections: HashMap<DestinationAddressBytes, ClientSessions>,
     clients_ledger: ClientLedger,
-    clients_inbox_storage: ClientStorage,
+    session_tokens: SessionTokens,
 }
 
 impl ClientsHandler {
-    pub(crate) fn new(clients_ledger: ClientLedger, clients_inbox_storage: ClientStorage) -> Self {
+    pub(crate) fn new(clients_ledger: ClientLedger, session_token_validity: Duration) -> Self {
         ClientsHandler {
             open_connections: HashMap::new(),
             clients_ledger,
-            clients_inbox_storage,
+            session_tokens: SessionTokens::new(session_token_validity),
         }
     }
 
@@ -85,69 +137,39 @@ impl ClientsHandler {
         ClientsHandlerResponse::Error(err.into())
     }
 
-    // best effort sending error responses
-    fn send_error_response<E>(&self, err: E, res_channel: ClientsHandlerResponseSender)
-    where
-        E: Into<Box<dyn std::error::Error + Send + Sync>>,
-    {
-        if let Err(_) = res_channel.send(self.make_error_response(err)) {
-            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+    // clients can have several sessions open at once, for example one per device
+    fn open_session(&mut self, address: DestinationAddressBytes, comm_channel: MixMessageSender) {
+        if let Some(sessions) = self.open_connections.get(&address) {
+            sessions.add(comm_channel);
+            return;
         }
-    }
 
-    async fn push_stored_messages_to_client_and_save_channel(
-        &mut self,
-        client_address: DestinationAddressBytes,
-        comm_channel: MixMessageSender,
-    ) {
-        // TODO: it is possible that during a small window some of client messages will be "lost",
-        // i.e. be stored on the disk rather than pushed to the client, reason for this is as follows:
-        // now we push all stored messages from client's inbox to its websocket connection
-        // however, say, at the same time there's new message to the client - it gets stored on the disk!
-        // And only after this methods exits, mix receivers will become aware of the client
-        // connection going online and being able to forward traffic there.
-        //
-        // possible solution: spawn a future to empty inbox in X seconds rather than immediately
-        // JS: I will most likely do that (with including entries to config, etc.) once the
-        // basic version is up and running as not to waste time on it now
-
-        // NOTE: THIS IGNORES MESSAGE RETRIEVAL LIMIT AND TAKES EVERYTHING!
-        let all_stored_messages = match self
-            .clients_inbox_storage
-            .retrieve_all_client_messages(client_address.clone())
-            .await
-        {
-            Ok(msgs) => msgs,
-            Err(e) => {
+        let policy = match self.clients_ledger.get_delivery_policy(&address) {
+            Ok(policy) => policy,
+            Err(err) => {
                 error!(
-                    "failed to retrieve client messages. {:?} inbox might be corrupted now - {:?}",
-                    client_address.to_base58_string(),
-                    e
+                    "Failed to read delivery policy of {:?} - {}. The default one is going to be used",
+                    address.to_base58_string(),
+                    err
                 );
-                return;
+                DeliveryPolicy::default()
             }
         };
+        self.open_connections
+            .insert(address, ClientSessions::new(comm_channel, policy));
+    }
 
-        let (messages, paths): (Vec<_>, Vec<_>) = all_stored_messages
-            .into_iter()
-            .map(|c| c.into_tuple())
-            .unzip();
-
-        if let Err(_) = comm_channel.unbounded_send(messages) {
-            error!("Somehow we failed to stored messages to a fresh client channel - there seem to be a weird bug present!");
-        } else {
-            // but if all went well, we can now delete it
-            if let Err(e) = self.clients_inbox_storage.delete_files(paths).await {
-                error!(
-                    "Failed to remove client ({:?}) files - {:?}",
-                    client_address.to_base58_string(),
-                    e
-                );
-            } else {
-                // finally, everything was fine - we retrieved everything, we deleted everything,
-                // we assume we can now safely delegate client message pushing
-                self.open_connections.insert(client_address, comm_channel);
-            }
+    // best effort, as it's purely informational
+    fn update_last_seen(&mut self, address: &DestinationAddressBytes) {
+        if let Err(err) = self
+            .clients_ledger
+            .update_last_seen(address, current_timestamp())
+        {
+            error!(
+                "Failed to update last seen time of {:?} - {}",
+                address.to_base58_string(),
+                err
+            );
         }
     }
 
@@ -163,12 +185,24 @@ impl ClientsHandler {
             address.to_base58_string()
         );
 
-        if self.open_connections.get(&address).is_some() {
-            warn!(
-                "Tried to process register request for a client with an already opened connection!"
-            );
-            self.send_error_response("duplicate connection detected", res_channel);
-            return;
+        match self.clients_ledger.is_revoked(&address) {
+            Ok(false) => (),
+            Ok(true) => {
+                debug!(
+                    "Rejecting registration of revoked client {:?}",
+                    address.to_base58_string()
+                );
+                if let Err(_) = res_channel.send(ClientsHandlerResponse::Revoked) {
+                    error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+                }
+                return;
+            }
+            Err(err) => {
+                if let Err(_) = res_channel.send(self.make_error_response(err)) {
+                    error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+                }
+                return;
+            }
         }
 
         if self
@@ -181,31 +215,42 @@ impl ClientsHandler {
                 "Client {:?} was already registered before!",
                 address.to_base58_string()
             )
-        } else if let Err(e) = self
-            .clients_inbox_storage
-            .create_storage_dir(address.clone())
-            .await
-        {
-            error!("We failed to create inbox directory for the client -{:?}\nReverting stored shared key...", e);
-            // we must revert our changes if this operation failed
-            self.clients_ledger.remove_shared_key(&address).unwrap();
-            self.send_error_response("failed to complete issuing shared key", res_channel);
-            return;
         }
 
-        self.push_stored_messages_to_client_and_save_channel(address, comm_channel)
-            .await;
+        // stored messages are handed over by the connection handler itself
+        self.update_last_seen(&address);
+        self.open_session(address, comm_channel);
 
         if let Err(_) = res_channel.send(ClientsHandlerResponse::Register(true)) {
             error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
         }
     }
 
+    // the client has proven its identity, but it still has to be registered with us
+    fn authenticate(
+        &mut self,
+        address: DestinationAddressBytes,
+        comm_channel: MixMessageSender,
+    ) -> ClientsHandlerResponse {
+        match self.clients_ledger.is_revoked(&address) {
+            Ok(false) => (),
+            Ok(true) => return ClientsHandlerResponse::Revoked,
+            Err(err) => return self.make_error_response(err),
+        }
+        match self.clients_ledger.get_shared_key(&address) {
+            Ok(Some(shared_key)) => {
+                self.update_last_seen(&address);
+                self.open_session(address, comm_channel);
+                ClientsHandlerResponse::Authenticate(Some(shared_key))
+            }
+            Ok(None) => ClientsHandlerResponse::Authenticate(None),
+            Err(err) => self.make_error_response(err),
+        }
+    }
+
     async fn handle_authenticate_request(
         &mut self,
         address: DestinationAddressBytes,
-        encrypted_address: EncryptedAddressBytes,
-        iv: AuthenticationIV,
         comm_channel: MixMessageSender,
         res_channel: ClientsHandlerResponseSender,
     ) {
@@ -214,34 +259,42 @@ impl ClientsHandler {
             address.to_base58_string()
         );
 
-        if self.open_connections.get(&address).is_some() {
-            warn!("Tried to process authenticate request for a client with an already opened connection!");
-            self.send_error_response("duplicate connection detected", res_channel);
-            return;
+        let response = self.authenticate(address, comm_channel);
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
         }
+    }
 
-        if self
-            .clients_ledger
-            .verify_shared_key(&address, &encrypted_address, &iv)
-            .unwrap()
-        {
-            // The first unwrap is due to possible db read errors, but I'm not entirely sure when could
-            // the second one happen.
-            let shared_key = self
-                .clients_ledger
-                .get_shared_key(&address)
-                .unwrap()
-                .unwrap();
-            self.push_stored_messages_to_client_and_save_channel(address, comm_channel)
-                .await;
-            if let Err(_) = res_channel.send(ClientsHandlerResponse::Authenticate(Some(shared_key)))
-            {
-                error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
-            }
+    async fn handle_resume_session_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        token: SessionToken,
+        comm_channel: MixMessageSender,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing resume session request: {:?}",
+            address.to_base58_string()
+        );
+
+        let response = if self.session_tokens.redeem(&token, &address) {
+            self.authenticate(address, comm_channel)
         } else {
-            if let Err(_) = res_channel.send(ClientsHandlerResponse::Authenticate(None)) {
-                error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
-            }
+            ClientsHandlerResponse::Authenticate(None)
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_issue_session_token_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        let token = self.session_tokens.issue(address);
+        if let Err(_) = res_channel.send(ClientsHandlerResponse::IssueSessionToken(token)) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
         }
     }
 
@@ -250,7 +303,193 @@ impl ClientsHandler {
             "Processing disconnect client request: {:?}",
             address.to_base58_string()
         );
-        self.open_connections.remove(&address);
+        // the connection might have been already closed due to the client being revoked
+        let sessions = match self.open_connections.get(&address) {
+            Some(sessions) => sessions,
+            None => return,
+        };
+        if sessions.remove_closed() {
+            // whatever the closed session has not acknowledged can now go to the remaining ones
+            sessions.notify_inbox_updated();
+        } else {
+            self.open_connections.remove(&address);
+        }
+        self.update_last_seen(&address);
+    }
+
+    fn handle_list_clients_request(&self, res_channel: ClientsHandlerResponseSender) {
+        debug!("Processing list clients request");
+
+        let listing: Result<Vec<_>, _> =
+            self.clients_ledger.current_clients().and_then(|addresses| {
+                addresses
+                    .into_iter()
+                    .map(|address| {
+                        Ok(RegisteredClient {
+                            online: self.open_connections.contains_key(&address),
+                            last_seen: self.clients_ledger.get_last_seen(&address)?,
+                            address,
+                        })
+                    })
+                    .collect()
+            });
+        let response = match listing {
+            Ok(clients) => ClientsHandlerResponse::ListClients(clients),
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to the admin endpoint - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_revoke_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing revoke client request: {:?}",
+            address.to_base58_string()
+        );
+
+        if let Some(sessions) = self.open_connections.remove(&address) {
+            sessions.revoke();
+        }
+        self.session_tokens.revoke(&address);
+
+        // the client is recorded as revoked first, so that it could not register again
+        // in the meantime
+        let response = match self
+            .clients_ledger
+            .revoke_client(&address, current_timestamp())
+            .and_then(|_| self.clients_ledger.remove_client(&address))
+        {
+            Ok(was_registered) => ClientsHandlerResponse::Revoke(was_registered),
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to the admin endpoint - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_reinstate_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing reinstate client request: {:?}",
+            address.to_base58_string()
+        );
+
+        let response = match self.clients_ledger.reinstate_client(&address) {
+            Ok(was_revoked) => ClientsHandlerResponse::Reinstate(was_revoked),
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to the admin endpoint - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_update_address_publication_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        published: bool,
+        timestamp: u64,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing address publication update of {:?} (published: {})",
+            address.to_base58_string(),
+            published
+        );
+
+        let response = match self
+            .clients_ledger
+            .update_address_publication(&address, published, timestamp)
+        {
+            Ok(updated) => ClientsHandlerResponse::UpdateAddressPublication(updated),
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_deregister_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing deregister client request: {:?}",
+            address.to_base58_string()
+        );
+
+        // the handler is going to close the connection once it gets the response,
+        // but the other sessions of the client have to be told to do the same
+        if let Some(sessions) = self.open_connections.remove(&address) {
+            sessions.revoke();
+        }
+        self.session_tokens.revoke(&address);
+        let response = match self.clients_ledger.remove_client(&address) {
+            Ok(was_registered) => ClientsHandlerResponse::Deregister(was_registered),
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_rotate_key_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        new_shared_key: SharedKey,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing rotate key request: {:?}",
+            address.to_base58_string()
+        );
+
+        // the previous key is simply overwritten, so it's either the old or the new one,
+        // but never neither of them
+        let response = match self
+            .clients_ledger
+            .insert_shared_key(new_shared_key, address)
+        {
+            Ok(_) => ClientsHandlerResponse::RotateKey,
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+        }
+    }
+
+    fn handle_set_delivery_policy_request(
+        &mut self,
+        address: DestinationAddressBytes,
+        policy: DeliveryPolicy,
+        res_channel: ClientsHandlerResponseSender,
+    ) {
+        debug!(
+            "Processing delivery policy update of {:?} ({:?})",
+            address.to_base58_string(),
+            policy
+        );
+
+        let response = match self.clients_ledger.set_delivery_policy(&address, policy) {
+            Ok(_) => {
+                if let Some(sessions) = self.open_connections.get(&address) {
+                    sessions.set_policy(policy);
+                }
+                ClientsHandlerResponse::SetDeliveryPolicy
+            }
+            Err(err) => self.make_error_response(err),
+        };
+        if let Err(_) = res_channel.send(response) {
+            error!("Somehow we failed to send response back to websocket handler - there seem to be a weird bug present!");
+        }
     }
 
     fn handle_is_online_request(
@@ -266,54 +505,84 @@ impl ClientsHandler {
         let response_value = self
             .open_connections
             .get(&address)
-            .map(|channel| channel.clone());
+            .map(|sessions| sessions.clone());
         // if this fails, it's a critical failure, because mix handlers should ALWAYS be online
         res_channel
             .send(ClientsHandlerResponse::IsOnline(response_value))
             .unwrap();
     }
 
+    async fn handle_request(&mut self, request: ClientsHandlerRequest) {
+        match request {
+            ClientsHandlerRequest::Register(
+                address,
+                derived_shared_key,
+                comm_channel,
+                res_channel,
+            ) => {
+                self.handle_register_request(address, derived_shared_key, comm_channel, res_channel)
+                    .await
+            }
+            ClientsHandlerRequest::Authenticate(address, comm_channel, res_channel) => {
+                self.handle_authenticate_request(address, comm_channel, res_channel)
+                    .await
+            }
+            ClientsHandlerRequest::ResumeSession(address, token, comm_channel, res_channel) => {
+                self.handle_resume_session_request(address, token, comm_channel, res_channel)
+                    .await
+            }
+            ClientsHandlerRequest::IssueSessionToken(address, res_channel) => {
+                self.handle_issue_session_token_request(address, res_channel)
+            }
+            ClientsHandlerRequest::Disconnect(address) => self.handle_disconnect(address),
+            ClientsHandlerRequest::UpdateAddressPublication(
+                address,
+                published,
+                timestamp,
+                res_channel,
+            ) => self.handle_update_address_publication_request(
+                address,
+                published,
+                timestamp,
+                res_channel,
+            ),
+            ClientsHandlerRequest::Deregister(address, res_channel) => {
+                self.handle_deregister_request(address, res_channel)
+            }
+            ClientsHandlerRequest::RotateKey(address, new_shared_key, res_channel) => {
+                self.handle_rotate_key_request(address, new_shared_key, res_channel)
+            }
+            ClientsHandlerRequest::SetDeliveryPolicy(address, policy, res_channel) => {
+                self.handle_set_delivery_policy_request(address, policy, res_channel)
+            }
+            ClientsHandlerRequest::IsOnline(address, res_channel) => {
+                self.handle_is_online_request(address, res_channel)
+            }
+            ClientsHandlerRequest::ListClients(res_channel) => {
+                self.handle_list_clients_request(res_channel)
+            }
+            ClientsHandlerRequest::Revoke(address, res_channel) => {
+                self.handle_revoke_request(address, res_channel)
+            }
+            ClientsHandlerRequest::Reinstate(address, res_channel) => {
+                self.handle_reinstate_request(address, res_channel)
+            }
+        };
+    }
+
     pub(crate) async fn run(
         &mut self,
         mut request_receiver_channel: ClientsHandlerRequestReceiver,
     ) {
-        while let Some(request) = request_receiver_channel.next().await {
-            match request {
-                ClientsHandlerRequest::Register(
-                    address,
-                    derived_shared_key,
-                    comm_channel,
-                    res_channel,
-                ) => {
-                    self.handle_register_request(
-                        address,
-                        derived_shared_key,
-                        comm_channel,
-                        res_channel,
-                    )
-                    .await
-                }
-                ClientsHandlerRequest::Authenticate(
-                    address,
-                    encrypted_address,
-                    iv,
-                    comm_channel,
-                    res_channel,
-                ) => {
-                    self.handle_authenticate_request(
-                        address,
-                        encrypted_address,
-                        iv,
-                        comm_channel,
-                        res_channel,
-                    )
-                    .await
-                }
-                ClientsHandlerRequest::Disconnect(address) => self.handle_disconnect(address),
-                ClientsHandlerRequest::IsOnline(address, res_channel) => {
-                    self.handle_is_online_request(address, res_channel)
-                }
-            };
+        let mut session_tokens_pruning = tokio::time::interval(SESSION_TOKENS_PRUNING_INTERVAL);
+        loop {
+            tokio::select! {
+                request = request_receiver_channel.next() => match request {
+                    Some(request) => self.handle_request(request).await,
+                    None => break,
+                },
+                _ = session_tokens_pruning.tick() => self.session_tokens.prune(),
+            }
         }
         error!("Something bad has happened and we stopped listening for requests!");
     }
@@ -12,5 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod client_sessions;
 pub(crate) mod clients_handler;
+pub(crate) mod session_tokens;
 pub(crate) mod websocket;
@@ -0,0 +1,202 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use gateway_requests::authentication::challenge::SessionToken;
+use gateway_requests::registration::handshake::DEFAULT_RNG;
+use nymsphinx::DestinationAddressBytes;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// a client gets a new token upon every authentication, but it only needs the most recent one
+// unless it has multiple sessions open at the same time
+const MAX_TOKENS_PER_CLIENT: usize = 8;
+
+/// Session tokens handed out to authenticated clients. Each token can be redeemed only once,
+/// by the client it was issued to and only until it expires.
+pub(crate) struct SessionTokens {
+    validity: Duration,
+    issued: HashMap<SessionToken, (DestinationAddressBytes, Instant)>,
+    // tokens of each client in the order they were issued in, i.e. from the soonest to expire
+    clients_tokens: HashMap<DestinationAddressBytes, VecDeque<SessionToken>>,
+}
+
+impl SessionTokens {
+    pub(crate) fn new(validity: Duration) -> Self {
+        SessionTokens {
+            validity,
+            issued: HashMap::new(),
+            clients_tokens: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn issue(&mut self, address: DestinationAddressBytes) -> SessionToken {
+        self.issue_at(address, Instant::now())
+    }
+
+    fn issue_at(&mut self, address: DestinationAddressBytes, now: Instant) -> SessionToken {
+        let token = SessionToken::new_random(&mut DEFAULT_RNG);
+        let client_tokens = self.clients_tokens.entry(address.clone()).or_default();
+        if client_tokens.len() >= MAX_TOKENS_PER_CLIENT {
+            if let Some(oldest) = client_tokens.pop_front() {
+                self.issued.remove(&oldest);
+            }
+        }
+        client_tokens.push_back(token);
+        self.issued.insert(token, (address, now + self.validity));
+        token
+    }
+
+    /// Consumes the token, returning whether it was valid for the address.
+    pub(crate) fn redeem(
+        &mut self,
+        token: &SessionToken,
+        address: &DestinationAddressBytes,
+    ) -> bool {
+        self.redeem_at(token, address, Instant::now())
+    }
+
+    fn redeem_at(
+        &mut self,
+        token: &SessionToken,
+        address: &DestinationAddressBytes,
+        now: Instant,
+    ) -> bool {
+        match self.issued.get(token) {
+            // do not let anyone else burn the token of the client
+            Some((owner, _)) if owner != address => false,
+            Some((_, expiration)) => {
+                let is_valid = *expiration > now;
+                self.issued.remove(token);
+                self.forget_client_token(address, token);
+                is_valid
+            }
+            None => false,
+        }
+    }
+
+    fn forget_client_token(&mut self, address: &DestinationAddressBytes, token: &SessionToken) {
+        if let Some(client_tokens) = self.clients_tokens.get_mut(address) {
+            client_tokens.retain(|client_token| client_token != token);
+            if client_tokens.is_empty() {
+                self.clients_tokens.remove(address);
+            }
+        }
+    }
+
+    /// Invalidates all tokens issued to the address.
+    pub(crate) fn revoke(&mut self, address: &DestinationAddressBytes) {
+        if let Some(client_tokens) = self.clients_tokens.remove(address) {
+            for token in client_tokens {
+                self.issued.remove(&token);
+            }
+        }
+    }
+
+    /// Forgets the tokens that have expired without being redeemed.
+    pub(crate) fn prune(&mut self) {
+        self.prune_at(Instant::now())
+    }
+
+    fn prune_at(&mut self, now: Instant) {
+        let issued = &mut self.issued;
+        self.clients_tokens.retain(|_, client_tokens| {
+            while let Some(token) = client_tokens.front() {
+                match issued.get(token) {
+                    Some((_, expiration)) if *expiration > now => break,
+                    _ => {
+                        issued.remove(token);
+                        client_tokens.pop_front();
+                    }
+                }
+            }
+            !client_tokens.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod session_token_store {
+    use super::*;
+
+    fn address(byte: u8) -> DestinationAddressBytes {
+        DestinationAddressBytes::from_bytes([byte; 32])
+    }
+
+    #[test]
+    fn can_only_be_redeemed_once() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let token = tokens.issue(address(1));
+        assert!(tokens.redeem(&token, &address(1)));
+        assert!(!tokens.redeem(&token, &address(1)));
+    }
+
+    #[test]
+    fn can_only_be_redeemed_by_their_owner() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let token = tokens.issue(address(1));
+        assert!(!tokens.redeem(&token, &address(2)));
+        // and the failed attempt did not invalidate it
+        assert!(tokens.redeem(&token, &address(1)));
+    }
+
+    #[test]
+    fn cannot_be_redeemed_after_expiring() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let token = tokens.issue_at(address(1), now);
+        assert!(!tokens.redeem_at(&token, &address(1), now + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn are_forgotten_once_expired() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let now = Instant::now();
+        tokens.issue_at(address(1), now);
+        tokens.issue_at(address(2), now + Duration::from_secs(61));
+        tokens.prune_at(now + Duration::from_secs(61));
+        assert_eq!(1, tokens.issued.len());
+        assert_eq!(1, tokens.clients_tokens.len());
+    }
+
+    #[test]
+    fn are_limited_per_client_by_dropping_the_oldest_ones() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let oldest = tokens.issue(address(1));
+        let newer: Vec<_> = (0..MAX_TOKENS_PER_CLIENT)
+            .map(|_| tokens.issue(address(1)))
+            .collect();
+        let other = tokens.issue(address(2));
+
+        assert_eq!(MAX_TOKENS_PER_CLIENT + 1, tokens.issued.len());
+        assert!(!tokens.redeem(&oldest, &address(1)));
+        for token in newer {
+            assert!(tokens.redeem(&token, &address(1)));
+        }
+        assert!(tokens.redeem(&other, &address(2)));
+        // nothing is left behind for the clients without any tokens
+        assert!(tokens.clients_tokens.is_empty());
+    }
+
+    #[test]
+    fn are_all_invalidated_upon_revocation() {
+        let mut tokens = SessionTokens::new(Duration::from_secs(60));
+        let first = tokens.issue(address(1));
+        let second = tokens.issue(address(1));
+        let other = tokens.issue(address(2));
+        tokens.revoke(&address(1));
+        assert!(!tokens.redeem(&first, &address(1)));
+        assert!(!tokens.redeem(&second, &address(1)));
+        assert!(tokens.redeem(&other, &address(2)));
+    }
+}
@@ -15,24 +15,39 @@
 use crate::node::client_handling::clients_handler::{
     ClientsHandlerRequest, ClientsHandlerRequestSender, ClientsHandlerResponse,
 };
+use crate::node::client_handling::websocket::inbox_delivery::InboxDelivery;
+use crate::node::client_handling::websocket::limits::{
+    ConnectionPermit, LimitExceeded, PacketRateLimiter,
+};
 use crate::node::client_handling::websocket::message_receiver::{
-    MixMessageReceiver, MixMessageSender,
+    MixMessageReceiver, MixMessageSender, MixMessages,
 };
-use crate::node::mixnet_handling::sender::OutboundMixMessageSender;
+use crate::node::mixnet_handling::OutboundMixMessageSender;
+use crate::node::storage::inboxes::ClientStorage;
 use crypto::asymmetric::identity;
 use futures::{
     channel::{mpsc, oneshot},
     SinkExt,
 };
-use gateway_requests::authentication::encrypted_address::EncryptedAddressBytes;
-use gateway_requests::authentication::iv::AuthenticationIV;
+use gateway_requests::authentication::challenge::{
+    AuthenticationChallenge, ChallengePurpose, ChallengeResponse, SessionToken,
+};
+use gateway_requests::authentication::CURRENT_AUTHENTICATION_VERSION;
+use gateway_requests::channel::{
+    derive_channel, ChannelDecryptor, ChannelEncryptor, ChannelSide, SessionNonce,
+};
+use gateway_requests::publication::{AddressPublication, PublicationAction};
 use gateway_requests::registration::handshake::error::HandshakeError;
 use gateway_requests::registration::handshake::{gateway_handshake, SharedKey, DEFAULT_RNG};
-use gateway_requests::types::{BinaryRequest, ClientControlRequest, ServerResponse};
+use gateway_requests::types::{
+    BinaryRequest, ClientControlRequest, DeliveryPolicy, RegistrationHandshake, RetrievalMode,
+    ServerData, ServerPush, ServerResponse,
+};
 use log::*;
 use nymsphinx::DestinationAddressBytes;
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::{prelude::*, stream::StreamExt};
 use tokio_tungstenite::{
     tungstenite::{protocol::Message, Error as WsError},
@@ -45,6 +60,31 @@ use tokio_tungstenite::{
 //// and sink for pumping responses AND mix traffic
 //// but as byproduct this might (or might not) break the clean "SocketStream" enum here
 
+// how far into the future the timestamp of an address publication can be, in milliseconds
+const MAX_PUBLICATION_CLOCK_SKEW: u64 = 5 * 60 * 1000; // 5min
+
+// how long we are willing to spend on informing a client why it is not going to be served
+const CONNECTION_REJECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Completes the websocket handshake only to send the reason for rejecting the connection.
+pub(crate) async fn reject_connection<S>(conn: S, reason: LimitExceeded)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let rejection = async {
+        let mut ws_stream = tokio_tungstenite::accept_async(conn).await?;
+        ws_stream
+            .send(ServerResponse::new_error(reason.to_string()).into())
+            .await?;
+        SinkExt::close(&mut ws_stream).await
+    };
+    match tokio::time::timeout(CONNECTION_REJECTION_TIMEOUT, rejection).await {
+        Ok(Err(err)) => trace!("Failed to inform the client about the rejection - {}", err),
+        Err(_) => trace!("Timed out while informing the client about the rejection"),
+        Ok(Ok(_)) => (),
+    }
+}
+
 enum SocketStream<S> {
     RawTCP(S),
     UpgradedWebSocket(WebSocketStream<S>),
@@ -62,12 +102,37 @@ impl<S> SocketStream<S> {
 
 pub(crate) struct Handle<S> {
     remote_address: Option<DestinationAddressBytes>,
+    // the most recent challenge sent to the client, each one can only be answered once
+    authentication_challenge: Option<AuthenticationChallenge>,
     shared_key: Option<SharedKey>,
+    // key established by the rotation handshake, it only replaces the current one
+    // once the client confirms it has switched to it as well
+    pending_shared_key: Option<SharedKey>,
+    // all binary data exchanged with the authenticated client goes through the channel
+    // derived from the shared key for the current session
+    session_nonce: Option<SessionNonce>,
+    channel_encryptor: Option<ChannelEncryptor>,
+    channel_decryptor: Option<ChannelDecryptor>,
     clients_handler_sender: ClientsHandlerRequestSender,
     outbound_mix_sender: OutboundMixMessageSender,
     socket_connection: SocketStream<S>,
 
     local_identity: Arc<identity::KeyPair>,
+
+    client_storage: ClientStorage,
+    message_retrieval_limit: usize,
+    // only exists once the client is authenticated
+    inbox_delivery: Option<InboxDelivery>,
+    // negotiated upon authentication
+    retrieval_mode: RetrievalMode,
+
+    // frees the slot taken by this connection once the handle is dropped
+    connection_permit: ConnectionPermit,
+    packet_rate_limiter: Option<PacketRateLimiter>,
+    // time the client has to authenticate before its connection is closed
+    handshake_timeout: Duration,
+    // whether the pre-Noise registration handshake is still accepted
+    legacy_registration: bool,
 }
 
 impl<S> Handle<S> {
@@ -75,20 +140,87 @@ impl<S> Handle<S> {
     // if we decide we want to change it, that's not too difficult
     pub(crate) fn new(
         conn: S,
+        connection_permit: ConnectionPermit,
         clients_handler_sender: ClientsHandlerRequestSender,
         outbound_mix_sender: OutboundMixMessageSender,
         local_identity: Arc<identity::KeyPair>,
+        client_storage: ClientStorage,
+        message_retrieval_limit: usize,
+        handshake_timeout: Duration,
     ) -> Self {
         Handle {
             remote_address: None,
+            authentication_challenge: None,
             shared_key: None,
+            pending_shared_key: None,
+            session_nonce: None,
+            channel_encryptor: None,
+            channel_decryptor: None,
             clients_handler_sender,
             outbound_mix_sender,
             socket_connection: SocketStream::RawTCP(conn),
             local_identity,
+            client_storage,
+            message_retrieval_limit,
+            inbox_delivery: None,
+            retrieval_mode: RetrievalMode::Push,
+            connection_permit,
+            packet_rate_limiter: None,
+            handshake_timeout,
+            legacy_registration: false,
         }
     }
 
+    pub(crate) fn with_packet_rate_limiter(
+        mut self,
+        packet_rate_limiter: PacketRateLimiter,
+    ) -> Self {
+        self.packet_rate_limiter = Some(packet_rate_limiter);
+        self
+    }
+
+    pub(crate) fn with_legacy_registration(mut self) -> Self {
+        self.legacy_registration = true;
+        self
+    }
+
+    /// Starts a new session with the authenticated client, returning its nonce.
+    fn start_session(&mut self, shared_key: SharedKey) -> SessionNonce {
+        let session_nonce = SessionNonce::new_random(&mut DEFAULT_RNG);
+        self.session_nonce = Some(session_nonce);
+        self.update_shared_key(shared_key);
+        session_nonce
+    }
+
+    // the channel has to be derived anew whenever the key changes
+    fn update_shared_key(&mut self, shared_key: SharedKey) {
+        let session_nonce = self
+            .session_nonce
+            .as_ref()
+            .expect("attempted to update shared key outside of a session!");
+        let (encryptor, decryptor) =
+            derive_channel(&shared_key, session_nonce, ChannelSide::Gateway);
+        self.channel_encryptor = Some(encryptor);
+        self.channel_decryptor = Some(decryptor);
+        self.shared_key = Some(shared_key);
+    }
+
+    fn end_session(&mut self) {
+        self.shared_key = None;
+        self.pending_shared_key = None;
+        self.session_nonce = None;
+        self.channel_encryptor = None;
+        self.channel_decryptor = None;
+    }
+
+    fn encrypt_server_data(&mut self, data: ServerData) -> Message {
+        data.into_ws_message(
+            self.channel_encryptor
+                .as_mut()
+                .expect("attempted to send data to unauthenticated client!"),
+        )
+    }
+
     async fn perform_websocket_handshake(&mut self) -> Result<(), WsError>
     where
         S: AsyncRead + AsyncWrite + Unpin,
@@ -112,10 +244,13 @@ impl<S> Handle<S> {
         Ok(())
     }
 
+    /// Performs the registration handshake, yielding the identity of the client
+    /// alongside the derived shared key.
     async fn perform_registration_handshake(
         &mut self,
+        version: u8,
         init_msg: Vec<u8>,
-    ) -> Result<SharedKey, HandshakeError>
+    ) -> Result<(identity::PublicKey, SharedKey), HandshakeError>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send,
     {
@@ -126,7 +261,9 @@ impl<S> Handle<S> {
                     &mut DEFAULT_RNG,
                     ws_stream,
                     self.local_identity.as_ref(),
+                    version,
                     init_msg,
+                    self.legacy_registration,
                 )
                 .await
             }
@@ -163,7 +300,7 @@ impl<S> Handle<S> {
     {
         let messages: Vec<Result<Message, WsError>> = packets
             .into_iter()
-            .map(|packet| Ok(Message::Binary(packet)))
+            .map(|packet| Ok(self.encrypt_server_data(ServerData::MixMessage(packet))))
             .collect();
         let mut send_stream = futures::stream::iter(messages);
         match self.socket_connection {
@@ -174,7 +311,13 @@ impl<S> Handle<S> {
         }
     }
 
-    fn disconnect(&self) {
+    fn disconnect(&mut self, mix_receiver: MixMessageReceiver) {
+        // the session has to be closed before announcing it, so that it could be told apart from
+        // the other sessions of the client, and whatever it has not acknowledged has to be
+        // released for them
+        drop(mix_receiver);
+        self.inbox_delivery = None;
+
         // if we never established what is the address of the client, its connection was never
         // announced hence we do not need to send 'disconnect' message
         self.remote_address.as_ref().map(|addr| {
@@ -184,14 +327,24 @@ impl<S> Handle<S> {
         });
     }
 
-    async fn handle_binary(&self, bin_msg: Vec<u8>) -> Message {
+    async fn handle_binary(&mut self, bin_msg: Vec<u8>) -> Message {
         trace!("Handling binary message (presumably sphinx packet)");
 
-        match BinaryRequest::try_from_encrypted_bytes(
-            bin_msg,
-            self.shared_key
+        if let Some(packet_rate_limiter) = self.packet_rate_limiter.as_ref() {
+            let client_address = self
+                .remote_address
                 .as_ref()
-                .expect("no shared key present even though we authenticated the client!"),
+                .expect("received binary request from unauthenticated client!");
+            if !packet_rate_limiter.try_take(client_address) {
+                return ServerResponse::new_error("packet rate limit exceeded").into();
+            }
+        }
+
+        match BinaryRequest::try_from_encrypted_bytes(
+            &bin_msg,
+            self.channel_decryptor
+                .as_mut()
+                .expect("no channel present even though we authenticated the client!"),
         ) {
             Err(e) => ServerResponse::new_error(e.to_string()),
             Ok(request) => match request {
@@ -211,58 +364,126 @@ impl<S> Handle<S> {
         .into()
     }
 
+    fn handle_challenge_request(&mut self) -> ServerResponse {
+        let challenge = AuthenticationChallenge::new_random(&mut DEFAULT_RNG);
+        self.authentication_challenge = Some(challenge);
+        ServerResponse::new_challenge(challenge)
+    }
+
     async fn handle_authenticate(
         &mut self,
         address: String,
-        enc_address: String,
-        iv: String,
+        signature: String,
+        retrieval_mode: RetrievalMode,
         mix_sender: MixMessageSender,
     ) -> ServerResponse {
-        let address = match DestinationAddressBytes::try_from_base58_string(address) {
-            Ok(address) => address,
-            Err(e) => {
-                trace!("failed to parse received DestinationAddress: {:?}", e);
-                return ServerResponse::new_error("malformed destination address").into();
-            }
+        let challenge = match self.authentication_challenge.take() {
+            Some(challenge) => challenge,
+            None => return ServerResponse::new_error("authentication without prior challenge"),
         };
 
-        let encrypted_address = match EncryptedAddressBytes::try_from_base58_string(enc_address) {
-            Ok(address) => address,
-            Err(e) => {
-                trace!("failed to parse received encrypted address: {:?}", e);
-                return ServerResponse::new_error("malformed encrypted address").into();
-            }
-        };
+        let challenge_response =
+            match ChallengeResponse::try_from_base58_fields(&address, &signature) {
+                Ok(challenge_response) => challenge_response,
+                Err(err) => {
+                    trace!("failed to parse received challenge response: {:?}", err);
+                    return ServerResponse::new_error(format!(
+                        "malformed challenge response - {}",
+                        err
+                    ));
+                }
+            };
 
-        let iv = match AuthenticationIV::try_from_base58_string(iv) {
-            Ok(iv) => iv,
+        if let Err(err) = challenge_response.verify(
+            self.local_identity.public_key(),
+            &challenge,
+            ChallengePurpose::Authentication,
+        ) {
+            trace!("received invalid challenge response: {:?}", err);
+            return ServerResponse::new_authenticate(None);
+        }
+
+        let address = challenge_response.client_address();
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request =
+            ClientsHandlerRequest::Authenticate(address.clone(), mix_sender, res_sender);
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        self.complete_authentication(address, retrieval_mode, res_receiver)
+            .await
+    }
+
+    async fn handle_resume_session(
+        &mut self,
+        address: String,
+        token: String,
+        signature: String,
+        retrieval_mode: RetrievalMode,
+        mix_sender: MixMessageSender,
+    ) -> ServerResponse {
+        let token = match SessionToken::try_from_base58_string(token) {
+            Ok(token) => token,
             Err(e) => {
-                trace!("failed to parse received IV {:?}", e);
-                return ServerResponse::new_error("malformed iv").into();
+                trace!("failed to parse received session token: {:?}", e);
+                return ServerResponse::new_error("malformed session token");
             }
         };
 
+        let challenge_response =
+            match ChallengeResponse::try_from_base58_fields(&address, &signature) {
+                Ok(challenge_response) => challenge_response,
+                Err(err) => {
+                    trace!("failed to parse received challenge response: {:?}", err);
+                    return ServerResponse::new_error(format!(
+                        "malformed challenge response - {}",
+                        err
+                    ));
+                }
+            };
+
+        if let Err(err) = challenge_response.verify(
+            self.local_identity.public_key(),
+            &token,
+            ChallengePurpose::SessionResumption,
+        ) {
+            trace!("received invalid session resumption: {:?}", err);
+            return ServerResponse::new_authenticate(None);
+        }
+
+        let address = challenge_response.client_address();
         let (res_sender, res_receiver) = oneshot::channel();
-        let clients_handler_request = ClientsHandlerRequest::Authenticate(
-            address.clone(),
-            encrypted_address,
-            iv,
-            mix_sender,
-            res_sender,
-        );
+        let clients_handler_request =
+            ClientsHandlerRequest::ResumeSession(address.clone(), token, mix_sender, res_sender);
         self.clients_handler_sender
             .unbounded_send(clients_handler_request)
             .unwrap(); // the receiver MUST BE alive
 
+        self.complete_authentication(address, retrieval_mode, res_receiver)
+            .await
+    }
+
+    async fn complete_authentication(
+        &mut self,
+        address: DestinationAddressBytes,
+        retrieval_mode: RetrievalMode,
+        res_receiver: oneshot::Receiver<ClientsHandlerResponse>,
+    ) -> ServerResponse {
         match res_receiver.await.unwrap() {
-            ClientsHandlerResponse::Authenticate(shared_key) => {
-                if shared_key.is_some() {
+            ClientsHandlerResponse::Authenticate(shared_key) => match shared_key {
+                Some(shared_key) => {
+                    let session_token = self.issue_session_token(address.clone()).await;
                     self.remote_address = Some(address);
-                    self.shared_key = shared_key;
-                    ServerResponse::Authenticate { status: true }
-                } else {
-                    ServerResponse::Authenticate { status: false }
+                    self.retrieval_mode = retrieval_mode;
+                    let session_nonce = self.start_session(shared_key);
+                    ServerResponse::new_authenticate(Some(session_nonce))
+                        .with_session_token(session_token)
                 }
+                None => ServerResponse::new_authenticate(None),
+            },
+            ClientsHandlerResponse::Revoked => {
+                ServerResponse::new_error("access revoked by the gateway")
             }
             ClientsHandlerResponse::Error(e) => {
                 error!("Authentication unexpectedly failed - {}", e);
@@ -274,32 +495,33 @@ impl<S> Handle<S> {
         }
     }
 
-    fn extract_remote_identity_from_register_init(init_data: &[u8]) -> Option<identity::PublicKey> {
-        if init_data.len() < identity::PUBLIC_KEY_LENGTH {
-            None
-        } else {
-            identity::PublicKey::from_bytes(&init_data[..identity::PUBLIC_KEY_LENGTH]).ok()
+    async fn issue_session_token(&self, address: DestinationAddressBytes) -> SessionToken {
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request = ClientsHandlerRequest::IssueSessionToken(address, res_sender);
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::IssueSessionToken(token) => token,
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
         }
     }
 
     async fn handle_register(
         &mut self,
+        version: u8,
         init_data: Vec<u8>,
         mix_sender: MixMessageSender,
     ) -> ServerResponse
     where
         S: AsyncRead + AsyncWrite + Unpin + Send,
     {
-        // not entirely sure how to it more "nicely"...
-        // hopefully, eventually this will go away once client's identity is known beforehand
-        let remote_identity = match Self::extract_remote_identity_from_register_init(&init_data) {
-            Some(address) => address,
-            None => return ServerResponse::new_error("malformed request"),
-        };
-        let remote_address = remote_identity.derive_address();
-
-        let derived_shared_key = match self.perform_registration_handshake(init_data).await {
-            Ok(shared_key) => shared_key,
+        let (remote_identity, derived_shared_key) = match self
+            .perform_registration_handshake(version, init_data)
+            .await
+        {
+            Ok(handshake_result) => handshake_result,
             Err(err) => {
                 return ServerResponse::new_error(format!(
                     "failed to perform the handshake - {}",
@@ -307,11 +529,12 @@ impl<S> Handle<S> {
                 ))
             }
         };
+        let remote_address = remote_identity.derive_address();
 
         let (res_sender, res_receiver) = oneshot::channel();
         let clients_handler_request = ClientsHandlerRequest::Register(
             remote_address.clone(),
-            derived_shared_key,
+            derived_shared_key.clone(),
             mix_sender,
             res_sender,
         );
@@ -323,9 +546,15 @@ impl<S> Handle<S> {
         match res_receiver.await.unwrap() {
             // currently register can't fail (as in if all machines are working correctly and you
             // managed to complete registration handshake)
-            ClientsHandlerResponse::Register(status) => {
+            ClientsHandlerResponse::Register(true) => {
+                let session_token = self.issue_session_token(remote_address.clone()).await;
                 self.remote_address = Some(remote_address);
-                ServerResponse::Register { status }
+                let session_nonce = self.start_session(derived_shared_key);
+                ServerResponse::new_register(Some(session_nonce)).with_session_token(session_token)
+            }
+            ClientsHandlerResponse::Register(false) => ServerResponse::new_register(None),
+            ClientsHandlerResponse::Revoked => {
+                ServerResponse::new_error("access revoked by the gateway")
             }
             ClientsHandlerResponse::Error(e) => {
                 error!("Post-handshake registration unexpectedly failed - {}", e);
@@ -337,21 +566,381 @@ impl<S> Handle<S> {
         }
     }
 
-    // currently there are no valid control messages you can send after authentication
-    async fn handle_text(&mut self, _: String) -> Message {
+    /// Retrieves the next batch of stored messages of the client, if it can be pushed now.
+    async fn next_stored_messages_push(&mut self) -> Option<Message> {
+        let inbox_delivery = self
+            .inbox_delivery
+            .as_mut()
+            .expect("attempted to push stored messages to unauthenticated client!");
+        match inbox_delivery.next_batch().await {
+            Ok(messages) => messages.map(|messages| {
+                self.encrypt_server_data(ServerData::Push(ServerPush::StoredMessages { messages }))
+            }),
+            Err(err) => {
+                error!("Failed to retrieve stored messages of the client - {}", err);
+                None
+            }
+        }
+    }
+
+    // the next batch of stored messages is the only "response" to the acknowledgement
+    async fn handle_ack_messages(&mut self, ids: Vec<u64>) -> Option<Message> {
+        let inbox_delivery = self
+            .inbox_delivery
+            .as_mut()
+            .expect("received acknowledgement from unauthenticated client!");
+        if let Err(err) = inbox_delivery.acknowledge(&ids).await {
+            error!("Failed to remove acknowledged stored messages - {}", err);
+            return None;
+        }
+        match self.retrieval_mode {
+            RetrievalMode::Push => self.next_stored_messages_push().await,
+            // the client fetches the next batch once it wants it
+            RetrievalMode::Pull => None,
+        }
+    }
+
+    // fetched messages are sent over the channel right before the actual response
+    async fn handle_fetch_messages(&mut self, max_count: u32, max_bytes: u64) -> ServerResponse
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if self.retrieval_mode != RetrievalMode::Pull {
+            return ServerResponse::new_error("messages can only be fetched in the pull mode");
+        }
+
+        let inbox_delivery = self
+            .inbox_delivery
+            .as_mut()
+            .expect("received fetch request from unauthenticated client!");
+        let messages = match inbox_delivery
+            .fetch(max_count as usize, max_bytes as usize)
+            .await
+        {
+            Ok(messages) => messages,
+            Err(err) => {
+                error!("Failed to retrieve stored messages of the client - {}", err);
+                return ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                };
+            }
+        };
+
+        let count = messages.len();
+        if count > 0 {
+            let fetched =
+                self.encrypt_server_data(ServerData::Push(ServerPush::StoredMessages { messages }));
+            if let Err(err) = self.send_websocket_response(fetched).await {
+                // the messages are going to be fetched again on the next request
+                warn!("Failed to send fetched messages over websocket: {}", err);
+                return ServerResponse::new_error("failed to send the messages");
+            }
+        }
+        ServerResponse::FetchMessages { count }
+    }
+
+    async fn handle_inbox_status(&self) -> ServerResponse {
+        let address = self
+            .remote_address
+            .as_ref()
+            .expect("received inbox status request from unauthenticated client!");
+
+        match self.client_storage.inbox_usage(address).await {
+            Ok(usage) => ServerResponse::InboxStatus {
+                count: usage.messages,
+                bytes: usage.bytes,
+            },
+            Err(err) => {
+                error!("Failed to obtain inbox usage of the client - {}", err);
+                ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                }
+            }
+        }
+    }
+
+    async fn handle_address_publication(
+        &mut self,
+        identity: String,
+        publish: bool,
+        timestamp: u64,
+        signature: String,
+    ) -> ServerResponse {
+        let publication = match AddressPublication::try_from_base58_fields(
+            &identity, publish, timestamp, &signature,
+        ) {
+            Ok(publication) => publication,
+            Err(err) => {
+                return ServerResponse::new_error(format!(
+                    "malformed address publication - {}",
+                    err
+                ))
+            }
+        };
+
+        let address = self
+            .remote_address
+            .clone()
+            .expect("received address publication from unauthenticated client!");
+        if publication.client_identity.derive_address() != address {
+            return ServerResponse::new_error("publication was made for a different address");
+        }
+        if let Err(err) = publication.verify(self.local_identity.public_key()) {
+            return ServerResponse::new_error(format!("invalid address publication - {}", err));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is before the unix epoch")
+            .as_millis() as u64;
+        if publication.timestamp > now + MAX_PUBLICATION_CLOCK_SKEW {
+            return ServerResponse::new_error("address publication is timestamped in the future");
+        }
+
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request = ClientsHandlerRequest::UpdateAddressPublication(
+            address,
+            publication.action == PublicationAction::Publish,
+            publication.timestamp,
+            res_sender,
+        );
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::UpdateAddressPublication(true) => {
+                ServerResponse::AddressPublication { status: true }
+            }
+            ClientsHandlerResponse::UpdateAddressPublication(false) => ServerResponse::new_error(
+                "address publication is not newer than the previously accepted one",
+            ),
+            ClientsHandlerResponse::Error(e) => {
+                error!("Updating address publication unexpectedly failed - {}", e);
+                ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                }
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        }
+    }
+
+    async fn handle_set_delivery_policy(&mut self, policy: DeliveryPolicy) -> ServerResponse {
+        let address = self
+            .remote_address
+            .clone()
+            .expect("received delivery policy change from unauthenticated client!");
+
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request =
+            ClientsHandlerRequest::SetDeliveryPolicy(address, policy, res_sender);
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::SetDeliveryPolicy => {
+                ServerResponse::SetDeliveryPolicy { status: true }
+            }
+            ClientsHandlerResponse::Error(e) => {
+                error!("Changing delivery policy unexpectedly failed - {}", e);
+                ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                }
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        }
+    }
+
+    async fn handle_deregister(&mut self) -> ServerResponse {
+        let address = self
+            .remote_address
+            .clone()
+            .expect("received deregister request from unauthenticated client!");
+
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request =
+            ClientsHandlerRequest::Deregister(address.clone(), res_sender);
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::Deregister(_) => {
+                if let Err(err) = self.client_storage.purge_inbox(&address).await {
+                    error!("Failed to purge inbox of deregistered client - {}", err);
+                }
+                // the client is no longer known to the gateway, so it must not be served anymore
+                self.remote_address = None;
+                self.end_session();
+                ServerResponse::Deregister { status: true }
+            }
+            ClientsHandlerResponse::Error(e) => {
+                error!("Deregistration unexpectedly failed - {}", e);
+                ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                }
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        }
+    }
+
+    // Aborts the handshake the client has started. Its outcome is still going to be sent
+    // as a normal response.
+    async fn abort_key_rotation_handshake(&mut self, message: &str)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let handshake_error = RegistrationHandshake::new_error(message);
+        // if it fails, the actual response is not going to be delivered either
+        if let Ok(handshake_error) = handshake_error.try_into() {
+            let _ = self
+                .send_websocket_response(Message::Text(handshake_error))
+                .await;
+        }
+    }
+
+    async fn handle_rotate_key(&mut self) -> ServerResponse
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let address = self
+            .remote_address
+            .clone()
+            .expect("received rotate key request from unauthenticated client!");
+
+        // the request is immediately followed by a fresh registration handshake. Note that
+        // nothing gets pushed to the client in the meantime as we are not listening
+        // for its mix messages until the handshake is over
+        let (version, init_data) = match self.next_websocket_request().await {
+            Some(Ok(Message::Text(raw_request))) => {
+                match ClientControlRequest::try_from(raw_request) {
+                    Ok(ClientControlRequest::RegisterHandshakeInitRequest { version, data }) => {
+                        (version, data)
+                    }
+                    _ => {
+                        let message = "expected the registration handshake to begin";
+                        self.abort_key_rotation_handshake(message).await;
+                        return ServerResponse::new_error(message);
+                    }
+                }
+            }
+            _ => return ServerResponse::new_error("failed to receive the handshake"),
+        };
+
+        let (remote_identity, new_shared_key) = match self
+            .perform_registration_handshake(version, init_data)
+            .await
+        {
+            Ok(handshake_result) => handshake_result,
+            Err(err) => {
+                return ServerResponse::new_error(format!(
+                    "failed to perform the handshake - {}",
+                    err
+                ))
+            }
+        };
+
+        // otherwise the client could replace the key of some other address
+        if remote_identity.derive_address() != address {
+            return ServerResponse::new_error(
+                "the handshake was performed with a different identity",
+            );
+        }
+
+        // if the client never confirms the new key (say, because it has not received this
+        // response), the previous one remains valid
+        self.pending_shared_key = Some(new_shared_key);
+        ServerResponse::RotateKey { status: true }
+    }
+
+    async fn handle_confirm_key_rotation(&mut self) -> ServerResponse {
+        let address = self
+            .remote_address
+            .clone()
+            .expect("received key rotation confirmation from unauthenticated client!");
+
+        let new_shared_key = match self.pending_shared_key.take() {
+            Some(new_shared_key) => new_shared_key,
+            None => return ServerResponse::new_error("there is no key rotation to confirm"),
+        };
+
+        let (res_sender, res_receiver) = oneshot::channel();
+        let clients_handler_request =
+            ClientsHandlerRequest::RotateKey(address, new_shared_key.clone(), res_sender);
+        self.clients_handler_sender
+            .unbounded_send(clients_handler_request)
+            .unwrap(); // the receiver MUST BE alive
+
+        match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::RotateKey => {
+                // everything sent after this response goes through the new channel
+                self.update_shared_key(new_shared_key);
+                ServerResponse::ConfirmKeyRotation { status: true }
+            }
+            ClientsHandlerResponse::Error(e) => {
+                error!("Key rotation unexpectedly failed - {}", e);
+                ServerResponse::Error {
+                    message: "unexpected failure".into(),
+                }
+            }
+            _ => panic!("received response to wrong query!"), // this should NEVER happen
+        }
+    }
+
+    async fn handle_text(&mut self, raw_request: String) -> Option<Message>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         trace!("Handling text message (presumably control message)");
 
-        error!("Currently there are no text messages besides 'Authenticate' and 'Register' and they were already dealt with!");
-        ServerResponse::new_error("invalid request").into()
+        match ClientControlRequest::try_from(raw_request) {
+            Ok(ClientControlRequest::AckMessages { ids }) => self.handle_ack_messages(ids).await,
+            Ok(ClientControlRequest::FetchMessages {
+                max_count,
+                max_bytes,
+            }) => Some(
+                self.handle_fetch_messages(max_count, max_bytes)
+                    .await
+                    .into(),
+            ),
+            Ok(ClientControlRequest::InboxStatus) => Some(self.handle_inbox_status().await.into()),
+            Ok(ClientControlRequest::AddressPublication {
+                identity,
+                publish,
+                timestamp,
+                signature,
+            }) => Some(
+                self.handle_address_publication(identity, publish, timestamp, signature)
+                    .await
+                    .into(),
+            ),
+            Ok(ClientControlRequest::Deregister) => Some(self.handle_deregister().await.into()),
+            Ok(ClientControlRequest::RotateKey) => Some(self.handle_rotate_key().await.into()),
+            Ok(ClientControlRequest::ConfirmKeyRotation) => {
+                Some(self.handle_confirm_key_rotation().await.into())
+            }
+            Ok(ClientControlRequest::SetDeliveryPolicy { policy }) => {
+                Some(self.handle_set_delivery_policy(policy).await.into())
+            }
+            Ok(_) => {
+                error!("Authentication and registration requests were already dealt with!");
+                Some(ServerResponse::new_error("invalid request").into())
+            }
+            Err(_) => Some(ServerResponse::new_error("malformed request").into()),
+        }
     }
 
-    async fn handle_request(&mut self, raw_request: Message) -> Option<Message> {
+    async fn handle_request(&mut self, raw_request: Message) -> Option<Message>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         // apparently tungstenite auto-handles ping/pong/close messages so for now let's ignore
         // them and let's test that claim. If that's not the case, just copy code from
         // desktop nym-client websocket as I've manually handled everything there
         match raw_request {
             Message::Binary(bin_msg) => Some(self.handle_binary(bin_msg).await),
-            Message::Text(text_msg) => Some(self.handle_text(text_msg).await),
+            Message::Text(text_msg) => self.handle_text(text_msg).await,
             _ => None,
         }
     }
@@ -368,16 +957,70 @@ impl<S> Handle<S> {
     {
         if let Ok(request) = ClientControlRequest::try_from(raw_request) {
             match request {
+                ClientControlRequest::RequestChallenge => self.handle_challenge_request(),
+                // clients that predate the challenges are told to upgrade,
+                // rather than have their requests rejected as malformed
+                ClientControlRequest::Authenticate { version, .. }
+                | ClientControlRequest::ResumeSession { version, .. }
+                    if version != CURRENT_AUTHENTICATION_VERSION =>
+                {
+                    ServerResponse::new_error(format!(
+                        "authentication version {} is not supported, please update the client. Supported versions: {:?}",
+                        version,
+                        [CURRENT_AUTHENTICATION_VERSION]
+                    ))
+                }
                 ClientControlRequest::Authenticate {
                     address,
-                    enc_address,
-                    iv,
+                    signature,
+                    retrieval_mode,
+                    ..
                 } => {
-                    self.handle_authenticate(address, enc_address, iv, mix_sender)
+                    self.handle_authenticate(address, signature, retrieval_mode, mix_sender)
                         .await
                 }
-                ClientControlRequest::RegisterHandshakeInitRequest { data } => {
-                    self.handle_register(data, mix_sender).await
+                ClientControlRequest::ResumeSession {
+                    address,
+                    token,
+                    signature,
+                    retrieval_mode,
+                    ..
+                } => {
+                    self.handle_resume_session(
+                        address,
+                        token,
+                        signature,
+                        retrieval_mode,
+                        mix_sender,
+                    )
+                    .await
+                }
+                ClientControlRequest::RegisterHandshakeInitRequest { version, data } => {
+                    self.handle_register(version, data, mix_sender).await
+                }
+                ClientControlRequest::AckMessages { .. } => {
+                    ServerResponse::new_error("acknowledgement without prior authentication")
+                }
+                ClientControlRequest::FetchMessages { .. } => {
+                    ServerResponse::new_error("fetching messages without prior authentication")
+                }
+                ClientControlRequest::InboxStatus => {
+                    ServerResponse::new_error("inbox status request without prior authentication")
+                }
+                ClientControlRequest::AddressPublication { .. } => {
+                    ServerResponse::new_error("address publication without prior authentication")
+                }
+                ClientControlRequest::Deregister => {
+                    ServerResponse::new_error("deregistration without prior authentication")
+                }
+                ClientControlRequest::RotateKey => {
+                    ServerResponse::new_error("key rotation without prior authentication")
+                }
+                ClientControlRequest::ConfirmKeyRotation => ServerResponse::new_error(
+                    "key rotation confirmation without prior authentication",
+                ),
+                ClientControlRequest::SetDeliveryPolicy { .. } => {
+                    ServerResponse::new_error("delivery policy change without prior authentication")
                 }
             }
         } else {
@@ -441,6 +1084,7 @@ impl<S> Handle<S> {
             // it means we successfully managed to perform authentication and announce our
             // presence to ClientsHandler
             if is_done {
+                self.connection_permit.complete_handshake();
                 return Some(mix_receiver);
             }
         }
@@ -452,10 +1096,36 @@ impl<S> Handle<S> {
     /// network that should be sent back to the client.
     async fn listen_for_requests(&mut self, mut mix_receiver: MixMessageReceiver)
     where
-        S: AsyncRead + AsyncWrite + Unpin,
+        S: AsyncRead + AsyncWrite + Unpin + Send,
     {
         trace!("Started listening for ALL incoming requests...");
 
+        let client_address = self
+            .remote_address
+            .clone()
+            .expect("started listening for requests of unauthenticated client!");
+        self.inbox_delivery = Some(InboxDelivery::new(
+            client_address,
+            self.client_storage.clone(),
+            self.message_retrieval_limit,
+        ));
+        // the client is now known to be online, so anything stored before has to be handed over,
+        // unless it wants to fetch the messages on its own
+        let initial_push = match self.retrieval_mode {
+            RetrievalMode::Push => self.next_stored_messages_push().await,
+            RetrievalMode::Pull => None,
+        };
+        if let Some(push) = initial_push {
+            if let Err(err) = self.send_websocket_response(push).await {
+                warn!(
+                    "Failed to send stored messages over websocket: {}. Assuming the connection is dead.",
+                    err
+                );
+                self.disconnect(mix_receiver);
+                return;
+            }
+        }
+
         loop {
             tokio::select! {
                 socket_msg = self.next_websocket_request() => {
@@ -483,10 +1153,30 @@ impl<S> Handle<S> {
                             break;
                         }
                     }
+
+                    // the client has just deregistered itself
+                    if self.remote_address.is_none() {
+                        break;
+                    }
                 },
                 mix_messages = mix_receiver.next() => {
                     let mix_messages = mix_messages.expect("sender was unexpectedly closed! this shouldn't have ever happened!");
-                    if let Err(e) = self.send_websocket_sphinx_packets(mix_messages).await {
+                    let send_res = match mix_messages {
+                        MixMessages::Received(packets) => self.send_websocket_sphinx_packets(packets).await,
+                        MixMessages::InboxUpdated if self.retrieval_mode == RetrievalMode::Pull => Ok(()),
+                        MixMessages::InboxUpdated => match self.next_stored_messages_push().await {
+                            Some(push) => self.send_websocket_response(push).await,
+                            None => Ok(()),
+                        },
+                        MixMessages::Revoked => {
+                            info!("Closing the connection of a revoked client");
+                            let revoked = ServerResponse::new_error("access revoked by the gateway").into();
+                            // the connection is closed regardless of whether the client learns why
+                            let _ = self.send_websocket_response(revoked).await;
+                            break;
+                        }
+                    };
+                    if let Err(e) = send_res {
                         warn!("failed to send sphinx packets back to the client - {:?}, assuming the connection is dead", e);
                         break;
                     }
@@ -494,7 +1184,7 @@ impl<S> Handle<S> {
             }
         }
 
-        self.disconnect();
+        self.disconnect(mix_receiver);
         trace!("The stream was closed!");
     }
 
@@ -502,15 +1192,27 @@ impl<S> Handle<S> {
     where
         S: AsyncRead + AsyncWrite + Unpin + Send,
     {
-        if let Err(e) = self.perform_websocket_handshake().await {
-            warn!(
-                "Failed to complete WebSocket handshake - {:?}. Stopping the handler",
-                e
-            );
-            return;
-        }
-        trace!("Managed to perform websocket handshake!");
-        let mix_receiver = self.wait_for_initial_authentication().await;
+        // the whole pre-authentication phase is limited in time, so that idle connections
+        // could not hold on to the pending handshake slots
+        let handshake_timeout = self.handshake_timeout;
+        let authentication = async {
+            if let Err(e) = self.perform_websocket_handshake().await {
+                warn!(
+                    "Failed to complete WebSocket handshake - {:?}. Stopping the handler",
+                    e
+                );
+                return None;
+            }
+            trace!("Managed to perform websocket handshake!");
+            self.wait_for_initial_authentication().await
+        };
+        let mix_receiver = match tokio::time::timeout(handshake_timeout, authentication).await {
+            Ok(mix_receiver) => mix_receiver,
+            Err(_) => {
+                debug!("The client has not authenticated in time. Stopping the handler");
+                return;
+            }
+        };
         trace!("Performed initial authentication");
         match mix_receiver {
             Some(receiver) => self.listen_for_requests(receiver).await,
@@ -0,0 +1,303 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::storage::inboxes::{self, ClientStorage, InboxStorageError, MessageId};
+use gateway_requests::StoredMessage;
+use nymsphinx::DestinationAddressBytes;
+
+/// Hands the messages stored in the inbox over to the connected client, one batch at a time.
+/// Messages are only removed from the inbox once the client acknowledges their receipt,
+/// so anything that was pushed to a client that disconnected is going to be pushed again
+/// upon its next connection.
+/// If the client has multiple sessions, a message pushed in one of them is not pushed in
+/// any other, unless the session is gone before acknowledging it.
+/// Clients in the pull retrieval mode fetch the messages themselves instead.
+pub(crate) struct InboxDelivery {
+    client_address: DestinationAddressBytes,
+    client_storage: ClientStorage,
+    batch_size: usize,
+    // messages that were pushed to the client, but whose receipt was not acknowledged yet
+    unacknowledged: Vec<MessageId>,
+}
+
+impl InboxDelivery {
+    pub(crate) fn new(
+        client_address: DestinationAddressBytes,
+        client_storage: ClientStorage,
+        batch_size: usize,
+    ) -> Self {
+        InboxDelivery {
+            client_address,
+            client_storage,
+            batch_size,
+            unacknowledged: Vec::new(),
+        }
+    }
+
+    /// Retrieves the next batch of stored messages that should be pushed to the client.
+    /// Nothing is returned while the inbox is empty or the previous batch still awaits
+    /// an acknowledgement.
+    pub(crate) async fn next_batch(
+        &mut self,
+    ) -> Result<Option<Vec<StoredMessage>>, InboxStorageError> {
+        if !self.unacknowledged.is_empty() {
+            return Ok(None);
+        }
+
+        let messages = self
+            .client_storage
+            .claim_client_messages(&self.client_address, self.batch_size, usize::MAX)
+            .await?;
+        if messages.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.hand_over(messages)))
+    }
+
+    /// Retrieves at most `max_count` oldest stored messages of at most `max_bytes` in total,
+    /// unless the oldest one alone is larger than that, as otherwise it could never be fetched.
+    /// The count is never allowed to exceed the batch size of the pushed messages.
+    /// Anything fetched before that is still not acknowledged is fetched again.
+    pub(crate) async fn fetch(
+        &mut self,
+        max_count: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError> {
+        // the client has evidently not received the previous batch
+        self.client_storage
+            .release_claims(&self.client_address, &self.unacknowledged);
+        self.unacknowledged.clear();
+
+        let messages = self
+            .client_storage
+            .claim_client_messages(
+                &self.client_address,
+                max_count.min(self.batch_size),
+                max_bytes,
+            )
+            .await?;
+
+        Ok(self.hand_over(messages))
+    }
+
+    fn hand_over(&mut self, messages: Vec<inboxes::StoredMessage>) -> Vec<StoredMessage> {
+        self.unacknowledged = messages.iter().map(|message| message.id).collect();
+        messages
+            .into_iter()
+            .map(|message| {
+                StoredMessage::new(message.id, message.arrival_timestamp, message.content)
+            })
+            .collect()
+    }
+
+    /// Removes the acknowledged messages from the inbox. Only messages of the current batch
+    /// can be acknowledged, any other ids are ignored.
+    pub(crate) async fn acknowledge(&mut self, ids: &[MessageId]) -> Result<(), InboxStorageError> {
+        let acknowledged: Vec<_> = ids
+            .iter()
+            .filter(|id| self.unacknowledged.contains(id))
+            .copied()
+            .collect();
+        if acknowledged.is_empty() {
+            return Ok(());
+        }
+
+        self.client_storage
+            .delete_messages(&self.client_address, &acknowledged)
+            .await?;
+        self.unacknowledged.retain(|id| !acknowledged.contains(id));
+        Ok(())
+    }
+}
+
+impl Drop for InboxDelivery {
+    fn drop(&mut self) {
+        // let other sessions (or the next one) receive what this one did not acknowledge
+        self.client_storage
+            .release_claims(&self.client_address, &self.unacknowledged);
+    }
+}
+
+#[cfg(test)]
+mod delivering_stored_messages {
+    use super::*;
+    use crate::node::storage::inboxes::StoreData;
+    use nymsphinx::DESTINATION_ADDRESS_LENGTH;
+
+    fn client() -> DestinationAddressBytes {
+        DestinationAddressBytes::from_bytes([1; DESTINATION_ADDRESS_LENGTH])
+    }
+
+    async fn storage_with_messages(count: u8) -> ClientStorage {
+        let client_storage = ClientStorage::temporary();
+        for i in 0..count {
+            client_storage
+                .store_processed_data(StoreData::new(client(), vec![i]))
+                .await
+                .unwrap();
+        }
+        client_storage
+    }
+
+    fn data(batch: Vec<StoredMessage>) -> Vec<Vec<u8>> {
+        batch.into_iter().map(|message| message.data).collect()
+    }
+
+    fn ids(batch: &[StoredMessage]) -> Vec<MessageId> {
+        batch.iter().map(|message| message.id).collect()
+    }
+
+    #[tokio::test]
+    async fn messages_are_delivered_in_batches_of_limited_size() {
+        let client_storage = storage_with_messages(3).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage, 2);
+
+        let first = delivery.next_batch().await.unwrap().unwrap();
+        delivery.acknowledge(&ids(&first)).await.unwrap();
+        let second = delivery.next_batch().await.unwrap().unwrap();
+        delivery.acknowledge(&ids(&second)).await.unwrap();
+
+        assert_eq!(vec![vec![0], vec![1]], data(first));
+        assert_eq!(vec![vec![2]], data(second));
+        assert!(delivery.next_batch().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn next_batch_is_withheld_until_acknowledgement() {
+        let client_storage = storage_with_messages(3).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage, 2);
+
+        let first = delivery.next_batch().await.unwrap().unwrap();
+        assert!(delivery.next_batch().await.unwrap().is_none());
+
+        // acknowledging only part of the batch is not enough
+        delivery.acknowledge(&ids(&first)[..1]).await.unwrap();
+        assert!(delivery.next_batch().await.unwrap().is_none());
+
+        delivery.acknowledge(&ids(&first)[1..]).await.unwrap();
+        assert!(delivery.next_batch().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_messages_are_redelivered() {
+        let client_storage = storage_with_messages(2).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage.clone(), 5);
+        let pushed = delivery.next_batch().await.unwrap().unwrap();
+
+        // say the client disconnected before acknowledging anything
+        drop(delivery);
+        let mut new_delivery = InboxDelivery::new(client(), client_storage, 5);
+        assert_eq!(pushed, new_delivery.next_batch().await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn messages_outside_of_current_batch_cannot_be_acknowledged() {
+        let client_storage = storage_with_messages(2).await;
+        let stored = client_storage
+            .retrieve_client_messages(&client(), 10)
+            .await
+            .unwrap();
+        let mut delivery = InboxDelivery::new(client(), client_storage.clone(), 1);
+
+        delivery.next_batch().await.unwrap().unwrap();
+        delivery.acknowledge(&[stored[1].id]).await.unwrap();
+
+        let remaining = client_storage
+            .retrieve_client_messages(&client(), 10)
+            .await
+            .unwrap();
+        assert_eq!(2, remaining.len());
+    }
+
+    #[tokio::test]
+    async fn messages_stored_during_delivery_are_delivered() {
+        let client_storage = storage_with_messages(1).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage.clone(), 5);
+
+        let first = delivery.next_batch().await.unwrap().unwrap();
+        client_storage
+            .store_processed_data(StoreData::new(client(), vec![42]))
+            .await
+            .unwrap();
+        delivery.acknowledge(&ids(&first)).await.unwrap();
+
+        let second = delivery.next_batch().await.unwrap().unwrap();
+        assert_eq!(vec![vec![42]], data(second));
+    }
+
+    #[tokio::test]
+    async fn pushed_messages_are_withheld_from_other_sessions() {
+        let client_storage = storage_with_messages(3).await;
+        let mut first_session = InboxDelivery::new(client(), client_storage.clone(), 2);
+        let mut second_session = InboxDelivery::new(client(), client_storage.clone(), 2);
+
+        let first = first_session.next_batch().await.unwrap().unwrap();
+        let second = second_session.next_batch().await.unwrap().unwrap();
+        assert_eq!(vec![vec![0], vec![1]], data(first));
+        assert_eq!(vec![vec![2]], data(second));
+    }
+
+    #[tokio::test]
+    async fn messages_of_closed_session_are_pushed_to_other_sessions() {
+        let client_storage = storage_with_messages(2).await;
+        let mut first_session = InboxDelivery::new(client(), client_storage.clone(), 5);
+        let mut second_session = InboxDelivery::new(client(), client_storage.clone(), 5);
+
+        let pushed = first_session.next_batch().await.unwrap().unwrap();
+        assert!(second_session.next_batch().await.unwrap().is_none());
+
+        drop(first_session);
+        assert_eq!(pushed, second_session.next_batch().await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn fetched_messages_are_limited_by_count_and_size() {
+        let client_storage = storage_with_messages(3).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage, 5);
+
+        assert_eq!(
+            vec![vec![0], vec![1]],
+            data(delivery.fetch(2, 5).await.unwrap())
+        );
+        assert_eq!(vec![vec![0]], data(delivery.fetch(5, 1).await.unwrap()));
+        // the oldest message is fetched even if it alone is too large
+        assert_eq!(vec![vec![0]], data(delivery.fetch(5, 0).await.unwrap()));
+        assert!(delivery.fetch(0, 5).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetched_messages_count_is_limited_by_batch_size() {
+        let client_storage = storage_with_messages(3).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage, 2);
+
+        assert_eq!(
+            vec![vec![0], vec![1]],
+            data(delivery.fetch(10, 100).await.unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn fetched_messages_are_only_removed_once_acknowledged() {
+        let client_storage = storage_with_messages(3).await;
+        let mut delivery = InboxDelivery::new(client(), client_storage, 5);
+
+        let fetched = delivery.fetch(2, 5).await.unwrap();
+        // not acknowledging means the messages are fetched again
+        assert_eq!(fetched, delivery.fetch(2, 5).await.unwrap());
+
+        delivery.acknowledge(&ids(&fetched)).await.unwrap();
+        assert_eq!(vec![vec![2]], data(delivery.fetch(2, 5).await.unwrap()));
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nymsphinx::DestinationAddressBytes;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// how often the buckets of clients that have not sent anything for a while are forgotten
+const PACKET_BUCKETS_PRUNING_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnectionLimits {
+    pub(crate) max_connections: usize,
+    pub(crate) max_pending_handshakes: usize,
+    pub(crate) max_connections_per_ip: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LimitExceeded {
+    Connections,
+    PendingHandshakes,
+    ConnectionsPerIp,
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Connections => write!(f, "too many connected clients"),
+            LimitExceeded::PendingHandshakes => {
+                write!(f, "too many clients in the middle of authentication")
+            }
+            LimitExceeded::ConnectionsPerIp => write!(f, "too many connections from your address"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ConnectionCounts {
+    connections: usize,
+    pending_handshakes: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Keeps track of client connections and makes sure none of the configured limits is exceeded.
+/// Every admitted connection holds a `ConnectionPermit` that frees its slot once dropped.
+#[derive(Clone)]
+pub(crate) struct ConnectionLimiter {
+    limits: ConnectionLimits,
+    counts: Arc<Mutex<ConnectionCounts>>,
+}
+
+impl ConnectionLimiter {
+    pub(crate) fn new(limits: ConnectionLimits) -> Self {
+        ConnectionLimiter {
+            limits,
+            counts: Arc::new(Mutex::new(Default::default())),
+        }
+    }
+
+    /// Admits new, not yet authenticated, connection from the given address.
+    pub(crate) fn try_admit(&self, ip: IpAddr) -> Result<ConnectionPermit, LimitExceeded> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.connections >= self.limits.max_connections {
+            return Err(LimitExceeded::Connections);
+        }
+        if counts.pending_handshakes >= self.limits.max_pending_handshakes {
+            return Err(LimitExceeded::PendingHandshakes);
+        }
+        if counts.per_ip.get(&ip).copied().unwrap_or(0) >= self.limits.max_connections_per_ip {
+            return Err(LimitExceeded::ConnectionsPerIp);
+        }
+
+        *counts.per_ip.entry(ip).or_insert(0) += 1;
+        counts.connections += 1;
+        counts.pending_handshakes += 1;
+        Ok(ConnectionPermit {
+            limiter: self.clone(),
+            ip,
+            handshake_pending: true,
+        })
+    }
+
+    pub(crate) fn connections(&self) -> usize {
+        self.counts.lock().unwrap().connections
+    }
+
+    pub(crate) fn pending_handshakes(&self) -> usize {
+        self.counts.lock().unwrap().pending_handshakes
+    }
+}
+
+pub(crate) struct ConnectionPermit {
+    limiter: ConnectionLimiter,
+    ip: IpAddr,
+    handshake_pending: bool,
+}
+
+impl ConnectionPermit {
+    /// Frees the pending handshake slot of this connection, while keeping the connection slot.
+    pub(crate) fn complete_handshake(&mut self) {
+        if self.handshake_pending {
+            self.handshake_pending = false;
+            self.limiter.counts.lock().unwrap().pending_handshakes -= 1;
+        }
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut counts = self.limiter.counts.lock().unwrap();
+        counts.connections -= 1;
+        if self.handshake_pending {
+            counts.pending_handshakes -= 1;
+        }
+        if let Some(ip_connections) = counts.per_ip.get_mut(&self.ip) {
+            *ip_connections -= 1;
+            if *ip_connections == 0 {
+                counts.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Token bucket limiting the rate of packets forwarded on behalf of a single client.
+/// It holds up to `burst` tokens and regains `rate` tokens each second.
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, burst: u32) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .checked_duration_since(self.last_refill)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.burst
+    }
+}
+
+struct PacketBuckets {
+    buckets: HashMap<DestinationAddressBytes, TokenBucket>,
+    last_pruned: Instant,
+}
+
+/// Limits the rate of packets forwarded on behalf of each client. All sessions of the client
+/// share the same token bucket, so opening more connections does not raise its limit.
+#[derive(Clone)]
+pub(crate) struct PacketRateLimiter {
+    rate: u32,
+    burst: u32,
+    buckets: Arc<Mutex<PacketBuckets>>,
+}
+
+impl PacketRateLimiter {
+    pub(crate) fn new(rate: u32, burst: u32) -> Self {
+        PacketRateLimiter {
+            rate,
+            burst,
+            buckets: Arc::new(Mutex::new(PacketBuckets {
+                buckets: HashMap::new(),
+                last_pruned: Instant::now(),
+            })),
+        }
+    }
+
+    fn try_take_at(&self, client_address: &DestinationAddressBytes, now: Instant) -> bool {
+        let mut guard = self.buckets.lock().unwrap();
+        let since_pruned = now
+            .checked_duration_since(guard.last_pruned)
+            .unwrap_or_else(|| Duration::from_secs(0));
+        if since_pruned >= PACKET_BUCKETS_PRUNING_INTERVAL {
+            // a full bucket is no different from a freshly created one
+            guard.buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                !bucket.is_full()
+            });
+            guard.last_pruned = now;
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+        guard
+            .buckets
+            .entry(client_address.clone())
+            .or_insert_with(|| TokenBucket::new(rate, burst))
+            .try_take_at(now)
+    }
+
+    /// Takes a single token out of the bucket of the client if there's any available.
+    pub(crate) fn try_take(&self, client_address: &DestinationAddressBytes) -> bool {
+        self.try_take_at(client_address, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod connection_limiter {
+    use super::*;
+
+    fn limiter(
+        max_connections: usize,
+        max_pending_handshakes: usize,
+        max_connections_per_ip: usize,
+    ) -> ConnectionLimiter {
+        ConnectionLimiter::new(ConnectionLimits {
+            max_connections,
+            max_pending_handshakes,
+            max_connections_per_ip,
+        })
+    }
+
+    fn ip(last_byte: u8) -> IpAddr {
+        [10, 0, 0, last_byte].into()
+    }
+
+    #[test]
+    fn rejects_connections_over_the_total_limit() {
+        let limiter = limiter(2, 10, 10);
+        let _first = limiter.try_admit(ip(1)).unwrap();
+        let _second = limiter.try_admit(ip(2)).unwrap();
+        assert_eq!(
+            LimitExceeded::Connections,
+            limiter.try_admit(ip(3)).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_connections_over_the_per_ip_limit() {
+        let limiter = limiter(10, 10, 1);
+        let _first = limiter.try_admit(ip(1)).unwrap();
+        assert_eq!(
+            LimitExceeded::ConnectionsPerIp,
+            limiter.try_admit(ip(1)).err().unwrap()
+        );
+        assert!(limiter.try_admit(ip(2)).is_ok());
+    }
+
+    #[test]
+    fn completed_handshakes_free_up_handshake_slots_only() {
+        let limiter = limiter(2, 1, 10);
+        let mut first = limiter.try_admit(ip(1)).unwrap();
+        assert_eq!(
+            LimitExceeded::PendingHandshakes,
+            limiter.try_admit(ip(2)).err().unwrap()
+        );
+
+        first.complete_handshake();
+        let mut second = limiter.try_admit(ip(2)).unwrap();
+        second.complete_handshake();
+        assert_eq!(0, limiter.pending_handshakes());
+        assert_eq!(
+            LimitExceeded::Connections,
+            limiter.try_admit(ip(3)).err().unwrap()
+        );
+    }
+
+    #[test]
+    fn dropped_permits_free_up_all_slots() {
+        let limiter = limiter(1, 1, 1);
+        let permit = limiter.try_admit(ip(1)).unwrap();
+        drop(permit);
+        assert_eq!(0, limiter.connections());
+        assert_eq!(0, limiter.pending_handshakes());
+        assert!(limiter.try_admit(ip(1)).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod token_bucket {
+    use super::*;
+
+    #[test]
+    fn allows_bursts_up_to_capacity() {
+        let mut bucket = TokenBucket::new(10, 3);
+        let now = bucket.last_refill;
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(!bucket.try_take_at(now));
+    }
+
+    #[test]
+    fn tokens_are_regained_over_time() {
+        let mut bucket = TokenBucket::new(10, 1);
+        let start = bucket.last_refill;
+        assert!(bucket.try_take_at(start));
+        assert!(!bucket.try_take_at(start + Duration::from_millis(50)));
+        assert!(bucket.try_take_at(start + Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn tokens_do_not_accumulate_over_capacity() {
+        let mut bucket = TokenBucket::new(10, 2);
+        let later = bucket.last_refill + Duration::from_secs(60);
+        assert!(bucket.try_take_at(later));
+        assert!(bucket.try_take_at(later));
+        assert!(!bucket.try_take_at(later));
+    }
+}
+
+#[cfg(test)]
+mod packet_rate_limiter {
+    use super::*;
+    use nymsphinx::DESTINATION_ADDRESS_LENGTH;
+
+    fn client(byte: u8) -> DestinationAddressBytes {
+        DestinationAddressBytes::from_bytes([byte; DESTINATION_ADDRESS_LENGTH])
+    }
+
+    #[test]
+    fn all_sessions_of_client_share_its_limit() {
+        let limiter = PacketRateLimiter::new(10, 2);
+        let other_session = limiter.clone();
+        let now = Instant::now();
+        assert!(limiter.try_take_at(&client(1), now));
+        assert!(other_session.try_take_at(&client(1), now));
+        assert!(!limiter.try_take_at(&client(1), now));
+        assert!(!other_session.try_take_at(&client(1), now));
+    }
+
+    #[test]
+    fn clients_are_limited_independently() {
+        let limiter = PacketRateLimiter::new(10, 1);
+        let now = Instant::now();
+        assert!(limiter.try_take_at(&client(1), now));
+        assert!(!limiter.try_take_at(&client(1), now));
+        assert!(limiter.try_take_at(&client(2), now));
+    }
+
+    #[test]
+    fn buckets_of_idle_clients_are_forgotten() {
+        let limiter = PacketRateLimiter::new(10, 1);
+        let now = Instant::now();
+        assert!(limiter.try_take_at(&client(1), now));
+        assert!(limiter.try_take_at(&client(2), now));
+
+        let later = now + PACKET_BUCKETS_PRUNING_INTERVAL;
+        assert!(limiter.try_take_at(&client(3), later));
+        // the only remaining bucket is the one that just got created
+        assert_eq!(1, limiter.buckets.lock().unwrap().buckets.len());
+    }
+}
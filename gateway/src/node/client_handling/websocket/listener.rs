@@ -13,24 +13,82 @@
 // limitations under the License.
 
 use crate::node::client_handling::clients_handler::ClientsHandlerRequestSender;
-use crate::node::client_handling::websocket::connection_handler::Handle;
-use crate::node::mixnet_handling::sender::OutboundMixMessageSender;
+use crate::node::client_handling::websocket::connection_handler::{reject_connection, Handle};
+use crate::node::client_handling::websocket::limits::{
+    ConnectionLimiter, LimitExceeded, PacketRateLimiter,
+};
+use crate::node::mixnet_handling::OutboundMixMessageSender;
+use crate::node::storage::inboxes::ClientStorage;
 use crypto::asymmetric::identity;
 use log::*;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 
+// maximum number of rejected clients that are simultaneously being told why, any further
+// rejected connections are closed straight away
+const MAX_CONCURRENT_REJECTIONS: usize = 16;
+
 pub(crate) struct Listener {
     address: SocketAddr,
     local_identity: Arc<identity::KeyPair>,
+    client_storage: ClientStorage,
+    message_retrieval_limit: usize,
+    connection_limiter: ConnectionLimiter,
+    handshake_timeout: Duration,
+    rejections: Arc<Semaphore>,
+    packet_rate_limiter: Option<PacketRateLimiter>,
+    legacy_registration: bool,
 }
 
 impl Listener {
-    pub(crate) fn new(address: SocketAddr, local_identity: Arc<identity::KeyPair>) -> Self {
+    pub(crate) fn new(
+        address: SocketAddr,
+        local_identity: Arc<identity::KeyPair>,
+        client_storage: ClientStorage,
+        message_retrieval_limit: usize,
+        connection_limiter: ConnectionLimiter,
+        handshake_timeout: Duration,
+    ) -> Self {
         Listener {
             address,
             local_identity,
+            client_storage,
+            message_retrieval_limit,
+            connection_limiter,
+            handshake_timeout,
+            rejections: Arc::new(Semaphore::new(MAX_CONCURRENT_REJECTIONS)),
+            packet_rate_limiter: None,
+            legacy_registration: false,
+        }
+    }
+
+    pub(crate) fn with_packet_rate_limit(mut self, rate: u32, burst: u32) -> Self {
+        self.packet_rate_limiter = Some(PacketRateLimiter::new(rate, burst));
+        self
+    }
+
+    /// Lets the clients register using the legacy version of the registration handshake.
+    pub(crate) fn with_legacy_registration(mut self) -> Self {
+        self.legacy_registration = true;
+        self
+    }
+
+    fn reject(&self, socket: TcpStream, limit: LimitExceeded) {
+        match self.rejections.try_acquire() {
+            Ok(permit) => {
+                // the permit borrows the semaphore, so it is given back manually instead
+                permit.forget();
+                let rejections = Arc::clone(&self.rejections);
+                tokio::spawn(async move {
+                    reject_connection(socket, limit).await;
+                    rejections.add_permits(1);
+                });
+            }
+            Err(_) => trace!("Too many pending rejections - closing the connection straight away"),
         }
     }
 
@@ -48,14 +106,40 @@ impl Listener {
             match tcp_listener.accept().await {
                 Ok((socket, remote_addr)) => {
                     trace!("received a socket connection from {}", remote_addr);
-                    // TODO: I think we *REALLY* need a mechanism for having a maximum number of connected
-                    // clients or spawned tokio tasks -> perhaps a worker system?
+                    let connection_permit = match self
+                        .connection_limiter
+                        .try_admit(remote_addr.ip())
+                    {
+                        Ok(permit) => permit,
+                        Err(limit) => {
+                            debug!(
+                                    "Rejecting connection from {} - {} ({} connected, {} authenticating)",
+                                    remote_addr,
+                                    limit,
+                                    self.connection_limiter.connections(),
+                                    self.connection_limiter.pending_handshakes(),
+                                );
+                            self.reject(socket, limit);
+                            continue;
+                        }
+                    };
+
                     let mut handle = Handle::new(
                         socket,
+                        connection_permit,
                         clients_handler_sender.clone(),
                         outbound_mix_sender.clone(),
                         Arc::clone(&self.local_identity),
+                        self.client_storage.clone(),
+                        self.message_retrieval_limit,
+                        self.handshake_timeout,
                     );
+                    if let Some(packet_rate_limiter) = &self.packet_rate_limiter {
+                        handle = handle.with_packet_rate_limiter(packet_rate_limiter.clone());
+                    }
+                    if self.legacy_registration {
+                        handle = handle.with_legacy_registration();
+                    }
                     tokio::spawn(async move { handle.start_handling().await });
                 }
                 Err(e) => warn!("failed to get client: {:?}", e),
@@ -14,5 +14,16 @@
 
 use futures::channel::mpsc;
 
-pub(crate) type MixMessageSender = mpsc::UnboundedSender<Vec<Vec<u8>>>;
-pub(crate) type MixMessageReceiver = mpsc::UnboundedReceiver<Vec<Vec<u8>>>;
+pub(crate) type MixMessageSender = mpsc::UnboundedSender<MixMessages>;
+pub(crate) type MixMessageReceiver = mpsc::UnboundedReceiver<MixMessages>;
+
+pub(crate) enum MixMessages {
+    /// Messages that were received from the mix network while the client was connected.
+    Received(Vec<Vec<u8>>),
+
+    /// New message got stored in the inbox of the client, even though it is connected.
+    InboxUpdated,
+
+    /// Shared key of the client got revoked, so the connection has to be closed.
+    Revoked,
+}
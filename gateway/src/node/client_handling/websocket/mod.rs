@@ -13,6 +13,8 @@
 // limitations under the License.
 
 pub(crate) mod connection_handler;
+pub(crate) mod inbox_delivery;
+pub(crate) mod limits;
 pub(crate) mod listener;
 pub(crate) mod message_receiver;
 
@@ -13,7 +13,8 @@
 // limitations under the License.
 
 pub(crate) mod receiver;
-pub(crate) mod sender;
 
 pub(crate) use receiver::listener::Listener;
 pub(crate) use receiver::packet_processing::PacketProcessor;
+
+pub(crate) type OutboundMixMessageSender = packet_forwarder::OutboundPacketSender;
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use crate::node::mixnet_handling::receiver::packet_processing::PacketProcessor;
+use layer_filter::LayerFilter;
+use link_encryption::{LinkCodec, TransportState};
 use log::*;
 use nymsphinx::framing::SphinxCodec;
 use nymsphinx::SphinxPacket;
@@ -23,8 +25,9 @@ use tokio_util::codec::Framed;
 
 pub(crate) struct Handle<S: AsyncRead + AsyncWrite + Unpin> {
     peer_address: SocketAddr,
-    framed_connection: Framed<S, SphinxCodec>,
+    framed_connection: Framed<S, LinkCodec<SphinxCodec>>,
     packet_processor: PacketProcessor,
+    layer_filter: Option<LayerFilter>,
 }
 
 impl<S> Handle<S>
@@ -36,14 +39,18 @@ where
     pub(crate) fn new(
         peer_address: SocketAddr,
         conn: S,
+        link_transport: Option<TransportState>,
         packet_processor: PacketProcessor,
+        layer_filter: Option<LayerFilter>,
     ) -> Self {
         // we expect only to receive sphinx packets on this socket, so let's frame it here
-        let framed = Framed::new(conn, SphinxCodec);
+        // (and decrypt them first if the link is encrypted)
+        let framed = Framed::new(conn, LinkCodec::new(SphinxCodec::default(), link_transport));
         Handle {
             peer_address,
             framed_connection: framed,
             packet_processor,
+            layer_filter,
         }
     }
 
@@ -57,17 +64,28 @@ where
         }
     }
 
+    fn accepts_packets(&self) -> bool {
+        match &self.layer_filter {
+            Some(layer_filter) => layer_filter.allows_ingress(
+                self.peer_address.ip(),
+                self.framed_connection.codec().remote_static_key(),
+            ),
+            None => true,
+        }
+    }
+
     pub(crate) async fn start_handling(&mut self) {
-        while let Some(sphinx_packet) = self.framed_connection.next().await {
-            match sphinx_packet {
-                Ok(sphinx_packet) => {
-                    // rather important TODO:
-                    // we *really* need a worker pool here, because if we receive too many packets,
-                    // we will spawn too many tasks and starve CPU due to context switching.
-                    // (because presumably tokio has some concept of context switching in its
-                    // scheduler)
+        while let Some(framed_sphinx_packet) = self.framed_connection.next().await {
+            match framed_sphinx_packet {
+                Ok(_) if !self.accepts_packets() => trace!(
+                    "dropping packet from {} - it's not a final layer mixnode",
+                    self.peer_address
+                ),
+                Ok(framed_sphinx_packet) => {
+                    // the actual unwrapping happens on the sphinx unwrapping pool, the spawned task
+                    // is only waiting for its result (or for the rejection if the pool is overloaded)
                     tokio::spawn(Self::process_received_packet(
-                        sphinx_packet,
+                        framed_sphinx_packet.into_inner(),
                         self.packet_processor.clone(),
                     ));
                 }
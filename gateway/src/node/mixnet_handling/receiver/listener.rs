@@ -15,17 +15,69 @@
 use crate::node::mixnet_handling::receiver::{
     connection_handler::Handle, packet_processing::PacketProcessor,
 };
+use layer_filter::LayerFilter;
+use link_encryption::ResponderConfig;
 use log::*;
 use std::net::SocketAddr;
+use tokio::net::TcpStream;
 use tokio::task::JoinHandle;
 
 pub(crate) struct Listener {
     address: SocketAddr,
+    link_config: Option<ResponderConfig>,
+    layer_filter: Option<LayerFilter>,
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    remote_addr: SocketAddr,
+    link_config: Option<ResponderConfig>,
+    packet_processor: PacketProcessor,
+    layer_filter: Option<LayerFilter>,
+) {
+    let link_transport = match link_encryption::accept(&mut socket, link_config.as_ref()).await {
+        Ok(transport) => transport,
+        Err(err) => {
+            warn!(
+                "failed to establish encrypted link with {} - {}. Closing the socket",
+                remote_addr, err
+            );
+            return;
+        }
+    };
+    if link_transport.is_some() {
+        debug!("established encrypted link with {}", remote_addr);
+    }
+
+    let mut handle = Handle::new(
+        remote_addr,
+        socket,
+        link_transport,
+        packet_processor,
+        layer_filter,
+    );
+    handle.start_handling().await
 }
 
 impl Listener {
     pub(crate) fn new(address: SocketAddr) -> Self {
-        Listener { address }
+        Listener {
+            address,
+            link_config: None,
+            layer_filter: None,
+        }
+    }
+
+    /// Only accepts mix packets from the mixnodes at the final layer of the network.
+    pub(crate) fn with_layer_filter(mut self, layer_filter: LayerFilter) -> Self {
+        self.layer_filter = Some(layer_filter);
+        self
+    }
+
+    /// Accepts encrypted links (in addition to the plaintext ones) established with our key.
+    pub(crate) fn with_link_encryption(mut self, link_config: ResponderConfig) -> Self {
+        self.link_config = Some(link_config);
+        self
     }
 
     pub(crate) async fn run(&mut self, packet_processor: PacketProcessor) {
@@ -38,8 +90,13 @@ impl Listener {
             match tcp_listener.accept().await {
                 Ok((socket, remote_addr)) => {
                     trace!("received a socket connection from {}", remote_addr);
-                    let mut handle = Handle::new(remote_addr, socket, packet_processor.clone());
-                    tokio::spawn(async move { handle.start_handling().await });
+                    tokio::spawn(handle_connection(
+                        socket,
+                        remote_addr,
+                        self.link_config.clone(),
+                        packet_processor.clone(),
+                        self.layer_filter.clone(),
+                    ));
                 }
                 Err(e) => warn!("failed to get client: {:?}", e),
             }
@@ -12,12 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::node::client_handling::client_sessions::ClientSessions;
 use crate::node::client_handling::clients_handler::{
     ClientsHandlerRequest, ClientsHandlerRequestSender, ClientsHandlerResponse,
 };
-use crate::node::client_handling::websocket::message_receiver::MixMessageSender;
-use crate::node::mixnet_handling::sender::OutboundMixMessageSender;
-use crate::node::storage::inboxes::{ClientStorage, StoreData};
+use crate::node::mixnet_handling::OutboundMixMessageSender;
+use crate::node::storage::inboxes::{ClientStorage, InboxStorageError, StoreData, StoreOutcome};
 use crypto::asymmetric::encryption;
 use futures::channel::oneshot;
 use futures::lock::Mutex;
@@ -25,9 +25,8 @@ use log::*;
 use nymsphinx::acknowledgements::surb_ack::{SURBAck, SURBAckRecoveryError};
 use nymsphinx::params::packet_sizes::PacketSize;
 use nymsphinx::{DestinationAddressBytes, Error as SphinxError, ProcessedPacket, SphinxPacket};
-
+use processing_pool::{PoolStats, ProcessingPool, ProcessingPoolError};
 use std::collections::HashMap;
-use std::io;
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -37,7 +36,8 @@ pub enum MixProcessingError {
     UnsupportedSphinxPacketSize(usize),
     SphinxProcessingError(SphinxError),
     IncorrectlyFormattedSURBAck(SURBAckRecoveryError),
-    IOError(io::Error),
+    InboxStorageError(InboxStorageError),
+    UnwrappingPoolError(ProcessingPoolError),
 }
 
 impl From<SphinxError> for MixProcessingError {
@@ -49,11 +49,19 @@ impl From<SphinxError> for MixProcessingError {
     }
 }
 
-impl From<io::Error> for MixProcessingError {
-    fn from(e: io::Error) -> Self {
+impl From<InboxStorageError> for MixProcessingError {
+    fn from(e: InboxStorageError) -> Self {
+        use MixProcessingError::*;
+
+        InboxStorageError(e)
+    }
+}
+
+impl From<ProcessingPoolError> for MixProcessingError {
+    fn from(err: ProcessingPoolError) -> Self {
         use MixProcessingError::*;
 
-        IOError(e)
+        UnwrappingPoolError(err)
     }
 }
 
@@ -65,13 +73,45 @@ impl From<SURBAckRecoveryError> for MixProcessingError {
     }
 }
 
+type UnwrappedPacket = (DestinationAddressBytes, Vec<u8>);
+
+// pool of workers performing the actual sphinx unwrapping, i.e. the CPU heavy part of processing
+type SphinxUnwrappingPool =
+    ProcessingPool<SphinxPacket, Result<UnwrappedPacket, MixProcessingError>>;
+
+fn unwrap_sphinx_packet(
+    encryption_keys: &encryption::KeyPair,
+    packet: SphinxPacket,
+) -> Result<UnwrappedPacket, MixProcessingError> {
+    match packet.process(&encryption_keys.private_key().into()) {
+        Ok(ProcessedPacket::ProcessedPacketForwardHop(_, _, _)) => {
+            warn!("Received a forward hop message - those are not implemented for gateways");
+            Err(MixProcessingError::ReceivedForwardHopError)
+        }
+        Ok(ProcessedPacket::ProcessedPacketFinalHop(client_address, _surb_id, payload)) => {
+            // in our current design, we do not care about the 'surb_id' in the header
+            // as it will always be empty anyway
+            let (payload_destination, message) = payload.try_recover_destination_and_plaintext()?;
+            // TODO: @AP, does that check still make sense?
+            if client_address != payload_destination {
+                return Err(MixProcessingError::NonMatchingRecipient);
+            }
+            Ok((client_address, message))
+        }
+        Err(e) => {
+            warn!("Failed to unwrap Sphinx packet: {:?}", e);
+            Err(MixProcessingError::SphinxProcessingError(e))
+        }
+    }
+}
+
 // PacketProcessor contains all data required to correctly unwrap and store sphinx packets
 #[derive(Clone)]
 pub struct PacketProcessor {
-    encryption_keys: Arc<encryption::KeyPair>,
+    unwrapping_pool: SphinxUnwrappingPool,
     // TODO: later investigate some concurrent hashmap solutions or perhaps RWLocks.
     // Right now Mutex is the simplest and fastest to implement approach
-    available_socket_senders_cache: Arc<Mutex<HashMap<DestinationAddressBytes, MixMessageSender>>>,
+    available_client_sessions_cache: Arc<Mutex<HashMap<DestinationAddressBytes, ClientSessions>>>,
     client_store: ClientStorage,
     clients_handler_sender: ClientsHandlerRequestSender,
     ack_sender: OutboundMixMessageSender,
@@ -83,42 +123,49 @@ impl PacketProcessor {
         clients_handler_sender: ClientsHandlerRequestSender,
         client_store: ClientStorage,
         ack_sender: OutboundMixMessageSender,
+        unwrapping_workers: usize,
+        unwrapping_queue_capacity: usize,
     ) -> Self {
         PacketProcessor {
-            available_socket_senders_cache: Arc::new(Mutex::new(HashMap::new())),
+            unwrapping_pool: ProcessingPool::new(
+                unwrapping_workers,
+                unwrapping_queue_capacity,
+                move |packet| unwrap_sphinx_packet(&encryption_keys, packet),
+            ),
+            available_client_sessions_cache: Arc::new(Mutex::new(HashMap::new())),
             clients_handler_sender,
             client_store,
-            encryption_keys,
             ack_sender,
         }
     }
 
+    pub(crate) fn unwrapping_pool_stats(&self) -> PoolStats {
+        self.unwrapping_pool.stats()
+    }
+
     fn try_push_message_to_client(
         &self,
-        sender_channel: Option<MixMessageSender>,
+        client_sessions: Option<ClientSessions>,
         message: Vec<u8>,
+        is_ack: bool,
     ) -> Result<(), Vec<u8>> {
-        match sender_channel {
+        match client_sessions {
             None => Err(message),
-            Some(sender_channel) => {
-                sender_channel
-                    .unbounded_send(vec![message])
-                    // right now it's a "simpler" case here as we're only ever sending 1 message
-                    // at the time, but the channel itself could accept arbitrary many messages at once
-                    .map_err(|try_send_err| try_send_err.into_inner().pop().unwrap())
-            }
+            // we don't know which of the sessions has sent the packet being acknowledged
+            Some(client_sessions) if is_ack => client_sessions.deliver_to_all(message),
+            Some(client_sessions) => client_sessions.deliver(message),
         }
     }
 
-    async fn try_to_obtain_client_ws_message_sender(
+    async fn try_to_obtain_client_sessions(
         &mut self,
         client_address: DestinationAddressBytes,
-    ) -> Option<MixMessageSender> {
-        let mut cache_guard = self.available_socket_senders_cache.lock().await;
+    ) -> Option<ClientSessions> {
+        let mut cache_guard = self.available_client_sessions_cache.lock().await;
 
-        if let Some(sender) = cache_guard.get(&client_address) {
-            if !sender.is_closed() {
-                return Some(sender.clone());
+        if let Some(sessions) = cache_guard.get(&client_address) {
+            if !sessions.is_closed() {
+                return Some(sessions.clone());
             } else {
                 cache_guard.remove(&client_address);
             }
@@ -127,8 +174,8 @@ impl PacketProcessor {
         // do not block other readers to the cache while we are doing some blocking work here
         drop(cache_guard);
 
-        // if we got here it means that either we have no sender channel for this client or it's closed
-        // so we must refresh it from the source, i.e. ClientsHandler
+        // if we got here it means that either we have no sessions of this client or they're all closed
+        // so we must refresh them from the source, i.e. ClientsHandler
         let (res_sender, res_receiver) = oneshot::channel();
         let clients_handler_request =
             ClientsHandlerRequest::IsOnline(client_address.clone(), res_sender);
@@ -136,28 +183,28 @@ impl PacketProcessor {
             .unbounded_send(clients_handler_request)
             .unwrap(); // the receiver MUST BE alive
 
-        let client_sender = match res_receiver.await.unwrap() {
-            ClientsHandlerResponse::IsOnline(client_sender) => client_sender,
+        let client_sessions = match res_receiver.await.unwrap() {
+            ClientsHandlerResponse::IsOnline(client_sessions) => client_sessions,
             _ => panic!("received response to wrong query!"), // again, this should NEVER happen
         };
 
-        if client_sender.is_none() {
+        if client_sessions.is_none() {
             return None;
         }
 
-        let client_sender = client_sender.unwrap();
+        let client_sessions = client_sessions.unwrap();
         // finally re-acquire the lock to update the cache
-        let mut cache_guard = self.available_socket_senders_cache.lock().await;
-        cache_guard.insert(client_address, client_sender.clone());
+        let mut cache_guard = self.available_client_sessions_cache.lock().await;
+        cache_guard.insert(client_address, client_sessions.clone());
 
-        Some(client_sender)
+        Some(client_sessions)
     }
 
     pub(crate) async fn store_processed_packet_payload(
         &self,
         client_address: DestinationAddressBytes,
         message: Vec<u8>,
-    ) -> io::Result<()> {
+    ) -> Result<StoreOutcome, InboxStorageError> {
         debug!(
             "Storing received packet for {:?} on the disk...",
             client_address.to_base58_string()
@@ -171,45 +218,28 @@ impl PacketProcessor {
         // it means client is online and hence all his messages should be pushed directly to him?
         if nymsphinx::cover::is_cover(&message) {
             debug!("Received a loop cover message - not going to store it");
-            return Ok(());
+            return Ok(StoreOutcome::Stored);
         }
 
         let store_data = StoreData::new(client_address, message);
         self.client_store.store_processed_data(store_data).await
     }
 
-    pub(crate) fn unwrap_sphinx_packet(
+    pub(crate) async fn unwrap_sphinx_packet(
         &self,
         packet: SphinxPacket,
-    ) -> Result<(DestinationAddressBytes, Vec<u8>), MixProcessingError> {
-        match packet.process(&self.encryption_keys.as_ref().private_key().into()) {
-            Ok(ProcessedPacket::ProcessedPacketForwardHop(_, _, _)) => {
-                warn!("Received a forward hop message - those are not implemented for gateways");
-                Err(MixProcessingError::ReceivedForwardHopError)
-            }
-            Ok(ProcessedPacket::ProcessedPacketFinalHop(client_address, _surb_id, payload)) => {
-                // in our current design, we do not care about the 'surb_id' in the header
-                // as it will always be empty anyway
-                let (payload_destination, message) =
-                    payload.try_recover_destination_and_plaintext()?;
-                // TODO: @AP, does that check still make sense?
-                if client_address != payload_destination {
-                    return Err(MixProcessingError::NonMatchingRecipient);
-                }
-                Ok((client_address, message))
-            }
-            Err(e) => {
-                warn!("Failed to unwrap Sphinx packet: {:?}", e);
-                Err(MixProcessingError::SphinxProcessingError(e))
-            }
-        }
+    ) -> Result<UnwrappedPacket, MixProcessingError> {
+        // the actual work happens on one of the workers so that we wouldn't block the executor
+        self.unwrapping_pool.process(packet).await?
     }
 
     fn split_plaintext_into_ack_and_message(
         &self,
         mut extracted_plaintext: Vec<u8>,
-    ) -> (Vec<u8>, Vec<u8>) {
-        if extracted_plaintext.len() < SURBAck::len() {
+    ) -> Result<(Vec<u8>, Vec<u8>), MixProcessingError> {
+        // the length of the ack depends on the encoding of its first hop address
+        let ack_len = SURBAck::try_get_len(&extracted_plaintext)?;
+        if extracted_plaintext.len() < ack_len {
             // TODO:
             // TODO:
             // this is mostly for dev purposes to see if we receive something we did not mean to send
@@ -221,9 +251,9 @@ impl PacketProcessor {
             panic!("received packet without an ack");
         }
 
-        let plaintext = extracted_plaintext.split_off(SURBAck::len());
+        let plaintext = extracted_plaintext.split_off(ack_len);
         let ack_data = extracted_plaintext;
-        (ack_data, plaintext)
+        Ok((ack_data, plaintext))
     }
 
     pub(crate) async fn process_sphinx_packet(
@@ -237,7 +267,7 @@ impl PacketProcessor {
         // 2. if client_address doesn't exist at this gateway, don't do any other work here
         // (as stupid as this sounds, there's currently no easy way of directly checking if the
         // client exists here)
-        let (client_address, plaintext) = self.unwrap_sphinx_packet(sphinx_packet)?;
+        let (client_address, plaintext) = self.unwrap_sphinx_packet(sphinx_packet).await?;
         let (routable_ack, plaintext) = match packet_len {
             n if n == PacketSize::ACKPacket.size() => {
                 trace!("received an ack packet!");
@@ -247,19 +277,22 @@ impl PacketProcessor {
                 || n == PacketSize::ExtendedPacket.size() =>
             {
                 trace!("received a normal packet!");
-                let (ack_data, plaintext) = self.split_plaintext_into_ack_and_message(plaintext);
+                let (ack_data, plaintext) = self.split_plaintext_into_ack_and_message(plaintext)?;
                 let (ack_first_hop, ack_packet) = SURBAck::try_recover_first_hop_packet(&ack_data)?;
                 (Some((ack_first_hop, ack_packet)), plaintext)
             }
             n => return Err(MixProcessingError::UnsupportedSphinxPacketSize(n)),
         };
 
-        let client_sender = self
-            .try_to_obtain_client_ws_message_sender(client_address.clone())
+        let client_sessions = self
+            .try_to_obtain_client_sessions(client_address.clone())
             .await;
 
-        if let Err(unsent_plaintext) = self.try_push_message_to_client(client_sender, plaintext) {
-            // means we failed to push message directly to the client (it might be offline)
+        if let Err(unsent_plaintext) =
+            self.try_push_message_to_client(client_sessions, plaintext, routable_ack.is_none())
+        {
+            // means we failed to push message directly to the client (it might be offline or
+            // its delivery policy requires the message to go through the inbox)
             // but we don't want to store an ack message for him - he won't be able to decode
             // it anyway.
             // TODO: after keybase discussion we *might* want to store them after all
@@ -268,16 +301,27 @@ impl PacketProcessor {
                 return Ok(());
             }
 
-            if let Err(io_err) = self
+            let outcome = self
                 .store_processed_packet_payload(client_address.clone(), unsent_plaintext)
+                .await?;
+            if outcome == StoreOutcome::Rejected {
+                // the message is dropped, so the sender should not be told it got delivered
+                trace!("Not sending an ack for a rejected message");
+                return Ok(());
+            }
+            trace!(
+                "Managed to store packet for {:?} on the disk",
+                client_address.to_base58_string()
+            );
+
+            // the client might have connected while we were storing the message, in which case
+            // its connection might have already gone through its inbox without seeing it
+            if let Some(client_sessions) = self
+                .try_to_obtain_client_sessions(client_address.clone())
                 .await
             {
-                return Err(io_err)?;
-            } else {
-                trace!(
-                    "Managed to store packet for {:?} on the disk",
-                    client_address.to_base58_string()
-                );
+                // if the connections are gone by now, the message will be pushed on the next one
+                client_sessions.notify_inbox_updated();
             }
         } else {
             trace!(
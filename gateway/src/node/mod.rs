@@ -15,14 +15,19 @@
 use crate::config::Config;
 use crate::node::client_handling::clients_handler::{ClientsHandler, ClientsHandlerRequestSender};
 use crate::node::client_handling::websocket;
-use crate::node::mixnet_handling::sender::{OutboundMixMessageSender, PacketForwarder};
+use crate::node::client_handling::websocket::limits::{ConnectionLimiter, ConnectionLimits};
+use crate::node::mixnet_handling::OutboundMixMessageSender;
 use crate::node::storage::{inboxes, ClientLedger};
 use crypto::asymmetric::{encryption, identity};
 use directory_client::DirectoryClient;
+use layer_filter::{LayerFilter, LayerFilterRefresher, NodeRole};
+use link_encryption::{InitiatorConfig, PeerKeys, ResponderConfig};
 use log::*;
+use packet_forwarder::{ForwardingStats, KnownPeers, PacketForwarder, PeerKeysRefresher};
 use std::sync::Arc;
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime};
 
+pub(crate) mod admin;
 pub(crate) mod client_handling;
 pub(crate) mod mixnet_handling;
 mod presence;
@@ -32,7 +37,8 @@ pub struct Gateway {
     config: Config,
     /// ed25519 keypair used to assert one's identity.
     identity: Arc<identity::KeyPair>,
-    /// x25519 keypair used for Diffie-Hellman. Currently only used for sphinx key derivation.
+    /// x25519 keypair used for Diffie-Hellman. Used for sphinx key derivation and, if enabled,
+    /// for authenticating links with other nodes.
     encryption_keys: Arc<encryption::KeyPair>,
     registered_clients_ledger: ClientLedger,
     client_inbox_storage: inboxes::ClientStorage,
@@ -48,11 +54,16 @@ impl Gateway {
             Err(e) => panic!(format!("Failed to load the ledger - {:?}", e)),
             Ok(ledger) => ledger,
         };
-        let client_inbox_storage = inboxes::ClientStorage::new(
-            config.get_message_retrieval_limit() as usize,
-            config.get_stored_messages_filename_length(),
-            config.get_clients_inboxes_dir(),
-        );
+        let inbox_quota = inboxes::InboxQuota {
+            max_messages: config.get_inbox_max_messages(),
+            max_bytes: config.get_inbox_max_bytes(),
+            full_inbox_policy: config.get_inbox_full_policy(),
+        };
+        let client_inbox_storage =
+            match inboxes::ClientStorage::open(config.get_clients_inboxes_dir(), inbox_quota) {
+                Err(e) => panic!(format!("Failed to open the inbox storage - {:?}", e)),
+                Ok(storage) => storage,
+            };
         Gateway {
             config,
             identity: Arc::new(identity),
@@ -66,7 +77,9 @@ impl Gateway {
         &self,
         clients_handler_sender: ClientsHandlerRequestSender,
         ack_sender: OutboundMixMessageSender,
-    ) {
+        forwarding_stats: ForwardingStats,
+        peer_keys: PeerKeys,
+    ) -> admin::MixnetStats {
         info!("Starting mix socket listener...");
 
         let packet_processor = mixnet_handling::PacketProcessor::new(
@@ -74,48 +87,184 @@ impl Gateway {
             clients_handler_sender,
             self.client_inbox_storage.clone(),
             ack_sender,
+            self.config.get_packet_processing_workers(),
+            self.config.get_packet_processing_queue_capacity(),
         );
 
-        mixnet_handling::Listener::new(self.config.get_mix_listening_address())
-            .start(packet_processor);
+        let layer_filter = if self.config.get_layer_filtering() {
+            Some(self.start_layer_filter())
+        } else {
+            None
+        };
+
+        let mut listener = mixnet_handling::Listener::new(self.config.get_mix_listening_address());
+        if let Some(layer_filter) = layer_filter.clone() {
+            listener = listener.with_layer_filter(layer_filter);
+        }
+        if self.config.get_link_encryption() {
+            // the sphinx key doubles as the static key of the links as it's the only key of ours
+            // that other nodes know from the topology. It's safe as noise never uses it directly,
+            // only as an input to DH whose outputs are hashed together with the protocol name,
+            // which domain-separates them from the shared secrets derived during sphinx processing
+            listener = listener.with_link_encryption(ResponderConfig::new(
+                &self.encryption_keys.private_key().to_bytes(),
+                peer_keys,
+                self.config.get_link_handshake_timeout(),
+            ));
+        }
+        listener.start(packet_processor.clone());
+
+        admin::MixnetStats {
+            packet_processor,
+            forwarding_stats,
+            layer_filter,
+        }
     }
 
     fn start_client_websocket_listener(
         &self,
         forwarding_channel: OutboundMixMessageSender,
         clients_handler_sender: ClientsHandlerRequestSender,
+        connection_limiter: ConnectionLimiter,
     ) {
         info!("Starting client [web]socket listener...");
 
-        websocket::Listener::new(
+        let mut listener = websocket::Listener::new(
             self.config.get_clients_listening_address(),
             Arc::clone(&self.identity),
+            self.client_inbox_storage.clone(),
+            self.config.get_message_retrieval_limit() as usize,
+            connection_limiter,
+            self.config.get_client_handshake_timeout(),
+        );
+        if self.config.get_client_packet_rate_limit() > 0 {
+            listener = listener.with_packet_rate_limit(
+                self.config.get_client_packet_rate_limit(),
+                self.config.get_client_packet_burst(),
+            );
+        }
+        if self.config.get_legacy_client_registration() {
+            listener = listener.with_legacy_registration();
+        }
+        listener.start(clients_handler_sender, forwarding_channel);
+    }
+
+    fn start_layer_filter(&self) -> LayerFilter {
+        info!("Starting layer filter refresher...");
+        let layer_filter = LayerFilter::new(NodeRole::Gateway);
+        LayerFilterRefresher::new(
+            self.config.get_presence_directory_server(),
+            layer_filter.clone(),
+            self.config.get_layer_filtering_refresh_rate(),
         )
-        .start(clients_handler_sender, forwarding_channel);
+        .start(&Handle::current());
+        layer_filter
     }
 
-    fn start_packet_forwarder(&self) -> OutboundMixMessageSender {
-        info!("Starting mix packet forwarder...");
+    fn start_peer_keys_refresher(&self) -> (PeerKeys, KnownPeers) {
+        info!("Starting peer keys refresher...");
+        let peer_keys = PeerKeys::default();
+        let known_peers = KnownPeers::default();
+        PeerKeysRefresher::new(
+            self.config.get_presence_directory_server(),
+            peer_keys.clone(),
+            known_peers.clone(),
+            self.config.get_link_keys_refresh_rate(),
+        )
+        .start(&Handle::current());
+        (peer_keys, known_peers)
+    }
 
-        let (_, forwarding_channel) = PacketForwarder::new(
+    // returns the channel for forwarding packets alongside the handle to the statistics
+    // of forwarding to particular peers
+    fn start_packet_forwarder(
+        &self,
+        peer_keys: PeerKeys,
+        known_peers: KnownPeers,
+    ) -> (OutboundMixMessageSender, ForwardingStats) {
+        let mut forwarder_config = packet_forwarder::Config::new(
             self.config.get_packet_forwarding_initial_backoff(),
             self.config.get_packet_forwarding_maximum_backoff(),
             self.config.get_initial_connection_timeout(),
+            self.config.get_packet_forwarding_queue_length(),
+            self.config.get_packet_forwarding_drop_policy(),
         )
-        .start();
-        forwarding_channel
+        .with_frame_version(self.config.get_frame_version())
+        .with_idle_peer_timeout(self.config.get_packet_forwarding_idle_peer_timeout())
+        .with_known_peers(known_peers);
+        if self.config.get_link_encryption() {
+            let link_config = InitiatorConfig::new(
+                &self.encryption_keys.private_key().to_bytes(),
+                peer_keys,
+                self.config.get_link_handshake_timeout(),
+            )
+            .with_plaintext_fallback(self.config.get_link_encryption_plaintext_fallback());
+            forwarder_config = forwarder_config.with_link_encryption(link_config);
+        }
+
+        info!("Starting mix packet forwarder...");
+        let packet_forwarder = PacketForwarder::new(forwarder_config);
+        let forwarding_stats = packet_forwarder.stats();
+        let (_, forwarding_channel) = packet_forwarder.start(&Handle::current());
+        (forwarding_channel, forwarding_stats)
+    }
+
+    fn create_connection_limiter(&self) -> ConnectionLimiter {
+        ConnectionLimiter::new(ConnectionLimits {
+            max_connections: self.config.get_max_client_connections(),
+            max_pending_handshakes: self.config.get_max_pending_client_handshakes(),
+            max_connections_per_ip: self.config.get_max_client_connections_per_ip(),
+        })
+    }
+
+    fn start_admin_endpoint(
+        &self,
+        clients_handler_sender: ClientsHandlerRequestSender,
+        connection_limiter: ConnectionLimiter,
+        mixnet_stats: admin::MixnetStats,
+    ) {
+        if let Some(admin_address) = self.config.get_admin_address() {
+            info!("Starting admin endpoint...");
+            let token_file = self.config.get_admin_token_file();
+            let token = match http_endpoint::token::load_or_create_token(&token_file) {
+                Ok(token) => token,
+                Err(err) => panic!(
+                    "Failed to load the admin token from {:?} - {}",
+                    token_file, err
+                ),
+            };
+            admin::AdminEndpoint::new(
+                admin_address,
+                token,
+                clients_handler_sender,
+                self.client_inbox_storage.clone(),
+                connection_limiter,
+                mixnet_stats,
+            )
+            .start(&Handle::current());
+        }
     }
 
     fn start_clients_handler(&self) -> ClientsHandlerRequestSender {
         info!("Starting clients handler");
         let (_, clients_handler_sender) = ClientsHandler::new(
             self.registered_clients_ledger.clone(),
-            self.client_inbox_storage.clone(),
+            self.config.get_session_token_validity(),
         )
         .start();
         clients_handler_sender
     }
 
+    fn start_expired_messages_sweeper(&self) {
+        info!("Starting expired messages sweeper...");
+        inboxes::ExpiredMessagesSweeper::new(
+            self.client_inbox_storage.clone(),
+            self.config.get_stored_message_ttl(),
+            self.config.get_expired_messages_sweep_interval(),
+        )
+        .start(&Handle::current());
+    }
+
     fn start_presence_notifier(&self) {
         info!("Starting presence notifier...");
         let notifier_config = presence::NotifierConfig::new(
@@ -181,11 +330,16 @@ impl Gateway {
 
 
 
-            let mix_forwarding_channel = self.start_packet_forwarder();
+            let (peer_keys, known_peers) = self.start_peer_keys_refresher();
+            let (mix_forwarding_channel, forwarding_stats) = self.start_packet_forwarder(peer_keys.clone(), known_peers);
             let clients_handler_sender = self.start_clients_handler();
 
-            self.start_mix_socket_listener(clients_handler_sender.clone(), mix_forwarding_channel.clone());
-            self.start_client_websocket_listener(mix_forwarding_channel, clients_handler_sender);
+            let connection_limiter = self.create_connection_limiter();
+
+            let mixnet_stats = self.start_mix_socket_listener(clients_handler_sender.clone(), mix_forwarding_channel.clone(), forwarding_stats, peer_keys);
+            self.start_client_websocket_listener(mix_forwarding_channel, clients_handler_sender.clone(), connection_limiter.clone());
+            self.start_expired_messages_sweeper();
+            self.start_admin_endpoint(clients_handler_sender, connection_limiter, mixnet_stats);
 
             self.start_presence_notifier();
 
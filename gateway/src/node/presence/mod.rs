@@ -83,7 +83,8 @@ impl Notifier {
     }
 
     async fn make_presence(&self) -> GatewayPresence {
-        let client_keys = self.client_ledger.current_clients().unwrap();
+        // only the clients that explicitly asked for it are announced
+        let client_keys = self.client_ledger.published_clients().unwrap();
         let registered_clients = client_keys
             .into_iter()
             .map(|key_bytes| GatewayClient {
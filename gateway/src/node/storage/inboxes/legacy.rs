@@ -0,0 +1,146 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{InboxFullPolicy, InboxQuota, InboxStorageError, InboxStore, Timestamp};
+use log::*;
+use nymsphinx::DestinationAddressBytes;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+// messages get imported regardless of the quota, they were already accepted after all
+const UNLIMITED_QUOTA: InboxQuota = InboxQuota {
+    max_messages: usize::MAX,
+    max_bytes: usize::MAX,
+    full_inbox_policy: InboxFullPolicy::Reject,
+};
+
+fn modification_timestamp(metadata: &fs::Metadata) -> Option<Timestamp> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as Timestamp)
+}
+
+/// Moves messages left in the file-based inboxes of previous versions of the gateway into the
+/// store. Those kept a directory per client, named after its base58 encoded address, holding
+/// a file per message. Imported files are removed, so that nothing is imported twice.
+/// Returns the number of imported messages.
+pub(crate) fn import_legacy_inboxes(
+    inboxes_dir: &Path,
+    store: &dyn InboxStore,
+) -> Result<usize, InboxStorageError> {
+    if !inboxes_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut imported = 0;
+    for entry in fs::read_dir(inboxes_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        // anything that is not named after a client address belongs to the store itself
+        let client_address = match entry
+            .file_name()
+            .to_str()
+            .and_then(|name| DestinationAddressBytes::try_from_base58_string(name).ok())
+        {
+            Some(client_address) => client_address,
+            None => continue,
+        };
+        imported += import_legacy_inbox(&entry.path(), &client_address, store)?;
+    }
+    Ok(imported)
+}
+
+fn import_legacy_inbox(
+    inbox_dir: &Path,
+    client_address: &DestinationAddressBytes,
+    store: &dyn InboxStore,
+) -> Result<usize, InboxStorageError> {
+    let mut messages = Vec::new();
+    for entry in fs::read_dir(inbox_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            warn!(
+                "potentially corrupted legacy client inbox! - found a non-file - {:?}",
+                entry.path()
+            );
+            continue;
+        }
+        messages.push((modification_timestamp(&metadata), entry.path()));
+    }
+    // file names were random, so the modification time is the only hint of the message order
+    messages.sort();
+
+    let now = super::current_timestamp();
+    for (arrival_timestamp, path) in &messages {
+        let content = fs::read(path)?;
+        store.store(
+            client_address,
+            content,
+            arrival_timestamp.unwrap_or(now),
+            &UNLIMITED_QUOTA,
+        )?;
+        fs::remove_file(path)?;
+    }
+
+    // the directory is left alone if there was anything else in it
+    if let Err(err) = fs::remove_dir(inbox_dir) {
+        warn!(
+            "Failed to remove legacy client inbox {:?} - {}",
+            inbox_dir, err
+        );
+    }
+    Ok(messages.len())
+}
+
+#[cfg(test)]
+mod legacy_inbox_import {
+    use super::super::SledInboxStore;
+    use super::*;
+    use nymsphinx::DESTINATION_ADDRESS_LENGTH;
+
+    #[test]
+    fn messages_are_moved_into_the_store() {
+        let inboxes_dir = tempfile::tempdir().unwrap();
+        let client = DestinationAddressBytes::from_bytes([1; DESTINATION_ADDRESS_LENGTH]);
+        let client_dir = inboxes_dir.path().join(client.to_base58_string());
+        fs::create_dir(&client_dir).unwrap();
+        fs::write(client_dir.join("foomp"), [1, 2, 3]).unwrap();
+        // directories not named after clients are not touched
+        let other_dir = inboxes_dir.path().join("blobs");
+        fs::create_dir(&other_dir).unwrap();
+        fs::write(other_dir.join("foomp"), [4]).unwrap();
+
+        let store = SledInboxStore::temporary();
+        assert_eq!(
+            1,
+            import_legacy_inboxes(inboxes_dir.path(), &store).unwrap()
+        );
+
+        let messages = store.retrieve(&client, 10).unwrap();
+        assert_eq!(1, messages.len());
+        assert_eq!(vec![1, 2, 3], messages[0].content);
+        assert!(!client_dir.exists());
+        assert!(other_dir.join("foomp").exists());
+
+        // nothing is imported twice
+        assert_eq!(
+            0,
+            import_legacy_inboxes(inboxes_dir.path(), &store).unwrap()
+        );
+    }
+}
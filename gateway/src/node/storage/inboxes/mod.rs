@@ -0,0 +1,389 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::InboxFullPolicy;
+use log::*;
+use nymsphinx::DestinationAddressBytes;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod legacy;
+mod sled_store;
+mod sweeper;
+
+pub(crate) use sled_store::SledInboxStore;
+pub(crate) use sweeper::ExpiredMessagesSweeper;
+
+/// Identifier of a stored message, unique within the entire storage.
+/// Identifiers of subsequently stored messages are increasing.
+pub(crate) type MessageId = u64;
+
+/// Unix timestamp, in milliseconds.
+pub(crate) type Timestamp = u64;
+
+pub(crate) fn current_timestamp() -> Timestamp {
+    // the clock would have to be set to before 1970 for this to fail
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the unix epoch")
+        .as_millis() as Timestamp
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct StoredMessage {
+    pub(crate) id: MessageId,
+    /// Time at which the message was received by the gateway.
+    pub(crate) arrival_timestamp: Timestamp,
+    pub(crate) content: Vec<u8>,
+}
+
+/// Current size of the inbox of particular client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct InboxUsage {
+    pub(crate) messages: usize,
+    pub(crate) bytes: usize,
+}
+
+/// Limits on the size of the inbox of each client.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InboxQuota {
+    pub(crate) max_messages: usize,
+    pub(crate) max_bytes: usize,
+    pub(crate) full_inbox_policy: InboxFullPolicy,
+}
+
+impl InboxQuota {
+    fn allows(&self, usage: InboxUsage, message_len: usize) -> bool {
+        usage.messages < self.max_messages && usage.bytes + message_len <= self.max_bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StoreOutcome {
+    Stored,
+    /// The message got stored after evicting the given number of the oldest messages.
+    StoredWithEviction(usize),
+    /// The message did not fit into the inbox (or is larger than the whole quota).
+    Rejected,
+}
+
+#[derive(Debug)]
+pub(crate) enum InboxStorageError {
+    DbError(sled::Error),
+    CorruptedUsage,
+    CorruptedMessage,
+    LegacyInboxError(io::Error),
+}
+
+impl Display for InboxStorageError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InboxStorageError::DbError(err) => write!(f, "inbox database failure - {}", err),
+            InboxStorageError::CorruptedUsage => write!(f, "inbox usage data is corrupted"),
+            InboxStorageError::CorruptedMessage => write!(f, "stored message is corrupted"),
+            InboxStorageError::LegacyInboxError(err) => {
+                write!(f, "failed to import legacy client inbox - {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InboxStorageError {}
+
+impl From<sled::Error> for InboxStorageError {
+    fn from(err: sled::Error) -> Self {
+        InboxStorageError::DbError(err)
+    }
+}
+
+impl From<io::Error> for InboxStorageError {
+    fn from(err: io::Error) -> Self {
+        InboxStorageError::LegacyInboxError(err)
+    }
+}
+
+/// Persistent storage of messages for clients that are currently offline.
+/// Each of the operations is atomic.
+pub(crate) trait InboxStore: Send + Sync {
+    /// Stores the message in the inbox of the client, applying the quota.
+    fn store(
+        &self,
+        client_address: &DestinationAddressBytes,
+        message: Vec<u8>,
+        arrival_timestamp: Timestamp,
+        quota: &InboxQuota,
+    ) -> Result<StoreOutcome, InboxStorageError>;
+
+    /// Retrieves up to `limit` oldest messages of the client without removing them.
+    fn retrieve(
+        &self,
+        client_address: &DestinationAddressBytes,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError> {
+        self.retrieve_batch(client_address, &HashSet::new(), limit, usize::MAX)
+    }
+
+    /// Retrieves up to `limit` oldest messages of the client, other than the `excluded` ones,
+    /// without removing them. The retrieval stops before the messages would take more than
+    /// `max_bytes` in total, unless the oldest one alone is larger than that, as otherwise
+    /// it could never be retrieved.
+    fn retrieve_batch(
+        &self,
+        client_address: &DestinationAddressBytes,
+        excluded: &HashSet<MessageId>,
+        limit: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError>;
+
+    /// Removes the specified messages of the client. Ids that are not present are ignored.
+    fn remove(
+        &self,
+        client_address: &DestinationAddressBytes,
+        ids: &[MessageId],
+    ) -> Result<(), InboxStorageError>;
+
+    /// Removes messages of all clients that arrived before the cutoff.
+    /// Returns the number of removed messages.
+    fn remove_expired(&self, cutoff: Timestamp) -> Result<usize, InboxStorageError>;
+
+    /// Removes all messages of the client. Returns the number of removed messages.
+    fn purge(&self, client_address: &DestinationAddressBytes) -> Result<usize, InboxStorageError>;
+
+    fn usage(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<InboxUsage, InboxStorageError>;
+}
+
+pub struct StoreData {
+    client_address: DestinationAddressBytes,
+    message: Vec<u8>,
+}
+
+impl StoreData {
+    pub(crate) fn new(client_address: DestinationAddressBytes, message: Vec<u8>) -> Self {
+        StoreData {
+            client_address,
+            message,
+        }
+    }
+}
+
+// Note: you should NEVER create more than a single instance of this using 'new()'.
+// You should always use .clone() to create additional instances
+#[derive(Clone)]
+pub struct ClientStorage {
+    store: Arc<dyn InboxStore>,
+    quota: InboxQuota,
+    // messages that were handed to one of the sessions of the client and are not to be handed
+    // to any other one, unless released
+    claims: Arc<Mutex<HashMap<DestinationAddressBytes, HashSet<MessageId>>>>,
+}
+
+impl ClientStorage {
+    pub(crate) fn new(store: Arc<dyn InboxStore>, quota: InboxQuota) -> Self {
+        ClientStorage {
+            store,
+            quota,
+            claims: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Opens the store in the given directory, importing any messages that were left there
+    /// by the file-based inboxes of previous versions of the gateway.
+    pub(crate) fn open(inboxes_dir: PathBuf, quota: InboxQuota) -> Result<Self, InboxStorageError> {
+        let store = SledInboxStore::open(inboxes_dir.clone())?;
+        let imported = legacy::import_legacy_inboxes(&inboxes_dir, &store)?;
+        if imported > 0 {
+            info!("Imported {} messages from legacy client inboxes", imported);
+        }
+        Ok(Self::new(Arc::new(store), quota))
+    }
+
+    // sled is blocking, so the store is never used directly from within the async context
+    async fn run_blocking<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&ClientStorage) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = self.clone();
+        tokio::task::spawn_blocking(move || f(&storage))
+            .await
+            .expect("the inbox storage task has panicked")
+    }
+
+    #[cfg(test)]
+    pub(crate) fn temporary() -> Self {
+        let quota = InboxQuota {
+            max_messages: usize::MAX,
+            max_bytes: usize::MAX,
+            full_inbox_policy: InboxFullPolicy::Reject,
+        };
+        Self::new(Arc::new(SledInboxStore::temporary()), quota)
+    }
+
+    pub(crate) async fn store_processed_data(
+        &self,
+        store_data: StoreData,
+    ) -> Result<StoreOutcome, InboxStorageError> {
+        let client_address = store_data.client_address.clone();
+        let outcome = self
+            .run_blocking(move |storage| {
+                storage.store.store(
+                    &store_data.client_address,
+                    store_data.message,
+                    current_timestamp(),
+                    &storage.quota,
+                )
+            })
+            .await?;
+        match outcome {
+            StoreOutcome::Stored => (),
+            StoreOutcome::StoredWithEviction(evicted) => debug!(
+                "Inbox of {:?} is full - evicted {} oldest messages",
+                client_address.to_base58_string(),
+                evicted
+            ),
+            StoreOutcome::Rejected => debug!(
+                "Inbox of {:?} is full - rejected new message",
+                client_address.to_base58_string()
+            ),
+        }
+        Ok(outcome)
+    }
+
+    /// Retrieves up to `limit` oldest messages of the client, without removing them.
+    pub(crate) async fn retrieve_client_messages(
+        &self,
+        client_address: &DestinationAddressBytes,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError> {
+        let client_address = client_address.clone();
+        self.run_blocking(move |storage| storage.store.retrieve(&client_address, limit))
+            .await
+    }
+
+    /// Retrieves up to `limit` oldest messages of the client that are not claimed yet, of at most
+    /// `max_bytes` in total (see `InboxStore::retrieve_batch`), without removing them, and claims
+    /// them, so that they would not be retrieved this way again until released or deleted.
+    pub(crate) async fn claim_client_messages(
+        &self,
+        client_address: &DestinationAddressBytes,
+        limit: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError> {
+        let client_address = client_address.clone();
+        self.run_blocking(move |storage| {
+            // the claims are not locked while reading from the store, so that releasing
+            // claims never has to wait for it
+            let already_claimed = storage
+                .claims
+                .lock()
+                .unwrap()
+                .get(&client_address)
+                .cloned()
+                .unwrap_or_default();
+            let stored = storage.store.retrieve_batch(
+                &client_address,
+                &already_claimed,
+                limit,
+                max_bytes,
+            )?;
+
+            // in the meantime, some of the messages might have been claimed by another session
+            let mut claims_guard = storage.claims.lock().unwrap();
+            let claimed = claims_guard.entry(client_address.clone()).or_default();
+            let unclaimed: Vec<_> = stored
+                .into_iter()
+                .filter(|message| !claimed.contains(&message.id))
+                .collect();
+            claimed.extend(unclaimed.iter().map(|message| message.id));
+            if claimed.is_empty() {
+                claims_guard.remove(&client_address);
+            }
+            Ok(unclaimed)
+        })
+        .await
+    }
+
+    /// Makes the claimed messages available to be claimed again.
+    pub(crate) fn release_claims(
+        &self,
+        client_address: &DestinationAddressBytes,
+        ids: &[MessageId],
+    ) {
+        let mut claims_guard = self.claims.lock().unwrap();
+        if let Some(claimed) = claims_guard.get_mut(client_address) {
+            for id in ids {
+                claimed.remove(id);
+            }
+            if claimed.is_empty() {
+                claims_guard.remove(client_address);
+            }
+        }
+    }
+
+    pub(crate) async fn delete_messages(
+        &self,
+        client_address: &DestinationAddressBytes,
+        ids: &[MessageId],
+    ) -> Result<(), InboxStorageError> {
+        let client_address = client_address.clone();
+        let ids = ids.to_vec();
+        self.run_blocking(move |storage| {
+            storage.store.remove(&client_address, &ids)?;
+            storage.release_claims(&client_address, &ids);
+            Ok(())
+        })
+        .await
+    }
+
+    pub(crate) async fn inbox_usage(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<InboxUsage, InboxStorageError> {
+        let client_address = client_address.clone();
+        self.run_blocking(move |storage| storage.store.usage(&client_address))
+            .await
+    }
+
+    /// Removes all messages stored for the client, returning how many got removed.
+    pub(crate) async fn purge_inbox(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<usize, InboxStorageError> {
+        let client_address = client_address.clone();
+        self.run_blocking(move |storage| {
+            let purged = storage.store.purge(&client_address)?;
+            storage.claims.lock().unwrap().remove(&client_address);
+            Ok(purged)
+        })
+        .await
+    }
+
+    /// Removes all messages that have been stored for longer than `ttl`,
+    /// returning how many got removed.
+    pub(crate) async fn remove_expired_messages(
+        &self,
+        ttl: Duration,
+    ) -> Result<usize, InboxStorageError> {
+        let cutoff = current_timestamp().saturating_sub(ttl.as_millis() as Timestamp);
+        self.run_blocking(move |storage| storage.store.remove_expired(cutoff))
+            .await
+    }
+}
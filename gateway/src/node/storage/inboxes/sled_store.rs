@@ -0,0 +1,637 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    InboxFullPolicy, InboxQuota, InboxStorageError, InboxStore, InboxUsage, MessageId,
+    StoreOutcome, StoredMessage, Timestamp,
+};
+use nymsphinx::DestinationAddressBytes;
+use sled::{TransactionError, TransactionResult, Transactional};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const MESSAGES_TREE: &str = "messages";
+const USAGE_TREE: &str = "usage";
+const ARRIVALS_TREE: &str = "arrivals";
+
+// maximum number of expired messages removed while holding the write lock, so that
+// storing new messages is not blocked for the entire duration of a large expiry
+const EXPIRY_BATCH_SIZE: usize = 1000;
+
+// length of the encoded `InboxUsage`
+const USAGE_LEN: usize = 16;
+
+// length of the arrival timestamp prepended to each stored message
+const TIMESTAMP_LEN: usize = std::mem::size_of::<Timestamp>();
+
+// messages are keyed by address of the client followed by big endian message id, so that
+// all messages of given client are next to each other and are sorted from the oldest
+fn message_key(client_address: &DestinationAddressBytes, id: MessageId) -> Vec<u8> {
+    let mut key = client_address.to_bytes().to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+// keys are always created with `message_key`, so both parts are always there
+fn split_message_key(key: &[u8]) -> (&[u8], MessageId) {
+    let (client_key, id_bytes) = key.split_at(key.len() - std::mem::size_of::<MessageId>());
+    (
+        client_key,
+        MessageId::from_be_bytes(id_bytes.try_into().unwrap()),
+    )
+}
+
+// the arrivals index is keyed by big endian arrival timestamp followed by the message key,
+// so that it is sorted from the oldest message across all clients
+fn arrival_key(arrival_timestamp: Timestamp, message_key: &[u8]) -> Vec<u8> {
+    let mut key = arrival_timestamp.to_be_bytes().to_vec();
+    key.extend_from_slice(message_key);
+    key
+}
+
+// messages are stored alongside big endian timestamp of their arrival
+fn encode_message(arrival_timestamp: Timestamp, content: &[u8]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(TIMESTAMP_LEN + content.len());
+    value.extend_from_slice(&arrival_timestamp.to_be_bytes());
+    value.extend_from_slice(content);
+    value
+}
+
+fn decode_message(value: &[u8]) -> Result<(Timestamp, &[u8]), InboxStorageError> {
+    if value.len() < TIMESTAMP_LEN {
+        return Err(InboxStorageError::CorruptedMessage);
+    }
+    let (timestamp_bytes, content) = value.split_at(TIMESTAMP_LEN);
+    Ok((
+        Timestamp::from_be_bytes(timestamp_bytes.try_into().unwrap()),
+        content,
+    ))
+}
+
+fn content_len(value: &[u8]) -> Result<usize, InboxStorageError> {
+    decode_message(value).map(|(_, content)| content.len())
+}
+
+fn encode_usage(usage: InboxUsage) -> [u8; USAGE_LEN] {
+    let mut bytes = [0u8; USAGE_LEN];
+    bytes[..8].copy_from_slice(&(usage.messages as u64).to_be_bytes());
+    bytes[8..].copy_from_slice(&(usage.bytes as u64).to_be_bytes());
+    bytes
+}
+
+fn decode_usage(bytes: &[u8]) -> Result<InboxUsage, InboxStorageError> {
+    if bytes.len() != USAGE_LEN {
+        return Err(InboxStorageError::CorruptedUsage);
+    }
+    // the slices have correct lengths as we've just checked the total length
+    Ok(InboxUsage {
+        messages: u64::from_be_bytes(bytes[..8].try_into().unwrap()) as usize,
+        bytes: u64::from_be_bytes(bytes[8..].try_into().unwrap()) as usize,
+    })
+}
+
+fn transaction_error(err: TransactionError<()>) -> InboxStorageError {
+    match err {
+        TransactionError::Storage(err) => err.into(),
+        TransactionError::Abort(()) => unreachable!("none of our transactions ever abort"),
+    }
+}
+
+/// Inbox storage backed by a sled database. Messages, per-client usage and the index of message
+/// arrivals are kept in separate trees that are always updated within a single transaction.
+pub(crate) struct SledInboxStore {
+    db: sled::Db,
+    messages: sled::Tree,
+    usage: sled::Tree,
+    arrivals: sled::Tree,
+    // modifications depend on data read outside of the transaction (the oldest messages
+    // to evict), so they are serialized to make sure nothing changes in between
+    write_lock: Mutex<()>,
+}
+
+impl SledInboxStore {
+    pub(crate) fn open(path: PathBuf) -> Result<Self, InboxStorageError> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn temporary() -> Self {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Self::from_db(db).unwrap()
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self, InboxStorageError> {
+        let store = SledInboxStore {
+            messages: db.open_tree(MESSAGES_TREE)?,
+            usage: db.open_tree(USAGE_TREE)?,
+            arrivals: db.open_tree(ARRIVALS_TREE)?,
+            db,
+            write_lock: Mutex::new(()),
+        };
+        // databases created before the index was introduced only have the messages
+        if store.arrivals.is_empty() && !store.messages.is_empty() {
+            store.rebuild_arrivals_index()?;
+        }
+        Ok(store)
+    }
+
+    fn rebuild_arrivals_index(&self) -> Result<(), InboxStorageError> {
+        for entry in self.messages.iter() {
+            let (key, value) = entry?;
+            let (arrival_timestamp, _) = decode_message(&value)?;
+            self.arrivals
+                .insert(arrival_key(arrival_timestamp, &key), &[] as &[u8])?;
+        }
+        Ok(())
+    }
+
+    fn client_usage(&self, client_key: &[u8]) -> Result<InboxUsage, InboxStorageError> {
+        match self.usage.get(client_key)? {
+            Some(usage) => decode_usage(&usage),
+            None => Ok(InboxUsage::default()),
+        }
+    }
+
+    // updates messages, their arrivals index and usage of the client atomically
+    fn commit(
+        &self,
+        client_key: &[u8],
+        inserted: Option<(sled::IVec, Vec<u8>)>,
+        removed: &[sled::IVec],
+        usage: InboxUsage,
+    ) -> Result<(), InboxStorageError> {
+        let res: TransactionResult<()> = (&self.messages, &self.usage, &self.arrivals).transaction(
+            |(messages, usages, arrivals)| {
+                for key in removed {
+                    if let Some(message) = messages.remove(key.clone())? {
+                        // messages are only ever stored through here, so they are well formed
+                        if let Ok((arrival_timestamp, _)) = decode_message(&message) {
+                            arrivals.remove(arrival_key(arrival_timestamp, key))?;
+                        }
+                    }
+                }
+                if let Some((key, message)) = &inserted {
+                    messages.insert(key.clone(), message.as_slice())?;
+                    if let Ok((arrival_timestamp, _)) = decode_message(message) {
+                        arrivals.insert(arrival_key(arrival_timestamp, key), &[] as &[u8])?;
+                    }
+                }
+                if usage == InboxUsage::default() {
+                    usages.remove(client_key)?;
+                } else {
+                    usages.insert(client_key, &encode_usage(usage)[..])?;
+                }
+                Ok(())
+            },
+        );
+        res.map_err(transaction_error)
+    }
+
+    // removes up to `EXPIRY_BATCH_SIZE` of the oldest messages that arrived before the cutoff,
+    // returning how many got removed
+    fn remove_expired_batch(&self, cutoff: Timestamp) -> Result<usize, InboxStorageError> {
+        let _guard = self.write_lock.lock().unwrap();
+
+        // the index is sorted by arrival, so only the expired messages are ever looked at.
+        // Expired messages are then removed separately for each client.
+        let mut expired: HashMap<Vec<u8>, Vec<sled::IVec>> = HashMap::new();
+        let mut expired_count = 0;
+        for arrival in self
+            .arrivals
+            .range(..cutoff.to_be_bytes())
+            .keys()
+            .take(EXPIRY_BATCH_SIZE)
+        {
+            let arrival = arrival?;
+            let key: sled::IVec = arrival[TIMESTAMP_LEN..].into();
+            let client_key = split_message_key(&key).0.to_vec();
+            expired.entry(client_key).or_default().push(key);
+            expired_count += 1;
+        }
+
+        for (client_key, keys) in expired {
+            let mut usage = self.client_usage(&client_key)?;
+            for key in &keys {
+                // we are holding the write lock, so the messages could not have disappeared
+                if let Some(message) = self.messages.get(key)? {
+                    usage.messages -= 1;
+                    usage.bytes -= content_len(&message)?;
+                }
+            }
+            self.commit(&client_key, None, &keys, usage)?;
+        }
+
+        Ok(expired_count)
+    }
+}
+
+impl InboxStore for SledInboxStore {
+    fn store(
+        &self,
+        client_address: &DestinationAddressBytes,
+        message: Vec<u8>,
+        arrival_timestamp: Timestamp,
+        quota: &InboxQuota,
+    ) -> Result<StoreOutcome, InboxStorageError> {
+        if message.len() > quota.max_bytes {
+            return Ok(StoreOutcome::Rejected);
+        }
+
+        let client_key = client_address.to_bytes();
+        // the lock can only be poisoned if some thread panicked while holding it and we never
+        // do anything that could panic with it
+        let _guard = self.write_lock.lock().unwrap();
+        let mut usage = self.client_usage(&client_key)?;
+
+        let mut evicted = Vec::new();
+        if !quota.allows(usage, message.len()) {
+            if quota.full_inbox_policy == InboxFullPolicy::Reject {
+                return Ok(StoreOutcome::Rejected);
+            }
+            for entry in self.messages.scan_prefix(client_key) {
+                if quota.allows(usage, message.len()) {
+                    break;
+                }
+                let (key, evicted_message) = entry?;
+                usage.messages -= 1;
+                usage.bytes -= content_len(&evicted_message)?;
+                evicted.push(key);
+            }
+        }
+
+        let key = message_key(client_address, self.db.generate_id()?);
+        usage.messages += 1;
+        usage.bytes += message.len();
+        let value = encode_message(arrival_timestamp, &message);
+        self.commit(&client_key, Some((key.into(), value)), &evicted, usage)?;
+
+        if evicted.is_empty() {
+            Ok(StoreOutcome::Stored)
+        } else {
+            Ok(StoreOutcome::StoredWithEviction(evicted.len()))
+        }
+    }
+
+    fn retrieve_batch(
+        &self,
+        client_address: &DestinationAddressBytes,
+        excluded: &HashSet<MessageId>,
+        limit: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<StoredMessage>, InboxStorageError> {
+        let mut retrieved = Vec::new();
+        let mut total_bytes = 0;
+        for entry in self.messages.scan_prefix(client_address.to_bytes()) {
+            if retrieved.len() >= limit {
+                break;
+            }
+
+            let (key, value) = entry?;
+            let id = split_message_key(&key).1;
+            if excluded.contains(&id) {
+                continue;
+            }
+
+            let (arrival_timestamp, content) = decode_message(&value)?;
+            total_bytes += content.len();
+            if total_bytes > max_bytes && !retrieved.is_empty() {
+                break;
+            }
+
+            retrieved.push(StoredMessage {
+                id,
+                arrival_timestamp,
+                content: content.to_vec(),
+            });
+        }
+
+        Ok(retrieved)
+    }
+
+    fn remove(
+        &self,
+        client_address: &DestinationAddressBytes,
+        ids: &[MessageId],
+    ) -> Result<(), InboxStorageError> {
+        let client_key = client_address.to_bytes();
+        let _guard = self.write_lock.lock().unwrap();
+        let mut usage = self.client_usage(&client_key)?;
+
+        // make sure each message is accounted for only once
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut removed = Vec::with_capacity(ids.len());
+        for id in ids {
+            let key: sled::IVec = message_key(client_address, id).into();
+            if let Some(message) = self.messages.get(&key)? {
+                usage.messages -= 1;
+                usage.bytes -= content_len(&message)?;
+                removed.push(key);
+            }
+        }
+
+        if removed.is_empty() {
+            return Ok(());
+        }
+        self.commit(&client_key, None, &removed, usage)
+    }
+
+    fn remove_expired(&self, cutoff: Timestamp) -> Result<usize, InboxStorageError> {
+        let mut removed_count = 0;
+        loop {
+            let removed = self.remove_expired_batch(cutoff)?;
+            removed_count += removed;
+            if removed < EXPIRY_BATCH_SIZE {
+                return Ok(removed_count);
+            }
+        }
+    }
+
+    fn purge(&self, client_address: &DestinationAddressBytes) -> Result<usize, InboxStorageError> {
+        let client_key = client_address.to_bytes();
+        let _guard = self.write_lock.lock().unwrap();
+
+        let removed = self
+            .messages
+            .scan_prefix(client_key)
+            .keys()
+            .collect::<Result<Vec<_>, _>>()?;
+        if removed.is_empty() {
+            return Ok(0);
+        }
+        self.commit(&client_key, None, &removed, InboxUsage::default())?;
+        Ok(removed.len())
+    }
+
+    fn usage(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<InboxUsage, InboxStorageError> {
+        self.client_usage(&client_address.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod sled_inbox_store {
+    use super::*;
+    use nymsphinx::DESTINATION_ADDRESS_LENGTH;
+
+    fn client(byte: u8) -> DestinationAddressBytes {
+        DestinationAddressBytes::from_bytes([byte; DESTINATION_ADDRESS_LENGTH])
+    }
+
+    fn quota(max_messages: usize, max_bytes: usize, policy: InboxFullPolicy) -> InboxQuota {
+        InboxQuota {
+            max_messages,
+            max_bytes,
+            full_inbox_policy: policy,
+        }
+    }
+
+    fn contents(messages: Vec<StoredMessage>) -> Vec<Vec<u8>> {
+        messages
+            .into_iter()
+            .map(|message| message.content)
+            .collect()
+    }
+
+    #[test]
+    fn messages_are_retrieved_oldest_first_and_per_client() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1], 0, &quota).unwrap();
+        store.store(&client(2), vec![2], 0, &quota).unwrap();
+        store.store(&client(1), vec![3], 0, &quota).unwrap();
+
+        assert_eq!(
+            vec![vec![1], vec![3]],
+            contents(store.retrieve(&client(1), 10).unwrap())
+        );
+        assert_eq!(
+            vec![vec![1]],
+            contents(store.retrieve(&client(1), 1).unwrap())
+        );
+        assert_eq!(
+            vec![vec![2]],
+            contents(store.retrieve(&client(2), 10).unwrap())
+        );
+    }
+
+    #[test]
+    fn batch_retrieval_skips_excluded_messages_and_stops_at_size_limit() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1], 0, &quota).unwrap();
+        store.store(&client(1), vec![2, 2], 0, &quota).unwrap();
+        store.store(&client(1), vec![3, 3, 3], 0, &quota).unwrap();
+        let first = store.retrieve(&client(1), 1).unwrap()[0].id;
+
+        assert_eq!(
+            vec![vec![1], vec![2, 2]],
+            contents(
+                store
+                    .retrieve_batch(&client(1), &HashSet::new(), 10, 5)
+                    .unwrap()
+            )
+        );
+
+        let excluded = std::iter::once(first).collect();
+        assert_eq!(
+            vec![vec![2, 2], vec![3, 3, 3]],
+            contents(store.retrieve_batch(&client(1), &excluded, 10, 5).unwrap())
+        );
+        assert_eq!(
+            vec![vec![2, 2]],
+            contents(store.retrieve_batch(&client(1), &excluded, 1, 5).unwrap())
+        );
+        // the oldest message is retrieved even if it alone is too large
+        assert_eq!(
+            vec![vec![2, 2]],
+            contents(store.retrieve_batch(&client(1), &excluded, 10, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn removal_updates_usage() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![0; 10], 0, &quota).unwrap();
+        store.store(&client(1), vec![0; 20], 0, &quota).unwrap();
+        assert_eq!(
+            InboxUsage {
+                messages: 2,
+                bytes: 30
+            },
+            store.usage(&client(1)).unwrap()
+        );
+
+        let first = store.retrieve(&client(1), 1).unwrap()[0].id;
+        // removing the same message twice is fine
+        store.remove(&client(1), &[first, first]).unwrap();
+        store.remove(&client(1), &[first]).unwrap();
+        assert_eq!(
+            InboxUsage {
+                messages: 1,
+                bytes: 20
+            },
+            store.usage(&client(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn messages_over_quota_are_rejected() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(2, 100, InboxFullPolicy::Reject);
+        assert_eq!(
+            StoreOutcome::Stored,
+            store.store(&client(1), vec![1], 0, &quota).unwrap()
+        );
+        store.store(&client(1), vec![2], 0, &quota).unwrap();
+        assert_eq!(
+            StoreOutcome::Rejected,
+            store.store(&client(1), vec![3], 0, &quota).unwrap()
+        );
+        assert_eq!(
+            StoreOutcome::Rejected,
+            store.store(&client(2), vec![0; 101], 0, &quota).unwrap()
+        );
+        assert_eq!(
+            vec![vec![1], vec![2]],
+            contents(store.retrieve(&client(1), 10).unwrap())
+        );
+    }
+
+    #[test]
+    fn oldest_messages_are_evicted_to_make_space() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 30, InboxFullPolicy::EvictOldest);
+        store.store(&client(1), vec![1; 10], 0, &quota).unwrap();
+        store.store(&client(1), vec![2; 10], 0, &quota).unwrap();
+        store.store(&client(1), vec![3; 10], 0, &quota).unwrap();
+
+        assert_eq!(
+            StoreOutcome::StoredWithEviction(2),
+            store.store(&client(1), vec![4; 20], 0, &quota).unwrap()
+        );
+        assert_eq!(
+            vec![vec![3; 10], vec![4; 20]],
+            contents(store.retrieve(&client(1), 10).unwrap())
+        );
+        assert_eq!(
+            InboxUsage {
+                messages: 2,
+                bytes: 30
+            },
+            store.usage(&client(1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn arrival_timestamps_are_retrieved_with_messages() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1], 42, &quota).unwrap();
+
+        let retrieved = store.retrieve(&client(1), 10).unwrap();
+        assert_eq!(42, retrieved[0].arrival_timestamp);
+        assert_eq!(vec![1], retrieved[0].content);
+    }
+
+    #[test]
+    fn only_messages_older_than_cutoff_are_expired() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1; 10], 100, &quota).unwrap();
+        store.store(&client(1), vec![2; 10], 200, &quota).unwrap();
+        store.store(&client(2), vec![3; 10], 100, &quota).unwrap();
+
+        assert_eq!(2, store.remove_expired(200).unwrap());
+        assert_eq!(
+            vec![vec![2; 10]],
+            contents(store.retrieve(&client(1), 10).unwrap())
+        );
+        assert!(store.retrieve(&client(2), 10).unwrap().is_empty());
+        assert_eq!(
+            InboxUsage {
+                messages: 1,
+                bytes: 10
+            },
+            store.usage(&client(1)).unwrap()
+        );
+        assert_eq!(InboxUsage::default(), store.usage(&client(2)).unwrap());
+        assert_eq!(0, store.remove_expired(200).unwrap());
+    }
+
+    #[test]
+    fn expiry_removes_messages_in_multiple_batches() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(usize::MAX, usize::MAX, InboxFullPolicy::Reject);
+        for i in 0..EXPIRY_BATCH_SIZE + 10 {
+            let client = client((i % 3) as u8);
+            store
+                .store(&client, vec![1], i as Timestamp, &quota)
+                .unwrap();
+        }
+
+        assert_eq!(
+            EXPIRY_BATCH_SIZE + 5,
+            store
+                .remove_expired((EXPIRY_BATCH_SIZE + 5) as Timestamp)
+                .unwrap()
+        );
+        let remaining: usize = (0..3)
+            .map(|byte| store.usage(&client(byte)).unwrap().messages)
+            .sum();
+        assert_eq!(5, remaining);
+        assert_eq!(5, store.arrivals.len());
+    }
+
+    #[test]
+    fn missing_arrivals_index_is_rebuilt() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1], 100, &quota).unwrap();
+        store.store(&client(1), vec![2], 200, &quota).unwrap();
+        store.arrivals.clear().unwrap();
+
+        let store = SledInboxStore::from_db(store.db).unwrap();
+        assert_eq!(1, store.remove_expired(200).unwrap());
+        assert_eq!(
+            vec![vec![2]],
+            contents(store.retrieve(&client(1), 10).unwrap())
+        );
+    }
+
+    #[test]
+    fn purging_removes_all_messages_of_only_the_given_client() {
+        let store = SledInboxStore::temporary();
+        let quota = quota(10, 100, InboxFullPolicy::Reject);
+        store.store(&client(1), vec![1; 10], 0, &quota).unwrap();
+        store.store(&client(1), vec![2; 10], 0, &quota).unwrap();
+        store.store(&client(2), vec![3; 10], 0, &quota).unwrap();
+
+        assert_eq!(2, store.purge(&client(1)).unwrap());
+        assert!(store.retrieve(&client(1), 10).unwrap().is_empty());
+        assert_eq!(InboxUsage::default(), store.usage(&client(1)).unwrap());
+        assert_eq!(
+            vec![vec![3; 10]],
+            contents(store.retrieve(&client(2), 10).unwrap())
+        );
+        assert_eq!(0, store.purge(&client(1)).unwrap());
+    }
+}
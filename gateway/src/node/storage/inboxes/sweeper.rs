@@ -0,0 +1,62 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::ClientStorage;
+use log::*;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// Periodically removes stored messages that have not been retrieved by their recipients
+/// within the configured time-to-live.
+pub(crate) struct ExpiredMessagesSweeper {
+    client_storage: ClientStorage,
+    message_ttl: Duration,
+    sweep_interval: Duration,
+}
+
+impl ExpiredMessagesSweeper {
+    pub(crate) fn new(
+        client_storage: ClientStorage,
+        message_ttl: Duration,
+        sweep_interval: Duration,
+    ) -> Self {
+        ExpiredMessagesSweeper {
+            client_storage,
+            message_ttl,
+            sweep_interval,
+        }
+    }
+
+    async fn sweep(&self) {
+        match self
+            .client_storage
+            .remove_expired_messages(self.message_ttl)
+            .await
+        {
+            Ok(0) => debug!("There were no expired messages to remove"),
+            Ok(removed) => info!("Removed {} expired stored messages", removed),
+            Err(err) => error!("Failed to remove expired stored messages - {}", err),
+        }
+    }
+
+    pub(crate) fn start(self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                self.sweep().await;
+                tokio::time::delay_for(self.sweep_interval).await;
+            }
+        })
+    }
+}
@@ -18,14 +18,22 @@
 //use sfw_provider_requests::auth_token::{AuthToken, AUTH_TOKEN_SIZE};
 //use std::path::PathBuf;
 
-use gateway_requests::authentication::encrypted_address::EncryptedAddressBytes;
-use gateway_requests::authentication::iv::AuthenticationIV;
 use gateway_requests::generic_array::typenum::Unsigned;
 use gateway_requests::registration::handshake::{SharedKey, SharedKeySize};
+use gateway_requests::DeliveryPolicy;
 use log::*;
 use nymsphinx::{DestinationAddressBytes, DESTINATION_ADDRESS_LENGTH};
+use std::convert::TryInto;
+use std::fmt::{self, Display, Formatter};
 use std::path::PathBuf;
 
+const ADDRESS_PUBLICATIONS_TREE: &str = "address_publications";
+const LAST_SEEN_TREE: &str = "last_seen";
+const DELIVERY_POLICIES_TREE: &str = "delivery_policies";
+const REVOKED_CLIENTS_TREE: &str = "revoked_clients";
+// timestamp of the statement followed by whether the address is published
+const ADDRESS_PUBLICATION_LENGTH: usize = 8 + 1;
+
 #[derive(Debug)]
 pub(crate) enum ClientLedgerError {
     DbReadError(sled::Error),
@@ -33,11 +41,34 @@ pub(crate) enum ClientLedgerError {
     DbOpenError(sled::Error),
 }
 
+impl Display for ClientLedgerError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClientLedgerError::DbReadError(err) => write!(f, "failed to read the ledger - {}", err),
+            ClientLedgerError::DbWriteError(err) => {
+                write!(f, "failed to write to the ledger - {}", err)
+            }
+            ClientLedgerError::DbOpenError(err) => write!(f, "failed to open the ledger - {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClientLedgerError {}
+
 #[derive(Debug, Clone)]
 // Note: you should NEVER create more than a single instance of this using 'new()'.
 // You should always use .clone() to create additional instances
 pub(crate) struct ClientLedger {
     db: sled::Db,
+    // clients that explicitly chose whether their addresses should be announced
+    address_publications: sled::Tree,
+    // unix timestamps, in milliseconds, of when the clients were last connected
+    last_seen: sled::Tree,
+    // clients that chose other than the default delivery policy
+    delivery_policies: sled::Tree,
+    // clients that are not allowed to register nor authenticate alongside the unix timestamps,
+    // in milliseconds, of their revocation
+    revoked_clients: sled::Tree,
 }
 
 impl ClientLedger {
@@ -47,17 +78,35 @@ impl ClientLedger {
             Ok(db) => db,
         };
 
-        let ledger = ClientLedger { db };
+        let address_publications = match db.open_tree(ADDRESS_PUBLICATIONS_TREE) {
+            Err(e) => return Err(ClientLedgerError::DbOpenError(e)),
+            Ok(tree) => tree,
+        };
+
+        let last_seen = match db.open_tree(LAST_SEEN_TREE) {
+            Err(e) => return Err(ClientLedgerError::DbOpenError(e)),
+            Ok(tree) => tree,
+        };
 
-        ledger.db.iter().keys().for_each(|key| {
-            println!(
-                "key: {:?}",
-                ledger
-                    .read_destination_address_bytes(key.unwrap())
-                    .to_base58_string()
-            );
-        });
+        let delivery_policies = match db.open_tree(DELIVERY_POLICIES_TREE) {
+            Err(e) => return Err(ClientLedgerError::DbOpenError(e)),
+            Ok(tree) => tree,
+        };
 
+        let revoked_clients = match db.open_tree(REVOKED_CLIENTS_TREE) {
+            Err(e) => return Err(ClientLedgerError::DbOpenError(e)),
+            Ok(tree) => tree,
+        };
+
+        let ledger = ClientLedger {
+            db,
+            address_publications,
+            last_seen,
+            delivery_policies,
+            revoked_clients,
+        };
+
+        debug!("Loaded ledger with {} registered clients", ledger.db.len());
         Ok(ledger)
     }
 
@@ -92,24 +141,6 @@ impl ClientLedger {
         DestinationAddressBytes::from_bytes(destination_bytes)
     }
 
-    pub(crate) fn verify_shared_key(
-        &self,
-        client_address: &DestinationAddressBytes,
-        encrypted_address: &EncryptedAddressBytes,
-        iv: &AuthenticationIV,
-    ) -> Result<bool, ClientLedgerError> {
-        match self.db.get(&client_address.to_bytes()) {
-            Err(e) => Err(ClientLedgerError::DbReadError(e)),
-            Ok(existing_key) => match existing_key {
-                Some(existing_key_ivec) => {
-                    let shared_key = &self.read_shared_key(existing_key_ivec);
-                    Ok(encrypted_address.verify(client_address, shared_key, iv))
-                }
-                None => Ok(false),
-            },
-        }
-    }
-
     pub(crate) fn get_shared_key(
         &self,
         client_address: &DestinationAddressBytes,
@@ -164,7 +195,7 @@ impl ClientLedger {
         let mut client_vec = Vec::new();
         for client in clients {
             match client {
-                Err(e) => return Err(ClientLedgerError::DbWriteError(e)),
+                Err(e) => return Err(ClientLedgerError::DbReadError(e)),
                 Ok(client_entry) => {
                     client_vec.push(self.read_destination_address_bytes(client_entry))
                 }
@@ -173,4 +204,225 @@ impl ClientLedger {
 
         Ok(client_vec)
     }
+
+    /// Removes everything known about the client, so that it would have to register again.
+    /// Returns whether the client was registered in the first place.
+    pub(crate) fn remove_client(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<bool, ClientLedgerError> {
+        let client_key = client_address.to_bytes();
+        if let Err(e) = self.address_publications.remove(&client_key) {
+            return Err(ClientLedgerError::DbWriteError(e));
+        }
+        if let Err(e) = self.last_seen.remove(&client_key) {
+            return Err(ClientLedgerError::DbWriteError(e));
+        }
+        if let Err(e) = self.delivery_policies.remove(&client_key) {
+            return Err(ClientLedgerError::DbWriteError(e));
+        }
+        Ok(self.remove_shared_key(client_address)?.is_some())
+    }
+
+    pub(crate) fn update_last_seen(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+        timestamp: u64,
+    ) -> Result<(), ClientLedgerError> {
+        match self
+            .last_seen
+            .insert(&client_address.to_bytes(), &timestamp.to_be_bytes())
+        {
+            Err(e) => Err(ClientLedgerError::DbWriteError(e)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn get_last_seen(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<Option<u64>, ClientLedgerError> {
+        match self.last_seen.get(&client_address.to_bytes()) {
+            Err(e) => Err(ClientLedgerError::DbReadError(e)),
+            Ok(timestamp) => Ok(timestamp.map(|timestamp| {
+                // if this fails it means we have some database corruption and we
+                // absolutely can't continue
+                let timestamp_bytes = timestamp.as_ref().try_into().unwrap_or_else(|_| {
+                    error!("CLIENT LEDGER DATA CORRUPTION - LAST SEEN HAS INVALID LENGTH");
+                    panic!("CLIENT LEDGER DATA CORRUPTION - LAST SEEN HAS INVALID LENGTH");
+                });
+                u64::from_be_bytes(timestamp_bytes)
+            })),
+        }
+    }
+
+    // returns timestamp of the statement and whether the address is published
+    fn read_address_publication(&self, raw_publication: sled::IVec) -> (u64, bool) {
+        let publication_ref = raw_publication.as_ref();
+        // if this fails it means we have some database corruption and we
+        // absolutely can't continue
+        if publication_ref.len() != ADDRESS_PUBLICATION_LENGTH {
+            error!("CLIENT LEDGER DATA CORRUPTION - ADDRESS PUBLICATION HAS INVALID LENGTH");
+            panic!("CLIENT LEDGER DATA CORRUPTION - ADDRESS PUBLICATION HAS INVALID LENGTH");
+        }
+
+        let timestamp = u64::from_be_bytes(publication_ref[..8].try_into().unwrap());
+        (timestamp, publication_ref[8] == 1)
+    }
+
+    /// Records whether the address of the client should be published, unless a statement with
+    /// the same or later timestamp was already recorded. Returns whether it got recorded.
+    pub(crate) fn update_address_publication(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+        published: bool,
+        timestamp: u64,
+    ) -> Result<bool, ClientLedgerError> {
+        let latest_timestamp = match self.address_publications.get(&client_address.to_bytes()) {
+            Err(e) => return Err(ClientLedgerError::DbReadError(e)),
+            Ok(existing) => existing.map(|existing| self.read_address_publication(existing).0),
+        };
+        if latest_timestamp.map_or(false, |latest_timestamp| latest_timestamp >= timestamp) {
+            return Ok(false);
+        }
+
+        let mut publication = timestamp.to_be_bytes().to_vec();
+        publication.push(published as u8);
+        if let Err(e) = self
+            .address_publications
+            .insert(&client_address.to_bytes(), publication)
+        {
+            return Err(ClientLedgerError::DbWriteError(e));
+        }
+
+        // same as with registration, this doesn't happen often enough to worry about flushing
+        self.db.flush().unwrap();
+        Ok(true)
+    }
+
+    /// Clients that explicitly chose to have their addresses published.
+    pub(crate) fn published_clients(
+        &self,
+    ) -> Result<Vec<DestinationAddressBytes>, ClientLedgerError> {
+        let mut client_vec = Vec::new();
+        for entry in self.address_publications.iter() {
+            match entry {
+                Err(e) => return Err(ClientLedgerError::DbReadError(e)),
+                Ok((client, publication)) => {
+                    if self.read_address_publication(publication).1 {
+                        client_vec.push(self.read_destination_address_bytes(client))
+                    }
+                }
+            }
+        }
+
+        Ok(client_vec)
+    }
+
+    pub(crate) fn get_delivery_policy(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<DeliveryPolicy, ClientLedgerError> {
+        match self.delivery_policies.get(&client_address.to_bytes()) {
+            Err(e) => Err(ClientLedgerError::DbReadError(e)),
+            Ok(None) => Ok(DeliveryPolicy::default()),
+            Ok(Some(policy)) => match policy.as_ref() {
+                [0] => Ok(DeliveryPolicy::FanOut),
+                [1] => Ok(DeliveryPolicy::FirstAcknowledged),
+                // if this fails it means we have some database corruption and we
+                // absolutely can't continue
+                _ => {
+                    error!("CLIENT LEDGER DATA CORRUPTION - DELIVERY POLICY IS INVALID");
+                    panic!("CLIENT LEDGER DATA CORRUPTION - DELIVERY POLICY IS INVALID");
+                }
+            },
+        }
+    }
+
+    pub(crate) fn set_delivery_policy(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+        policy: DeliveryPolicy,
+    ) -> Result<(), ClientLedgerError> {
+        let policy_byte: u8 = match policy {
+            DeliveryPolicy::FanOut => 0,
+            DeliveryPolicy::FirstAcknowledged => 1,
+        };
+        match self
+            .delivery_policies
+            .insert(&client_address.to_bytes(), &[policy_byte])
+        {
+            Err(e) => Err(ClientLedgerError::DbWriteError(e)),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Prevents the client from registering and authenticating until it is reinstated.
+    pub(crate) fn revoke_client(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+        timestamp: u64,
+    ) -> Result<(), ClientLedgerError> {
+        if let Err(e) = self
+            .revoked_clients
+            .insert(&client_address.to_bytes(), &timestamp.to_be_bytes())
+        {
+            return Err(ClientLedgerError::DbWriteError(e));
+        }
+
+        // the revocation has to survive a crash, and it is rare enough to flush it right away
+        self.db.flush().unwrap();
+        Ok(())
+    }
+
+    /// Lifts the revocation of the client. Returns whether it was revoked in the first place.
+    pub(crate) fn reinstate_client(
+        &mut self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<bool, ClientLedgerError> {
+        match self.revoked_clients.remove(&client_address.to_bytes()) {
+            Err(e) => Err(ClientLedgerError::DbWriteError(e)),
+            Ok(revocation) => {
+                self.db.flush().unwrap();
+                Ok(revocation.is_some())
+            }
+        }
+    }
+
+    pub(crate) fn is_revoked(
+        &self,
+        client_address: &DestinationAddressBytes,
+    ) -> Result<bool, ClientLedgerError> {
+        match self
+            .revoked_clients
+            .contains_key(&client_address.to_bytes())
+        {
+            Err(e) => Err(ClientLedgerError::DbReadError(e)),
+            Ok(revoked) => Ok(revoked),
+        }
+    }
+}
+
+#[cfg(test)]
+mod client_revocation {
+    use super::*;
+
+    #[test]
+    fn revoked_clients_stay_revoked_until_reinstated() {
+        let ledger_dir = tempfile::tempdir().unwrap();
+        let mut ledger = ClientLedger::load(ledger_dir.path().join("ledger")).unwrap();
+        let client = DestinationAddressBytes::from_bytes([1; DESTINATION_ADDRESS_LENGTH]);
+        assert!(!ledger.is_revoked(&client).unwrap());
+
+        ledger.revoke_client(&client, 42).unwrap();
+        assert!(ledger.is_revoked(&client).unwrap());
+        // removing the client does not lift its revocation
+        assert!(!ledger.remove_client(&client).unwrap());
+        assert!(ledger.is_revoked(&client).unwrap());
+        assert!(ledger.current_clients().unwrap().is_empty());
+
+        assert!(ledger.reinstate_client(&client).unwrap());
+        assert!(!ledger.is_revoked(&client).unwrap());
+        assert!(!ledger.reinstate_client(&client).unwrap());
+    }
 }
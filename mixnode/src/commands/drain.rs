@@ -0,0 +1,77 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::Config;
+use crate::node::admin::DRAIN_PATH;
+use clap::{App, Arg, ArgMatches};
+use config::NymConfig;
+use http_endpoint::token::{load_token, send_authorized_request};
+use hyper::Method;
+
+pub fn command_args<'a, 'b>() -> App<'a, 'b> {
+    App::new("drain")
+        .about("Gracefully stops the running mixnode - it stops accepting new packets, forwards the ones it holds and exits")
+        .arg(
+            Arg::with_name("id")
+                .long("id")
+                .help("Id of the nym-mixnode we want to drain")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("admin-address")
+                .long("admin-address")
+                .help("Address of the admin endpoint of the node, if different than the one in its config")
+                .takes_value(true),
+        )
+}
+
+pub fn execute(matches: &ArgMatches) {
+    let id = matches.value_of("id").unwrap();
+
+    let config = Config::load_from_file(None, Some(id)).expect("Failed to load config file");
+    let admin_address = match matches.value_of("admin-address") {
+        Some(address) => address.parse().expect("Invalid admin address provided"),
+        None => match config.get_admin_address() {
+            Some(address) => address,
+            None => {
+                println!("The admin endpoint of mixnode {} is not enabled - set 'admin_address' in its config", id);
+                return;
+            }
+        },
+    };
+
+    // the token gets created by the mixnode itself when it starts its admin endpoint
+    let token = match load_token(&config.get_admin_token_file()) {
+        Ok(token) => token,
+        Err(err) => {
+            println!(
+                "Failed to read the admin token of mixnode {} (was it started with the admin endpoint enabled?) - {}",
+                id, err
+            );
+            return;
+        }
+    };
+
+    println!("Requesting drain of mixnode {}...", id);
+    let uri = format!("http://{}{}", admin_address, DRAIN_PATH);
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    match runtime.block_on(send_authorized_request(Method::POST, uri, token)) {
+        Ok(response) => println!("The node responded with: {}", response),
+        Err(err) => println!(
+            "Failed to reach the admin endpoint at {} - {}",
+            admin_address, err
+        ),
+    }
+}
@@ -16,7 +16,7 @@ use crate::commands::override_config;
 use crate::config::persistence::pathfinder::MixNodePathfinder;
 use clap::{App, Arg, ArgMatches};
 use config::NymConfig;
-use crypto::asymmetric::encryption;
+use crypto::asymmetric::{encryption, identity};
 use pemstore::pemstore::PemStore;
 
 pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
@@ -38,9 +38,8 @@ pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
         .arg(
             Arg::with_name("layer")
                 .long("layer")
-                .help("The mixnet layer of this particular node")
-                .takes_value(true)
-                .required(true),
+                .help("The mixnet layer of this particular node. If not provided, the layer is going to be assigned by the validator")
+                .takes_value(true),
         )
         .arg(
             Arg::with_name("host")
@@ -73,24 +72,49 @@ pub fn command_args<'a, 'b>() -> clap::App<'a, 'b> {
                 .help("Address of the directory server the node is sending presence and metrics to")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("validator")
+                .long("validator")
+                .help("Address of the validator the node is obtaining its layer assignment from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metrics-address")
+                .long("metrics-address")
+                .help("Optional socket address on which the prometheus metrics endpoint will be exposed")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("admin-address")
+                .long("admin-address")
+                .help("Optional local socket address on which the admin endpoint will be exposed")
+                .takes_value(true),
+        )
 }
 
 pub fn execute(matches: &ArgMatches) {
     let id = matches.value_of("id").unwrap();
     println!("Initialising mixnode {}...", id);
 
-    let layer = matches.value_of("layer").unwrap().parse().unwrap();
-    let mut config = crate::config::Config::new(id, layer);
+    let mut config = match matches.value_of("layer") {
+        Some(layer) => crate::config::Config::new(id, layer.parse().unwrap()),
+        // the first layer is only used until the node gets its assignment
+        None => crate::config::Config::new(id, 1).with_automatic_layer_assignment(),
+    };
 
     config = override_config(config, matches);
 
+    let identity_keys = identity::KeyPair::new();
     let sphinx_keys = encryption::KeyPair::new();
     let pathfinder = MixNodePathfinder::new_from_config(&config);
     let pem_store = PemStore::new(pathfinder);
     pem_store
         .write_encryption_keypair(&sphinx_keys)
         .expect("Failed to save sphinx keys");
-    println!("Saved mixnet sphinx keypair");
+    pem_store
+        .write_identity_keypair(&identity_keys)
+        .expect("Failed to save identity keys");
+    println!("Saved identity and mixnet sphinx keypairs");
 
     let config_save_location = config.get_config_file_save_location();
     config
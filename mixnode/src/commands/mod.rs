@@ -14,7 +14,9 @@
 
 use crate::config::Config;
 use clap::ArgMatches;
+use std::net::SocketAddr;
 
+pub mod drain;
 pub mod init;
 pub mod run;
 
@@ -37,6 +39,10 @@ pub(crate) fn override_config(mut config: Config, matches: &ArgMatches) -> Confi
         config = config.with_custom_directory(directory);
     }
 
+    if let Some(validator) = matches.value_of("validator") {
+        config = config.with_custom_validator(validator);
+    }
+
     if let Some(announce_host) = matches.value_of("announce-host") {
         config = config.with_announce_host(announce_host);
     } else if was_host_overridden {
@@ -55,9 +61,39 @@ pub(crate) fn override_config(mut config: Config, matches: &ArgMatches) -> Confi
         config = config.with_announce_port(announce_port.unwrap());
     }
 
+    if let Some(layer) = matches.value_of("layer").map(|layer| layer.parse::<u64>()) {
+        if let Err(err) = layer {
+            // if layer was overridden, it must be parsable
+            panic!("Invalid layer value provided - {:?}", err);
+        }
+        config = config.with_layer(layer.unwrap());
+    }
+
     if let Some(location) = matches.value_of("location") {
         config = config.with_location(location);
     }
 
+    if let Some(metrics_address) = matches
+        .value_of("metrics-address")
+        .map(|address| address.parse::<SocketAddr>())
+    {
+        if let Err(err) = metrics_address {
+            // if address was provided, it must be parsable
+            panic!("Invalid metrics address provided - {:?}", err);
+        }
+        config = config.with_prometheus_metrics_address(metrics_address.unwrap());
+    }
+
+    if let Some(admin_address) = matches
+        .value_of("admin-address")
+        .map(|address| address.parse::<SocketAddr>())
+    {
+        if let Err(err) = admin_address {
+            // if address was provided, it must be parsable
+            panic!("Invalid admin address provided - {:?}", err);
+        }
+        config = config.with_admin_address(admin_address.unwrap());
+    }
+
     config
 }
@@ -17,7 +17,7 @@ use crate::config::{persistence::pathfinder::MixNodePathfinder, Config};
 use crate::node::MixNode;
 use clap::{App, Arg, ArgMatches};
 use config::NymConfig;
-use crypto::asymmetric::encryption;
+use crypto::asymmetric::{encryption, identity};
 use pemstore::pemstore::PemStore;
 
 pub fn command_args<'a, 'b>() -> App<'a, 'b> {
@@ -46,7 +46,7 @@ pub fn command_args<'a, 'b>() -> App<'a, 'b> {
         .arg(
             Arg::with_name("layer")
                 .long("layer")
-                .help("The mixnet layer of this particular node")
+                .help("The mixnet layer of this particular node. Disables automatic layer assignment")
                 .takes_value(true),
         )
         .arg(
@@ -79,6 +79,24 @@ pub fn command_args<'a, 'b>() -> App<'a, 'b> {
                 .help("Address of the directory server the node is sending presence and metrics to")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("validator")
+                .long("validator")
+                .help("Address of the validator the node is obtaining its layer assignment from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metrics-address")
+                .long("metrics-address")
+                .help("Optional socket address on which the prometheus metrics endpoint will be exposed")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("admin-address")
+                .long("admin-address")
+                .help("Optional local socket address on which the admin endpoint will be exposed")
+                .takes_value(true),
+        )
 }
 
 fn show_binding_warning(address: String) {
@@ -107,6 +125,28 @@ fn load_sphinx_keys(config_file: &Config) -> encryption::KeyPair {
     sphinx_keypair
 }
 
+// mixnodes initialised before they had identity keys get them generated on their first run
+fn load_identity_keys(config_file: &Config) -> identity::KeyPair {
+    let pem_store = PemStore::new(MixNodePathfinder::new_from_config(&config_file));
+    let identity_keypair = if config_file.get_private_identity_key_file().exists() {
+        pem_store
+            .read_identity_keypair()
+            .expect("Failed to read stored identity key files")
+    } else {
+        let identity_keypair = identity::KeyPair::new();
+        pem_store
+            .write_identity_keypair(&identity_keypair)
+            .expect("Failed to save identity keys");
+        println!("Generated and saved a new identity keypair");
+        identity_keypair
+    };
+    println!(
+        "Public identity key: {}\n",
+        identity_keypair.public_key().to_base58_string()
+    );
+    identity_keypair
+}
+
 pub fn execute(matches: &ArgMatches) {
     let id = matches.value_of("id").unwrap();
 
@@ -118,7 +158,13 @@ pub fn execute(matches: &ArgMatches) {
 
     config = override_config(config, matches);
 
+    if let Err(err) = config.validate() {
+        println!("Invalid configuration - {}", err);
+        return;
+    }
+
     let sphinx_keypair = load_sphinx_keys(&config);
+    let identity_keypair = load_identity_keys(&config);
 
     let listening_ip_string = config.get_listening_address().ip().to_string();
     if special_addresses().contains(&listening_ip_string.as_ref()) {
@@ -133,6 +179,9 @@ pub fn execute(matches: &ArgMatches) {
         "Directory server [metrics]: {}",
         config.get_metrics_directory_server()
     );
+    if config.get_automatic_layer_assignment() {
+        println!("Validator: {}", config.get_validator_server());
+    }
 
     println!(
         "Listening for incoming packets on {}",
@@ -143,5 +192,5 @@ pub fn execute(matches: &ArgMatches) {
         config.get_announce_address()
     );
 
-    MixNode::new(config, sphinx_keypair).run();
+    MixNode::new(config, sphinx_keypair, identity_keypair).run();
 }
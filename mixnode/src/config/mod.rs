@@ -13,9 +13,13 @@
 // limitations under the License.
 
 use crate::config::template::config_template;
+use crate::node::MAXIMUM_QUEUE_DELAY;
 use config::NymConfig;
 use log::*;
+use nymsphinx::framing::FrameVersion;
+use packet_forwarder::DropPolicy;
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
 use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -27,6 +31,7 @@ mod template;
 // 'MIXNODE'
 const DEFAULT_LISTENING_PORT: u16 = 1789;
 const DEFAULT_DIRECTORY_SERVER: &str = "https://directory.nymtech.net";
+const DEFAULT_VALIDATOR_SERVER: &str = "http://localhost:3000";
 
 // 'DEBUG'
 // where applicable, the below are defined in milliseconds
@@ -35,7 +40,88 @@ const DEFAULT_METRICS_SENDING_DELAY: u64 = 1000;
 const DEFAULT_METRICS_RUNNING_STATS_LOGGING_DELAY: u64 = 60_000; // 1min
 const DEFAULT_PACKET_FORWARDING_INITIAL_BACKOFF: u64 = 10_000; // 10s
 const DEFAULT_PACKET_FORWARDING_MAXIMUM_BACKOFF: u64 = 300_000; // 5min
+const DEFAULT_PACKET_FORWARDING_QUEUE_LENGTH: usize = 1_000;
+const DEFAULT_PACKET_FORWARDING_IDLE_PEER_TIMEOUT: u64 = 600_000; // 10min
 const DEFAULT_INITIAL_CONNECTION_TIMEOUT: u64 = 1_500; // 1.5s
+const DEFAULT_LINK_HANDSHAKE_TIMEOUT: u64 = 1_500; // 1.5s
+const DEFAULT_LINK_KEYS_REFRESH_RATE: u64 = 60_000; // 1min
+const DEFAULT_LAYER_FILTERING_REFRESH_RATE: u64 = 60_000; // 1min
+const DEFAULT_LAYER_ASSIGNMENT_EPOCH: u64 = 3_600_000; // 1h
+const DEFAULT_DRAIN_TIMEOUT: u64 = 90_000; // 1.5min
+const DEFAULT_PACKET_PROCESSING_WORKERS: usize = 4;
+const DEFAULT_PACKET_PROCESSING_QUEUE_CAPACITY: usize = 10_000;
+const DEFAULT_MIXING_QUEUE_CAPACITY: usize = 10_000;
+const DEFAULT_MAXIMUM_PACKET_DELAY: u64 = 60_000; // 1min
+const DEFAULT_MAXIMUM_IN_FLIGHT_PACKETS: usize = 100_000;
+const DEFAULT_MAXIMUM_IN_FLIGHT_BYTES: usize = 256 * 1024 * 1024; // 256MB
+const DEFAULT_POOL_MIXING_ROUND_DURATION: u64 = 1_000; // 1s
+const DEFAULT_POOL_MIXING_MINIMUM_POOL_SIZE: usize = 10;
+const DEFAULT_POOL_MIXING_FLUSH_FRACTION: f64 = 0.7;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum MixingStrategy {
+    /// Each packet is delayed independently for the duration encoded in its sphinx header.
+    Continuous,
+
+    /// Packets are collected into a pool that is partially flushed, in random order,
+    /// at the end of each round or whenever the pool reaches the flush threshold.
+    /// Delays encoded in sphinx headers are ignored.
+    Pool,
+}
+
+impl Default for MixingStrategy {
+    fn default() -> Self {
+        MixingStrategy::Continuous
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum ExcessiveDelayPolicy {
+    /// Packets with delays exceeding the maximum are dropped.
+    Drop,
+
+    /// Packets with delays exceeding the maximum are delayed for the maximum duration instead.
+    Clamp,
+}
+
+impl Default for ExcessiveDelayPolicy {
+    fn default() -> Self {
+        ExcessiveDelayPolicy::Drop
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigValidationError {
+    InvalidPoolMixingFlushFraction(f64),
+    ZeroPoolMixingRoundDuration,
+    ZeroPacketProcessingWorkers,
+    ExcessiveMaximumPacketDelay(time::Duration),
+}
+
+impl Display for ConfigValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValidationError::InvalidPoolMixingFlushFraction(fraction) => write!(
+                f,
+                "pool mixing flush fraction has to be in range (0, 1], got {}",
+                fraction
+            ),
+            ConfigValidationError::ZeroPoolMixingRoundDuration => {
+                write!(f, "pool mixing round duration has to be non-zero")
+            }
+            ConfigValidationError::ZeroPacketProcessingWorkers => {
+                write!(f, "at least a single packet processing worker is required")
+            }
+            ConfigValidationError::ExcessiveMaximumPacketDelay(delay) => write!(
+                f,
+                "maximum packet delay can't exceed {:?}, got {:?}",
+                MAXIMUM_QUEUE_DELAY, delay
+            ),
+        }
+    }
+}
 
 #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -99,12 +185,31 @@ impl Config {
             self.mixnode.public_sphinx_key_file =
                 self::MixNode::default_public_sphinx_key_file(&id);
         }
+        if self
+            .mixnode
+            .private_identity_key_file
+            .as_os_str()
+            .is_empty()
+        {
+            self.mixnode.private_identity_key_file =
+                self::MixNode::default_private_identity_key_file(&id);
+        }
+        if self.mixnode.public_identity_key_file.as_os_str().is_empty() {
+            self.mixnode.public_identity_key_file =
+                self::MixNode::default_public_identity_key_file(&id);
+        }
         self.mixnode.id = id;
         self
     }
 
     pub fn with_layer(mut self, layer: u64) -> Self {
         self.mixnode.layer = layer;
+        self.mixnode.automatic_layer_assignment = false;
+        self
+    }
+
+    pub fn with_automatic_layer_assignment(mut self) -> Self {
+        self.mixnode.automatic_layer_assignment = true;
         self
     }
 
@@ -122,6 +227,11 @@ impl Config {
         self
     }
 
+    pub fn with_custom_validator<S: Into<String>>(mut self, validator_server: S) -> Self {
+        self.mixnode.validator_server = validator_server.into();
+        self
+    }
+
     pub fn with_listening_host<S: Into<String>>(mut self, host: S) -> Self {
         // see if the provided `host` is just an ip address or ip:port
         let host = host.into();
@@ -197,6 +307,44 @@ impl Config {
         self
     }
 
+    pub fn with_prometheus_metrics_address(mut self, address: SocketAddr) -> Self {
+        self.mixnode.prometheus_metrics_address = Some(address);
+        self
+    }
+
+    pub fn with_admin_address(mut self, address: SocketAddr) -> Self {
+        self.mixnode.admin_address = Some(address);
+        self
+    }
+
+    /// Checks values that can't be represented by their types alone, so that invalid
+    /// configuration is rejected at startup rather than misbehaving at runtime.
+    pub fn validate(&self) -> Result<(), ConfigValidationError> {
+        let flush_fraction = self.debug.pool_mixing_flush_fraction;
+        if !(flush_fraction > 0.0 && flush_fraction <= 1.0) {
+            return Err(ConfigValidationError::InvalidPoolMixingFlushFraction(
+                flush_fraction,
+            ));
+        }
+
+        if self.debug.pool_mixing_round_duration == 0 {
+            return Err(ConfigValidationError::ZeroPoolMixingRoundDuration);
+        }
+
+        if self.debug.packet_processing_workers == 0 {
+            return Err(ConfigValidationError::ZeroPacketProcessingWorkers);
+        }
+
+        let maximum_packet_delay = self.get_maximum_packet_delay();
+        if maximum_packet_delay > MAXIMUM_QUEUE_DELAY {
+            return Err(ConfigValidationError::ExcessiveMaximumPacketDelay(
+                maximum_packet_delay,
+            ));
+        }
+
+        Ok(())
+    }
+
     // getters
     pub fn get_config_file_save_location(&self) -> PathBuf {
         self.config_directory().join(Self::config_file_name())
@@ -214,6 +362,28 @@ impl Config {
         self.mixnode.public_sphinx_key_file.clone()
     }
 
+    // configs created before mixnodes had identity keys don't specify the key files
+    pub fn get_private_identity_key_file(&self) -> PathBuf {
+        if self
+            .mixnode
+            .private_identity_key_file
+            .as_os_str()
+            .is_empty()
+        {
+            self::MixNode::default_private_identity_key_file(&self.mixnode.id)
+        } else {
+            self.mixnode.private_identity_key_file.clone()
+        }
+    }
+
+    pub fn get_public_identity_key_file(&self) -> PathBuf {
+        if self.mixnode.public_identity_key_file.as_os_str().is_empty() {
+            self::MixNode::default_public_identity_key_file(&self.mixnode.id)
+        } else {
+            self.mixnode.public_identity_key_file.clone()
+        }
+    }
+
     pub fn get_presence_directory_server(&self) -> String {
         self.mixnode.presence_directory_server.clone()
     }
@@ -226,6 +396,10 @@ impl Config {
         self.mixnode.metrics_directory_server.clone()
     }
 
+    pub fn get_validator_server(&self) -> String {
+        self.mixnode.validator_server.clone()
+    }
+
     pub fn get_metrics_sending_delay(&self) -> time::Duration {
         time::Duration::from_millis(self.debug.metrics_sending_delay)
     }
@@ -234,10 +408,26 @@ impl Config {
         time::Duration::from_millis(self.debug.metrics_running_stats_logging_delay)
     }
 
+    pub fn get_prometheus_metrics_address(&self) -> Option<SocketAddr> {
+        self.mixnode.prometheus_metrics_address
+    }
+
+    pub fn get_admin_address(&self) -> Option<SocketAddr> {
+        self.mixnode.admin_address
+    }
+
+    pub fn get_admin_token_file(&self) -> PathBuf {
+        self.data_directory().join("admin_token")
+    }
+
     pub fn get_layer(&self) -> u64 {
         self.mixnode.layer
     }
 
+    pub fn get_automatic_layer_assignment(&self) -> bool {
+        self.mixnode.automatic_layer_assignment
+    }
+
     pub fn get_listening_address(&self) -> SocketAddr {
         self.mixnode.listening_address
     }
@@ -254,9 +444,109 @@ impl Config {
         time::Duration::from_millis(self.debug.packet_forwarding_maximum_backoff)
     }
 
+    pub fn get_packet_forwarding_queue_length(&self) -> usize {
+        self.debug.packet_forwarding_queue_length
+    }
+
+    pub fn get_packet_forwarding_drop_policy(&self) -> DropPolicy {
+        self.debug.packet_forwarding_drop_policy
+    }
+
+    pub fn get_packet_forwarding_idle_peer_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.packet_forwarding_idle_peer_timeout)
+    }
+
     pub fn get_initial_connection_timeout(&self) -> time::Duration {
         time::Duration::from_millis(self.debug.initial_connection_timeout)
     }
+
+    pub fn get_frame_version(&self) -> FrameVersion {
+        if self.debug.versioned_framing {
+            FrameVersion::V1
+        } else {
+            FrameVersion::Legacy
+        }
+    }
+
+    pub fn get_link_encryption(&self) -> bool {
+        self.debug.link_encryption
+    }
+
+    pub fn get_link_encryption_plaintext_fallback(&self) -> bool {
+        self.debug.link_encryption_plaintext_fallback
+    }
+
+    pub fn get_link_handshake_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.link_handshake_timeout)
+    }
+
+    pub fn get_link_keys_refresh_rate(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.link_keys_refresh_rate)
+    }
+
+    pub fn get_layer_filtering(&self) -> bool {
+        self.debug.layer_filtering
+    }
+
+    pub fn get_layer_filtering_refresh_rate(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.layer_filtering_refresh_rate)
+    }
+
+    pub fn get_layer_assignment_epoch(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.layer_assignment_epoch)
+    }
+
+    pub fn get_drain_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.drain_timeout)
+    }
+
+    pub fn get_packet_processing_workers(&self) -> usize {
+        self.debug.packet_processing_workers
+    }
+
+    pub fn get_packet_processing_queue_capacity(&self) -> usize {
+        self.debug.packet_processing_queue_capacity
+    }
+
+    pub fn get_mixing_queue_capacity(&self) -> usize {
+        self.debug.mixing_queue_capacity
+    }
+
+    pub fn get_mixing_strategy(&self) -> MixingStrategy {
+        self.debug.mixing_strategy
+    }
+
+    pub fn get_maximum_packet_delay(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.maximum_packet_delay)
+    }
+
+    pub fn get_excessive_delay_policy(&self) -> ExcessiveDelayPolicy {
+        self.debug.excessive_delay_policy
+    }
+
+    pub fn get_maximum_in_flight_packets(&self) -> usize {
+        self.debug.maximum_in_flight_packets
+    }
+
+    pub fn get_maximum_in_flight_bytes(&self) -> usize {
+        self.debug.maximum_in_flight_bytes
+    }
+
+    pub fn get_pool_mixing_round_duration(&self) -> time::Duration {
+        time::Duration::from_millis(self.debug.pool_mixing_round_duration)
+    }
+
+    pub fn get_pool_mixing_flush_threshold(&self) -> Option<usize> {
+        self.debug.pool_mixing_flush_threshold
+    }
+
+    pub fn get_pool_mixing_minimum_pool_size(&self) -> usize {
+        self.debug.pool_mixing_minimum_pool_size
+    }
+
+    pub fn get_pool_mixing_flush_fraction(&self) -> f64 {
+        self.debug.pool_mixing_flush_fraction
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
@@ -272,8 +562,15 @@ pub struct MixNode {
     location: String,
 
     /// Layer of this particular mixnode determining its position in the network.
+    /// If automatic layer assignment is enabled, it's only used if the assignment can't be obtained.
     layer: u64,
 
+    /// Whether the layer should be assigned by the validator, based on the current sizes
+    /// of all layers, rather than being fixed to `layer`. The assignment is obtained on startup
+    /// and then refreshed at the start of each layer assignment epoch.
+    #[serde(default)]
+    automatic_layer_assignment: bool,
+
     /// Socket address to which this mixnode will bind to and will be listening for packets.
     listening_address: SocketAddr,
 
@@ -291,6 +588,14 @@ pub struct MixNode {
     /// Path to file containing public sphinx key.
     public_sphinx_key_file: PathBuf,
 
+    /// Path to file containing private identity key.
+    #[serde(default)]
+    private_identity_key_file: PathBuf,
+
+    /// Path to file containing public identity key.
+    #[serde(default)]
+    public_identity_key_file: PathBuf,
+
     // The idea of additional 'directory servers' is to let mixes report their presence
     // and metrics to separate places
     /// Directory server to which the server will be reporting their presence data.
@@ -299,6 +604,23 @@ pub struct MixNode {
     /// Directory server to which the server will be reporting their metrics data.
     metrics_directory_server: String,
 
+    /// Validator from which the node obtains its layer assignment,
+    /// if automatic layer assignment is enabled.
+    #[serde(default = "MixNode::default_validator_server")]
+    validator_server: String,
+
+    /// Optional socket address on which the node will expose its metrics in the Prometheus
+    /// text format under the `/metrics` path. If not set, the endpoint is disabled.
+    #[serde(default)]
+    prometheus_metrics_address: Option<SocketAddr>,
+
+    /// Optional socket address on which the node will expose its admin endpoint, used, for example,
+    /// by the `drain` command. Requests to it have to carry the token stored in the `admin_token`
+    /// file of the data directory, but it should still only ever be bound to a local interface.
+    /// If not set, the endpoint is disabled.
+    #[serde(default)]
+    admin_address: Option<SocketAddr>,
+
     /// nym_home_directory specifies absolute path to the home nym MixNodes directory.
     /// It is expected to use default value and hence .toml file should not redefine this field.
     nym_root_directory: PathBuf,
@@ -313,6 +635,18 @@ impl MixNode {
         Config::default_data_directory(Some(id)).join("public_sphinx.pem")
     }
 
+    fn default_private_identity_key_file(id: &str) -> PathBuf {
+        Config::default_data_directory(Some(id)).join("private_identity.pem")
+    }
+
+    fn default_public_identity_key_file(id: &str) -> PathBuf {
+        Config::default_data_directory(Some(id)).join("public_identity.pem")
+    }
+
+    fn default_validator_server() -> String {
+        DEFAULT_VALIDATOR_SERVER.to_string()
+    }
+
     fn default_location() -> String {
         "unknown".into()
     }
@@ -324,14 +658,20 @@ impl Default for MixNode {
             id: "".to_string(),
             location: Self::default_location(),
             layer: 0,
+            automatic_layer_assignment: false,
             listening_address: format!("0.0.0.0:{}", DEFAULT_LISTENING_PORT)
                 .parse()
                 .unwrap(),
             announce_address: format!("127.0.0.1:{}", DEFAULT_LISTENING_PORT),
             private_sphinx_key_file: Default::default(),
             public_sphinx_key_file: Default::default(),
+            private_identity_key_file: Default::default(),
+            public_identity_key_file: Default::default(),
             presence_directory_server: DEFAULT_DIRECTORY_SERVER.to_string(),
             metrics_directory_server: DEFAULT_DIRECTORY_SERVER.to_string(),
+            validator_server: Self::default_validator_server(),
+            prometheus_metrics_address: None,
+            admin_address: None,
             nym_root_directory: Config::default_root_directory(),
         }
     }
@@ -372,9 +712,122 @@ pub struct Debug {
     /// The provided value is interpreted as milliseconds.
     packet_forwarding_maximum_backoff: u64,
 
+    /// Maximum number of packets waiting to get forwarded to a single destination.
+    packet_forwarding_queue_length: usize,
+
+    /// Policy applied once the queue of particular destination is full,
+    /// either `DropNewest` or `DropOldest`.
+    packet_forwarding_drop_policy: DropPolicy,
+
+    /// Duration after which the connection to a destination without any packets to forward
+    /// is closed.
+    /// The provided value is interpreted as milliseconds.
+    packet_forwarding_idle_peer_timeout: u64,
+
     /// Timeout for establishing initial connection when trying to forward a sphinx packet.
     /// The provider value is interpreted as milliseconds.
     initial_connection_timeout: u64,
+
+    /// Whether forwarded packets should be framed with the versioned header, carrying the packet
+    /// type and flags, rather than the legacy one. It should only be enabled once all nodes
+    /// in the network are able to decode it. Both are always accepted.
+    versioned_framing: bool,
+
+    /// Whether links to other nodes should be encrypted and authenticated with their sphinx keys,
+    /// as announced to the directory server. When enabled, encrypted links from other nodes
+    /// are accepted as well. Plaintext links are always accepted.
+    link_encryption: bool,
+
+    /// Whether the link to a node that failed to complete the encryption handshake, for example
+    /// because it's running an older version, should be re-established in plaintext.
+    /// Encryption is attempted again after a while. Note that enabling it allows anyone able
+    /// to interfere with the handshake to downgrade the link.
+    link_encryption_plaintext_fallback: bool,
+
+    /// Timeout for completing the link encryption handshake.
+    /// The provided value is interpreted as milliseconds.
+    link_handshake_timeout: u64,
+
+    /// Delay between each subsequent refresh of the keys of other nodes used for authenticating
+    /// the links to them, alongside their addresses packets are allowed to be forwarded to.
+    /// The provided value is interpreted as milliseconds.
+    link_keys_refresh_rate: u64,
+
+    /// Whether packets should only be accepted from the mixnodes at the previous layer (or from
+    /// gateways) and only forwarded to the mixnodes at the next layer (or to gateways if this
+    /// node is at the final layer), according to the topology announced to the directory server.
+    /// Any violating packets are dropped. Note that this is incompatible with routes that
+    /// do not go through the layers in order.
+    layer_filtering: bool,
+
+    /// Delay between each subsequent refresh of the topology used for layer filtering.
+    /// The provided value is interpreted as milliseconds.
+    layer_filtering_refresh_rate: u64,
+
+    /// Duration of a single layer assignment epoch. With automatic layer assignment enabled,
+    /// the node fetches its (possibly new) layer at the start of each epoch. Epochs are aligned
+    /// to the unix epoch, so that all nodes switch layers at roughly the same time.
+    /// The provided value is interpreted as milliseconds.
+    layer_assignment_epoch: u64,
+
+    /// Maximum duration of the drain of the node, after which it terminates regardless of
+    /// whether all held packets got forwarded. It should exceed `maximum_packet_delay`.
+    /// The provided value is interpreted as milliseconds.
+    drain_timeout: u64,
+
+    /// Number of worker threads dedicated to unwrapping received sphinx packets.
+    packet_processing_workers: usize,
+
+    /// Maximum number of received packets waiting to get unwrapped by the workers.
+    /// Any packets received while the queue is full are dropped.
+    packet_processing_queue_capacity: usize,
+
+    /// Maximum number of unwrapped packets waiting to get delayed or put into the mixing pool.
+    /// Any packets unwrapped while the queue is full are dropped.
+    mixing_queue_capacity: usize,
+
+    /// Strategy used for mixing the received packets, either `Continuous` or `Pool`.
+    mixing_strategy: MixingStrategy,
+
+    /// Maximum delay a single packet can be held for before getting forwarded.
+    /// It can't exceed 2 years.
+    /// Only used with the `Continuous` mixing strategy.
+    /// The provided value is interpreted as milliseconds.
+    maximum_packet_delay: u64,
+
+    /// Policy for packets with delays exceeding `maximum_packet_delay`, either `Drop` or `Clamp`.
+    /// Only used with the `Continuous` mixing strategy.
+    excessive_delay_policy: ExcessiveDelayPolicy,
+
+    /// Maximum number of packets that can be held by the node, either waiting for their delays
+    /// to expire or in the mixing pool, at any given time.
+    /// Any packets received while at the limit are dropped.
+    maximum_in_flight_packets: usize,
+
+    /// Maximum total size of packets that can be held by the node, either waiting for their
+    /// delays to expire or in the mixing pool, at any given time.
+    /// Any packets received while at the limit are dropped.
+    /// The provided value is interpreted as bytes.
+    maximum_in_flight_bytes: usize,
+
+    /// Duration of a single round of the pool mix, after which part of the pool is flushed.
+    /// Only used with the `Pool` mixing strategy.
+    /// The provided value is interpreted as milliseconds.
+    pool_mixing_round_duration: u64,
+
+    /// If specified, the pool is also going to be flushed as soon as it contains this many packets,
+    /// without waiting for the round to end.
+    /// Only used with the `Pool` mixing strategy.
+    pool_mixing_flush_threshold: Option<usize>,
+
+    /// Number of packets that are always kept back in the pool when it's being flushed.
+    /// Only used with the `Pool` mixing strategy.
+    pool_mixing_minimum_pool_size: usize,
+
+    /// Fraction of the packets exceeding the minimum pool size that are sent out on each flush.
+    /// The value is expected to be within (0, 1] range.
+    /// Only used with the `Pool` mixing strategy.
+    pool_mixing_flush_fraction: f64,
 }
 
 impl Default for Debug {
@@ -385,7 +838,31 @@ impl Default for Debug {
             metrics_running_stats_logging_delay: DEFAULT_METRICS_RUNNING_STATS_LOGGING_DELAY,
             packet_forwarding_initial_backoff: DEFAULT_PACKET_FORWARDING_INITIAL_BACKOFF,
             packet_forwarding_maximum_backoff: DEFAULT_PACKET_FORWARDING_MAXIMUM_BACKOFF,
+            packet_forwarding_queue_length: DEFAULT_PACKET_FORWARDING_QUEUE_LENGTH,
+            packet_forwarding_drop_policy: Default::default(),
+            packet_forwarding_idle_peer_timeout: DEFAULT_PACKET_FORWARDING_IDLE_PEER_TIMEOUT,
             initial_connection_timeout: DEFAULT_INITIAL_CONNECTION_TIMEOUT,
+            versioned_framing: false,
+            link_encryption: false,
+            link_encryption_plaintext_fallback: false,
+            link_handshake_timeout: DEFAULT_LINK_HANDSHAKE_TIMEOUT,
+            link_keys_refresh_rate: DEFAULT_LINK_KEYS_REFRESH_RATE,
+            layer_filtering: false,
+            layer_filtering_refresh_rate: DEFAULT_LAYER_FILTERING_REFRESH_RATE,
+            layer_assignment_epoch: DEFAULT_LAYER_ASSIGNMENT_EPOCH,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            packet_processing_workers: DEFAULT_PACKET_PROCESSING_WORKERS,
+            packet_processing_queue_capacity: DEFAULT_PACKET_PROCESSING_QUEUE_CAPACITY,
+            mixing_queue_capacity: DEFAULT_MIXING_QUEUE_CAPACITY,
+            mixing_strategy: Default::default(),
+            maximum_packet_delay: DEFAULT_MAXIMUM_PACKET_DELAY,
+            excessive_delay_policy: Default::default(),
+            maximum_in_flight_packets: DEFAULT_MAXIMUM_IN_FLIGHT_PACKETS,
+            maximum_in_flight_bytes: DEFAULT_MAXIMUM_IN_FLIGHT_BYTES,
+            pool_mixing_round_duration: DEFAULT_POOL_MIXING_ROUND_DURATION,
+            pool_mixing_flush_threshold: None,
+            pool_mixing_minimum_pool_size: DEFAULT_POOL_MIXING_MINIMUM_POOL_SIZE,
+            pool_mixing_flush_fraction: DEFAULT_POOL_MIXING_FLUSH_FRACTION,
         }
     }
 }
@@ -408,4 +885,57 @@ mod mixnode_config {
 
         assert_eq!(default_config, loaded_config);
     }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn flush_fraction_outside_of_unit_interval_is_rejected() {
+        for &fraction in &[0.0, -0.5, 1.5, std::f64::NAN] {
+            let mut config = Config::default();
+            config.debug.pool_mixing_flush_fraction = fraction;
+            assert!(config.validate().is_err());
+        }
+
+        let mut config = Config::default();
+        config.debug.pool_mixing_flush_fraction = 1.0;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn zero_pool_mixing_round_duration_is_rejected() {
+        let mut config = Config::default();
+        config.debug.pool_mixing_round_duration = 0;
+        assert_eq!(
+            Err(ConfigValidationError::ZeroPoolMixingRoundDuration),
+            config.validate()
+        );
+    }
+
+    #[test]
+    fn zero_packet_processing_workers_are_rejected() {
+        let mut config = Config::default();
+        config.debug.packet_processing_workers = 0;
+        assert_eq!(
+            Err(ConfigValidationError::ZeroPacketProcessingWorkers),
+            config.validate()
+        );
+    }
+
+    #[test]
+    fn maximum_packet_delay_beyond_delay_queue_capacity_is_rejected() {
+        let mut config = Config::default();
+        config.debug.maximum_packet_delay = MAXIMUM_QUEUE_DELAY.as_millis() as u64;
+        assert!(config.validate().is_ok());
+
+        config.debug.maximum_packet_delay += 1;
+        assert_eq!(
+            Err(ConfigValidationError::ExcessiveMaximumPacketDelay(
+                MAXIMUM_QUEUE_DELAY + time::Duration::from_millis(1)
+            )),
+            config.validate()
+        );
+    }
 }
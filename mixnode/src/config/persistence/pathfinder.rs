@@ -21,6 +21,8 @@ pub struct MixNodePathfinder {
     pub config_dir: PathBuf,
     pub private_sphinx_key: PathBuf,
     pub public_sphinx_key: PathBuf,
+    pub private_identity_key: PathBuf,
+    pub public_identity_key: PathBuf,
 }
 
 impl MixNodePathfinder {
@@ -29,6 +31,8 @@ impl MixNodePathfinder {
             config_dir: config.get_config_file_save_location(),
             private_sphinx_key: config.get_private_sphinx_key_file(),
             public_sphinx_key: config.get_public_sphinx_key_file(),
+            private_identity_key: config.get_private_identity_key_file(),
+            public_identity_key: config.get_public_identity_key_file(),
         }
     }
 }
@@ -39,13 +43,11 @@ impl PathFinder for MixNodePathfinder {
     }
 
     fn private_identity_key(&self) -> PathBuf {
-        // TEMPORARILY USE SAME KEYS AS ENCRYPTION
-        self.private_sphinx_key.clone()
+        self.private_identity_key.clone()
     }
 
     fn public_identity_key(&self) -> PathBuf {
-        // TEMPORARILY USE SAME KEYS AS ENCRYPTION
-        self.public_sphinx_key.clone()
+        self.public_identity_key.clone()
     }
 
     fn private_encryption_key(&self) -> Option<PathBuf> {
@@ -34,17 +34,28 @@ id = '{{ mixnode.id }}'
 location = '{{ mixnode.location }}'
     
 # Layer of this particular mixnode determining its position in the network.
+# If automatic layer assignment is enabled, it's only used if the assignment can't be obtained.
 layer = {{ mixnode.layer }}
 
+# Whether the layer should be assigned by the validator, based on the current sizes
+# of all layers, rather than being fixed to the value above.
+automatic_layer_assignment = {{ mixnode.automatic_layer_assignment }}
+
 # Socket address to which this mixnode will bind to and will be listening for packets.
 listening_address = '{{ mixnode.listening_address }}'
 
-# Path to file containing private identity key.
+# Path to file containing private sphinx key.
 private_sphinx_key_file = '{{ mixnode.private_sphinx_key_file }}'
 
 # Path to file containing public sphinx key.
 public_sphinx_key_file = '{{ mixnode.public_sphinx_key_file }}'
 
+# Path to file containing private identity key.
+private_identity_key_file = '{{ mixnode.private_identity_key_file }}'
+
+# Path to file containing public identity key.
+public_identity_key_file = '{{ mixnode.public_identity_key_file }}'
+
 ##### additional mixnode config options #####
 
 # Optional address announced to the directory server for the clients to connect to.
@@ -61,6 +72,24 @@ presence_directory_server = '{{ mixnode.presence_directory_server }}'
 # Directory server to which the server will be reporting their metrics data.
 metrics_directory_server = '{{ mixnode.metrics_directory_server }}'
 
+# Validator from which the node obtains its layer assignment,
+# if automatic layer assignment is enabled.
+validator_server = '{{ mixnode.validator_server }}'
+
+# Optional socket address on which the node will expose its metrics in the Prometheus
+# text format under the `/metrics` path. If not set, the endpoint is disabled.
+{{#if mixnode.prometheus_metrics_address }}
+prometheus_metrics_address = '{{ mixnode.prometheus_metrics_address }}'
+{{/if}}
+
+# Optional socket address on which the node will expose its admin endpoint, used, for example,
+# by the `drain` command. Requests to it have to carry the token stored in the `admin_token`
+# file of the data directory, but it should still only ever be bound to a local interface.
+# If not set, the endpoint is disabled.
+{{#if mixnode.admin_address }}
+admin_address = '{{ mixnode.admin_address }}'
+{{/if}}
+
 ##### advanced configuration options #####
 
 # Absolute path to the home Nym Clients directory.
@@ -28,6 +28,7 @@ fn main() {
         .version(built_info::PKG_VERSION)
         .author("Nymtech")
         .about("Implementation of the Loopix-based Mixnode")
+        .subcommand(commands::drain::command_args())
         .subcommand(commands::init::command_args())
         .subcommand(commands::run::command_args())
         .get_matches();
@@ -37,6 +38,7 @@ fn main() {
 
 fn execute(matches: ArgMatches) {
     match matches.subcommand() {
+        ("drain", Some(m)) => commands::drain::execute(m),
         ("init", Some(m)) => commands::init::execute(m),
         ("run", Some(m)) => commands::run::execute(m),
         _ => println!("{}", usage()),
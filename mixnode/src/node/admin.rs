@@ -0,0 +1,69 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::drain::DrainTrigger;
+use http_endpoint::response;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::*;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+pub(crate) const DRAIN_PATH: &str = "/drain";
+
+/// Optional HTTP endpoint for administering the running node.
+/// Each request has to carry the admin token as `Authorization: Bearer <token>`.
+/// It should still only ever be exposed on a local interface.
+pub(crate) struct AdminEndpoint {
+    address: SocketAddr,
+    token: String,
+    drain_trigger: DrainTrigger,
+}
+
+impl AdminEndpoint {
+    pub(crate) fn new(address: SocketAddr, token: String, drain_trigger: DrainTrigger) -> Self {
+        AdminEndpoint {
+            address,
+            token,
+            drain_trigger,
+        }
+    }
+
+    fn handle_request(&self, request: Request<Body>) -> Response<Body> {
+        if !http_endpoint::token::is_authorized(&request, &self.token) {
+            return response(StatusCode::UNAUTHORIZED, "");
+        }
+
+        if request.method() != Method::POST || request.uri().path() != DRAIN_PATH {
+            return response(StatusCode::NOT_FOUND, "");
+        }
+
+        if self.drain_trigger.trigger() {
+            info!("Drain of the node was requested via the admin endpoint");
+            response(StatusCode::ACCEPTED, "drain started\n")
+        } else {
+            response(StatusCode::ACCEPTED, "drain already in progress\n")
+        }
+    }
+
+    pub(crate) fn start(self, handle: &Handle) -> JoinHandle<()> {
+        let address = self.address;
+        let endpoint = Arc::new(self);
+        http_endpoint::start(handle, address, "admin endpoint", move |request| {
+            let endpoint = Arc::clone(&endpoint);
+            async move { endpoint.handle_request(request) }
+        })
+    }
+}
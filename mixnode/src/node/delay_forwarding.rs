@@ -0,0 +1,190 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::ExcessiveDelayPolicy;
+use crate::node::in_flight::{HeldPackets, InFlightLimiter};
+use crate::node::metrics::{self, DropReason};
+use crate::node::packet_processing::ProcessedPacketSender;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use log::*;
+use nymsphinx::SphinxPacket;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::time::DelayQueue;
+
+// how often the current number of packets in the queue is reported to the metrics
+const IN_FLIGHT_REPORTING_INTERVAL: Duration = Duration::from_secs(1);
+
+// inserting anything beyond the longest delay the tokio timer wheel can represent (2^36 ms,
+// about 2.18 years) into the `DelayQueue` results in a panic. The limit is measured from the last
+// time the queue got polled, so leave a safety margin
+pub(crate) const MAXIMUM_QUEUE_DELAY: Duration = Duration::from_secs(2 * 365 * 24 * 60 * 60);
+
+pub(crate) struct Config {
+    queue_capacity: usize,
+    maximum_delay: Duration,
+    excessive_delay_policy: ExcessiveDelayPolicy,
+    maximum_in_flight_packets: usize,
+    maximum_in_flight_bytes: usize,
+}
+
+impl Config {
+    pub(crate) fn new(
+        queue_capacity: usize,
+        maximum_delay: Duration,
+        excessive_delay_policy: ExcessiveDelayPolicy,
+        maximum_in_flight_packets: usize,
+        maximum_in_flight_bytes: usize,
+    ) -> Self {
+        Config {
+            queue_capacity,
+            maximum_delay,
+            excessive_delay_policy,
+            maximum_in_flight_packets,
+            maximum_in_flight_bytes,
+        }
+    }
+}
+
+/// Single place holding all processed packets for the duration of their delays, as opposed to
+/// having a separate sleeping task for each of them. Once the delay of a packet expires,
+/// it is released to the packet forwarder.
+pub(crate) struct DelayForwarder {
+    delay_queue: DelayQueue<(SocketAddr, SphinxPacket)>,
+    queue_capacity: usize,
+    maximum_delay: Duration,
+    excessive_delay_policy: ExcessiveDelayPolicy,
+    in_flight_limiter: InFlightLimiter,
+    forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    metrics_reporter: metrics::MetricsReporter,
+}
+
+impl DelayForwarder {
+    pub(crate) fn new(
+        config: Config,
+        forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+        metrics_reporter: metrics::MetricsReporter,
+    ) -> Self {
+        DelayForwarder {
+            delay_queue: DelayQueue::new(),
+            queue_capacity: config.queue_capacity,
+            maximum_delay: config.maximum_delay,
+            excessive_delay_policy: config.excessive_delay_policy,
+            in_flight_limiter: InFlightLimiter::new(
+                config.maximum_in_flight_packets,
+                config.maximum_in_flight_bytes,
+            ),
+            forwarding_channel,
+            metrics_reporter,
+        }
+    }
+
+    pub(crate) fn held_packets(&self) -> HeldPackets {
+        self.in_flight_limiter.held_packets()
+    }
+
+    // checks the delay against the maximum and applies the policy if it's exceeded
+    fn sanitize_delay(&self, delay: Duration) -> Result<Duration, DropReason> {
+        if delay <= self.maximum_delay {
+            return Ok(delay);
+        }
+
+        match self.excessive_delay_policy {
+            ExcessiveDelayPolicy::Drop => Err(DropReason::ExcessiveDelay),
+            ExcessiveDelayPolicy::Clamp => {
+                self.metrics_reporter.report_clamped_delay();
+                Ok(self.maximum_delay)
+            }
+        }
+    }
+
+    fn insert(&mut self, address: SocketAddr, packet: SphinxPacket, delay: nymsphinx::Delay) {
+        let delay = match self.sanitize_delay(delay.to_duration()) {
+            Ok(delay) => delay,
+            Err(reason) => {
+                debug!(
+                    "Dropping packet to {} - its delay of {:?} exceeds the maximum of {:?}",
+                    address,
+                    delay.to_duration(),
+                    self.maximum_delay
+                );
+                self.metrics_reporter.report_dropped(reason);
+                return;
+            }
+        };
+
+        if let Err(reason) = self.in_flight_limiter.try_admit(packet.len()) {
+            debug!(
+                "Dropping packet to {} - the limit of packets in flight has been reached ({})",
+                address,
+                reason.as_str()
+            );
+            self.metrics_reporter.report_dropped(reason);
+            return;
+        }
+
+        // the delay is chosen by the sender, so regardless of the configured maximum
+        // it can't be allowed to exceed what the queue can hold
+        let delay = std::cmp::min(delay, MAXIMUM_QUEUE_DELAY);
+        self.metrics_reporter.report_packet_delay(delay);
+        self.delay_queue.insert((address, packet), delay);
+    }
+
+    fn release(&mut self, address: SocketAddr, packet: SphinxPacket) {
+        self.in_flight_limiter.release(packet.len());
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.forwarding_channel
+            .unbounded_send((address, packet))
+            .unwrap();
+        self.metrics_reporter.report_sent(address.to_string());
+    }
+
+    // returns channel to which the processed packets should be sent to. The forwarder is not
+    // holding any sender itself, so it stops once all the packet producers are gone.
+    pub(crate) fn start(mut self, handle: &Handle) -> ProcessedPacketSender {
+        let (packet_tx, mut packet_rx) = mpsc::channel(self.queue_capacity);
+        handle.spawn(async move {
+            let mut reporting_timer = tokio::time::interval(IN_FLIGHT_REPORTING_INTERVAL);
+            loop {
+                tokio::select! {
+                    processed_packet = packet_rx.next() => match processed_packet {
+                        Some((address, packet, delay)) => self.insert(address, packet, delay),
+                        None => {
+                            warn!("Delay forwarder's channel got closed - no more packets are going to be forwarded");
+                            break;
+                        }
+                    },
+                    // note: if the queue is empty, `next()` resolves to `None` and this branch
+                    // is disabled until the next iteration of the loop
+                    Some(expired) = self.delay_queue.next() => match expired {
+                        Ok(expired) => {
+                            let (address, packet) = expired.into_inner();
+                            self.release(address, packet)
+                        }
+                        // this can only happen if the timer is shutting down or is at capacity
+                        Err(err) => error!("The delay queue timer has failed - {:?}", err),
+                    },
+                    _ = reporting_timer.tick() => self.metrics_reporter.report_in_flight(
+                        self.in_flight_limiter.packets(),
+                        self.in_flight_limiter.bytes(),
+                    ),
+                }
+            }
+        });
+        packet_tx
+    }
+}
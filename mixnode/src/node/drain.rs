@@ -0,0 +1,94 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Creates a pair of handles for starting the drain of the node and for observing it.
+pub(crate) fn drain_channel() -> (DrainTrigger, DrainSignal) {
+    let (tx, rx) = watch::channel(false);
+    (
+        DrainTrigger {
+            tx: Arc::new(tx),
+            triggered: Arc::new(AtomicBool::new(false)),
+        },
+        DrainSignal { rx },
+    )
+}
+
+/// Starts the drain of the node, after which it no longer accepts any new packets,
+/// but keeps forwarding the ones it already holds.
+#[derive(Clone)]
+pub(crate) struct DrainTrigger {
+    tx: Arc<watch::Sender<bool>>,
+    triggered: Arc<AtomicBool>,
+}
+
+impl DrainTrigger {
+    /// Returns whether this call has started the drain, i.e. it wasn't already in progress.
+    pub(crate) fn trigger(&self) -> bool {
+        if self.triggered.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        // this can only fail if all signals were dropped, in which case there's nothing to notify
+        let _ = self.tx.broadcast(true);
+        true
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct DrainSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl DrainSignal {
+    pub(crate) fn is_draining(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once the drain has started. Never resolves if it can no longer be started.
+    pub(crate) async fn draining(&mut self) {
+        while !self.is_draining() {
+            if self.rx.recv().await.is_none() {
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod drain_signal {
+    use super::*;
+
+    #[tokio::test]
+    async fn resolves_once_drain_is_triggered() {
+        let (trigger, mut signal) = drain_channel();
+        let mut observer = signal.clone();
+        let waiting = tokio::spawn(async move { observer.draining().await });
+
+        assert!(!signal.is_draining());
+        assert!(trigger.trigger());
+        waiting.await.unwrap();
+        signal.draining().await;
+        assert!(signal.is_draining());
+    }
+
+    #[test]
+    fn drain_is_only_started_once() {
+        let (trigger, _signal) = drain_channel();
+        assert!(trigger.trigger());
+        assert!(!trigger.clone().trigger());
+    }
+}
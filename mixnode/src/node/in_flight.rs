@@ -0,0 +1,127 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::metrics::DropReason;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Shared view of the number of packets currently held by the node.
+pub(crate) type HeldPackets = Arc<AtomicUsize>;
+
+/// Keeps track of packets held by the node (either in the delay queue or in the mixing pool)
+/// and makes sure neither their number nor their total size exceeds the configured limits.
+/// Any packet that would go over the limits should be dropped.
+pub(crate) struct InFlightLimiter {
+    max_packets: usize,
+    max_bytes: usize,
+    packets: HeldPackets,
+    bytes: usize,
+}
+
+impl InFlightLimiter {
+    pub(crate) fn new(max_packets: usize, max_bytes: usize) -> Self {
+        InFlightLimiter {
+            max_packets,
+            max_bytes,
+            packets: Arc::new(AtomicUsize::new(0)),
+            bytes: 0,
+        }
+    }
+
+    pub(crate) fn try_admit(&mut self, packet_len: usize) -> Result<(), DropReason> {
+        if self.packets() >= self.max_packets {
+            return Err(DropReason::InFlightPacketsLimit);
+        }
+        if self.bytes + packet_len > self.max_bytes {
+            return Err(DropReason::InFlightBytesLimit);
+        }
+
+        self.packets.fetch_add(1, Ordering::SeqCst);
+        self.bytes += packet_len;
+        Ok(())
+    }
+
+    pub(crate) fn release(&mut self, packet_len: usize) {
+        debug_assert!(self.packets() > 0 && self.bytes >= packet_len);
+        self.packets.fetch_sub(1, Ordering::SeqCst);
+        self.bytes -= packet_len;
+    }
+
+    pub(crate) fn packets(&self) -> usize {
+        self.packets.load(Ordering::SeqCst)
+    }
+
+    /// Handle for observing the number of held packets from outside of the holder.
+    pub(crate) fn held_packets(&self) -> HeldPackets {
+        Arc::clone(&self.packets)
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod in_flight_limiter {
+    use super::*;
+
+    #[test]
+    fn admits_packets_within_limits() {
+        let mut limiter = InFlightLimiter::new(2, 100);
+        assert!(limiter.try_admit(50).is_ok());
+        assert!(limiter.try_admit(50).is_ok());
+        assert_eq!(limiter.packets(), 2);
+        assert_eq!(limiter.bytes(), 100);
+    }
+
+    #[test]
+    fn rejects_packets_over_the_count_limit() {
+        let mut limiter = InFlightLimiter::new(1, 100);
+        assert!(limiter.try_admit(10).is_ok());
+        assert_eq!(
+            limiter.try_admit(10).unwrap_err(),
+            DropReason::InFlightPacketsLimit
+        );
+        assert_eq!(limiter.packets(), 1);
+    }
+
+    #[test]
+    fn rejects_packets_over_the_bytes_limit() {
+        let mut limiter = InFlightLimiter::new(10, 100);
+        assert!(limiter.try_admit(60).is_ok());
+        assert_eq!(
+            limiter.try_admit(60).unwrap_err(),
+            DropReason::InFlightBytesLimit
+        );
+        assert_eq!(limiter.bytes(), 60);
+    }
+
+    #[test]
+    fn released_packets_free_up_space() {
+        let mut limiter = InFlightLimiter::new(1, 100);
+        assert!(limiter.try_admit(100).is_ok());
+        limiter.release(100);
+        assert!(limiter.try_admit(100).is_ok());
+    }
+
+    #[test]
+    fn held_packets_are_observable_through_the_handle() {
+        let mut limiter = InFlightLimiter::new(10, 100);
+        let held_packets = limiter.held_packets();
+        assert!(limiter.try_admit(10).is_ok());
+        assert_eq!(1, held_packets.load(Ordering::SeqCst));
+        limiter.release(10);
+        assert_eq!(0, held_packets.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crypto::asymmetric::identity;
+use directory_client::presence::layers::LayerAssignmentRequest;
+use directory_client::DirectoryClient;
+use layer_filter::{LayerFilter, NodeRole};
+use log::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// Layer the mixnode is currently part of, shared between everything that depends on it.
+pub(crate) type SharedLayer = Arc<AtomicU64>;
+
+/// Obtains the layer assignment of this mixnode from the validator and applies it,
+/// both on startup and then at the start of each subsequent epoch.
+pub(crate) struct LayerAssigner {
+    validator_client: directory_client::Client,
+    pub_key: String,
+    identity_keypair: Arc<identity::KeyPair>,
+    epoch: Duration,
+    layer: SharedLayer,
+    layer_filter: Option<LayerFilter>,
+}
+
+// time remaining until the start of the next epoch, with epochs being aligned to the unix epoch
+fn time_until_next_epoch(now: Duration, epoch: Duration) -> Duration {
+    let epoch_millis = epoch.as_millis().max(1);
+    let now_millis = now.as_millis();
+    let next_epoch_start = (now_millis / epoch_millis + 1) * epoch_millis;
+    Duration::from_millis((next_epoch_start - now_millis) as u64)
+}
+
+impl LayerAssigner {
+    pub(crate) fn new(
+        validator_server: String,
+        pub_key: String,
+        identity_keypair: Arc<identity::KeyPair>,
+        epoch: Duration,
+        layer: SharedLayer,
+    ) -> Self {
+        LayerAssigner {
+            // the validator exposes the assignment through the same kind of rest api
+            validator_client: directory_client::Client::new(directory_client::Config::new(
+                validator_server,
+            )),
+            pub_key,
+            identity_keypair,
+            epoch,
+            layer,
+            layer_filter: None,
+        }
+    }
+
+    /// Makes the assigned layer also change the role of the node in the layer filter.
+    pub(crate) fn with_layer_filter(mut self, layer_filter: LayerFilter) -> Self {
+        self.layer_filter = Some(layer_filter);
+        self
+    }
+
+    fn apply(&self, layer: u64) {
+        let previous = self.layer.swap(layer, Ordering::SeqCst);
+        if previous != layer {
+            info!("Mixnode was assigned to layer {} (was {})", layer, previous);
+        }
+        if let Some(layer_filter) = &self.layer_filter {
+            layer_filter.set_role(NodeRole::Mix { layer });
+        }
+    }
+
+    fn signed_request(&self) -> LayerAssignmentRequest {
+        // if the clock is somehow set before the unix epoch, the validator will reject the request
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let message = LayerAssignmentRequest::signed_message(&self.pub_key, timestamp);
+        let signature = self.identity_keypair.private_key().sign(&message);
+        LayerAssignmentRequest {
+            pub_key: self.pub_key.clone(),
+            identity_key: self.identity_keypair.public_key().to_base58_string(),
+            timestamp,
+            signature: bs58::encode(&signature.to_bytes()[..]).into_string(),
+        }
+    }
+
+    /// Obtains the current assignment and applies it. Returns the assigned layer,
+    /// or `None` if it could not be obtained from the validator.
+    pub(crate) async fn assign(&self) -> Option<u64> {
+        match self
+            .validator_client
+            .post_layer_assignment_request(self.signed_request())
+            .await
+        {
+            Ok(assignment) => {
+                self.apply(assignment.layer);
+                Some(assignment.layer)
+            }
+            Err(err) => {
+                error!("Failed to obtain the layer assignment - {}", err);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn start(self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                // if the clock is somehow set before the unix epoch, just wait for a full epoch
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                tokio::time::delay_for(time_until_next_epoch(now, self.epoch)).await;
+                // on failure we just keep the current layer until the next epoch
+                self.assign().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod epoch_timing {
+    use super::*;
+
+    #[test]
+    fn next_epoch_is_aligned_to_epoch_duration() {
+        let epoch = Duration::from_secs(60);
+        assert_eq!(
+            Duration::from_secs(15),
+            time_until_next_epoch(Duration::from_secs(165), epoch)
+        );
+    }
+
+    #[test]
+    fn at_epoch_boundary_waits_for_full_epoch() {
+        let epoch = Duration::from_secs(60);
+        assert_eq!(
+            epoch,
+            time_until_next_epoch(Duration::from_secs(120), epoch)
+        );
+    }
+}
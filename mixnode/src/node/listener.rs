@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::node::packet_processing::{MixProcessingResult, PacketProcessor};
-use futures::channel::mpsc;
+use crate::node::drain::DrainSignal;
+use crate::node::packet_processing::{MixProcessingResult, PacketProcessor, ProcessedPacketSender};
+use link_encryption::{LinkCodec, ResponderConfig};
 use log::*;
 use nymsphinx::framing::SphinxCodec;
 use nymsphinx::SphinxPacket;
@@ -27,22 +28,27 @@ use tokio_util::codec::Framed;
 async fn process_received_packet(
     sphinx_packet: SphinxPacket,
     packet_processor: PacketProcessor,
-    forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    mut processed_packets_channel: ProcessedPacketSender,
 ) {
-    // all processing incl. delay was done, the only thing left is to forward it
     match packet_processor.process_sphinx_packet(sphinx_packet).await {
         Err(e) => debug!("We failed to process received sphinx packet - {:?}", e),
         Ok(res) => match res {
-            MixProcessingResult::ForwardHop(hop_address, forward_packet) => {
-                // send our data to tcp client for forwarding. If forwarding fails, then it fails,
-                // it's not like we can do anything about it
-                //
-                // in unbounded_send() failed it means that the receiver channel was disconnected
-                // and hence something weird must have happened without a way of recovering
-                forwarding_channel
-                    .unbounded_send((hop_address, forward_packet))
-                    .unwrap();
-                packet_processor.report_sent(hop_address);
+            MixProcessingResult::ForwardHop(hop_address, forward_packet, delay) => {
+                // send our data to either the delay queue or the pool mixer (depending on the
+                // mixing strategy used) that will decide when the packet should be forwarded.
+                match processed_packets_channel.try_send((hop_address, forward_packet, delay)) {
+                    Ok(_) => (),
+                    Err(err) if err.is_full() => {
+                        debug!(
+                            "Dropping packet to {} - the mixing queue is full",
+                            hop_address
+                        );
+                        packet_processor.report_mixing_queue_full();
+                    }
+                    // if the receiver channel got disconnected, something weird must have
+                    // happened without a way of recovering
+                    Err(err) => panic!("The mixing channel got disconnected - {}", err),
+                }
             }
             MixProcessingResult::LoopMessage => {
                 warn!("Somehow processed a loop cover message that we haven't implemented yet!")
@@ -52,22 +58,65 @@ async fn process_received_packet(
 }
 
 async fn process_socket_connection(
-    socket: tokio::net::TcpStream,
+    mut socket: tokio::net::TcpStream,
+    link_config: Option<ResponderConfig>,
     packet_processor: PacketProcessor,
-    forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    processed_packets_channel: ProcessedPacketSender,
+    mut drain_signal: DrainSignal,
 ) {
-    let mut framed = Framed::new(socket, SphinxCodec);
-    while let Some(sphinx_packet) = framed.next().await {
-        match sphinx_packet {
-            Ok(sphinx_packet) => {
-                // we *really* need a worker pool here, because if we receive too many packets,
-                // we will spawn too many tasks and starve CPU due to context switching.
-                // (because presumably tokio has some concept of context switching in its
-                // scheduler)
+    let transport = match link_encryption::accept(&mut socket, link_config.as_ref()).await {
+        Ok(transport) => transport,
+        Err(err) => {
+            warn!(
+                "Failed to establish encrypted link with {:?} - {}. Closing the socket",
+                socket.peer_addr(),
+                err
+            );
+            return;
+        }
+    };
+    if transport.is_some() {
+        debug!("Established encrypted link with {:?}", socket.peer_addr());
+    }
+
+    // if we can't even tell who the remote is, there's no point in handling the connection
+    let peer_ip = match socket.peer_addr() {
+        Ok(peer_address) => peer_address.ip(),
+        Err(err) => {
+            warn!("Failed to obtain address of the remote - {}", err);
+            return;
+        }
+    };
+
+    let mut framed = Framed::new(socket, LinkCodec::new(SphinxCodec::default(), transport));
+    loop {
+        let framed_sphinx_packet = tokio::select! {
+            framed_sphinx_packet = framed.next() => match framed_sphinx_packet {
+                Some(framed_sphinx_packet) => framed_sphinx_packet,
+                None => break,
+            },
+            _ = drain_signal.draining() => {
+                debug!("Closing connection from {} as the node is draining", peer_ip);
+                return;
+            }
+        };
+        match framed_sphinx_packet {
+            Ok(_)
+                if !packet_processor
+                    .accepts_packets_from(peer_ip, framed.codec().remote_static_key()) =>
+            {
+                trace!(
+                    "Dropping packet from {} - it's not a valid previous hop",
+                    peer_ip
+                )
+            }
+            Ok(framed_sphinx_packet) => {
+                // the actual unwrapping happens on the sphinx unwrapping pool, the spawned task
+                // is only waiting for its result (or for the rejection if the pool is overloaded)
                 tokio::spawn(process_received_packet(
-                    sphinx_packet,
+                    framed_sphinx_packet.into_inner(),
                     packet_processor.clone(),
-                    forwarding_channel.clone(),
+                    processed_packets_channel.clone(),
                 ));
             }
             Err(err) => {
@@ -88,22 +137,34 @@ async fn process_socket_connection(
 pub(crate) fn run_socket_listener(
     handle: &Handle,
     addr: SocketAddr,
+    link_config: Option<ResponderConfig>,
     packet_processor: PacketProcessor,
-    forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    processed_packets_channel: ProcessedPacketSender,
+    mut drain_signal: DrainSignal,
 ) -> JoinHandle<io::Result<()>> {
     let handle_clone = handle.clone();
     handle.spawn(async move {
         let mut listener = tokio::net::TcpListener::bind(addr).await?;
         loop {
-            let (socket, _) = listener.accept().await?;
+            let socket = tokio::select! {
+                accepted = listener.accept() => accepted?.0,
+                _ = drain_signal.draining() => {
+                    info!("The node is draining - no longer accepting new connections");
+                    return Ok(());
+                }
+            };
 
+            let thread_link_config = link_config.clone();
             let thread_packet_processor = packet_processor.clone();
-            let forwarding_channel_clone = forwarding_channel.clone();
+            let processed_packets_channel_clone = processed_packets_channel.clone();
+            let thread_drain_signal = drain_signal.clone();
             handle_clone.spawn(async move {
                 process_socket_connection(
                     socket,
+                    thread_link_config,
                     thread_packet_processor,
-                    forwarding_channel_clone,
+                    processed_packets_channel_clone,
+                    thread_drain_signal,
                 )
                 .await;
             });
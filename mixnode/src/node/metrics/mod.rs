@@ -0,0 +1,592 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::metrics::prometheus::{DelayHistogram, PrometheusExporter};
+use crate::node::packet_processing::SphinxUnwrappingPool;
+use directory_client::metrics::MixMetric;
+use directory_client::DirectoryClient;
+use futures::channel::mpsc;
+use futures::lock::Mutex;
+use futures::StreamExt;
+use log::*;
+use packet_forwarder::ForwardingStats;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+mod prometheus;
+
+type SentMetricsMap = HashMap<String, u64>;
+type DroppedMetricsMap = HashMap<DropReason, u64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DropReason {
+    ExcessiveDelay,
+    InFlightPacketsLimit,
+    InFlightBytesLimit,
+    ProcessingQueueFull,
+    MixingQueueFull,
+    IngressFilter,
+    EgressFilter,
+}
+
+impl DropReason {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::ExcessiveDelay => "excessive_delay",
+            DropReason::InFlightPacketsLimit => "in_flight_packets_limit",
+            DropReason::InFlightBytesLimit => "in_flight_bytes_limit",
+            DropReason::ProcessingQueueFull => "processing_queue_full",
+            DropReason::MixingQueueFull => "mixing_queue_full",
+            DropReason::IngressFilter => "ingress_filter",
+            DropReason::EgressFilter => "egress_filter",
+        }
+    }
+}
+
+pub(crate) enum MetricEvent {
+    Sent(String),
+    Received,
+    PoolSize(usize),
+    Dropped(DropReason),
+    ProcessingError,
+    ClampedDelay,
+    PacketDelay(Duration),
+    InFlight { packets: usize, bytes: usize },
+}
+
+// Pool sizes observed by the pool mixer (if used) since the last metrics report
+#[derive(Debug, Default, Clone, Copy)]
+struct PoolSizeStats {
+    samples: u64,
+    total: u64,
+    max: usize,
+    last: usize,
+}
+
+impl PoolSizeStats {
+    fn add_sample(&mut self, pool_size: usize) {
+        self.samples += 1;
+        self.total += pool_size as u64;
+        self.max = std::cmp::max(self.max, pool_size);
+        self.last = pool_size;
+    }
+
+    fn average(&self) -> f64 {
+        if self.samples == 0 {
+            0.0
+        } else {
+            self.total as f64 / self.samples as f64
+        }
+    }
+}
+
+// Packets currently held by the node, i.e. waiting for their delays to expire
+// or for the pool to get flushed
+#[derive(Debug, Default, Clone, Copy)]
+struct InFlightStats {
+    packets: usize,
+    bytes: usize,
+}
+
+// Metrics gathered since the startup, as opposed to the ones reset on each report
+#[derive(Debug, Default, Clone)]
+struct TotalMetrics {
+    received: u64,
+    sent: SentMetricsMap,
+    dropped: DroppedMetricsMap,
+    processing_errors: u64,
+    clamped_delays: u64,
+    delays: DelayHistogram,
+    in_flight: InFlightStats,
+}
+
+// Metrics gathered since the last report
+struct MetricsSnapshot {
+    received: u64,
+    sent: SentMetricsMap,
+    dropped: DroppedMetricsMap,
+    clamped_delays: u64,
+    pool_sizes: PoolSizeStats,
+    in_flight: InFlightStats,
+}
+
+#[derive(Debug, Clone)]
+// Note: you should NEVER create more than a single instance of this using 'new()'.
+// You should always use .clone() to create additional instances
+struct MixMetrics {
+    inner: Arc<Mutex<MixMetricsInner>>,
+}
+
+struct MixMetricsInner {
+    received: u64,
+    sent: SentMetricsMap,
+    dropped: DroppedMetricsMap,
+    clamped_delays: u64,
+    pool_sizes: PoolSizeStats,
+    in_flight: InFlightStats,
+
+    totals: TotalMetrics,
+}
+
+impl MixMetrics {
+    pub(crate) fn new() -> Self {
+        MixMetrics {
+            inner: Arc::new(Mutex::new(MixMetricsInner {
+                received: 0,
+                sent: HashMap::new(),
+                dropped: HashMap::new(),
+                clamped_delays: 0,
+                pool_sizes: Default::default(),
+                in_flight: Default::default(),
+                totals: Default::default(),
+            })),
+        }
+    }
+
+    async fn increment_received_metrics(&mut self) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.received += 1;
+        unlocked.totals.received += 1;
+    }
+
+    async fn increment_sent_metrics(&mut self, destination: String) {
+        let mut unlocked = self.inner.lock().await;
+        *unlocked.totals.sent.entry(destination.clone()).or_insert(0) += 1;
+        let receiver_count = unlocked.sent.entry(destination).or_insert(0);
+        *receiver_count += 1;
+    }
+
+    async fn add_pool_size_sample(&mut self, pool_size: usize) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.pool_sizes.add_sample(pool_size);
+    }
+
+    async fn increment_dropped_metrics(&mut self, reason: DropReason) {
+        let mut unlocked = self.inner.lock().await;
+        *unlocked.dropped.entry(reason).or_insert(0) += 1;
+        *unlocked.totals.dropped.entry(reason).or_insert(0) += 1;
+    }
+
+    async fn increment_processing_errors_metrics(&mut self) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.totals.processing_errors += 1;
+    }
+
+    async fn increment_clamped_delays_metrics(&mut self) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.clamped_delays += 1;
+        unlocked.totals.clamped_delays += 1;
+    }
+
+    async fn observe_packet_delay(&mut self, delay: Duration) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.totals.delays.observe(delay);
+    }
+
+    async fn update_in_flight_metrics(&mut self, packets: usize, bytes: usize) {
+        let mut unlocked = self.inner.lock().await;
+        unlocked.in_flight = InFlightStats { packets, bytes };
+        unlocked.totals.in_flight = InFlightStats { packets, bytes };
+    }
+
+    async fn current_totals(&self) -> TotalMetrics {
+        self.inner.lock().await.totals.clone()
+    }
+
+    async fn acquire_and_reset_metrics(&mut self) -> MetricsSnapshot {
+        let mut unlocked = self.inner.lock().await;
+        let received = unlocked.received;
+        let clamped_delays = unlocked.clamped_delays;
+
+        let sent = std::mem::replace(&mut unlocked.sent, HashMap::new());
+        let dropped = std::mem::replace(&mut unlocked.dropped, HashMap::new());
+        let pool_sizes = std::mem::take(&mut unlocked.pool_sizes);
+        unlocked.received = 0;
+        unlocked.clamped_delays = 0;
+
+        MetricsSnapshot {
+            received,
+            sent,
+            dropped,
+            clamped_delays,
+            pool_sizes,
+            // in-flight packets are not reset as it's the current state rather than a counter
+            in_flight: unlocked.in_flight,
+        }
+    }
+}
+
+struct MetricsReceiver {
+    metrics: MixMetrics,
+    metrics_rx: mpsc::UnboundedReceiver<MetricEvent>,
+}
+
+impl MetricsReceiver {
+    fn new(metrics: MixMetrics, metrics_rx: mpsc::UnboundedReceiver<MetricEvent>) -> Self {
+        MetricsReceiver {
+            metrics,
+            metrics_rx,
+        }
+    }
+
+    fn start(mut self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            while let Some(metrics_data) = self.metrics_rx.next().await {
+                match metrics_data {
+                    MetricEvent::Received => self.metrics.increment_received_metrics().await,
+                    MetricEvent::Sent(destination) => {
+                        self.metrics.increment_sent_metrics(destination).await
+                    }
+                    MetricEvent::PoolSize(pool_size) => {
+                        self.metrics.add_pool_size_sample(pool_size).await
+                    }
+                    MetricEvent::Dropped(reason) => {
+                        self.metrics.increment_dropped_metrics(reason).await
+                    }
+                    MetricEvent::ProcessingError => {
+                        self.metrics.increment_processing_errors_metrics().await
+                    }
+                    MetricEvent::ClampedDelay => {
+                        self.metrics.increment_clamped_delays_metrics().await
+                    }
+                    MetricEvent::PacketDelay(delay) => {
+                        self.metrics.observe_packet_delay(delay).await
+                    }
+                    MetricEvent::InFlight { packets, bytes } => {
+                        self.metrics.update_in_flight_metrics(packets, bytes).await
+                    }
+                }
+            }
+        })
+    }
+}
+
+struct MetricsSender {
+    metrics: MixMetrics,
+    directory_client: directory_client::Client,
+    pub_key_str: String,
+    sending_delay: Duration,
+    metrics_informer: MetricsInformer,
+    unwrapping_pool: SphinxUnwrappingPool,
+}
+
+impl MetricsSender {
+    fn new(
+        metrics: MixMetrics,
+        directory_server: String,
+        pub_key_str: String,
+        sending_delay: Duration,
+        running_logging_delay: Duration,
+        unwrapping_pool: SphinxUnwrappingPool,
+    ) -> Self {
+        MetricsSender {
+            metrics,
+            directory_client: directory_client::Client::new(directory_client::Config::new(
+                directory_server,
+            )),
+            pub_key_str,
+            sending_delay,
+            metrics_informer: MetricsInformer::new(running_logging_delay),
+            unwrapping_pool,
+        }
+    }
+
+    fn start(mut self, handle: &Handle) -> JoinHandle<()> {
+        handle.spawn(async move {
+            loop {
+                // set the deadline in the future
+                let sending_delay = tokio::time::delay_for(self.sending_delay);
+                let snapshot = self.metrics.acquire_and_reset_metrics().await;
+
+                self.metrics_informer.update_running_stats(&snapshot);
+                self.metrics_informer.log_report_stats(&snapshot);
+                self.metrics_informer.try_log_running_stats();
+                debug!("Sphinx unwrapping pool - {}", self.unwrapping_pool.stats());
+
+                match self
+                    .directory_client
+                    .post_mix_metrics(MixMetric {
+                        pub_key: self.pub_key_str.clone(),
+                        received: snapshot.received,
+                        sent: snapshot.sent,
+                    })
+                    .await
+                {
+                    Err(err) => error!("failed to send metrics - {:?}", err),
+                    Ok(_) => debug!("sent metrics information"),
+                }
+
+                // wait for however much is left
+                sending_delay.await;
+            }
+        })
+    }
+}
+
+struct MetricsInformer {
+    total_received: u64,
+    sent_map: SentMetricsMap,
+    dropped_map: DroppedMetricsMap,
+    total_clamped_delays: u64,
+    max_pool_size: usize,
+
+    running_stats_logging_delay: Duration,
+    last_reported_stats: SystemTime,
+}
+
+impl MetricsInformer {
+    fn new(running_stats_logging_delay: Duration) -> Self {
+        MetricsInformer {
+            total_received: 0,
+            sent_map: HashMap::new(),
+            dropped_map: HashMap::new(),
+            total_clamped_delays: 0,
+            max_pool_size: 0,
+            running_stats_logging_delay,
+            last_reported_stats: SystemTime::now(),
+        }
+    }
+
+    fn should_log_running_stats(&self) -> bool {
+        self.last_reported_stats + self.running_stats_logging_delay < SystemTime::now()
+    }
+
+    fn try_log_running_stats(&mut self) {
+        if self.should_log_running_stats() {
+            self.log_running_stats()
+        }
+    }
+
+    fn update_running_stats(&mut self, pre_reset: &MetricsSnapshot) {
+        self.total_received += pre_reset.received;
+
+        for (mix, count) in pre_reset.sent.iter() {
+            *self.sent_map.entry(mix.clone()).or_insert(0) += *count;
+        }
+
+        for (reason, count) in pre_reset.dropped.iter() {
+            *self.dropped_map.entry(*reason).or_insert(0) += *count;
+        }
+
+        self.total_clamped_delays += pre_reset.clamped_delays;
+        self.max_pool_size = std::cmp::max(self.max_pool_size, pre_reset.pool_sizes.max);
+    }
+
+    fn log_report_stats(&self, pre_reset: &MetricsSnapshot) {
+        debug!(
+            "Since last metrics report mixed {} packets!",
+            pre_reset.received
+        );
+        debug!(
+            "Since last metrics report received {} packets",
+            pre_reset.sent.values().sum::<u64>()
+        );
+        trace!(
+            "Since last metrics report sent packets to the following: \n{:#?}",
+            pre_reset.sent
+        );
+        for (reason, count) in pre_reset.dropped.iter() {
+            debug!(
+                "Since last metrics report dropped {} packets due to {}",
+                count,
+                reason.as_str()
+            );
+        }
+        if pre_reset.clamped_delays > 0 {
+            debug!(
+                "Since last metrics report clamped delays of {} packets",
+                pre_reset.clamped_delays
+            );
+        }
+        debug!(
+            "Currently holding {} packets ({} bytes) in flight",
+            pre_reset.in_flight.packets, pre_reset.in_flight.bytes
+        );
+        // pool sizes are only ever reported when pool mixing is used
+        if pre_reset.pool_sizes.samples > 0 {
+            debug!(
+                "Since last metrics report the pool size was {:.2} on average (max: {}, current: {})",
+                pre_reset.pool_sizes.average(),
+                pre_reset.pool_sizes.max,
+                pre_reset.pool_sizes.last
+            );
+        }
+    }
+
+    fn log_running_stats(&mut self) {
+        info!(
+            "Since startup mixed {} packets!",
+            self.sent_map.values().sum::<u64>()
+        );
+        debug!("Since startup received {} packets", self.total_received);
+        let total_dropped = self.dropped_map.values().sum::<u64>();
+        if total_dropped > 0 {
+            info!(
+                "Since startup dropped {} packets ({} delays were clamped)",
+                total_dropped, self.total_clamped_delays
+            );
+            debug!(
+                "Since startup dropped packets due to the following: \n{:#?}",
+                self.dropped_map
+            );
+        }
+        if self.max_pool_size > 0 {
+            debug!(
+                "Since startup the largest pool size was {}",
+                self.max_pool_size
+            );
+        }
+        trace!(
+            "Since startup sent packets to the following: \n{:#?}",
+            self.sent_map
+        );
+        self.last_reported_stats = SystemTime::now();
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsReporter {
+    metrics_tx: mpsc::UnboundedSender<MetricEvent>,
+}
+
+impl MetricsReporter {
+    pub(crate) fn new(metrics_tx: mpsc::UnboundedSender<MetricEvent>) -> Self {
+        MetricsReporter { metrics_tx }
+    }
+
+    pub(crate) fn report_sent(&self, destination: String) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::Sent(destination))
+            .unwrap()
+    }
+
+    pub(crate) fn report_pool_size(&self, pool_size: usize) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::PoolSize(pool_size))
+            .unwrap()
+    }
+
+    pub(crate) fn report_dropped(&self, reason: DropReason) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::Dropped(reason))
+            .unwrap()
+    }
+
+    pub(crate) fn report_processing_error(&self) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::ProcessingError)
+            .unwrap()
+    }
+
+    pub(crate) fn report_packet_delay(&self, delay: Duration) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::PacketDelay(delay))
+            .unwrap()
+    }
+
+    pub(crate) fn report_clamped_delay(&self) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::ClampedDelay)
+            .unwrap()
+    }
+
+    pub(crate) fn report_in_flight(&self, packets: usize, bytes: usize) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::InFlight { packets, bytes })
+            .unwrap()
+    }
+
+    pub(crate) fn report_received(&self) {
+        // in unbounded_send() failed it means that the receiver channel was disconnected
+        // and hence something weird must have happened without a way of recovering
+        self.metrics_tx
+            .unbounded_send(MetricEvent::Received)
+            .unwrap()
+    }
+}
+
+// basically an easy single entry point to start all metrics related tasks
+pub struct MetricsController {
+    receiver: MetricsReceiver,
+    reporter: MetricsReporter,
+    sender: MetricsSender,
+    prometheus_exporter: Option<PrometheusExporter>,
+}
+
+impl MetricsController {
+    pub(crate) fn new(
+        directory_server: String,
+        pub_key_str: String,
+        sending_delay: Duration,
+        running_stats_logging_delay: Duration,
+        unwrapping_pool: SphinxUnwrappingPool,
+        forwarding_stats: ForwardingStats,
+        prometheus_metrics_address: Option<SocketAddr>,
+    ) -> Self {
+        let (metrics_tx, metrics_rx) = mpsc::unbounded();
+        let shared_metrics = MixMetrics::new();
+
+        let prometheus_exporter = prometheus_metrics_address.map(|address| {
+            PrometheusExporter::new(
+                address,
+                shared_metrics.clone(),
+                unwrapping_pool.clone(),
+                forwarding_stats,
+            )
+        });
+
+        MetricsController {
+            sender: MetricsSender::new(
+                shared_metrics.clone(),
+                directory_server,
+                pub_key_str,
+                sending_delay,
+                running_stats_logging_delay,
+                unwrapping_pool,
+            ),
+            receiver: MetricsReceiver::new(shared_metrics, metrics_rx),
+            reporter: MetricsReporter::new(metrics_tx),
+            prometheus_exporter,
+        }
+    }
+
+    // reporter is how node is going to be accessing the metrics data
+    pub(crate) fn start(self, handle: &Handle) -> MetricsReporter {
+        // TODO: should we do anything with JoinHandle(s) returned by start methods?
+        self.receiver.start(handle);
+        self.sender.start(handle);
+        if let Some(prometheus_exporter) = self.prometheus_exporter {
+            prometheus_exporter.start(handle);
+        }
+        self.reporter
+    }
+}
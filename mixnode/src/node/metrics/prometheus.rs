@@ -0,0 +1,389 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{MixMetrics, TotalMetrics};
+use crate::node::packet_processing::SphinxUnwrappingPool;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use packet_forwarder::{ForwardingStats, PeerForwardingStats};
+use processing_pool::PoolStats;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// upper bounds (in seconds) of the buckets of the packet delay histogram
+const DELAY_BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0, 60.0,
+];
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct DelayHistogram {
+    // note: unlike in the rendered output, those are not cumulative
+    bucket_counts: [u64; DELAY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl DelayHistogram {
+    pub(super) fn observe(&mut self, delay: Duration) {
+        let seconds = delay.as_secs_f64();
+        if let Some(bucket) = DELAY_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.bucket_counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+fn write_metric_header(output: &mut String, name: &str, help: &str, metric_type: &str) {
+    // writing to a String can't fail
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} {}", name, metric_type).unwrap();
+}
+
+fn render_metrics(
+    totals: &TotalMetrics,
+    unwrapping_pool: PoolStats,
+    peers: &[(SocketAddr, PeerForwardingStats)],
+) -> String {
+    let mut output = String::new();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_packets_received_total",
+        "Total number of received sphinx packets.",
+        "counter",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_packets_received_total {}",
+        totals.received
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_packets_forwarded_total",
+        "Total number of packets forwarded to the next hop.",
+        "counter",
+    );
+    for (destination, count) in totals.sent.iter() {
+        writeln!(
+            output,
+            "nym_mixnode_packets_forwarded_total{{destination=\"{}\"}} {}",
+            destination, count
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_packets_dropped_total",
+        "Total number of dropped packets.",
+        "counter",
+    );
+    for (reason, count) in totals.dropped.iter() {
+        writeln!(
+            output,
+            "nym_mixnode_packets_dropped_total{{reason=\"{}\"}} {}",
+            reason.as_str(),
+            count
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_sphinx_processing_errors_total",
+        "Total number of packets that failed to get processed.",
+        "counter",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_sphinx_processing_errors_total {}",
+        totals.processing_errors
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_clamped_delays_total",
+        "Total number of packets with delays clamped to the maximum.",
+        "counter",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_clamped_delays_total {}",
+        totals.clamped_delays
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_in_flight_packets",
+        "Number of packets currently held by the node.",
+        "gauge",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_in_flight_packets {}",
+        totals.in_flight.packets
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_in_flight_bytes",
+        "Total size of packets currently held by the node.",
+        "gauge",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_in_flight_bytes {}",
+        totals.in_flight.bytes
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_packet_delay_seconds",
+        "Delays applied to the forwarded packets.",
+        "histogram",
+    );
+    let mut cumulative_count = 0;
+    for (bound, count) in DELAY_BUCKETS.iter().zip(totals.delays.bucket_counts.iter()) {
+        cumulative_count += count;
+        writeln!(
+            output,
+            "nym_mixnode_packet_delay_seconds_bucket{{le=\"{}\"}} {}",
+            bound, cumulative_count
+        )
+        .unwrap();
+    }
+    writeln!(
+        output,
+        "nym_mixnode_packet_delay_seconds_bucket{{le=\"+Inf\"}} {}",
+        totals.delays.count
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "nym_mixnode_packet_delay_seconds_sum {}",
+        totals.delays.sum
+    )
+    .unwrap();
+    writeln!(
+        output,
+        "nym_mixnode_packet_delay_seconds_count {}",
+        totals.delays.count
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_processing_queue_depth",
+        "Number of packets waiting to get unwrapped.",
+        "gauge",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_processing_queue_depth {}",
+        unwrapping_pool.queue_depth
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_processing_pool_processed_total",
+        "Total number of packets unwrapped by the processing pool.",
+        "counter",
+    );
+    writeln!(
+        output,
+        "nym_mixnode_processing_pool_processed_total {}",
+        unwrapping_pool.processed
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_forwarding_queue_depth",
+        "Number of packets waiting to get forwarded to particular peer.",
+        "gauge",
+    );
+    for (peer, stats) in peers {
+        writeln!(
+            output,
+            "nym_mixnode_forwarding_queue_depth{{peer=\"{}\"}} {}",
+            peer,
+            stats.queued_packets + stats.buffered_packets
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_forwarding_failures_total",
+        "Total number of failed attempts to forward a packet to particular peer.",
+        "counter",
+    );
+    for (peer, stats) in peers {
+        writeln!(
+            output,
+            "nym_mixnode_forwarding_failures_total{{peer=\"{}\"}} {}",
+            peer, stats.failed
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_forwarding_queue_drops_total",
+        "Total number of packets to particular peer dropped due to its queue being full.",
+        "counter",
+    );
+    for (peer, stats) in peers {
+        writeln!(
+            output,
+            "nym_mixnode_forwarding_queue_drops_total{{peer=\"{}\"}} {}",
+            peer, stats.dropped
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut output,
+        "nym_mixnode_connection_attempts_total",
+        "Total number of attempts to (re)establish connection to particular peer.",
+        "counter",
+    );
+    for (peer, stats) in peers {
+        writeln!(
+            output,
+            "nym_mixnode_connection_attempts_total{{peer=\"{}\"}} {}",
+            peer, stats.connection_attempts
+        )
+        .unwrap();
+    }
+
+    output
+}
+
+/// Optional HTTP endpoint exposing metrics of the mixnode in the Prometheus text format.
+pub(super) struct PrometheusExporter {
+    address: SocketAddr,
+    metrics: MixMetrics,
+    unwrapping_pool: SphinxUnwrappingPool,
+    forwarding_stats: ForwardingStats,
+}
+
+impl PrometheusExporter {
+    pub(super) fn new(
+        address: SocketAddr,
+        metrics: MixMetrics,
+        unwrapping_pool: SphinxUnwrappingPool,
+        forwarding_stats: ForwardingStats,
+    ) -> Self {
+        PrometheusExporter {
+            address,
+            metrics,
+            unwrapping_pool,
+            forwarding_stats,
+        }
+    }
+
+    async fn current_metrics(&self) -> String {
+        let totals = self.metrics.current_totals().await;
+        render_metrics(
+            &totals,
+            self.unwrapping_pool.stats(),
+            &self.forwarding_stats.snapshot(),
+        )
+    }
+
+    async fn handle_request(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+            let mut not_found = Response::new(Body::empty());
+            *not_found.status_mut() = StatusCode::NOT_FOUND;
+            return not_found;
+        }
+
+        let mut response = Response::new(Body::from(self.current_metrics().await));
+        // the header value is a valid static string
+        response
+            .headers_mut()
+            .insert(hyper::header::CONTENT_TYPE, CONTENT_TYPE.parse().unwrap());
+        response
+    }
+
+    pub(super) fn start(self, handle: &Handle) -> JoinHandle<()> {
+        let address = self.address;
+        let exporter = std::sync::Arc::new(self);
+        http_endpoint::start(
+            handle,
+            address,
+            "prometheus metrics endpoint",
+            move |request| {
+                let exporter = exporter.clone();
+                async move { exporter.handle_request(request).await }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod prometheus_rendering {
+    use super::*;
+
+    #[test]
+    fn delays_are_put_into_correct_buckets() {
+        let mut histogram = DelayHistogram::default();
+        histogram.observe(Duration::from_millis(1));
+        histogram.observe(Duration::from_millis(2));
+        histogram.observe(Duration::from_millis(200));
+        histogram.observe(Duration::from_secs(120));
+
+        assert_eq!(histogram.bucket_counts[0], 1);
+        assert_eq!(histogram.bucket_counts[1], 1);
+        assert_eq!(histogram.bucket_counts[5], 1);
+        // the last one exceeds all bounds so it's only included in the total count
+        assert_eq!(histogram.bucket_counts.iter().sum::<u64>(), 3);
+        assert_eq!(histogram.count, 4);
+    }
+
+    #[test]
+    fn histogram_buckets_are_rendered_cumulatively() {
+        let mut totals = TotalMetrics::default();
+        totals.delays.observe(Duration::from_millis(1));
+        totals.delays.observe(Duration::from_millis(200));
+        let pool_stats = PoolStats {
+            queue_depth: 0,
+            processed: 0,
+            dropped: 0,
+            average_queue_latency: Default::default(),
+            average_processing_latency: Default::default(),
+        };
+
+        let rendered = render_metrics(&totals, pool_stats, &[]);
+        assert!(rendered.contains("nym_mixnode_packet_delay_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(rendered.contains("nym_mixnode_packet_delay_seconds_bucket{le=\"0.1\"} 1\n"));
+        assert!(rendered.contains("nym_mixnode_packet_delay_seconds_bucket{le=\"0.25\"} 2\n"));
+        assert!(rendered.contains("nym_mixnode_packet_delay_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(rendered.contains("nym_mixnode_packet_delay_seconds_count 2\n"));
+    }
+}
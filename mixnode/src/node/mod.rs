@@ -12,92 +12,367 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::Config;
-use crate::node::packet_processing::PacketProcessor;
-use crypto::asymmetric::encryption;
+use crate::config::{Config, MixingStrategy};
+use crate::node::drain::{DrainSignal, DrainTrigger};
+use crate::node::in_flight::HeldPackets;
+use crate::node::layer_assignment::{LayerAssigner, SharedLayer};
+use crate::node::packet_processing::{
+    PacketProcessor, ProcessedPacketSender, SphinxUnwrappingPool,
+};
+use crypto::asymmetric::{encryption, identity};
+use directory_client::presence::mixnodes::MixNodeDeregistration;
 use directory_client::DirectoryClient;
 use futures::channel::mpsc;
+use layer_filter::{LayerFilter, LayerFilterRefresher, NodeRole};
+use link_encryption::{InitiatorConfig, PeerKeys, ResponderConfig};
 use log::*;
 use nymsphinx::SphinxPacket;
+use packet_forwarder::{ForwardingStats, KnownPeers, PacketForwarder, PeerKeysRefresher};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
 
+pub(crate) mod admin;
+mod delay_forwarding;
+mod drain;
+mod in_flight;
+mod layer_assignment;
 mod listener;
 mod metrics;
-mod packet_forwarding;
 pub(crate) mod packet_processing;
+mod pool_mixing;
 mod presence;
 
+pub(crate) use delay_forwarding::MAXIMUM_QUEUE_DELAY;
+
+// how often the drain progress is checked
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 // the MixNode will live for whole duration of this program
 pub struct MixNode {
     runtime: Runtime,
     config: Config,
     sphinx_keypair: encryption::KeyPair,
+    /// ed25519 keypair used to assert one's identity.
+    identity_keypair: Arc<identity::KeyPair>,
+    layer: SharedLayer,
 }
 
 impl MixNode {
-    pub fn new(config: Config, sphinx_keypair: encryption::KeyPair) -> Self {
+    pub fn new(
+        config: Config,
+        sphinx_keypair: encryption::KeyPair,
+        identity_keypair: identity::KeyPair,
+    ) -> Self {
         MixNode {
             runtime: Runtime::new().unwrap(),
+            layer: Arc::new(AtomicU64::new(config.get_layer())),
             config,
             sphinx_keypair,
+            identity_keypair: Arc::new(identity_keypair),
         }
     }
 
-    fn start_presence_notifier(&self) {
+    fn start_presence_notifier(&self, drain_signal: DrainSignal) {
         info!("Starting presence notifier...");
         let notifier_config = presence::NotifierConfig::new(
             self.config.get_location(),
             self.config.get_presence_directory_server(),
             self.config.get_announce_address(),
             self.sphinx_keypair.public_key().to_base58_string(),
-            self.config.get_layer(),
+            Arc::clone(&self.layer),
             self.config.get_presence_sending_delay(),
         );
-        presence::Notifier::new(notifier_config).start(self.runtime.handle());
+        presence::Notifier::new(notifier_config).start(self.runtime.handle(), drain_signal);
     }
 
-    fn start_metrics_reporter(&self) -> metrics::MetricsReporter {
+    fn start_metrics_reporter(
+        &self,
+        unwrapping_pool: SphinxUnwrappingPool,
+        forwarding_stats: ForwardingStats,
+    ) -> metrics::MetricsReporter {
         info!("Starting metrics reporter...");
         metrics::MetricsController::new(
             self.config.get_metrics_directory_server(),
             self.sphinx_keypair.public_key().to_base58_string(),
             self.config.get_metrics_sending_delay(),
             self.config.get_metrics_running_stats_logging_delay(),
+            unwrapping_pool,
+            forwarding_stats,
+            self.config.get_prometheus_metrics_address(),
         )
         .start(self.runtime.handle())
     }
 
+    fn start_sphinx_unwrapping_pool(&self) -> SphinxUnwrappingPool {
+        info!(
+            "Starting sphinx unwrapping pool with {} workers...",
+            self.config.get_packet_processing_workers()
+        );
+        // apart from the link encryption configs, this is the only location where our private key
+        // is going to be copied. It will be held in memory owned by `MixNode` and by the closure
+        // shared by the workers
+        packet_processing::new_unwrapping_pool(
+            self.sphinx_keypair.private_key().clone(),
+            self.config.get_packet_processing_workers(),
+            self.config.get_packet_processing_queue_capacity(),
+        )
+    }
+
     fn start_socket_listener(
         &self,
+        unwrapping_pool: SphinxUnwrappingPool,
         metrics_reporter: metrics::MetricsReporter,
-        forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+        processed_packets_channel: ProcessedPacketSender,
+        layer_filter: Option<LayerFilter>,
+        peer_keys: PeerKeys,
+        drain_signal: DrainSignal,
     ) {
         info!("Starting socket listener...");
-        // this is the only location where our private key is going to be copied
-        // it will be held in memory owned by `MixNode` and inside an Arc of `PacketProcessor`
-        let packet_processor =
-            PacketProcessor::new(self.sphinx_keypair.private_key().clone(), metrics_reporter);
+        let mut packet_processor = PacketProcessor::new(unwrapping_pool, metrics_reporter);
+        if let Some(layer_filter) = layer_filter {
+            packet_processor = packet_processor.with_layer_filter(layer_filter);
+        }
+        let link_config = if self.config.get_link_encryption() {
+            // the sphinx key doubles as the static key of the links as it's the only key of ours
+            // that other nodes know from the topology. It's safe as noise never uses it directly,
+            // only as an input to DH whose outputs are hashed together with the protocol name,
+            // which domain-separates them from the shared secrets derived during sphinx processing
+            Some(ResponderConfig::new(
+                &self.sphinx_keypair.private_key().to_bytes(),
+                peer_keys,
+                self.config.get_link_handshake_timeout(),
+            ))
+        } else {
+            None
+        };
 
         listener::run_socket_listener(
             self.runtime.handle(),
             self.config.get_listening_address(),
+            link_config,
             packet_processor,
+            processed_packets_channel,
+            drain_signal,
+        );
+    }
+
+    fn start_delay_forwarder(
+        &self,
+        metrics_reporter: metrics::MetricsReporter,
+        forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    ) -> (ProcessedPacketSender, HeldPackets) {
+        info!("Starting delay forwarder...");
+        let delay_forwarder_config = delay_forwarding::Config::new(
+            self.config.get_mixing_queue_capacity(),
+            self.config.get_maximum_packet_delay(),
+            self.config.get_excessive_delay_policy(),
+            self.config.get_maximum_in_flight_packets(),
+            self.config.get_maximum_in_flight_bytes(),
+        );
+        let delay_forwarder = self.runtime.enter(|| {
+            delay_forwarding::DelayForwarder::new(
+                delay_forwarder_config,
+                forwarding_channel,
+                metrics_reporter,
+            )
+        });
+        let held_packets = delay_forwarder.held_packets();
+        (delay_forwarder.start(self.runtime.handle()), held_packets)
+    }
+
+    fn start_pool_mixer(
+        &self,
+        metrics_reporter: metrics::MetricsReporter,
+        forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+        drain_signal: DrainSignal,
+    ) -> (ProcessedPacketSender, HeldPackets) {
+        info!("Starting pool mixer...");
+        let pool_mixer_config = pool_mixing::Config::new(
+            self.config.get_mixing_queue_capacity(),
+            self.config.get_pool_mixing_round_duration(),
+            self.config.get_pool_mixing_flush_threshold(),
+            self.config.get_pool_mixing_minimum_pool_size(),
+            self.config.get_pool_mixing_flush_fraction(),
+            self.config.get_maximum_in_flight_packets(),
+            self.config.get_maximum_in_flight_bytes(),
+        );
+        let pool_mixer = pool_mixing::PoolMixer::new(
+            pool_mixer_config,
             forwarding_channel,
+            metrics_reporter,
+            drain_signal,
         );
+        let held_packets = pool_mixer.held_packets();
+        (pool_mixer.start(self.runtime.handle()), held_packets)
+    }
+
+    fn start_admin_endpoint(&self, drain_trigger: DrainTrigger) {
+        if let Some(admin_address) = self.config.get_admin_address() {
+            info!("Starting admin endpoint...");
+            let token_file = self.config.get_admin_token_file();
+            let token = match http_endpoint::token::load_or_create_token(&token_file) {
+                Ok(token) => token,
+                Err(err) => panic!(
+                    "Failed to load the admin token from {:?} - {}",
+                    token_file, err
+                ),
+            };
+            admin::AdminEndpoint::new(admin_address, token, drain_trigger)
+                .start(self.runtime.handle());
+        }
     }
 
-    fn start_packet_forwarder(&mut self) -> mpsc::UnboundedSender<(SocketAddr, SphinxPacket)> {
+    // waits until all received packets got unwrapped and forwarded or the drain timeout has passed
+    async fn wait_for_drain(
+        drain_timeout: Duration,
+        unwrapping_pool: SphinxUnwrappingPool,
+        held_packets: HeldPackets,
+        forwarding_stats: ForwardingStats,
+    ) {
+        // packets move from one stage to the next, so the stages are checked in the same order
+        let pending_packets = || {
+            unwrapping_pool.pending()
+                + held_packets.load(Ordering::SeqCst)
+                + forwarding_stats.pending_packets()
+        };
+
+        let drained = async {
+            while pending_packets() > 0 {
+                tokio::time::delay_for(DRAIN_POLL_INTERVAL).await;
+            }
+        };
+
+        match tokio::time::timeout(drain_timeout, drained).await {
+            Ok(_) => info!("All held packets got forwarded"),
+            Err(_) => warn!(
+                "The drain has timed out - {} packets were not forwarded",
+                pending_packets()
+            ),
+        }
+    }
+
+    // tells the validator to remove the mixnode from the topology and free its layer,
+    // so that clients stop sending new packets through it while it is being drained
+    fn deregister(&self) {
+        // if the clock is somehow set before the unix epoch, the validator will reject the request
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let pub_key = self.sphinx_keypair.public_key().to_base58_string();
+        let message = MixNodeDeregistration::signed_message(&pub_key, timestamp);
+        let signature = self.identity_keypair.private_key().sign(&message);
+        let deregistration = MixNodeDeregistration {
+            pub_key,
+            identity_key: self.identity_keypair.public_key().to_base58_string(),
+            timestamp,
+            signature: bs58::encode(&signature.to_bytes()[..]).into_string(),
+        };
+
+        let validator_client = directory_client::Client::new(directory_client::Config::new(
+            self.config.get_validator_server(),
+        ));
+        match self
+            .runtime
+            .block_on(validator_client.post_mixnode_deregistration(deregistration))
+        {
+            Ok(_) => info!("Deregistered the mixnode from the validator"),
+            Err(err) => warn!(
+                "Failed to deregister the mixnode from the validator - {}. It might keep receiving packets until its presence goes stale",
+                err
+            ),
+        }
+    }
+
+    fn start_layer_filter(&self) -> LayerFilter {
+        info!("Starting layer filter refresher...");
+        let layer_filter = LayerFilter::new(NodeRole::Mix {
+            layer: self.layer.load(Ordering::SeqCst),
+        });
+        LayerFilterRefresher::new(
+            self.config.get_presence_directory_server(),
+            layer_filter.clone(),
+            self.config.get_layer_filtering_refresh_rate(),
+        )
+        .start(self.runtime.handle());
+        layer_filter
+    }
+
+    fn new_layer_assigner(&self) -> LayerAssigner {
+        LayerAssigner::new(
+            self.config.get_validator_server(),
+            self.sphinx_keypair.public_key().to_base58_string(),
+            Arc::clone(&self.identity_keypair),
+            self.config.get_layer_assignment_epoch(),
+            Arc::clone(&self.layer),
+        )
+    }
+
+    fn start_layer_assigner(
+        &self,
+        layer_assigner: LayerAssigner,
+        layer_filter: Option<LayerFilter>,
+    ) {
+        info!("Starting layer assigner...");
+        let layer_assigner = match layer_filter {
+            Some(layer_filter) => layer_assigner.with_layer_filter(layer_filter),
+            None => layer_assigner,
+        };
+        layer_assigner.start(self.runtime.handle());
+    }
+
+    fn start_peer_keys_refresher(&self) -> (PeerKeys, KnownPeers) {
+        info!("Starting peer keys refresher...");
+        let peer_keys = PeerKeys::default();
+        let known_peers = KnownPeers::default();
+        PeerKeysRefresher::new(
+            self.config.get_presence_directory_server(),
+            peer_keys.clone(),
+            known_peers.clone(),
+            self.config.get_link_keys_refresh_rate(),
+        )
+        .start(self.runtime.handle());
+        (peer_keys, known_peers)
+    }
+
+    // returns the channel for forwarding packets alongside the handle to the statistics
+    // of forwarding to particular peers
+    fn start_packet_forwarder(
+        &self,
+        peer_keys: PeerKeys,
+        known_peers: KnownPeers,
+    ) -> (
+        mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+        ForwardingStats,
+    ) {
+        let mut forwarder_config = packet_forwarder::Config::new(
+            self.config.get_packet_forwarding_initial_backoff(),
+            self.config.get_packet_forwarding_maximum_backoff(),
+            self.config.get_initial_connection_timeout(),
+            self.config.get_packet_forwarding_queue_length(),
+            self.config.get_packet_forwarding_drop_policy(),
+        )
+        .with_frame_version(self.config.get_frame_version())
+        .with_idle_peer_timeout(self.config.get_packet_forwarding_idle_peer_timeout())
+        .with_known_peers(known_peers);
+        if self.config.get_link_encryption() {
+            let link_config = InitiatorConfig::new(
+                &self.sphinx_keypair.private_key().to_bytes(),
+                peer_keys,
+                self.config.get_link_handshake_timeout(),
+            )
+            .with_plaintext_fallback(self.config.get_link_encryption_plaintext_fallback());
+            forwarder_config = forwarder_config.with_link_encryption(link_config);
+        }
+
         info!("Starting packet forwarder...");
-        self.runtime
-            .enter(|| {
-                packet_forwarding::PacketForwarder::new(
-                    self.config.get_packet_forwarding_initial_backoff(),
-                    self.config.get_packet_forwarding_maximum_backoff(),
-                    self.config.get_initial_connection_timeout(),
-                )
-            })
-            .start(self.runtime.handle())
+        let packet_forwarder = PacketForwarder::new(forwarder_config);
+        let forwarding_stats = packet_forwarder.stats();
+        let (_, forwarding_channel) = packet_forwarder.start(self.runtime.handle());
+        (forwarding_channel, forwarding_stats)
     }
 
     fn check_if_same_ip_node_exists(&mut self) -> Option<String> {
@@ -125,22 +400,99 @@ impl MixNode {
             );
             return;
         }
-        let forwarding_channel = self.start_packet_forwarder();
-        let metrics_reporter = self.start_metrics_reporter();
-        self.start_socket_listener(metrics_reporter, forwarding_channel);
-        self.start_presence_notifier();
+
+        // the layer has to be known before starting anything that depends on it
+        let layer_assigner = if self.config.get_automatic_layer_assignment() {
+            let layer_assigner = self.new_layer_assigner();
+            match self.runtime.block_on(layer_assigner.assign()) {
+                Some(layer) => info!("Obtained layer assignment - layer {}", layer),
+                // the assignment is going to be retried at the start of the next epoch
+                None => warn!(
+                    "Could not obtain the layer assignment from the validator - falling back to the configured layer {}",
+                    self.config.get_layer()
+                ),
+            }
+            Some(layer_assigner)
+        } else {
+            None
+        };
+        let layer_filter = if self.config.get_layer_filtering() {
+            Some(self.start_layer_filter())
+        } else {
+            None
+        };
+
+        let (drain_trigger, drain_signal) = drain::drain_channel();
+        let (peer_keys, known_peers) = self.start_peer_keys_refresher();
+        let (forwarding_channel, forwarding_stats) =
+            self.start_packet_forwarder(peer_keys.clone(), known_peers);
+        let unwrapping_pool = self.start_sphinx_unwrapping_pool();
+        let metrics_reporter =
+            self.start_metrics_reporter(unwrapping_pool.clone(), forwarding_stats.clone());
+        let (processed_packets_channel, held_packets) = match self.config.get_mixing_strategy() {
+            MixingStrategy::Continuous => {
+                self.start_delay_forwarder(metrics_reporter.clone(), forwarding_channel)
+            }
+            MixingStrategy::Pool => self.start_pool_mixer(
+                metrics_reporter.clone(),
+                forwarding_channel,
+                drain_signal.clone(),
+            ),
+        };
+        self.start_socket_listener(
+            unwrapping_pool.clone(),
+            metrics_reporter,
+            processed_packets_channel,
+            layer_filter.clone(),
+            peer_keys,
+            drain_signal.clone(),
+        );
+        self.start_presence_notifier(drain_signal.clone());
+        if let Some(layer_assigner) = layer_assigner {
+            self.start_layer_assigner(layer_assigner, layer_filter);
+        }
+        self.start_admin_endpoint(drain_trigger);
 
         info!("Finished nym mixnode startup procedure - it should now be able to receive mix traffic!");
 
-        if let Err(e) = self.runtime.block_on(tokio::signal::ctrl_c()) {
-            error!(
-                "There was an error while capturing SIGINT - {:?}. We will terminate regardless",
-                e
+        let mut drain_signal = drain_signal;
+        let drain_requested = self.runtime.block_on(async {
+            tokio::select! {
+                res = tokio::signal::ctrl_c() => {
+                    if let Err(e) = res {
+                        error!(
+                            "There was an error while capturing SIGINT - {:?}. We will terminate regardless",
+                            e
+                        );
+                    }
+                    false
+                }
+                _ = drain_signal.draining() => true,
+            }
+        });
+
+        if !drain_requested {
+            println!(
+                "Received SIGINT - the mixnode will terminate now (threads are not YET nicely stopped)"
             );
+            return;
         }
 
-        println!(
-            "Received SIGINT - the mixnode will terminate now (threads are not YET nicely stopped)"
+        self.deregister();
+        info!("Draining the mixnode - waiting for all held packets to get forwarded...");
+        let drain = Self::wait_for_drain(
+            self.config.get_drain_timeout(),
+            unwrapping_pool,
+            held_packets,
+            forwarding_stats,
         );
+        self.runtime.block_on(async {
+            tokio::select! {
+                _ = drain => (),
+                _ = tokio::signal::ctrl_c() => warn!("Received SIGINT - the drain is going to be cut short"),
+            }
+        });
+
+        println!("The mixnode has been drained and will terminate now");
     }
 }
@@ -14,24 +14,47 @@
 
 use crate::node::metrics;
 use crypto::asymmetric::encryption;
+use futures::channel::mpsc;
+use layer_filter::LayerFilter;
 use log::*;
 use nymsphinx::addressing::nodes::{NymNodeRoutingAddress, NymNodeRoutingAddressError};
 use nymsphinx::{
     Delay as SphinxDelay, Error as SphinxError, NodeAddressBytes, ProcessedPacket, SphinxPacket,
 };
+use processing_pool::{ProcessingPool, ProcessingPoolError};
 use std::convert::TryFrom;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+
+// processed packets alongside their next hops and delays as specified in their headers.
+// The channel is bounded so that packets can't pile up before reaching the in-flight limits
+pub(crate) type ProcessedPacketSender = mpsc::Sender<(SocketAddr, SphinxPacket, SphinxDelay)>;
+pub(crate) type ProcessedPacketReceiver = mpsc::Receiver<(SocketAddr, SphinxPacket, SphinxDelay)>;
+
+// pool of workers performing the actual sphinx unwrapping, i.e. the CPU heavy part of processing
+pub(crate) type SphinxUnwrappingPool =
+    ProcessingPool<SphinxPacket, Result<ProcessedPacket, SphinxError>>;
+
+pub(crate) fn new_unwrapping_pool(
+    secret_key: encryption::PrivateKey,
+    num_workers: usize,
+    queue_capacity: usize,
+) -> SphinxUnwrappingPool {
+    ProcessingPool::new(num_workers, queue_capacity, move |packet: SphinxPacket| {
+        packet.process(&(&secret_key).into())
+    })
+}
 
 #[derive(Debug)]
 pub enum MixProcessingError {
     ReceivedFinalHopError,
     SphinxProcessingError(SphinxError),
     InvalidHopAddress,
+    DisallowedNextHop(SocketAddr),
+    UnwrappingPoolError(ProcessingPoolError),
 }
 
 pub enum MixProcessingResult {
-    ForwardHop(SocketAddr, SphinxPacket),
+    ForwardHop(SocketAddr, SphinxPacket, SphinxDelay),
     #[allow(dead_code)]
     LoopMessage,
 }
@@ -45,6 +68,14 @@ impl From<SphinxError> for MixProcessingError {
     }
 }
 
+impl From<ProcessingPoolError> for MixProcessingError {
+    fn from(err: ProcessingPoolError) -> Self {
+        use MixProcessingError::*;
+
+        UnwrappingPoolError(err)
+    }
+}
+
 impl From<NymNodeRoutingAddressError> for MixProcessingError {
     fn from(_: NymNodeRoutingAddressError) -> Self {
         use MixProcessingError::*;
@@ -56,37 +87,70 @@ impl From<NymNodeRoutingAddressError> for MixProcessingError {
 // PacketProcessor contains all data required to correctly unwrap and forward sphinx packets
 #[derive(Clone)]
 pub struct PacketProcessor {
-    secret_key: Arc<encryption::PrivateKey>,
+    unwrapping_pool: SphinxUnwrappingPool,
     metrics_reporter: metrics::MetricsReporter,
+    layer_filter: Option<LayerFilter>,
 }
 
 impl PacketProcessor {
     pub(crate) fn new(
-        secret_key: encryption::PrivateKey,
+        unwrapping_pool: SphinxUnwrappingPool,
         metrics_reporter: metrics::MetricsReporter,
     ) -> Self {
         PacketProcessor {
-            secret_key: Arc::new(secret_key),
+            unwrapping_pool,
             metrics_reporter,
+            layer_filter: None,
         }
     }
 
-    pub(crate) fn report_sent(&self, addr: SocketAddr) {
-        self.metrics_reporter.report_sent(addr.to_string())
+    /// Restricts peers we accept packets from and forward packets to according to our layer.
+    pub(crate) fn with_layer_filter(mut self, layer_filter: LayerFilter) -> Self {
+        self.layer_filter = Some(layer_filter);
+        self
     }
 
-    async fn process_forward_hop(
+    /// Checks whether packets received from the given peer should be processed at all.
+    /// The remote static key has to be provided if the link with the peer is authenticated.
+    pub(crate) fn accepts_packets_from(&self, peer: IpAddr, static_key: Option<&[u8]>) -> bool {
+        match &self.layer_filter {
+            Some(layer_filter) if !layer_filter.allows_ingress(peer, static_key) => {
+                self.metrics_reporter
+                    .report_dropped(metrics::DropReason::IngressFilter);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Reports an unwrapped packet that got dropped as the mixing queue was full.
+    pub(crate) fn report_mixing_queue_full(&self) {
+        self.metrics_reporter
+            .report_dropped(metrics::DropReason::MixingQueueFull);
+    }
+
+    // note that it's up to the caller to decide whether the packet should be delayed
+    // for the duration specified in its header. It depends on the mixing strategy used.
+    fn process_forward_hop(
         &self,
         packet: SphinxPacket,
         forward_address: NodeAddressBytes,
         delay: SphinxDelay,
     ) -> Result<MixProcessingResult, MixProcessingError> {
         let next_hop_address: SocketAddr = NymNodeRoutingAddress::try_from(forward_address)?.into();
+        if let Some(layer_filter) = &self.layer_filter {
+            if !layer_filter.allows_egress(next_hop_address.ip()) {
+                self.metrics_reporter
+                    .report_dropped(metrics::DropReason::EgressFilter);
+                return Err(MixProcessingError::DisallowedNextHop(next_hop_address));
+            }
+        }
 
-        // Delay packet for as long as required
-        tokio::time::delay_for(delay.to_duration()).await;
-
-        Ok(MixProcessingResult::ForwardHop(next_hop_address, packet))
+        Ok(MixProcessingResult::ForwardHop(
+            next_hop_address,
+            packet,
+            delay,
+        ))
     }
 
     pub(crate) async fn process_sphinx_packet(
@@ -95,9 +159,23 @@ impl PacketProcessor {
     ) -> Result<MixProcessingResult, MixProcessingError> {
         // we received something resembling a sphinx packet, report it!
         self.metrics_reporter.report_received();
-        match packet.process(&self.secret_key.as_ref().into()) {
+        let unwrapped_packet = match self.unwrapping_pool.process(packet).await {
+            Ok(unwrapped_packet) => unwrapped_packet,
+            Err(ProcessingPoolError::QueueFull) => {
+                debug!("Dropping packet - the sphinx unwrapping queue is full");
+                self.metrics_reporter
+                    .report_dropped(metrics::DropReason::ProcessingQueueFull);
+                return Err(ProcessingPoolError::QueueFull.into());
+            }
+            Err(err) => {
+                self.metrics_reporter.report_processing_error();
+                return Err(err.into());
+            }
+        };
+
+        match unwrapped_packet {
             Ok(ProcessedPacket::ProcessedPacketForwardHop(packet, address, delay)) => {
-                self.process_forward_hop(packet, address, delay).await
+                self.process_forward_hop(packet, address, delay)
             }
             Ok(ProcessedPacket::ProcessedPacketFinalHop(_, _, _)) => {
                 warn!("Received a loop cover message that we haven't implemented yet!");
@@ -105,6 +183,7 @@ impl PacketProcessor {
             }
             Err(e) => {
                 warn!("Failed to unwrap Sphinx packet: {:?}", e);
+                self.metrics_reporter.report_processing_error();
                 Err(MixProcessingError::SphinxProcessingError(e))
             }
         }
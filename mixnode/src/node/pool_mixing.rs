@@ -0,0 +1,220 @@
+// Copyright 2020 Nym Technologies SA
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::node::drain::DrainSignal;
+use crate::node::in_flight::{HeldPackets, InFlightLimiter};
+use crate::node::metrics;
+use crate::node::packet_processing::ProcessedPacketSender;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use log::*;
+use nymsphinx::SphinxPacket;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+pub(crate) struct Config {
+    queue_capacity: usize,
+    round_duration: Duration,
+    flush_threshold: Option<usize>,
+    minimum_pool_size: usize,
+    flush_fraction: f64,
+    maximum_in_flight_packets: usize,
+    maximum_in_flight_bytes: usize,
+}
+
+impl Config {
+    pub(crate) fn new(
+        queue_capacity: usize,
+        round_duration: Duration,
+        flush_threshold: Option<usize>,
+        minimum_pool_size: usize,
+        flush_fraction: f64,
+        maximum_in_flight_packets: usize,
+        maximum_in_flight_bytes: usize,
+    ) -> Self {
+        Config {
+            queue_capacity,
+            round_duration,
+            flush_threshold,
+            minimum_pool_size,
+            flush_fraction,
+            maximum_in_flight_packets,
+            maximum_in_flight_bytes,
+        }
+    }
+}
+
+// Determines how many packets should be sent out of a pool of given size. All packets exceeding
+// the minimum pool size are eligible, but only the specified fraction of them gets sent.
+fn number_of_packets_to_flush(
+    pool_size: usize,
+    minimum_pool_size: usize,
+    flush_fraction: f64,
+) -> usize {
+    if pool_size <= minimum_pool_size {
+        return 0;
+    }
+    let eligible = pool_size - minimum_pool_size;
+    let to_flush = (eligible as f64 * flush_fraction).ceil() as usize;
+
+    // make sure that bad fraction value can't make us send more than what is eligible
+    std::cmp::min(to_flush, eligible)
+}
+
+/// Timed pool mix with an optional threshold trigger. All received packets are put into a pool
+/// and at the end of each round (or as soon as the threshold is reached) a random subset of them
+/// is sent to the packet forwarder, while at least `minimum_pool_size` packets are kept back.
+/// Delays specified in the packet headers are ignored.
+pub(crate) struct PoolMixer {
+    config: Config,
+    pool: Vec<(SocketAddr, SphinxPacket)>,
+    in_flight_limiter: InFlightLimiter,
+    forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+    metrics_reporter: metrics::MetricsReporter,
+    drain_signal: DrainSignal,
+    rng: OsRng,
+}
+
+impl PoolMixer {
+    pub(crate) fn new(
+        config: Config,
+        forwarding_channel: mpsc::UnboundedSender<(SocketAddr, SphinxPacket)>,
+        metrics_reporter: metrics::MetricsReporter,
+        drain_signal: DrainSignal,
+    ) -> Self {
+        PoolMixer {
+            in_flight_limiter: InFlightLimiter::new(
+                config.maximum_in_flight_packets,
+                config.maximum_in_flight_bytes,
+            ),
+            config,
+            pool: Vec::new(),
+            forwarding_channel,
+            metrics_reporter,
+            drain_signal,
+            rng: OsRng,
+        }
+    }
+
+    pub(crate) fn held_packets(&self) -> HeldPackets {
+        self.in_flight_limiter.held_packets()
+    }
+
+    fn should_flush_early(&self) -> bool {
+        match self.config.flush_threshold {
+            Some(threshold) => self.pool.len() >= threshold,
+            None => false,
+        }
+    }
+
+    fn flush(&mut self) {
+        // when draining, nothing is kept back so that the pool could get emptied
+        let to_flush = if self.drain_signal.is_draining() {
+            self.pool.len()
+        } else {
+            number_of_packets_to_flush(
+                self.pool.len(),
+                self.config.minimum_pool_size,
+                self.config.flush_fraction,
+            )
+        };
+
+        if to_flush > 0 {
+            self.pool.shuffle(&mut self.rng);
+            let retained = self.pool.len() - to_flush;
+            for (address, packet) in self.pool.drain(retained..) {
+                self.in_flight_limiter.release(packet.len());
+                // in unbounded_send() failed it means that the receiver channel was disconnected
+                // and hence something weird must have happened without a way of recovering
+                self.forwarding_channel
+                    .unbounded_send((address, packet))
+                    .unwrap();
+                self.metrics_reporter.report_sent(address.to_string());
+            }
+            trace!("flushed {} packets out of the pool", to_flush);
+        }
+
+        self.metrics_reporter.report_pool_size(self.pool.len());
+        self.metrics_reporter.report_in_flight(
+            self.in_flight_limiter.packets(),
+            self.in_flight_limiter.bytes(),
+        );
+    }
+
+    fn add_to_pool(&mut self, address: SocketAddr, packet: SphinxPacket) {
+        if let Err(reason) = self.in_flight_limiter.try_admit(packet.len()) {
+            debug!(
+                "Dropping packet to {} - the limit of packets in flight has been reached ({})",
+                address,
+                reason.as_str()
+            );
+            self.metrics_reporter.report_dropped(reason);
+            return;
+        }
+
+        self.pool.push((address, packet));
+        if self.should_flush_early() {
+            self.flush()
+        }
+    }
+
+    // returns channel to which the processed packets should be sent to. The mixer is not holding
+    // any sender itself, so it stops once all the packet producers are gone.
+    pub(crate) fn start(mut self, handle: &Handle) -> ProcessedPacketSender {
+        let (mixing_tx, mut mixing_rx) = mpsc::channel(self.config.queue_capacity);
+        handle.spawn(async move {
+            let mut round_timer = tokio::time::interval(self.config.round_duration);
+            loop {
+                tokio::select! {
+                    _ = round_timer.tick() => self.flush(),
+                    processed_packet = mixing_rx.next() => match processed_packet {
+                        Some((address, packet, _)) => self.add_to_pool(address, packet),
+                        None => {
+                            warn!("Pool mixer's channel got closed - no more packets are going to be mixed");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        mixing_tx
+    }
+}
+
+#[cfg(test)]
+mod pool_flushing {
+    use super::*;
+
+    #[test]
+    fn nothing_is_flushed_if_pool_is_not_larger_than_minimum() {
+        assert_eq!(0, number_of_packets_to_flush(0, 10, 0.7));
+        assert_eq!(0, number_of_packets_to_flush(5, 10, 0.7));
+        assert_eq!(0, number_of_packets_to_flush(10, 10, 1.0));
+    }
+
+    #[test]
+    fn fraction_of_packets_above_minimum_is_flushed() {
+        assert_eq!(7, number_of_packets_to_flush(20, 10, 0.7));
+        assert_eq!(1, number_of_packets_to_flush(11, 10, 0.5));
+        assert_eq!(10, number_of_packets_to_flush(10, 0, 1.0));
+    }
+
+    #[test]
+    fn more_than_eligible_packets_are_never_flushed() {
+        assert_eq!(10, number_of_packets_to_flush(20, 10, 1.5));
+    }
+}
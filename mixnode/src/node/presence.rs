@@ -13,9 +13,12 @@
 // limitations under the License.
 
 use crate::built_info;
+use crate::node::drain::DrainSignal;
+use crate::node::layer_assignment::SharedLayer;
 use directory_client::presence::mixnodes::MixNodePresence;
 use directory_client::DirectoryClient;
-use log::{error, trace};
+use log::{error, info, trace};
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use tokio::runtime::Handle;
 use tokio::task::JoinHandle;
@@ -25,7 +28,7 @@ pub(crate) struct NotifierConfig {
     directory_server: String,
     announce_host: String,
     pub_key_string: String,
-    layer: u64,
+    layer: SharedLayer,
     sending_delay: Duration,
 }
 
@@ -35,7 +38,7 @@ impl NotifierConfig {
         directory_server: String,
         announce_host: String,
         pub_key_string: String,
-        layer: u64,
+        layer: SharedLayer,
         sending_delay: Duration,
     ) -> Self {
         NotifierConfig {
@@ -52,6 +55,7 @@ impl NotifierConfig {
 pub(crate) struct Notifier {
     net_client: directory_client::Client,
     presence: MixNodePresence,
+    layer: SharedLayer,
     sending_delay: Duration,
 }
 
@@ -65,37 +69,43 @@ impl Notifier {
             location: config.location,
             host: config.announce_host,
             pub_key: config.pub_key_string,
-            layer: config.layer,
+            layer: config.layer.load(Ordering::SeqCst),
             last_seen: 0,
             version: built_info::PKG_VERSION.to_string(),
         };
         Notifier {
             net_client,
             presence,
+            layer: config.layer,
             sending_delay: config.sending_delay,
         }
     }
 
     async fn notify(&self) {
-        match self
-            .net_client
-            .post_mixnode_presence(self.presence.clone())
-            .await
-        {
+        // the layer might have been reassigned since the last announcement
+        let mut presence = self.presence.clone();
+        presence.layer = self.layer.load(Ordering::SeqCst);
+        match self.net_client.post_mixnode_presence(presence).await {
             Err(err) => error!("failed to send presence - {:?}", err),
             Ok(_) => trace!("sent presence information"),
         }
     }
 
-    pub fn start(self, handle: &Handle) -> JoinHandle<()> {
+    /// Once the node starts draining, it stops announcing its presence, so that the directory
+    /// server could remove it from the topology and no new traffic got routed through it.
+    pub fn start(self, handle: &Handle, mut drain_signal: DrainSignal) -> JoinHandle<()> {
         handle.spawn(async move {
-            loop {
+            while !drain_signal.is_draining() {
                 // set the deadline in the future
                 let sending_delay = tokio::time::delay_for(self.sending_delay);
                 self.notify().await;
                 // wait for however much is left
-                sending_delay.await;
+                tokio::select! {
+                    _ = sending_delay => (),
+                    _ = drain_signal.draining() => (),
+                }
             }
+            info!("The node is draining - no longer announcing its presence");
         })
     }
 }
@@ -13,6 +13,7 @@
 // limitations under the License.
 use crate::services::mixmining;
 use iron::prelude::*;
+use presence::layer;
 use presence::mixnode;
 use presence::topology;
 use router::Router;
@@ -55,7 +56,10 @@ impl Api {
         let capacity_get = capacity::Get::new(Arc::clone(&self.mixmining_service));
         let presence_mixnode_create =
             mixnode::CreatePresence::new(Arc::clone(&self.mixmining_service));
+        let presence_mixnode_remove =
+            mixnode::RemovePresence::new(Arc::clone(&self.mixmining_service));
         let topology_get = topology::GetTopology::new(Arc::clone(&self.mixmining_service));
+        let presence_mixnode_layer = layer::AssignLayer::new(Arc::clone(&self.mixmining_service));
 
         // tie routes to handlers
         router.get("/capacity", capacity_get, "capacity_get");
@@ -66,6 +70,16 @@ impl Api {
             presence_mixnode_create,
             "presence_mixnodes_post",
         );
+        router.post(
+            "/presence/mixnodes/layer",
+            presence_mixnode_layer,
+            "presence_mixnodes_layer_post",
+        );
+        router.post(
+            "/presence/mixnodes/deregister",
+            presence_mixnode_remove,
+            "presence_mixnodes_deregister_post",
+        );
 
         router
     }
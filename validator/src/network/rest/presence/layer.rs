@@ -0,0 +1,64 @@
+use super::*;
+use bodyparser::Struct;
+use iron::mime::Mime;
+use iron::status;
+use iron::Handler;
+use models::{LayerAssignment, LayerAssignmentRequest, Timestamp};
+use signature::verify_signed_request;
+
+pub struct AssignLayer {
+    service: Arc<Mutex<mixmining::Service>>,
+}
+
+impl AssignLayer {
+    pub fn new(service: Arc<Mutex<mixmining::Service>>) -> AssignLayer {
+        AssignLayer { service }
+    }
+}
+
+impl Handler for AssignLayer {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let json_parse = req.get::<Struct<LayerAssignmentRequest>>();
+
+        if json_parse.is_ok() {
+            let request = json_parse
+                .unwrap()
+                .expect("Unexpected JSON parsing problem");
+            let now = Timestamp::default().into();
+            let message =
+                LayerAssignmentRequest::signed_message(&request.pub_key, request.timestamp);
+            if let Err(error) = verify_signed_request(
+                &request.identity_key,
+                &request.signature,
+                request.timestamp,
+                &message,
+                now,
+            ) {
+                return Ok(Response::with((status::Unauthorized, error)));
+            }
+            let layer = match self.service.lock().unwrap().assign_layer(
+                &request.pub_key,
+                &request.identity_key,
+                now,
+            ) {
+                Some(layer) => layer,
+                None => {
+                    return Ok(Response::with((
+                        status::Forbidden,
+                        "the key is assigned to a mixnode with a different identity",
+                    )))
+                }
+            };
+            let content_type = "application/json".parse::<Mime>().unwrap();
+            let json = serde_json::to_string(&LayerAssignment {
+                pub_key: request.pub_key,
+                layer,
+            })
+            .unwrap();
+            Ok(Response::with((content_type, status::Ok, json)))
+        } else {
+            let error = json_parse.unwrap_err();
+            Ok(Response::with((status::BadRequest, error.detail)))
+        }
+    }
+}
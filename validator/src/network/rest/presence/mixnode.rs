@@ -4,7 +4,8 @@ use crate::services::mixmining::models::Mixnode as ServiceMixnode;
 use bodyparser::Struct;
 use iron::status;
 use iron::Handler;
-use models::Timestamp;
+use models::{MixNodeDeregistration, Timestamp};
+use signature::verify_signed_request;
 
 pub struct CreatePresence {
     service: Arc<Mutex<mixmining::Service>>,
@@ -38,3 +39,54 @@ impl Handler for CreatePresence {
         }
     }
 }
+
+pub struct RemovePresence {
+    service: Arc<Mutex<mixmining::Service>>,
+}
+
+impl RemovePresence {
+    pub fn new(service: Arc<Mutex<mixmining::Service>>) -> RemovePresence {
+        RemovePresence { service }
+    }
+}
+
+impl Handler for RemovePresence {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let json_parse = req.get::<Struct<MixNodeDeregistration>>();
+
+        if json_parse.is_ok() {
+            let deregistration = json_parse
+                .unwrap()
+                .expect("Unexpected JSON parsing problem");
+            let message = MixNodeDeregistration::signed_message(
+                &deregistration.pub_key,
+                deregistration.timestamp,
+            );
+            if let Err(error) = verify_signed_request(
+                &deregistration.identity_key,
+                &deregistration.signature,
+                deregistration.timestamp,
+                &message,
+                Timestamp::default().into(),
+            ) {
+                return Ok(Response::with((status::Unauthorized, error)));
+            }
+            if self
+                .service
+                .lock()
+                .unwrap()
+                .deregister(&deregistration.pub_key, &deregistration.identity_key)
+            {
+                Ok(Response::with(status::Ok))
+            } else {
+                Ok(Response::with((
+                    status::Forbidden,
+                    "the key is assigned to a mixnode with a different identity",
+                )))
+            }
+        } else {
+            let error = json_parse.unwrap_err();
+            Ok(Response::with((status::BadRequest, error.detail)))
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use super::*;
 
 mod conversions;
+pub mod layer;
 pub mod mixnode;
 mod models;
+mod signature;
 pub mod topology;
@@ -64,3 +64,7 @@ impl Into<u64> for Timestamp {
         self.0
     }
 }
+
+// shared with the mixnodes, as they have to sign the exact same message that is verified here
+pub use directory_client::presence::layers::{LayerAssignment, LayerAssignmentRequest};
+pub use directory_client::presence::mixnodes::MixNodeDeregistration;
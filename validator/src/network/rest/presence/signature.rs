@@ -0,0 +1,87 @@
+use crypto::asymmetric::identity;
+
+/// How far, in milliseconds, the timestamp of a signed request may be from the current time
+/// for the request to be accepted. It limits how long a captured request could be replayed.
+const MAXIMUM_REQUEST_TIME_DIFFERENCE: u64 = 5 * 60 * 1000;
+
+/// Checks whether the request made at `timestamp` is recent and whether `message` is signed
+/// with the base58 encoded identity key.
+pub(super) fn verify_signed_request(
+    identity_key: &str,
+    signature: &str,
+    timestamp: u64,
+    message: &[u8],
+    now: u64,
+) -> Result<(), &'static str> {
+    let time_difference = now.max(timestamp) - now.min(timestamp);
+    if time_difference > MAXIMUM_REQUEST_TIME_DIFFERENCE {
+        return Err("request timestamp is too far from the current time");
+    }
+
+    let identity_key = bs58::decode(identity_key)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| identity::PublicKey::from_bytes(&bytes).ok())
+        .ok_or("malformed identity key")?;
+    let signature = bs58::decode(signature)
+        .into_vec()
+        .ok()
+        .and_then(|bytes| identity::Signature::from_bytes(&bytes).ok())
+        .ok_or("malformed signature")?;
+    identity_key
+        .verify(message, &signature)
+        .map_err(|_| "invalid signature")
+}
+
+#[cfg(test)]
+mod verifying_signed_requests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000_000;
+    const MESSAGE: &[u8] = b"foomp";
+
+    fn sign(keypair: &identity::KeyPair, message: &[u8]) -> String {
+        let signature = keypair.private_key().sign(message);
+        bs58::encode(&signature.to_bytes()[..]).into_string()
+    }
+
+    #[test]
+    fn accepts_recent_correctly_signed_request() {
+        let keypair = identity::KeyPair::new();
+        let identity_key = keypair.public_key().to_base58_string();
+        let signature = sign(&keypair, MESSAGE);
+        assert!(verify_signed_request(&identity_key, &signature, NOW, MESSAGE, NOW).is_ok());
+    }
+
+    #[test]
+    fn rejects_different_message() {
+        let keypair = identity::KeyPair::new();
+        let identity_key = keypair.public_key().to_base58_string();
+        let signature = sign(&keypair, MESSAGE);
+        assert!(verify_signed_request(&identity_key, &signature, NOW, b"foomq", NOW).is_err());
+    }
+
+    #[test]
+    fn rejects_request_signed_with_different_identity() {
+        let keypair = identity::KeyPair::new();
+        let identity_key = identity::KeyPair::new().public_key().to_base58_string();
+        let signature = sign(&keypair, MESSAGE);
+        assert!(verify_signed_request(&identity_key, &signature, NOW, MESSAGE, NOW).is_err());
+    }
+
+    #[test]
+    fn rejects_old_request() {
+        let keypair = identity::KeyPair::new();
+        let identity_key = keypair.public_key().to_base58_string();
+        let signature = sign(&keypair, MESSAGE);
+        let timestamp = NOW - MAXIMUM_REQUEST_TIME_DIFFERENCE - 1;
+        assert!(verify_signed_request(&identity_key, &signature, timestamp, MESSAGE, NOW).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_signature() {
+        let keypair = identity::KeyPair::new();
+        let identity_key = keypair.public_key().to_base58_string();
+        assert!(verify_signed_request(&identity_key, "not base58!", NOW, MESSAGE, NOW).is_err());
+    }
+}
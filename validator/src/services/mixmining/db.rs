@@ -1,4 +1,14 @@
 use super::Mixnode;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+struct LayerAssignment {
+    layer: u64,
+    // identity key of the mixnode that obtained the assignment
+    identity_key: String,
+    // last time the assignment was requested
+    last_requested: u64,
+}
 
 /// A (currently RAM-based) data store to keep tabs on which nodes have what
 /// stake assigned to them.
@@ -6,6 +16,7 @@ use super::Mixnode;
 pub struct MixminingDb {
     mixnodes: Vec<Mixnode>,
     capacity: usize,
+    layer_assignments: HashMap<String, LayerAssignment>,
 }
 
 impl MixminingDb {
@@ -14,6 +25,7 @@ impl MixminingDb {
         MixminingDb {
             capacity: 6,
             mixnodes,
+            layer_assignments: HashMap::new(),
         }
     }
 
@@ -32,6 +44,67 @@ impl MixminingDb {
     pub fn capacity(&self) -> usize {
         self.capacity
     }
+
+    pub fn layer_of(&self, public_key: &str) -> Option<u64> {
+        self.layer_assignments
+            .get(public_key)
+            .map(|assignment| assignment.layer)
+    }
+
+    /// Identity key of the mixnode the layer of the given public key was assigned to.
+    pub fn assignment_identity_of(&self, public_key: &str) -> Option<&str> {
+        self.layer_assignments
+            .get(public_key)
+            .map(|assignment| assignment.identity_key.as_ref())
+    }
+
+    pub fn set_layer(&mut self, public_key: &str, identity_key: &str, layer: u64, now: u64) {
+        self.layer_assignments.insert(
+            public_key.to_string(),
+            LayerAssignment {
+                layer,
+                identity_key: identity_key.to_string(),
+                last_requested: now,
+            },
+        );
+    }
+
+    /// Removes every announced presence and the layer assignment of the given public key.
+    pub fn remove(&mut self, public_key: &str) {
+        self.mixnodes
+            .retain(|mixnode| mixnode.public_key != public_key);
+        self.layer_assignments.remove(public_key);
+    }
+
+    /// Removes assignments of mixnodes that neither announced their presence nor requested
+    /// their assignment within `max_age` milliseconds, so that they no longer count towards
+    /// the sizes of their layers.
+    pub fn evict_stale_layer_assignments(&mut self, now: u64, max_age: u64) {
+        let mixnodes = &self.mixnodes;
+        let last_presence = |public_key: &str| {
+            mixnodes
+                .iter()
+                .filter(|mixnode| mixnode.public_key == public_key)
+                .map(|mixnode| mixnode.last_seen)
+                .max()
+                .unwrap_or_default()
+        };
+        self.layer_assignments.retain(|public_key, assignment| {
+            let last_seen = assignment.last_requested.max(last_presence(public_key));
+            now.saturating_sub(last_seen) <= max_age
+        });
+    }
+
+    /// Number of mixnodes assigned to each of the layers in `1..=layers`.
+    pub fn layer_sizes(&self, layers: u64) -> Vec<usize> {
+        let mut sizes = vec![0; layers as usize];
+        for assignment in self.layer_assignments.values() {
+            if (1..=layers).contains(&assignment.layer) {
+                sizes[(assignment.layer - 1) as usize] += 1;
+            }
+        }
+        sizes
+    }
 }
 
 #[cfg(test)]
@@ -52,6 +125,68 @@ mod capacity {
     }
 }
 
+#[cfg(test)]
+mod layer_assignments {
+    use super::*;
+
+    #[test]
+    fn unknown_nodes_have_no_layer() {
+        let db = MixminingDb::new();
+        assert_eq!(None, db.layer_of("abc123"));
+    }
+
+    #[test]
+    fn setting_layer_overwrites_previous_assignment() {
+        let mut db = MixminingDb::new();
+        db.set_layer("abc123", "id", 1, 100);
+        db.set_layer("abc123", "id", 3, 200);
+        assert_eq!(Some(3), db.layer_of("abc123"));
+        assert_eq!(Some("id"), db.assignment_identity_of("abc123"));
+        assert_eq!(vec![0, 0, 1], db.layer_sizes(3));
+    }
+
+    #[test]
+    fn stale_assignments_are_evicted() {
+        let mut db = MixminingDb::new();
+        db.set_layer("abc123", "id", 1, 100);
+        db.set_layer("def456", "id2", 2, 100);
+        db.add(Mixnode {
+            host: String::from("foo.com"),
+            last_seen: 1000,
+            location: String::from("London, UK"),
+            public_key: String::from("def456"),
+            stake: 8,
+            version: String::from("1.0"),
+        });
+
+        db.evict_stale_layer_assignments(1100, 500);
+        assert_eq!(None, db.layer_of("abc123"));
+        // the recent presence keeps the assignment alive
+        assert_eq!(Some(2), db.layer_of("def456"));
+        assert_eq!(vec![0, 1, 0], db.layer_sizes(3));
+    }
+
+    #[test]
+    fn removing_drops_the_assignment_and_presence() {
+        let mut db = MixminingDb::new();
+        db.set_layer("abc123", "id", 1, 100);
+        db.set_layer("def456", "id2", 2, 100);
+        db.add(Mixnode {
+            host: String::from("foo.com"),
+            last_seen: 100,
+            location: String::from("London, UK"),
+            public_key: String::from("abc123"),
+            stake: 8,
+            version: String::from("1.0"),
+        });
+
+        db.remove("abc123");
+        assert_eq!(None, db.layer_of("abc123"));
+        assert_eq!(Some(2), db.layer_of("def456"));
+        assert!(db.get_mixnodes().is_empty());
+    }
+}
+
 #[cfg(test)]
 mod adding_and_retrieving_mixnodes {
     use super::*;
@@ -19,6 +19,14 @@ pub mod db;
 pub mod models;
 mod tests;
 
+/// Number of mix layers the network is currently made of.
+pub const MIXNODE_LAYERS: u64 = 3;
+
+/// Time, in milliseconds, after which a layer assignment is dropped if the mixnode neither
+/// announced its presence nor requested the assignment again. Mixnodes renew their assignments
+/// every epoch (an hour by default), so it has to be comfortably longer than that.
+pub const LAYER_ASSIGNMENT_MAX_AGE: u64 = 3 * 60 * 60 * 1000;
+
 pub struct Service {
     db: MixminingDb,
 }
@@ -73,6 +81,60 @@ impl Service {
         self.db.capacity()
     }
 
+    /// Assigns the mixnode to a layer, trying to keep all layers roughly equally sized.
+    /// New mixnodes go to the currently smallest layer. Already assigned ones keep their layer,
+    /// unless it has grown larger by more than a single node than the smallest one, in which case
+    /// they get moved there.
+    ///
+    /// The public key is bound to the identity key of the mixnode that first requested its
+    /// assignment, until the assignment goes stale. Requests made with any other identity
+    /// are rejected by returning `None`.
+    pub fn assign_layer(&mut self, public_key: &str, identity_key: &str, now: u64) -> Option<u64> {
+        self.db
+            .evict_stale_layer_assignments(now, LAYER_ASSIGNMENT_MAX_AGE);
+        match self.db.assignment_identity_of(public_key) {
+            Some(assigned_identity) if assigned_identity != identity_key => return None,
+            _ => (),
+        }
+
+        let sizes = self.db.layer_sizes(MIXNODE_LAYERS);
+        // on a tie, the lowest layer is chosen
+        let (smallest_idx, smallest_size) = sizes
+            .iter()
+            .enumerate()
+            .min_by_key(|(idx, size)| (**size, *idx))
+            .map(|(idx, size)| (idx, *size))
+            .expect("there are no mix layers");
+        let smallest_layer = smallest_idx as u64 + 1;
+
+        let layer = match self.db.layer_of(public_key) {
+            Some(current) if (1..=MIXNODE_LAYERS).contains(&current) => {
+                if sizes[(current - 1) as usize] > smallest_size + 1 {
+                    smallest_layer
+                } else {
+                    current
+                }
+            }
+            _ => smallest_layer,
+        };
+
+        self.db.set_layer(public_key, identity_key, layer, now);
+        Some(layer)
+    }
+
+    /// Removes the mixnode from the topology and frees its layer assignment. Only the identity
+    /// its layer got assigned to may do so - requests made with any other identity are rejected
+    /// by returning `false`.
+    pub fn deregister(&mut self, public_key: &str, identity_key: &str) -> bool {
+        match self.db.assignment_identity_of(public_key) {
+            Some(assigned_identity) if assigned_identity != identity_key => false,
+            _ => {
+                self.db.remove(public_key);
+                true
+            }
+        }
+    }
+
     /*
 
     /// Update (or create) a given mixnode stake, identified by the mixnode's public key
@@ -139,6 +201,77 @@ mod mixnodes {
     }
 }
 
+#[cfg(test)]
+mod layer_assignment {
+    use super::*;
+
+    const NOW: u64 = 1_000_000_000;
+
+    // each of the test mixnodes uses its public key as its identity as well
+    fn assign(service: &mut Service, key: &str) -> Option<u64> {
+        service.assign_layer(key, key, NOW)
+    }
+
+    #[test]
+    fn new_mixnodes_fill_layers_evenly() {
+        let mut service = Service::new(MixminingDb::new());
+        let layers: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|key| assign(&mut service, key).unwrap())
+            .collect();
+        assert_eq!(vec![1, 2, 3, 1], layers);
+    }
+
+    #[test]
+    fn assigned_mixnodes_keep_their_layer_if_balanced() {
+        let mut service = Service::new(MixminingDb::new());
+        assign(&mut service, "a");
+        assign(&mut service, "b");
+        assert_eq!(Some(2), assign(&mut service, "b"));
+    }
+
+    #[test]
+    fn assigned_mixnodes_are_moved_out_of_overpopulated_layers() {
+        let mut db = MixminingDb::new();
+        db.set_layer("a", "a", 1, NOW);
+        db.set_layer("b", "b", 1, NOW);
+        db.set_layer("c", "c", 1, NOW);
+        db.set_layer("d", "d", 2, NOW);
+        let mut service = Service::new(db);
+        assert_eq!(Some(3), assign(&mut service, "a"));
+        // layer 1 is no longer overpopulated
+        assert_eq!(Some(1), assign(&mut service, "b"));
+    }
+
+    #[test]
+    fn assignments_can_only_be_renewed_with_the_same_identity() {
+        let mut service = Service::new(MixminingDb::new());
+        assert_eq!(Some(1), assign(&mut service, "a"));
+        assert_eq!(None, service.assign_layer("a", "impostor", NOW));
+        assert_eq!(Some(1), assign(&mut service, "a"));
+    }
+
+    #[test]
+    fn stale_assignments_no_longer_count_towards_layer_sizes() {
+        let mut service = Service::new(MixminingDb::new());
+        assign(&mut service, "a");
+        let later = NOW + LAYER_ASSIGNMENT_MAX_AGE + 1;
+        // "a" went away, so layer 1 is empty again
+        assert_eq!(Some(1), service.assign_layer("b", "b", later));
+        // and its key is no longer bound to its identity
+        assert_eq!(Some(2), service.assign_layer("a", "other", later));
+    }
+
+    #[test]
+    fn deregistered_mixnodes_free_their_layer() {
+        let mut service = Service::new(MixminingDb::new());
+        assign(&mut service, "a");
+        assert!(!service.deregister("a", "impostor"));
+        assert!(service.deregister("a", "a"));
+        assert_eq!(Some(1), assign(&mut service, "b"));
+    }
+}
+
 #[cfg(test)]
 mod constructor {
     use super::*;